@@ -2,10 +2,10 @@ use axum::{routing::get, Router};
 use tokio::net::TcpListener;
 use std::sync::Arc;
 use std::env;
+use cadmus_kernel::modules::content::bus::PgRoomBus;
 use cadmus_kernel::modules::content::socket::ContentRegistry;
-use cadmus_kernel::modules::content::storage::ContentStorage;
+use cadmus_kernel::modules::content::storage::{ContentStorage, DbPool};
 use cadmus_kernel::shared::database::{Db, CoreState};
-use cadmus_kernel::shared::migrations::run_migrations;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 
@@ -52,60 +52,94 @@ async fn main() {
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
     // 1. Establish Database Connection with Persistent Retry Logic
-    // The application will continuously attempt to connect to the database until successful.
+    // The application keeps attempting to reach the database until it answers. Errors that
+    // retrying cannot fix, such as a malformed URL or rejected credentials, stop it instead.
     let db = loop {
         match Db::new(&database_url).await {
             Ok(db) => {
                 tracing::info!("Database connection established.");
                 break db;
             },
-            Err(e) => {
+            Err(e) if is_transient(&e) => {
                 tracing::error!("DATABASE_CONNECTION_ERROR: {}. Retrying in 10s...", e);
                 tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+            },
+            Err(e) => {
+                tracing::error!("DATABASE_CONFIGURATION_ERROR: {}", e);
+                std::process::exit(1);
             }
         }
     };
     
-    // EMERGENCY RECONCILIATION: Attempt to clean up potentially failed or outdated migrations.
-    // This query deletes migration records that are newer than a specific timestamp,
-    // allowing for re-application of subsequent migrations.
-    match sqlx::query("DELETE FROM _sqlx_migrations WHERE version >= 20260126").execute(&db.pool).await {
-        Ok(_) => tracing::info!("Migration table reconciled."),
-        Err(e) => tracing::warn!("Migration reconciliation failed: {}", e),
-    }
+    // The Postgres deployment carries repairs of its own history around the migration run.
+    // A SQLite database only needs its migrations.
+    if let DbPool::Postgres(pg) = &db.pool {
+        // EMERGENCY RECONCILIATION: Attempt to clean up potentially failed or outdated migrations.
+        // This query deletes migration records that are newer than a specific timestamp,
+        // allowing for re-application of subsequent migrations.
+        match sqlx::query("DELETE FROM _sqlx_migrations WHERE version >= 20260126").execute(pg).await {
+            Ok(_) => tracing::info!("Migration table reconciled."),
+            Err(e) => tracing::warn!("Migration reconciliation failed: {}", e),
+        }
+
+        // SYNC ARCHETYPES: Force correct schema for Ledger and Asset classes.
+        // This ensures critical archetypes have their UI and behavior rules up-to-date
+        // even if some migrations were missed or altered manually.
+        let _ = sqlx::query(r#"
+            UPDATE classes SET 
+                ui_schema = '[{"key":"balance","type":"money","label":"TOTAL_BALANCE","read_only":true},{"key":"last_audit","type":"date","label":"LAST_AUDIT"},{"key":"status","type":"badge","label":"LEDGER_STATUS"}]'::jsonb,
+                behavior_rules = '{"groups":[{"id":"aggregation","actions":[{"type":"AGGREGATE","params":{"target_key":"balance","source_key":"value"}}]}]}'::jsonb
+            WHERE id = 'ledger';
+            UPDATE classes SET 
+                ui_schema = '[{"key":"value","type":"money","label":"VALUATION","confidential":true},{"key":"category","type":"text","label":"CATEGORY"}]'::jsonb
+            WHERE id = 'asset';
+        "#).execute(pg).await;
+        tracing::info!("Core Archetypes synchronized with latest definitions.");
+
+        // Run pending database migrations using sqlx-cli.
+        tracing::info!("Starting migration runner...");
+        let _ = db.migrate().await;
 
-    // SYNC ARCHETYPES: Force correct schema for Ledger and Asset classes.
-    // This ensures critical archetypes have their UI and behavior rules up-to-date
-    // even if some migrations were missed or altered manually.
-    let _ = sqlx::query(r#"
-        UPDATE classes SET 
-            ui_schema = '[{"key":"balance","type":"money","label":"TOTAL_BALANCE","read_only":true},{"key":"last_audit","type":"date","label":"LAST_AUDIT"},{"key":"status","type":"badge","label":"LEDGER_STATUS"}]'::jsonb,
-            behavior_rules = '{"groups":[{"id":"aggregation","actions":[{"type":"AGGREGATE","params":{"target_key":"balance","source_key":"value"}}]}]}'::jsonb
-        WHERE id = 'ledger';
-        UPDATE classes SET 
-            ui_schema = '[{"key":"value","type":"money","label":"VALUATION","confidential":true},{"key":"category","type":"text","label":"CATEGORY"}]'::jsonb
-        WHERE id = 'asset';
-    "#).execute(&db.pool).await;
-    tracing::info!("Core Archetypes synchronized with latest definitions.");
-
-    // Run pending database migrations using sqlx-cli.
-    tracing::info!("Starting migration runner...");
-    let _ = run_migrations(&db.pool).await;
-
-    // FORCE EXTINCTION OF LEGACY CLASSES: Clean up outdated or deprecated class definitions.
-    // This updates existing documents to a 'note' class if they were using a legacy class ID,
-    // and then deletes the legacy class definitions.
-    let _ = sqlx::query("DELETE FROM classes WHERE id IN ('blueprint', 'meeting', 'contract', 'finance', 'inventory')")
-        .execute(&db.pool).await;
-    let _ = sqlx::query("UPDATE documents SET class_id = 'note' WHERE class_id IN ('blueprint', 'meeting', 'contract', 'finance', 'inventory')")
-        .execute(&db.pool).await;
-    tracing::info!("Legacy classes sanitized from registry.");
+        // FORCE EXTINCTION OF LEGACY CLASSES: Clean up outdated or deprecated class definitions.
+        // This updates existing documents to a 'note' class if they were using a legacy class ID,
+        // and then deletes the legacy class definitions.
+        let _ = sqlx::query("DELETE FROM classes WHERE id IN ('blueprint', 'meeting', 'contract', 'finance', 'inventory')")
+            .execute(pg).await;
+        let _ = sqlx::query("UPDATE documents SET class_id = 'note' WHERE class_id IN ('blueprint', 'meeting', 'contract', 'finance', 'inventory')")
+            .execute(pg).await;
+        tracing::info!("Legacy classes sanitized from registry.");
+    } else {
+        tracing::info!("Starting migration runner...");
+        db.migrate().await.expect("Failed to migrate the SQLite database");
+    }
 
     // 2. Initialize Kernel Services: Content storage and registry, and the overall CoreState.
-    let content_storage = Arc::new(ContentStorage::new_pg(db.pool.clone()));
-    let content_registry = Arc::new(ContentRegistry::new(Some(content_storage)));
+    let content_storage = Arc::new(ContentStorage::new(db.pool.clone()));
+    let mut content_registry = ContentRegistry::new(Some(content_storage));
+    // Replicas behind a load balancer share their rooms over Postgres LISTEN/NOTIFY.
+    if env::var("CONTENT_ROOM_BUS").is_ok_and(|bus| bus == "postgres") {
+        match &db.pool {
+            DbPool::Postgres(pg) => {
+                tracing::info!("Relaying collaboration rooms between instances over Postgres.");
+                content_registry = content_registry.with_bus(Arc::new(PgRoomBus::new(pg.clone())));
+            }
+            DbPool::Sqlite(_) => tracing::warn!("CONTENT_ROOM_BUS=postgres ignored: the database is SQLite."),
+        }
+    }
+    let content_registry = Arc::new(content_registry);
     let core_state = Arc::new(CoreState::new(db.pool.clone(), content_registry.clone()));
 
+    // 3. Background Tasks: Unload collaboration rooms that have gone idle, apply peer updates,
+    // purge documents that have outlived the trash retention period.
+    tokio::spawn(content_registry.clone().run_eviction());
+    tokio::spawn(core_state.trash.clone().run_purge());
+    let bus_registry = content_registry.clone();
+    tokio::spawn(async move {
+        if let Err(e) = bus_registry.run_bus().await {
+            tracing::error!("Room bus stopped: {}", e);
+        }
+    });
+
     // 4. API Routing: Define all application routes and apply middleware.
     let app = Router::new()
         .route("/health", get(|| async { "SYSTEM_OPERATIONAL" })) // Health check endpoint
@@ -113,6 +147,9 @@ async fn main() {
         .nest("/api/v1/content/docs", routes::content::document_routes().with_state(core_state.clone())) // Document-specific API routes
         .nest("/api/v1/auth", routes::auth::auth_routes().with_state(core_state.clone())) // Authentication API routes
         .nest("/api/v1/stats", routes::stats::routes().with_state(core_state.clone())) // Statistics API routes
+        .nest("/api/v1/graph", routes::graph::routes().with_state(core_state.clone())) // Graph traversal routes
+        .nest("/api/v1/sync", routes::sync::routes().with_state(core_state.clone())) // Desktop vault replication routes
+        .nest("/api/v1/workspace", routes::workspace::routes().with_state(core_state.clone())) // Workspace export/import bundles
         .layer(CorsLayer::permissive()) // Enable CORS for all origins (for development/frontend access)
         .layer(axum::extract::DefaultBodyLimit::max(50 * 1024 * 1024)) // Set max request body size to 50MB
         .layer(TraceLayer::new_for_http()); // Add HTTP tracing for request/response logging
@@ -120,5 +157,47 @@ async fn main() {
     // Bind the TCP listener and start serving the Axum application.
     let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
     tracing::info!("Cadmus API listening on 0.0.0.0:3000");
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+
+    // 5. Shutdown: write the collaboration updates still buffered for persistence.
+    tracing::info!("Shutting down, flushing pending document updates...");
+    if let Err(e) = content_registry.flush().await {
+        tracing::error!("Failed to persist pending document updates: {}", e);
+    }
+}
+
+/// Resolves on Ctrl+C or, on Unix, on SIGTERM, which container runtimes send to stop the process.
+async fn shutdown_signal() {
+    let ctrl_c = async { let _ = tokio::signal::ctrl_c().await; };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => { signal.recv().await; },
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// True for failures to reach the database, which clear up once it is running: the connection
+/// is refused or times out, or Postgres is still starting up (`57P03`).
+fn is_transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => true,
+        sqlx::Error::Database(db) => db.code().as_deref() == Some("57P03"),
+        _ => false,
+    }
 }
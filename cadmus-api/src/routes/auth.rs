@@ -7,7 +7,6 @@ use axum::{
 use uuid::Uuid;
 use cadmus_kernel::modules::security::domain::{LoginRequest, RegisterRequest, UpdateProfileRequest, User};
 use cadmus_kernel::shared::database::CoreState;
-use cadmus_kernel::domain::repository::AuditRepository;
 use std::sync::Arc;
 use crate::routes::content::ApiError;
 
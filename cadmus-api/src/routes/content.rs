@@ -1,16 +1,29 @@
 use axum::{
-    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, State, Path},
+    extract::{ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade}, State, Path, Query},
     response::IntoResponse,
     routing::{get, post, delete},
     Router, Json,
 };
 use std::sync::Arc;
+use std::time::Duration;
 use cadmus_kernel::shared::database::CoreState;
-use cadmus_kernel::domain::repository::{DocumentRepository, ArchetypeRepository};
+use cadmus_kernel::modules::security::domain::Session;
+use cadmus_kernel::modules::content::history::{BeforeCompaction, DocumentVersion, DEFAULT_SESSION_GAP};
+use cadmus_kernel::modules::content::extract::DocumentContent;
+use cadmus_kernel::modules::content::socket::RoomBusy;
+use cadmus_kernel::modules::graph::domain::{DocumentLink, GraphError};
+use cadmus_kernel::modules::hierarchy::domain::HierarchyError;
+use cadmus_kernel::modules::query::domain::{DocumentPage, DocumentQuery, QueryError};
+use cadmus_kernel::modules::trash::domain::{TrashError, TrashItem};
+use chrono::{DateTime, Utc};
+use y_sync::awareness::AwarenessUpdate;
 use y_sync::sync::{Message as YSyncMessage, SyncMessage};
+use yrs::block::ClientID;
+use std::collections::HashSet;
 use yrs::updates::encoder::Encode;
 use yrs::updates::decoder::Decode;
-use yrs::{Transact, ReadTxn};
+use yrs::StateVector;
+use tokio::sync::broadcast::error::RecvError;
 use futures::{SinkExt, StreamExt};
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
@@ -24,16 +37,96 @@ pub struct ApiError {
     pub code: String,
 }
 
-impl IntoResponse for ApiError {
-    fn into_response(self) -> axum::response::Response {
-        let status = match self.code.as_str() {
+impl ApiError {
+    fn status(&self) -> axum::http::StatusCode {
+        match self.code.as_str() {
             "UNAUTHORIZED" => axum::http::StatusCode::UNAUTHORIZED,
+            "FORBIDDEN" => axum::http::StatusCode::FORBIDDEN,
             "404" => axum::http::StatusCode::NOT_FOUND,
             "DB_ERROR" | "INTERNAL" | "STORAGE_FAIL" => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
             "VALIDATION" | "VALIDATION_FAIL" | "INVALID_ID" => axum::http::StatusCode::BAD_REQUEST,
+            "ROOM_BUSY" => axum::http::StatusCode::SERVICE_UNAVAILABLE,
             _ => axum::http::StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        (self.status(), Json(self)).into_response()
+    }
+}
+
+/// Error of a change to the document tree. When a parent's class does not accept the child's,
+/// both classes are named, so clients can explain the refusal without parsing the message.
+#[derive(Serialize)]
+pub struct HierarchyApiError {
+    #[serde(flatten)]
+    pub error: ApiError,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub child_class: Option<String>,
+}
+
+impl IntoResponse for HierarchyApiError {
+    fn into_response(self) -> axum::response::Response {
+        (self.error.status(), Json(self)).into_response()
+    }
+}
+
+impl From<ApiError> for HierarchyApiError {
+    fn from(error: ApiError) -> Self {
+        HierarchyApiError { error, parent_class: None, child_class: None }
+    }
+}
+
+impl From<HierarchyError> for HierarchyApiError {
+    fn from(e: HierarchyError) -> Self {
+        let code = match &e {
+            HierarchyError::NotFound(_) => "404",
+            HierarchyError::Forbidden(_) => "FORBIDDEN",
+            HierarchyError::Internal(_) => "DB_ERROR",
+            _ => "VALIDATION",
+        };
+        let (parent_class, child_class) = match &e {
+            HierarchyError::ChildNotAllowed { parent_class, child_class } => (Some(parent_class.clone()), Some(child_class.clone())),
+            _ => (None, None),
+        };
+        HierarchyApiError { error: ApiError { error: e.to_string(), code: code.into() }, parent_class, child_class }
+    }
+}
+
+impl From<GraphError> for ApiError {
+    fn from(e: GraphError) -> Self {
+        let code = match &e {
+            GraphError::NotFound(_) | GraphError::LinkNotFound { .. } => "404",
+            GraphError::Forbidden(_) => "FORBIDDEN",
+            GraphError::Internal(_) => "DB_ERROR",
+            _ => "VALIDATION",
+        };
+        ApiError { error: e.to_string(), code: code.into() }
+    }
+}
+
+impl From<QueryError> for ApiError {
+    fn from(e: QueryError) -> Self {
+        let code = match &e {
+            QueryError::Internal(_) => "DB_ERROR",
+            _ => "VALIDATION",
         };
-        (status, Json(self)).into_response()
+        ApiError { error: e.to_string(), code: code.into() }
+    }
+}
+
+impl From<TrashError> for ApiError {
+    fn from(e: TrashError) -> Self {
+        let code = match &e {
+            TrashError::NotFound(_) | TrashError::AlreadyTrashed(_) | TrashError::NotTrashed(_) => "404",
+            TrashError::Forbidden(_) => "FORBIDDEN",
+            TrashError::Internal(_) => "DB_ERROR",
+        };
+        ApiError { error: e.to_string(), code: code.into() }
     }
 }
 
@@ -49,6 +142,7 @@ pub fn document_routes() -> Router<Arc<CoreState>> {
     Router::new()
         .route("/all", get(list_all))
         .route("/recent", get(list_recent))
+        .route("/query", post(query_docs))
         .route("/create", post(create_doc))
         .route("/stats", get(get_stats))
         .route("/archetypes", get(list_archetypes))
@@ -58,6 +152,10 @@ pub fn document_routes() -> Router<Arc<CoreState>> {
         .route("/tags/:id", get(get_tags))
         .route("/links", get(list_links))
         .route("/links/create", post(create_link))
+        .route("/links/delete", post(delete_link))
+        .route("/trash", get(list_trash))
+        .route("/trash/:id", delete(purge_doc))
+        .route("/trash/:id/restore", post(restore_doc))
         .route("/collection/:id", get(get_collection))
         .route("/collection/:id/cell", post(update_collection_cell))
         .route("/collection/:id/row", post(add_collection_row))
@@ -65,6 +163,13 @@ pub fn document_routes() -> Router<Arc<CoreState>> {
         .route("/:id/updates", get(get_updates_route))
         .route("/:id/latest", get(get_latest_update_route))
         .route("/:id/latest_snapshot", get(get_latest_snapshot_route))
+        .route("/:id/history", get(get_history))
+        .route("/:id/history/state", get(get_state_at))
+        .route("/:id/restore", post(restore_version))
+        .route("/:id/content", get(get_content))
+        .route("/:id/move", post(move_doc))
+        .route("/:id/duplicate", post(duplicate_doc))
+        .route("/:id/backlinks", get(get_backlinks))
         .route("/:id", get(get_doc))
         .route("/:id", delete(delete_doc))
         .route("/health/db", get(db_health_check)) // New Health Check
@@ -87,10 +192,11 @@ pub struct SnapshotRequest {
     pub data: Vec<u8>,
 }
 
-/// Saves a document snapshot.
-async fn save_snapshot(_auth: AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<String>, Json(req): Json<SnapshotRequest>) -> Result<String, ApiError> {
+/// Saves a document snapshot, recorded in the version history under the caller.
+async fn save_snapshot(AuthenticatedUser(user_id): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<String>, Json(req): Json<SnapshotRequest>) -> Result<String, ApiError> {
+    authorize_document(&state, user_id, &id).await?;
     if let Some(storage) = state.registry.get_storage() {
-        storage.save_update(&id, req.data).await
+        storage.save_update_as(&id, req.data, Some(user_id)).await
             .map_err(|e| ApiError { error: e.to_string(), code: "STORAGE_FAIL".into() })?;
         Ok("SNAPSHOT_SAVED".into())
     } else {
@@ -120,6 +226,74 @@ async fn get_updates_route(_auth: AuthenticatedUser, State(state): State<Arc<Cor
     }
 }
 
+/// Query parameters for listing a document's versions.
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// Seconds of inactivity that close an editing session.
+    pub gap_secs: Option<u64>,
+}
+
+/// Lists the versions of a document, grouped into editing sessions by pause and author.
+async fn get_history(AuthenticatedUser(user_id): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<String>, Query(query): Query<HistoryQuery>) -> Result<Json<Vec<DocumentVersion>>, ApiError> {
+    authorize_document(&state, user_id, &id).await?;
+    let storage = state.registry.get_storage()
+        .ok_or(ApiError { error: "NO_STORAGE_CONFIGURED".into(), code: "CONFIG_ERROR".into() })?;
+    let gap = query.gap_secs.map(Duration::from_secs).unwrap_or(DEFAULT_SESSION_GAP);
+    storage.load_versions(&id, gap).await
+        .map(Json)
+        .map_err(|e| ApiError { error: e.to_string(), code: "STORAGE_FAIL".into() })
+}
+
+/// A point in a document's history.
+#[derive(Deserialize)]
+pub struct PointInTime {
+    pub at: DateTime<Utc>,
+}
+
+/// Maps a failure to rebuild a past state. Instants older than the last compaction are refused;
+/// a room that stays busy answers `ROOM_BUSY`, so the client can retry.
+fn history_error(e: anyhow::Error) -> ApiError {
+    let code = if e.is::<BeforeCompaction>() {
+        "VALIDATION"
+    } else if e.is::<RoomBusy>() {
+        "ROOM_BUSY"
+    } else {
+        "STORAGE_FAIL"
+    };
+    ApiError { error: e.to_string(), code: code.into() }
+}
+
+/// Rebuilds the Yjs state of a document as of a given instant, for previewing a version.
+async fn get_state_at(AuthenticatedUser(user_id): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<String>, Query(query): Query<PointInTime>) -> Result<Json<Vec<u8>>, ApiError> {
+    authorize_document(&state, user_id, &id).await?;
+    let storage = state.registry.get_storage()
+        .ok_or(ApiError { error: "NO_STORAGE_CONFIGURED".into(), code: "CONFIG_ERROR".into() })?;
+    storage.load_state_at(&id, query.at).await
+        .map(Json)
+        .map_err(history_error)
+}
+
+/// Restores a document to its state as of a given instant. Connected editors converge live.
+async fn restore_version(AuthenticatedUser(user_id): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<String>, Json(req): Json<PointInTime>) -> Result<String, ApiError> {
+    let doc_id = authorize_document(&state, user_id, &id).await?;
+    let restored = state.registry.restore(&id, req.at, Some(user_id)).await
+        .map_err(history_error)?;
+    let _ = state.audit.log(Some(user_id), Some(doc_id), "Content", "RESTORE_VERSION", Some(req.at.to_rfc3339())).await;
+    Ok(if restored { "VERSION_RESTORED".into() } else { "ALREADY_CURRENT".into() })
+}
+
+/// Returns the plain text, Markdown and heading outline of a document, as of its latest edits.
+/// A room that updates keep busy past the read timeout answers `ROOM_BUSY`, so the client can retry.
+async fn get_content(AuthenticatedUser(user_id): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<String>) -> Result<Json<DocumentContent>, ApiError> {
+    authorize_document(&state, user_id, &id).await?;
+    state.registry.content(&id).await
+        .map(|content| Json(content.as_ref().clone()))
+        .map_err(|e| {
+            let code = if e.is::<RoomBusy>() { "ROOM_BUSY" } else { "DB_ERROR" };
+            ApiError { error: e.to_string(), code: code.into() }
+        })
+}
+
 /// Retrieves a single document by its ID.
 async fn get_doc(_auth: AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>) -> Result<Json<cadmus_kernel::modules::content::workspace::WorkspaceNode>, ApiError> {
     let doc = state.documents.find_by_id(id).await
@@ -162,18 +336,40 @@ async fn list_links(AuthenticatedUser(uid): AuthenticatedUser, State(state): Sta
         .map_err(|e| ApiError { error: e.to_string(), code: "DB_ERROR".into() })
 }
 
-/// Request payload for creating a new document link.
+/// Request payload for creating a new document link. Without a relation the link is a plain
+/// "link"; metadata must be a JSON object.
 #[derive(Deserialize)]
 pub struct CreateLinkRequest {
     pub from_id: Uuid,
     pub to_id: Uuid,
+    pub relation: Option<String>,
+    pub metadata: Option<serde_json::Value>,
 }
 
-/// Creates a new link between two documents.
-async fn create_link(_auth: AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(req): Json<CreateLinkRequest>) -> Result<String, ApiError> {
-    state.documents.add_link(req.from_id, req.to_id).await
-        .map(|_| "LINK_CREATED".into())
-        .map_err(|e| ApiError { error: e.to_string(), code: "DB_ERROR".into() })
+/// Links two documents of the authenticated user, or updates the metadata of an existing link of the same relation.
+async fn create_link(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(req): Json<CreateLinkRequest>) -> Result<Json<DocumentLink>, ApiError> {
+    let link = state.graph.link(uid, req.from_id, req.to_id, req.relation, req.metadata).await?;
+    Ok(Json(link))
+}
+
+/// Request payload for deleting a link. Without a relation every link between the two documents is removed.
+#[derive(Deserialize)]
+pub struct DeleteLinkRequest {
+    pub from_id: Uuid,
+    pub to_id: Uuid,
+    pub relation: Option<String>,
+}
+
+/// Deletes links between two documents of the authenticated user.
+async fn delete_link(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(req): Json<DeleteLinkRequest>) -> Result<String, ApiError> {
+    state.graph.unlink(uid, req.from_id, req.to_id, req.relation.as_deref()).await?;
+    Ok("LINK_DELETED".into())
+}
+
+/// Lists the links pointing at a document, with their relations and metadata.
+async fn get_backlinks(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>) -> Result<Json<Vec<DocumentLink>>, ApiError> {
+    let links = state.graph.backlinks(uid, id).await?;
+    Ok(Json(links))
 }
 
 /// Retrieves tags associated with a document, including inherited tags.
@@ -201,18 +397,62 @@ async fn list_all(AuthenticatedUser(uid): AuthenticatedUser, State(state): State
         })
 }
 
-/// Creates a new document.
-async fn create_doc(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(req): Json<CreateDocRequest>) -> Result<Json<cadmus_kernel::modules::content::workspace::WorkspaceNode>, ApiError> {
-    state.documents.create(uid, req.title, req.class_id, req.parent_id).await
-        .map(Json)
-        .map_err(|e| ApiError { error: e.to_string(), code: "DB_ERROR".into() })
+/// Creates a new document. A parent must belong to the user and accept the document's class.
+async fn create_doc(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(req): Json<CreateDocRequest>) -> Result<Json<cadmus_kernel::modules::content::workspace::WorkspaceNode>, HierarchyApiError> {
+    let node = state.hierarchy.create_document(uid, req.title, req.class_id, req.parent_id).await?;
+    Ok(Json(node))
+}
+
+/// Request payload for moving a document. A missing `parent_id` moves it to the root.
+#[derive(Deserialize)]
+pub struct MoveDocRequest {
+    pub parent_id: Option<Uuid>,
+}
+
+/// Moves a document under another parent, refusing cycles and children the parent's archetype does not allow.
+async fn move_doc(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>, Json(req): Json<MoveDocRequest>) -> Result<Json<cadmus_kernel::modules::content::workspace::WorkspaceNode>, HierarchyApiError> {
+    let node = state.hierarchy.move_document(uid, id, req.parent_id).await?;
+    let _ = state.audit.log(Some(uid), Some(id), "Document", "MOVE", req.parent_id.map(|p| p.to_string())).await;
+    Ok(Json(node))
 }
 
-/// Deletes a document by its ID.
+/// Copies a document and its descendants next to it, with their content, rows and internal links.
+/// The copy is made from storage, so edits still buffered for the subtree are written first.
+async fn duplicate_doc(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>) -> Result<Json<cadmus_kernel::modules::content::workspace::WorkspaceNode>, HierarchyApiError> {
+    for doc_id in state.hierarchy.subtree(uid, id).await? {
+        state.registry.flush_doc(&doc_id.to_string()).await
+            .map_err(|e| ApiError { error: e.to_string(), code: "STORAGE_FAIL".into() })?;
+    }
+    let copy = state.hierarchy.duplicate_document(uid, id).await?;
+    let _ = state.audit.log(Some(uid), Some(id), "Document", "DUPLICATE", Some(copy.id.to_string())).await;
+    Ok(Json(copy))
+}
+
+/// Moves a document of the authenticated user, and its descendants, to the trash.
 async fn delete_doc(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>) -> Result<String, ApiError> {
-    state.documents.delete(id, uid).await
-        .map(|_| "DELETED".into())
-        .map_err(|e| ApiError { error: e, code: "DB_ERROR".into() })
+    let trashed = state.trash.trash(uid, id).await?;
+    let _ = state.audit.log(Some(uid), Some(id), "Document", "TRASH", Some(trashed.to_string())).await;
+    Ok("TRASHED".into())
+}
+
+/// Lists the documents the authenticated user has deleted, most recent first.
+async fn list_trash(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>) -> Result<Json<Vec<TrashItem>>, ApiError> {
+    let items = state.trash.list(uid).await?;
+    Ok(Json(items))
+}
+
+/// Brings a deleted document back with the descendants deleted along with it.
+async fn restore_doc(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>) -> Result<String, ApiError> {
+    let restored = state.trash.restore(uid, id).await?;
+    let _ = state.audit.log(Some(uid), Some(id), "Document", "RESTORE", Some(restored.to_string())).await;
+    Ok("RESTORED".into())
+}
+
+/// Deletes a trashed document and its trashed descendants for good.
+async fn purge_doc(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>) -> Result<String, ApiError> {
+    let purged = state.trash.purge(uid, id).await?;
+    let _ = state.audit.log(Some(uid), Some(id), "Document", "PURGE", Some(purged.to_string())).await;
+    Ok("PURGED".into())
 }
 
 /// Retrieves system statistics for the authenticated user.
@@ -258,45 +498,245 @@ async fn list_recent(AuthenticatedUser(uid): AuthenticatedUser, State(state): St
         })
 }
 
-/// Handles WebSocket upgrades for real-time document collaboration.
-async fn ws_handler(ws: WebSocketUpgrade, Path(id): Path<String>, State(state): State<Arc<CoreState>>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, id, state.registry.clone()))
+/// Lists the documents of the authenticated user matching a query, a page at a time.
+async fn query_docs(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(query): Json<DocumentQuery>) -> Result<Json<DocumentPage>, ApiError> {
+    let page = state.query.query(uid, &query).await?;
+    Ok(Json(page))
+}
+
+/// How long a socket that carried no token in its URL may take to send one.
+const WS_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the closing handshake may take to reach the client once the session ends.
+const WS_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Application close codes (4000-4999) used when a socket is refused or its session ends.
+const CLOSE_UNAUTHORIZED: u16 = 4401;
+const CLOSE_FORBIDDEN: u16 = 4403;
+const CLOSE_NOT_FOUND: u16 = 4404;
+/// Close code asking the client to reconnect later: the room stayed busy past a read.
+const CLOSE_TRY_AGAIN: u16 = 1013;
+
+/// Query parameters accepted on the collaboration socket upgrade.
+#[derive(Deserialize)]
+pub struct WsAuthQuery {
+    pub token: Option<String>,
+}
+
+/// Upgrades to the collaboration socket. Browsers cannot set headers on a WebSocket
+/// upgrade, so the token is read from `?token=` or, when absent, from the first text frame.
+async fn ws_handler(ws: WebSocketUpgrade, Path(id): Path<String>, Query(query): Query<WsAuthQuery>, State(state): State<Arc<CoreState>>) -> Result<impl IntoResponse, ApiError> {
+    let session = match query.token {
+        Some(token) => Some(authorize_socket(&state, &token, &id).await?),
+        None => None,
+    };
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, id, state, session)))
+}
+
+/// Validates a token and checks that its user owns the document the socket wants to join.
+async fn authorize_socket(state: &CoreState, token: &str, doc_id: &str) -> Result<Session, ApiError> {
+    let session = state.security.validate_session(token.trim().trim_start_matches("Bearer "))
+        .map_err(|_| ApiError { error: "INVALID_OR_EXPIRED_TOKEN".into(), code: "UNAUTHORIZED".into() })?;
+    authorize_document(state, session.user_id, doc_id).await?;
+    Ok(session)
+}
+
+/// Checks that `user_id` owns the document, before any of its content or history is touched.
+/// A trashed document is not found: it can only be restored or purged through the trash.
+async fn authorize_document(state: &CoreState, user_id: Uuid, doc_id: &str) -> Result<Uuid, ApiError> {
+    let id = Uuid::parse_str(doc_id)
+        .map_err(|_| ApiError { error: "INVALID_DOCUMENT_ID".into(), code: "INVALID_ID".into() })?;
+
+    match state.documents.find_live_owner(id).await {
+        Ok(Some(owner)) if owner == user_id => Ok(id),
+        Ok(Some(_)) => {
+            tracing::warn!("Content: User {} denied access to document '{}'", user_id, doc_id);
+            Err(ApiError { error: "ACCESS_DENIED".into(), code: "FORBIDDEN".into() })
+        },
+        Ok(None) => Err(ApiError { error: "DOCUMENT_NOT_FOUND".into(), code: "404".into() }),
+        Err(e) => Err(ApiError { error: e.to_string(), code: "DB_ERROR".into() }),
+    }
+}
+
+/// Waits for the first frame of an unauthenticated socket and authorizes the token it carries.
+async fn authorize_first_frame(receiver: &mut futures::stream::SplitStream<WebSocket>, state: &CoreState, doc_id: &str) -> Result<Session, ApiError> {
+    let unauthorized = || ApiError { error: "MISSING_AUTH_TOKEN".into(), code: "UNAUTHORIZED".into() };
+    match tokio::time::timeout(WS_AUTH_TIMEOUT, receiver.next()).await {
+        Ok(Some(Ok(Message::Text(token)))) => authorize_socket(state, &token, doc_id).await,
+        _ => Err(unauthorized()),
+    }
+}
+
+fn close_message(code: u16, reason: impl Into<String>) -> Message {
+    Message::Close(Some(CloseFrame { code, reason: reason.into().into() }))
 }
 
 /// Manages the WebSocket connection for real-time Yjs document synchronization.
-/// This function broadcasts updates and handles incoming messages.
-async fn handle_socket(socket: WebSocket, doc_id: String, registry: Arc<cadmus_kernel::modules::content::socket::ContentRegistry>) {
+/// Speaks the y-sync protocol: sync steps are answered to the requesting socket only,
+/// document updates and awareness (cursors, presence) are relayed to the whole room.
+/// A socket that lags behind the room's broadcast channel is sent a catch-up diff.
+/// The socket is closed with `CLOSE_UNAUTHORIZED` once its token expires, and with
+/// `CLOSE_NOT_FOUND` once its document is trashed or purged.
+async fn handle_socket(socket: WebSocket, doc_id: String, state: Arc<CoreState>, session: Option<Session>) {
     let (mut sender, mut receiver) = socket.split();
-    let room = registry.get_room(&doc_id).await;
+    let session = match session {
+        Some(session) => session,
+        None => match authorize_first_frame(&mut receiver, &state, &doc_id).await {
+            Ok(session) => session,
+            Err(e) => {
+                let code = match e.code.as_str() {
+                    "UNAUTHORIZED" => CLOSE_UNAUTHORIZED,
+                    "404" => CLOSE_NOT_FOUND,
+                    _ => CLOSE_FORBIDDEN,
+                };
+                let _ = sender.send(close_message(code, e.error)).await;
+                return;
+            }
+        },
+    };
+
+    let registry = state.registry.clone();
+    let room = registry.join(&doc_id).await;
     let mut bcast_rx = room.tx.subscribe();
+    let mut awareness_rx = room.awareness_tx.subscribe();
 
     let (tx, mut rx) = tokio::sync::mpsc::channel(100);
-    // Send initial state vector to new client for synchronization.
-    let initial_sv = room.doc.transact().state_vector();
+    // Handshake: send our state vector and the current presence of the room to the new client.
+    let initial_sv = match room.state_vector().await {
+        Ok(sv) => sv,
+        Err(e) => {
+            tracing::warn!("Content: Cannot greet socket in room '{}': {}", doc_id, e);
+            let _ = sender.send(close_message(CLOSE_TRY_AGAIN, "ROOM_BUSY")).await;
+            return;
+        }
+    };
     let _ = tx.send(Message::Binary(YSyncMessage::Sync(SyncMessage::SyncStep1(initial_sv)).encode_v1())).await;
+    if let Some(update) = room.awareness_update() {
+        let _ = tx.send(Message::Binary(YSyncMessage::Awareness(update).encode_v1())).await;
+    }
 
     // Forward messages from our mpsc channel to the websocket.
-    let fwd = tokio::spawn(async move { while let Some(m) = rx.recv().await { if sender.send(m).await.is_err() { break; } } });
-    // Broadcast Yjs updates from the document room to our mpsc channel.
-    let bcast = tokio::spawn(async move { while let Ok(u) = bcast_rx.recv().await { let _ = tx.send(Message::Binary(YSyncMessage::Sync(SyncMessage::Update(u)).encode_v1())).await; } });
-
-    // Process incoming WebSocket messages (Yjs updates) from the client.
-    while let Some(Ok(Message::Binary(data))) = receiver.next().await {
-        if let Ok(YSyncMessage::Sync(sm)) = YSyncMessage::decode_v1(&data) {
-            match sm {
-                // If client sends SyncStep1, respond with an update.
-                SyncMessage::SyncStep1(sv) => {
-                    let upd = room.doc.transact().encode_diff_v1(&sv);
-                    let _ = registry.process_update(&doc_id, upd).await;
+    let mut fwd = tokio::spawn(async move { while let Some(m) = rx.recv().await { if sender.send(m).await.is_err() { break; } } });
+
+    // Awareness client IDs announced through this socket, cleared when it closes.
+    let mut controlled_clients: HashSet<ClientID> = HashSet::new();
+    // Lower bound of the client's document state: what it announced, raised by every resync.
+    let mut client_sv = StateVector::default();
+
+    // Ends the session when the token does; tokens without an expiration never fire.
+    let expires_in = session.remaining();
+    let expired = async move {
+        match expires_in {
+            Some(remaining) => tokio::time::sleep(remaining).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(expired);
+
+    // Multiplex client messages, room broadcasts and session expiry.
+    loop {
+        let outgoing = tokio::select! {
+            msg = receiver.next() => {
+                let data = match msg {
+                    Some(Ok(Message::Binary(data))) => data,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+
+                match YSyncMessage::decode_v1(&data) {
+                    // The client asks for what it is missing: answer it alone with SyncStep2.
+                    Ok(YSyncMessage::Sync(SyncMessage::SyncStep1(sv))) => match room.diff_since(&sv).await {
+                        Ok((diff, _)) => {
+                            client_sv = sv;
+                            Some(YSyncMessage::Sync(SyncMessage::SyncStep2(diff)))
+                        },
+                        Err(e) => {
+                            tracing::warn!("Content: Cannot answer sync step 1 in room '{}': {}", doc_id, e);
+                            let _ = tx.send(close_message(CLOSE_TRY_AGAIN, "ROOM_BUSY")).await;
+                            break;
+                        },
+                    },
+                    // Updates racing the deletion of the document are dropped with it.
+                    Ok(YSyncMessage::Sync(SyncMessage::SyncStep2(_))) | Ok(YSyncMessage::Sync(SyncMessage::Update(_))) if room.is_closed() => None,
+                    // Apply incoming Yjs updates to the shared document.
+                    Ok(YSyncMessage::Sync(SyncMessage::SyncStep2(u))) | Ok(YSyncMessage::Sync(SyncMessage::Update(u))) => {
+                        if let Err(e) = registry.process_update_as(&doc_id, u, Some(session.user_id)).await {
+                            tracing::warn!("Content: Rejected update for '{}': {}", doc_id, e);
+                        }
+                        None
+                    },
+                    Ok(YSyncMessage::Awareness(update)) => {
+                        match room.apply_awareness(update) {
+                            Ok(change) => {
+                                controlled_clients.extend(change.added);
+                                for id in change.removed { controlled_clients.remove(&id); }
+                            },
+                            Err(e) => tracing::warn!("Content: Invalid awareness update for '{}': {}", doc_id, e),
+                        }
+                        None
+                    },
+                    Ok(YSyncMessage::AwarenessQuery) => room.awareness_update().map(YSyncMessage::Awareness),
+                    Ok(_) => None, // Auth and custom messages are not used by this server.
+                    Err(e) => {
+                        tracing::warn!("Content: Undecodable y-sync message for '{}': {}", doc_id, e);
+                        None
+                    }
+                }
+            },
+            // Relay Yjs updates from the room. A subscriber that fell behind the channel is
+            // resynchronized with everything it may have missed instead of being dropped.
+            update = bcast_rx.recv() => match update {
+                Ok(u) => Some(YSyncMessage::Sync(SyncMessage::Update(u))),
+                Err(RecvError::Lagged(skipped)) => {
+                    room.record_lag(skipped);
+                    tracing::warn!("Content: Socket in room '{}' lagged {} updates behind, resynchronizing", doc_id, skipped);
+                    match room.diff_since(&client_sv).await {
+                        Ok((diff, sv)) => {
+                            client_sv = sv;
+                            Some(YSyncMessage::Sync(SyncMessage::Update(diff)))
+                        },
+                        // Without the diff the client would silently diverge; it resyncs on reconnect.
+                        Err(e) => {
+                            tracing::warn!("Content: Cannot resynchronize socket in room '{}': {}", doc_id, e);
+                            let _ = tx.send(close_message(CLOSE_TRY_AGAIN, "ROOM_BUSY")).await;
+                            break;
+                        },
+                    }
                 },
-                // Apply incoming Yjs updates to the shared document.
-                SyncMessage::Update(u) => { registry.process_update(&doc_id, u).await; },
-                _ => {} // Ignore other sync messages for now.
-            }
+                Err(RecvError::Closed) => break,
+            },
+            // Relay awareness changes made by other participants.
+            bytes = awareness_rx.recv() => match bytes {
+                Ok(bytes) => AwarenessUpdate::decode_v1(&bytes).ok().map(YSyncMessage::Awareness),
+                // Presence is last-writer-wins: the current state supersedes whatever was missed.
+                Err(RecvError::Lagged(skipped)) => {
+                    room.record_lag(skipped);
+                    tracing::debug!("Content: Socket in room '{}' lagged {} awareness updates behind", doc_id, skipped);
+                    room.awareness_update().map(YSyncMessage::Awareness)
+                },
+                Err(RecvError::Closed) => break,
+            },
+            _ = room.closed() => {
+                tracing::info!("Content: Closing socket of user {} in deleted room '{}'", session.user_id, doc_id);
+                let _ = tx.send(close_message(CLOSE_NOT_FOUND, "DOCUMENT_DELETED")).await;
+                break;
+            },
+            _ = &mut expired => {
+                tracing::info!("Content: Session of user {} expired in room '{}'", session.user_id, doc_id);
+                let _ = tx.send(close_message(CLOSE_UNAUTHORIZED, "TOKEN_EXPIRED")).await;
+                break;
+            },
+        };
+
+        if let Some(msg) = outgoing && tx.send(Message::Binary(msg.encode_v1())).await.is_err() {
+            break;
         }
     }
-    // Abort spawned tasks when the WebSocket connection closes.
-    fwd.abort(); bcast.abort();
+
+    // Expire the presence of this socket's clients for everyone still in the room.
+    room.remove_awareness(controlled_clients);
+    // Let the forwarder drain what is queued (including any close frame).
+    drop(tx);
+    let _ = tokio::time::timeout(WS_CLOSE_TIMEOUT, &mut fwd).await;
+    fwd.abort();
 }
 
 /// Retrieves rows from a collection document.
@@ -331,7 +771,54 @@ async fn add_collection_row(_auth: AuthenticatedUser, State(state): State<Arc<Co
 
 /// Health Check endpoint for the database connection.
 async fn db_health_check(State(state): State<Arc<CoreState>>) -> Result<String, ApiError> {
-    sqlx::query("SELECT 1").execute(&state.pool).await
+    state.pool.ping().await
         .map(|_| "DB_OK".into())
         .map_err(|e| ApiError { error: e.to_string(), code: "DB_DOWN".into() })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cadmus_kernel::modules::content::socket::ContentRegistry;
+    use cadmus_kernel::shared::database::Db;
+
+    async fn sqlite_state() -> Arc<CoreState> {
+        let db = Db::new("sqlite::memory:").await.expect("Failed to open in-memory SQLite");
+        db.migrate().await.expect("Failed to migrate");
+        Arc::new(CoreState::new(db.pool.clone(), Arc::new(ContentRegistry::new(None))))
+    }
+
+    #[tokio::test]
+    async fn test_trashed_document_refuses_socket_and_history() {
+        let state = sqlite_state().await;
+        let user_id = Uuid::new_v4();
+        let doc = state.documents.create(user_id, "Draft".into(), None, None).await.unwrap();
+        let token = state.security.generate_token(user_id).unwrap();
+        let id = doc.id.to_string();
+        let history = |state: Arc<CoreState>| get_history(AuthenticatedUser(user_id), State(state), Path(id.clone()), Query(HistoryQuery { gap_secs: None }));
+        assert!(authorize_socket(&state, &token, &id).await.is_ok());
+
+        // 1. Trashed, the document is not found by the socket or the history.
+        state.trash.trash(user_id, doc.id).await.unwrap();
+        let refused = authorize_socket(&state, &token, &id).await.expect_err("Socket joined a trashed document");
+        assert_eq!(refused.code, "404");
+        let refused = history(state.clone()).await.expect_err("History of a trashed document was read");
+        assert_eq!(refused.code, "404");
+
+        // 2. Restored through the trash, it opens again.
+        state.trash.restore(user_id, doc.id).await.unwrap();
+        assert!(authorize_socket(&state, &token, &id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_requires_the_owner() {
+        let state = sqlite_state().await;
+        let (owner_id, stranger) = (Uuid::new_v4(), Uuid::new_v4());
+        let doc = state.documents.create(owner_id, "Mine".into(), None, None).await.unwrap();
+        let snapshot = Json(SnapshotRequest { data: vec![0, 0] });
+
+        let refused = save_snapshot(AuthenticatedUser(stranger), State(state.clone()), Path(doc.id.to_string()), snapshot).await
+            .expect_err("Snapshot written to another user's document");
+        assert_eq!(refused.code, "FORBIDDEN");
+    }
+}
@@ -0,0 +1,78 @@
+use axum::{Router, routing::get, extract::{State, Path, Query}, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+use cadmus_kernel::shared::database::CoreState;
+use cadmus_kernel::modules::graph::domain::{Subgraph, Traversal, MAX_DEPTH};
+use cadmus_kernel::modules::graph::service::MAX_CYCLES;
+use uuid::Uuid;
+use crate::routes::content::ApiError;
+use crate::routes::auth::AuthenticatedUser;
+
+/// Depth of a neighbourhood, and of a path search, when the request gives none.
+const DEFAULT_DEPTH: u32 = 2;
+
+/// Defines the routes that traverse the graph of links and the document tree. Every route takes
+/// `follow=links|hierarchy|both` (default `both`).
+pub fn routes() -> Router<Arc<CoreState>> {
+    Router::new()
+        .route("/neighbourhood/:id", get(neighbourhood))
+        .route("/path", get(shortest_path))
+        .route("/components", get(components))
+        .route("/cycles", get(cycles))
+}
+
+#[derive(Deserialize)]
+pub struct NeighbourhoodQuery {
+    pub depth: Option<u32>,
+    #[serde(default)]
+    pub follow: Traversal,
+}
+
+/// Documents within `depth` edges of a document, and the edges between them.
+async fn neighbourhood(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>, Query(q): Query<NeighbourhoodQuery>) -> Result<Json<Subgraph>, ApiError> {
+    let subgraph = state.graph.neighbourhood(uid, id, q.depth.unwrap_or(DEFAULT_DEPTH), q.follow).await?;
+    Ok(Json(subgraph))
+}
+
+#[derive(Deserialize)]
+pub struct PathQuery {
+    pub from: Uuid,
+    pub to: Uuid,
+    pub max_depth: Option<u32>,
+    #[serde(default)]
+    pub follow: Traversal,
+}
+
+/// A shortest path between two documents, as the ids along it, or `null` when none is within `max_depth`.
+async fn shortest_path(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Query(q): Query<PathQuery>) -> Result<Json<Option<Vec<Uuid>>>, ApiError> {
+    let max_depth = q.max_depth.unwrap_or(MAX_DEPTH);
+    let path = state.graph.shortest_path(uid, q.from, q.to, q.follow, max_depth).await?;
+    Ok(Json(path))
+}
+
+#[derive(Deserialize)]
+pub struct ComponentsQuery {
+    #[serde(default)]
+    pub follow: Traversal,
+}
+
+/// The user's documents grouped into connected components, largest first.
+async fn components(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Query(q): Query<ComponentsQuery>) -> Result<Json<Vec<Vec<Uuid>>>, ApiError> {
+    let components = state.graph.components(uid, q.follow).await?;
+    Ok(Json(components))
+}
+
+#[derive(Deserialize)]
+pub struct CyclesQuery {
+    pub max_length: Option<u32>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub follow: Traversal,
+}
+
+/// Directed cycles among the user's documents, shortest first.
+async fn cycles(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Query(q): Query<CyclesQuery>) -> Result<Json<Vec<Vec<Uuid>>>, ApiError> {
+    let max_length = q.max_length.unwrap_or(MAX_DEPTH);
+    let cycles = state.graph.cycles(uid, q.follow, max_length, q.limit.unwrap_or(MAX_CYCLES)).await?;
+    Ok(Json(cycles))
+}
@@ -1,3 +1,6 @@
 pub mod auth;
 pub mod content;
+pub mod graph;
 pub mod stats;
+pub mod sync;
+pub mod workspace;
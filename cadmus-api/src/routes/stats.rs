@@ -1,7 +1,7 @@
 use axum::{Router, routing::get, extract::{State, Path}, Json};
 use std::sync::Arc;
 use cadmus_kernel::shared::database::CoreState;
-use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::modules::content::socket::RegistryStats;
 use uuid::Uuid;
 use crate::routes::content::ApiError;
 use crate::routes::auth::AuthenticatedUser;
@@ -10,6 +10,7 @@ pub fn routes() -> Router<Arc<CoreState>> {
     Router::new()
         .route("/", get(get_system_stats))
         .route("/aggregate/:id/:key", get(get_doc_aggregation))
+        .route("/rooms", get(get_room_stats))
 }
 
 async fn get_system_stats(
@@ -39,4 +40,12 @@ async fn get_doc_aggregation(
         })?;
         
     Ok(Json(sum))
-}
\ No newline at end of file
+}
+
+/// Lists the collaboration rooms resident in memory, with subscriber counts and size estimates.
+async fn get_room_stats(
+    _auth: AuthenticatedUser,
+    State(state): State<Arc<CoreState>>,
+) -> Json<RegistryStats> {
+    Json(state.registry.stats().await)
+}
@@ -0,0 +1,66 @@
+use axum::{Router, routing::{get, post}, extract::{State, Path}, Json};
+use std::sync::Arc;
+use cadmus_kernel::shared::database::CoreState;
+use cadmus_kernel::modules::sync::domain::{ChangeSet, DocExchange, PushResult, SyncCursor, SyncSession};
+use cadmus_kernel::modules::sync::engine::DEFAULT_PAGE_SIZE;
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::routes::content::ApiError;
+use crate::routes::auth::AuthenticatedUser;
+
+/// Defines the replication routes desktop vaults sync through.
+pub fn routes() -> Router<Arc<CoreState>> {
+    Router::new()
+        .route("/session", get(session))
+        .route("/pull", post(pull))
+        .route("/push", post(push))
+        .route("/docs/:id/exchange", post(exchange))
+        .route("/docs/:id/update", post(push_update))
+}
+
+/// Returns the user the token belongs to, whose workspace a replica syncs.
+async fn session(AuthenticatedUser(uid): AuthenticatedUser) -> Json<SyncSession> {
+    Json(SyncSession { user_id: uid })
+}
+
+/// Request payload for reading the next page of changes.
+#[derive(Deserialize)]
+pub struct PullRequest {
+    #[serde(default)]
+    pub cursor: SyncCursor,
+    pub limit: Option<i64>,
+}
+
+/// Returns the changes of the user's workspace after the given cursor.
+async fn pull(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(payload): Json<PullRequest>) -> Result<Json<ChangeSet>, ApiError> {
+    state.sync.pull(uid, &payload.cursor, payload.limit.unwrap_or(DEFAULT_PAGE_SIZE)).await
+        .map(Json)
+        .map_err(|e| ApiError { error: e.to_string(), code: "DB_ERROR".into() })
+}
+
+/// Writes the changes of a replica, keeping the latest version of each row.
+async fn push(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(changes): Json<ChangeSet>) -> Result<Json<PushResult>, ApiError> {
+    state.sync.push(uid, &changes).await
+        .map(Json)
+        .map_err(|e| ApiError { error: e.to_string(), code: "DB_ERROR".into() })
+}
+
+/// Answers a replica's state vector with the content it is missing and the server's state vector.
+async fn exchange(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>, Json(payload): Json<DocExchange>) -> Result<Json<DocExchange>, ApiError> {
+    state.sync.exchange(uid, id, &payload.state_vector).await
+        .map(Json)
+        .map_err(|e| ApiError { error: e.to_string(), code: "404".into() })
+}
+
+/// Request payload carrying the content the server was missing.
+#[derive(Deserialize)]
+pub struct UpdateRequest {
+    pub update: Vec<u8>,
+}
+
+/// Applies content a replica had and the server did not.
+async fn push_update(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Path(id): Path<Uuid>, Json(payload): Json<UpdateRequest>) -> Result<String, ApiError> {
+    state.sync.push_update(uid, id, payload.update).await
+        .map(|_| "APPLIED".into())
+        .map_err(|e| ApiError { error: e.to_string(), code: "VALIDATION".into() })
+}
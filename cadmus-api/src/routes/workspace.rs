@@ -0,0 +1,34 @@
+use axum::{Router, routing::{get, post}, extract::State, Json};
+use std::sync::Arc;
+use cadmus_kernel::shared::database::CoreState;
+use cadmus_kernel::modules::portability::bundle::{ImportReport, WorkspaceBundle};
+use crate::routes::content::ApiError;
+use crate::routes::auth::AuthenticatedUser;
+
+/// Defines the routes a whole workspace is backed up and moved through.
+pub fn routes() -> Router<Arc<CoreState>> {
+    Router::new()
+        .route("/export", get(export))
+        .route("/import", post(import))
+}
+
+/// Returns the user's workspace as a bundle, content included.
+async fn export(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>) -> Result<Json<WorkspaceBundle>, ApiError> {
+    // Updates still buffered by open rooms belong in the export.
+    state.registry.flush().await
+        .map_err(|e| ApiError { error: e.to_string(), code: "STORAGE_FAIL".into() })?;
+    state.portability.export(uid).await
+        .map(Json)
+        .map_err(|e| ApiError { error: e.to_string(), code: "DB_ERROR".into() })
+}
+
+/// Recreates a bundle in the user's workspace under new ids.
+async fn import(AuthenticatedUser(uid): AuthenticatedUser, State(state): State<Arc<CoreState>>, Json(bundle): Json<WorkspaceBundle>) -> Result<Json<ImportReport>, ApiError> {
+    bundle.verify()
+        .map_err(|e| ApiError { error: e.to_string(), code: "VALIDATION".into() })?;
+    let report = state.portability.import(uid, &bundle).await
+        .map_err(|e| ApiError { error: e.to_string(), code: "DB_ERROR".into() })?;
+    let details = serde_json::json!({ "documents": report.documents, "exported_by": bundle.manifest.owner_id }).to_string();
+    let _ = state.audit.log(Some(uid), None, "Workspace", "IMPORT_BUNDLE", Some(details)).await;
+    Ok(Json(report))
+}
@@ -0,0 +1,9 @@
+-- Yjs Update Log Compaction:
+-- Stores one merged Yjs state per document. `ContentStorage` folds the `document_updates`
+-- history into this row once a threshold is passed, leaving only the tail written after it.
+
+CREATE TABLE IF NOT EXISTS document_snapshots (
+    doc_id UUID PRIMARY KEY REFERENCES documents(id) ON DELETE CASCADE, -- Document this state belongs to.
+    data BYTEA NOT NULL,                                                -- Encoded Yjs state (update v1) of all merged updates.
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP                      -- Time of the last compaction.
+);
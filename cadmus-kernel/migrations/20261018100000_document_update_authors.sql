@@ -0,0 +1,7 @@
+-- Document Version History:
+-- Records who wrote each Yjs update so the version history can group edits into sessions per author.
+
+ALTER TABLE document_updates ADD COLUMN IF NOT EXISTS author_id UUID REFERENCES users(id) ON DELETE SET NULL;
+
+-- History listings and point-in-time replays scan a document's log in time order.
+CREATE INDEX IF NOT EXISTS idx_document_updates_doc_created ON document_updates(doc_id, created_at);
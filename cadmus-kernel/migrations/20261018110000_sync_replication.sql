@@ -0,0 +1,15 @@
+-- Offline-First Replication:
+-- Replicas read each other's changes in timestamp order. Links get a creation time so they can
+-- be read incrementally, and a replica records how far it has synced with each remote.
+
+ALTER TABLE document_links ADD COLUMN IF NOT EXISTS created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP;
+
+-- Incremental reads scan each table in (timestamp, key) order.
+CREATE INDEX IF NOT EXISTS idx_document_links_created ON document_links(created_at, from_id, to_id);
+CREATE INDEX IF NOT EXISTS idx_collection_rows_updated ON collection_rows(updated_at, id);
+
+CREATE TABLE IF NOT EXISTS sync_state (
+    remote TEXT PRIMARY KEY,                          -- Remote this replica syncs with.
+    state TEXT NOT NULL,                              -- JSON of the pull and push cursors.
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP    -- Time of the last saved progress.
+);
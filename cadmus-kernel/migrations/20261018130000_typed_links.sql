@@ -0,0 +1,13 @@
+-- Typed Links:
+-- A link carries a relation (e.g. "depends_on", "cites") and optional JSON metadata. Two
+-- documents may be related in several ways, so the relation is part of the key. Existing
+-- links become plain "link" relations.
+
+ALTER TABLE document_links ADD COLUMN IF NOT EXISTS relation TEXT NOT NULL DEFAULT 'link';
+ALTER TABLE document_links ADD COLUMN IF NOT EXISTS metadata JSONB NOT NULL DEFAULT '{}';
+
+ALTER TABLE document_links DROP CONSTRAINT IF EXISTS document_links_pkey;
+ALTER TABLE document_links ADD PRIMARY KEY (from_id, to_id, relation);
+
+-- Backlinks are read by target.
+CREATE INDEX IF NOT EXISTS idx_document_links_to ON document_links(to_id);
@@ -0,0 +1,10 @@
+-- Trash:
+-- Deleting a document moves it and its live descendants to the trash instead of dropping the
+-- rows. `trash_root_id` names the document the user deleted, so the whole set is restored or
+-- purged together; both columns are NULL for live documents.
+
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS trash_root_id UUID;
+
+-- Trash listings, restores and the retention purge all read by set.
+CREATE INDEX IF NOT EXISTS idx_documents_trash ON documents(trash_root_id, deleted_at) WHERE trash_root_id IS NOT NULL;
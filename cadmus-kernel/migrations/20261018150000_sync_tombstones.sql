@@ -0,0 +1,13 @@
+-- Sync Tombstones:
+-- A hard delete leaves no row for replicas to read, so each one is recorded here and
+-- replicated like any other change, in (deleted_at, key) order.
+
+CREATE TABLE IF NOT EXISTS sync_tombstones (
+    kind TEXT NOT NULL,                               -- What was deleted, e.g. 'document'.
+    key TEXT NOT NULL,                                -- Key of the deleted row, e.g. the document id.
+    owner_id UUID NOT NULL,                           -- Owner whose replicas apply the deletion.
+    deleted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, -- Time of the deletion.
+    PRIMARY KEY (kind, key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_tombstones_deleted ON sync_tombstones(owner_id, deleted_at, key);
@@ -0,0 +1,10 @@
+-- Link Updates:
+-- Relinking two documents replaces the metadata of the link in place, which `created_at` does
+-- not reflect. Links are replicated in (updated_at, key) order, so every change moves it.
+
+ALTER TABLE document_links ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP; -- Time the link or its metadata last changed.
+UPDATE document_links SET updated_at = COALESCE(created_at, CURRENT_TIMESTAMP) WHERE updated_at IS NULL;
+ALTER TABLE document_links ALTER COLUMN updated_at SET DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE document_links ALTER COLUMN updated_at SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_document_links_updated ON document_links(updated_at, from_id, to_id, relation);
@@ -0,0 +1,80 @@
+-- Baseline of the local vault schema, as desktop vaults were created before SQLite migrations.
+-- Every statement is idempotent so the migration can be recorded against an existing vault.
+-- Vaults older than update authors and link times get those columns before this runs
+-- (see `run_sqlite_migrations`); SQLite has no ADD COLUMN IF NOT EXISTS.
+
+CREATE TABLE IF NOT EXISTS documents (
+    id TEXT PRIMARY KEY,                              -- Document UUID.
+    owner_id TEXT NOT NULL,                           -- User who owns the document.
+    title TEXT NOT NULL,                              -- Main title of the document.
+    class_id TEXT,                                    -- Archetype of the document.
+    parent_id TEXT,                                   -- Optional parent document.
+    properties TEXT DEFAULT '{}' NOT NULL,            -- JSON of the document properties.
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP     -- Last update timestamp.
+);
+
+CREATE TABLE IF NOT EXISTS document_links (
+    from_id TEXT NOT NULL,                            -- Source document of the link.
+    to_id TEXT NOT NULL,                              -- Target document of the link.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,    -- Time the link was made.
+    PRIMARY KEY (from_id, to_id)
+);
+
+CREATE TABLE IF NOT EXISTS document_updates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,             -- Auto-incrementing ID.
+    doc_id TEXT NOT NULL,                             -- Document this update belongs to.
+    data BLOB NOT NULL,                               -- Binary data of the Yjs update.
+    author_id TEXT,                                   -- User whose edit produced the update.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP     -- Timestamp of the update.
+);
+
+CREATE TABLE IF NOT EXISTS document_snapshots (
+    doc_id TEXT PRIMARY KEY,                          -- Document this state belongs to.
+    data BLOB NOT NULL,                               -- Full Yjs state of the document.
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP     -- Time of the last compaction.
+);
+
+CREATE TABLE IF NOT EXISTS collection_rows (
+    id TEXT PRIMARY KEY,                              -- Row UUID.
+    document_id TEXT NOT NULL,                        -- The parent collection document.
+    data TEXT DEFAULT '{}' NOT NULL,                  -- JSON of the row cells.
+    order_index INTEGER,                              -- Position of the row in the collection.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS classes (
+    id TEXT PRIMARY KEY,                              -- Unique class identifier (e.g., "note").
+    name TEXT NOT NULL,                               -- Human-readable name of the class.
+    json_schema TEXT DEFAULT '{}',                    -- JSON Schema of the document properties.
+    ui_schema TEXT DEFAULT '[]',                      -- JSON of the UI fields of the class.
+    behavior_rules TEXT DEFAULT '{}',                 -- JSON of the class automation rules.
+    icon TEXT,                                        -- Icon for visual representation.
+    has_collection BOOLEAN DEFAULT 0,                 -- Documents of the class hold collection rows.
+    group_id TEXT DEFAULT 'primitiva',                -- Group the class is listed under.
+    required_tier TEXT DEFAULT 'Community'            -- Tier needed to create documents of the class.
+);
+
+CREATE TABLE IF NOT EXISTS sync_state (
+    remote TEXT PRIMARY KEY,                          -- Remote this replica syncs with.
+    state TEXT NOT NULL,                              -- JSON of the pull and push cursors.
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP     -- Time of the last saved progress.
+);
+
+CREATE INDEX IF NOT EXISTS idx_document_updates_doc_created ON document_updates(doc_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_document_links_created ON document_links(created_at, from_id, to_id);
+CREATE INDEX IF NOT EXISTS idx_collection_rows_updated ON collection_rows(updated_at, id);
+
+-- Seed archetypes. Classes already present in the vault are kept as they are.
+INSERT OR IGNORE INTO classes (id, name, json_schema, ui_schema, behavior_rules, icon, has_collection, group_id, required_tier) VALUES
+('note', 'Note', '{}', '[{"key":"sealed_at","type":"text","label":"INTEGRITY_SEAL","read_only":true}]', '{}', 'FileText', 0, 'primitiva', 'Community'),
+('project', 'Project', '{}', '[{"key":"status","type":"badge","label":"STATE"},{"key":"progress","type":"progress","label":"COMPLETION"}]', '{}', 'Briefcase', 1, 'primitiva', 'Community'),
+('task', 'Task', '{}', '[{"key":"status","type":"select","label":"STATUS","options":["todo","doing","done"]}]', '{}', 'CheckSquare', 0, 'primitiva', 'Community'),
+('container', 'Container', '{}', '[]', '{}', 'Box', 0, 'primitiva', 'Community'),
+('folha', 'Planilha', '{}', '[]', '{}', 'Table', 1, 'dados', 'Community'),
+('meeting', 'Reunião', '{}', '[{"key":"attendees","type":"text","label":"Participantes"}]', '{}', 'Users', 0, 'operacional', 'PRO'),
+('blueprint', 'Blueprint', '{}', '[]', '{}', 'Zap', 0, 'operacional', 'PRO'),
+('inventory', 'Estoque', '{}', '[]', '{}', 'Box', 1, 'operacional', 'PRO'),
+('contract', 'Contrato', '{}', '[]', '{}', 'Shield', 0, 'recursos', 'PRO'),
+('finance', 'Financeiro', '{}', '[]', '{}', 'Gem', 1, 'recursos', 'PRO'),
+('ledger', 'Ledger', '{}', '[{"key":"total_balance","type":"money","label":"AGGREGATE_BALANCE","read_only":true}]', '{}', 'Table', 1, 'dados', 'Community');
@@ -0,0 +1,85 @@
+-- Schema Parity with Postgres:
+-- Brings the local vault to the tables and columns of the server schema, so a workspace can move
+-- between backends without losing fields. Types follow the logical mapping checked by
+-- `logical_schema`: UUIDs and JSON are TEXT, arrays are JSON TEXT, vectors are BLOB.
+
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,                              -- Unique user identifier.
+    username TEXT UNIQUE NOT NULL,                    -- User's unique username for login.
+    password_hash TEXT NOT NULL,                      -- Hashed password for security.
+    tier TEXT DEFAULT 'Community',                    -- User's service tier (e.g., Community, Pro).
+    settings_json TEXT DEFAULT '{}',                  -- JSON of user-specific settings.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,    -- Timestamp of user creation.
+    avatar_url TEXT,                                  -- Profile picture.
+    recovery_key TEXT,                                -- Hash of the account recovery key.
+    trial_expires_at DATETIME                         -- End of the trial period, if any.
+);
+
+CREATE TABLE IF NOT EXISTS audit_logs (
+    id TEXT PRIMARY KEY,                              -- Unique audit log entry ID.
+    user_id TEXT,                                     -- User who performed the action.
+    resource_id TEXT,                                 -- ID of the resource affected.
+    resource_type TEXT,                               -- Type of resource (e.g., Document, User).
+    action TEXT,                                      -- Action performed (e.g., READ, WRITE).
+    ip_address TEXT,                                  -- IP address of the request origin.
+    user_agent TEXT,                                  -- User-Agent string from the client.
+    details TEXT DEFAULT '{}',                        -- JSON of extra metadata about the action.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,    -- Timestamp of the audit event.
+    prev_hash TEXT,                                   -- Hash of the previous entry in the chain.
+    hash TEXT                                         -- Hash of this entry.
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_user ON audit_logs(user_id);
+CREATE INDEX IF NOT EXISTS idx_audit_resource ON audit_logs(resource_id);
+CREATE INDEX IF NOT EXISTS idx_audit_created ON audit_logs(created_at);
+
+CREATE TABLE IF NOT EXISTS neural_metadata (
+    document_id TEXT PRIMARY KEY,                     -- Document associated with this metadata.
+    mass REAL DEFAULT 1.0,                            -- Physics-based ranking (Gravity) - mass attribute.
+    hp INTEGER DEFAULT 100,                           -- Health/integrity points.
+    entropy REAL DEFAULT 0.0,                         -- Measure of disorder/randomness.
+    embedding BLOB,                                   -- Vector embedding, as little-endian f32.
+    last_tick_at DATETIME                             -- Last time physics simulation was updated.
+);
+
+-- `documents` gains `is_public`, `config` and `created_at`. A column added by ALTER TABLE cannot
+-- default to the current time, so the table is rebuilt; existing documents were created no later
+-- than their last update.
+CREATE TABLE documents_parity (
+    id TEXT PRIMARY KEY,                              -- Document UUID.
+    owner_id TEXT NOT NULL,                           -- User who owns the document.
+    parent_id TEXT,                                   -- Optional parent document.
+    class_id TEXT,                                    -- Archetype of the document.
+    title TEXT NOT NULL,                              -- Main title of the document.
+    is_public BOOLEAN DEFAULT 0,                      -- Visibility status of the document.
+    properties TEXT DEFAULT '{}' NOT NULL,            -- JSON of the document properties.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,    -- Timestamp of document creation.
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,    -- Last update timestamp.
+    config TEXT DEFAULT '{}' NOT NULL                 -- JSON of cascading configuration overrides.
+);
+
+INSERT INTO documents_parity (id, owner_id, parent_id, class_id, title, properties, created_at, updated_at)
+SELECT id, owner_id, parent_id, class_id, title, properties, COALESCE(updated_at, CURRENT_TIMESTAMP), updated_at FROM documents;
+
+DROP TABLE documents;
+ALTER TABLE documents_parity RENAME TO documents;
+
+CREATE INDEX IF NOT EXISTS idx_documents_class_id ON documents(class_id);
+CREATE INDEX IF NOT EXISTS idx_documents_updated_at ON documents(updated_at DESC);
+CREATE INDEX IF NOT EXISTS idx_documents_parent_id ON documents(parent_id);
+
+-- `classes` gains its owner and the archetypes it accepts as children (JSON array).
+ALTER TABLE classes ADD COLUMN owner_id TEXT;
+ALTER TABLE classes ADD COLUMN allowed_children TEXT DEFAULT '[]';
+
+INSERT OR IGNORE INTO classes (id, name, json_schema, ui_schema, behavior_rules, icon, has_collection, group_id, required_tier) VALUES
+('asset', 'Asset', '{"properties": {"value": {"type": "number"}}}', '[{"key":"value","type":"money","label":"Estimated Value","currency":"USD","confidential":true},{"key":"currency","type":"select","label":"Currency","options":["USD","EUR","BRL","BTC"]},{"key":"category","type":"select","label":"Category","options":["Hardware","Software","License","Property"]},{"key":"acquired_at","type":"date","label":"Acquisition Date"}]', '{"on_update":"recalculate_ledger"}', 'Gem', 0, 'primitiva', 'Community'),
+('profile', 'Profile', '{"properties": {"role": {"type": "string"}, "email": {"type": "string"}}}', '[{"key":"role","type":"text","label":"Organizational Role"},{"key":"email","type":"email","label":"Contact Email","confidential":true},{"key":"access_level","type":"select","label":"System Clearance","options":["L1","L2","L3","Admin"]},{"key":"status","type":"badge","label":"Availability"}]', '{"indexing":{"track_mentions":true}}', 'User', 0, 'primitiva', 'Community');
+
+UPDATE classes SET allowed_children = '["note","canvas","media"]' WHERE id = 'note';
+UPDATE classes SET allowed_children = '["note","media","doc"]' WHERE id = 'asset';
+UPDATE classes SET allowed_children = '["asset","note"]' WHERE id = 'ledger';
+UPDATE classes SET allowed_children = '["project","ledger","note","container","task","profile","asset"]' WHERE id = 'container';
+UPDATE classes SET allowed_children = '["note","task"]' WHERE id = 'profile';
+UPDATE classes SET allowed_children = '["task","note","canvas","ledger","container"]' WHERE id = 'project';
+UPDATE classes SET allowed_children = '["note","checklist"]' WHERE id = 'task';
@@ -0,0 +1,21 @@
+-- Typed Links:
+-- Counterpart of the server migration. SQLite cannot change a primary key in place, so the
+-- table is rebuilt with the relation in its key; existing links become plain "link" relations.
+
+CREATE TABLE document_links_typed (
+    from_id TEXT NOT NULL,                            -- Source document of the link.
+    to_id TEXT NOT NULL,                              -- Target document of the link.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,    -- Time the link was made.
+    relation TEXT NOT NULL DEFAULT 'link',            -- Kind of relation (e.g. depends_on, cites).
+    metadata TEXT NOT NULL DEFAULT '{}',              -- JSON of extra data about the relation.
+    PRIMARY KEY (from_id, to_id, relation)
+);
+
+INSERT INTO document_links_typed (from_id, to_id, created_at)
+SELECT from_id, to_id, created_at FROM document_links;
+
+DROP TABLE document_links;
+ALTER TABLE document_links_typed RENAME TO document_links;
+
+CREATE INDEX IF NOT EXISTS idx_document_links_created ON document_links(created_at, from_id, to_id);
+CREATE INDEX IF NOT EXISTS idx_document_links_to ON document_links(to_id);
@@ -0,0 +1,9 @@
+-- Trash:
+-- Counterpart of the server migration. Deleted documents keep their rows until purged;
+-- `trash_root_id` names the document the user deleted, so the whole set is restored or purged
+-- together.
+
+ALTER TABLE documents ADD COLUMN deleted_at DATETIME;                 -- Time the document was trashed.
+ALTER TABLE documents ADD COLUMN trash_root_id TEXT;                  -- Document whose deletion trashed this one.
+
+CREATE INDEX IF NOT EXISTS idx_documents_trash ON documents(trash_root_id, deleted_at) WHERE trash_root_id IS NOT NULL;
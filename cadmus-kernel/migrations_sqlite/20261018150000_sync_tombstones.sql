@@ -0,0 +1,12 @@
+-- Sync Tombstones:
+-- Counterpart of the server migration. Hard deletes are recorded here so they replicate.
+
+CREATE TABLE IF NOT EXISTS sync_tombstones (
+    kind TEXT NOT NULL,                               -- What was deleted, e.g. 'document'.
+    key TEXT NOT NULL,                                -- Key of the deleted row, e.g. the document id.
+    owner_id TEXT NOT NULL,                           -- Owner whose replicas apply the deletion.
+    deleted_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, -- Time of the deletion.
+    PRIMARY KEY (kind, key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_tombstones_deleted ON sync_tombstones(owner_id, deleted_at, key);
@@ -0,0 +1,22 @@
+-- Link Updates:
+-- Counterpart of the server migration. A column added in place cannot default to the current
+-- time, so the table is rebuilt; existing links are stamped with the time they were made.
+
+CREATE TABLE document_links_next (
+    from_id TEXT NOT NULL,                            -- Source document of the link.
+    to_id TEXT NOT NULL,                              -- Target document of the link.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,    -- Time the link was made.
+    relation TEXT NOT NULL DEFAULT 'link',            -- Kind of relation (e.g. depends_on, cites).
+    metadata TEXT NOT NULL DEFAULT '{}',              -- JSON of extra data about the relation.
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, -- Time the link or its metadata last changed.
+    PRIMARY KEY (from_id, to_id, relation)
+);
+
+INSERT INTO document_links_next (from_id, to_id, created_at, relation, metadata, updated_at)
+SELECT from_id, to_id, created_at, relation, metadata, COALESCE(created_at, CURRENT_TIMESTAMP) FROM document_links;
+
+DROP TABLE document_links;
+ALTER TABLE document_links_next RENAME TO document_links;
+
+CREATE INDEX IF NOT EXISTS idx_document_links_updated ON document_links(updated_at, from_id, to_id, relation);
+CREATE INDEX IF NOT EXISTS idx_document_links_to ON document_links(to_id);
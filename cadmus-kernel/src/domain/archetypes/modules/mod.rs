@@ -28,6 +28,11 @@ impl ModuleRegistry {
         Self { behaviors }
     }
 
+    /// Registers a module's behaviour, replacing any registered under the same id.
+    pub fn register(&mut self, module_id: &str, behavior: Arc<dyn SovereignBehavior>) {
+        self.behaviors.insert(module_id.to_string(), behavior);
+    }
+
     pub fn get_behavior(&self, module_id: &str) -> Option<Arc<dyn SovereignBehavior>> {
         self.behaviors.get(module_id).cloned()
     }
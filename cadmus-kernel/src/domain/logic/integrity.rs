@@ -1,5 +1,32 @@
 use sha2::{Sha256, Digest};
 use hex;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Um elo da corrente de auditoria, como gravado em `audit_logs`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub resource_id: Option<Uuid>,
+    pub resource_type: Option<String>,
+    pub action: String,
+    pub details: String,
+    pub created_at: DateTime<Utc>,
+    pub prev_hash: Option<String>,
+    pub hash: Option<String>,
+}
+
+/// Resultado da verificação de uma corrente inteira.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainVerification {
+    /// Elos válidos antes do primeiro inválido; todos, quando a corrente está íntegra.
+    pub checked: usize,
+    pub valid: bool,
+    /// Primeiro elo adulterado, removido do lugar ou fora da ordem.
+    pub broken_at: Option<Uuid>,
+}
 
 pub struct IntegrityEngine;
 
@@ -36,4 +63,26 @@ impl IntegrityEngine {
         let calculated = Self::calculate_audit_hash(user_id, resource_id, action, details, prev_hash);
         calculated == current_hash
     }
+
+    /// Verifica uma corrente em ordem de gravação: cada elo deve apontar para o hash do
+    /// anterior e conferir com o próprio conteúdo.
+    pub fn verify_chain(entries: &[AuditEntry]) -> ChainVerification {
+        let mut prev: Option<&str> = None;
+        for (i, entry) in entries.iter().enumerate() {
+            let linked = entry.prev_hash.as_deref() == prev;
+            let sealed = entry.hash.as_deref().is_some_and(|hash| Self::verify_link(
+                hash,
+                entry.user_id.map(|u| u.to_string()).as_deref(),
+                entry.resource_id.map(|r| r.to_string()).as_deref(),
+                &entry.action,
+                &entry.details,
+                entry.prev_hash.as_deref(),
+            ));
+            if !linked || !sealed {
+                return ChainVerification { checked: i, valid: false, broken_at: Some(entry.id) };
+            }
+            prev = entry.hash.as_deref();
+        }
+        ChainVerification { checked: entries.len(), valid: true, broken_at: None }
+    }
 }
@@ -1,16 +1,53 @@
 use async_trait::async_trait;
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use crate::modules::content::workspace::WorkspaceNode;
+use crate::modules::graph::domain::{DocumentLink, GraphEdge, Subgraph, Traversal};
+use crate::modules::query::domain::{DocumentPage, DocumentQuery};
+use crate::modules::trash::domain::TrashItem;
 use crate::domain::archetypes::Archetype;
+use crate::modules::security::domain::Credentials;
 
 #[async_trait]
 pub trait DocumentRepository: Send + Sync {
+    /// Inserts a document as given. Neither the parent's owner nor its `allowed_children` are
+    /// checked here: requests go through `HierarchyService::create_document`, which checks both.
     async fn create(&self, owner_id: Uuid, title: String, class_id: Option<String>, parent_id: Option<Uuid>) -> anyhow::Result<WorkspaceNode>;
     async fn find_recent(&self, owner_id: Uuid, limit: i64) -> anyhow::Result<Vec<WorkspaceNode>>;
     async fn find_all(&self, owner_id: Uuid) -> anyhow::Result<Vec<WorkspaceNode>>;
     async fn find_by_id(&self, id: Uuid) -> anyhow::Result<Option<WorkspaceNode>>;
-    async fn delete(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<()>;
+    /// A page of the live documents of `owner_id` matching a validated `query`.
+    async fn query(&self, owner_id: Uuid, query: &DocumentQuery) -> anyhow::Result<DocumentPage>;
+    /// Owner of a document, trashed or not; `None` when it does not exist.
+    async fn find_owner(&self, id: Uuid) -> anyhow::Result<Option<Uuid>>;
+    /// Owner of a document that is not in the trash; `None` when it is trashed or missing.
+    async fn find_live_owner(&self, id: Uuid) -> anyhow::Result<Option<Uuid>>;
+    /// Puts a document under `parent_id`, or at the root. Returns `false`, changing nothing,
+    /// when the parent is the document itself or one of its descendants. `allowed_children` is
+    /// checked by `HierarchyService::move_document`.
+    async fn set_parent(&self, doc_id: Uuid, parent_id: Option<Uuid>) -> anyhow::Result<bool>;
+    /// Copies a live document of `owner_id` and its live descendants under new ids, with their
+    /// properties, config, content, collection rows and the links between them, in one
+    /// transaction. The copy of `id` is titled `title` and shares its parent. Returns the new id
+    /// of every copied document keyed by the original; empty when `id` is not theirs or is gone.
+    async fn duplicate(&self, id: Uuid, owner_id: Uuid, title: &str) -> anyhow::Result<HashMap<Uuid, Uuid>>;
     async fn get_stats(&self, owner_id: Uuid) -> anyhow::Result<crate::kernel::types::SystemStats>;
+
+    // Trash
+    /// Moves a live document of `owner_id` and its live descendants to the trash. Returns the
+    /// ids of the documents trashed: none when it is missing, not theirs or already trashed.
+    async fn trash(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<Vec<Uuid>>;
+    /// Brings back the documents trashed along with `id`, moving `id` to the root when its
+    /// parent is no longer live. Returns how many documents were restored.
+    async fn restore(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<u64>;
+    /// Documents of `owner_id` deleted by the user, most recent first.
+    async fn find_trash(&self, owner_id: Uuid) -> anyhow::Result<Vec<TrashItem>>;
+    /// Deletes the documents trashed along with `id` for good, with their content, links,
+    /// collection rows and metadata. Returns the ids of the documents purged.
+    async fn purge(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<Vec<Uuid>>;
+    /// Purges every document of any owner trashed before `before`, returning their ids.
+    async fn purge_expired(&self, before: DateTime<Utc>) -> anyhow::Result<Vec<Uuid>>;
     
     // Properties & Content
     async fn update_property(&self, doc_id: Uuid, key: &str, value: serde_json::Value) -> anyhow::Result<()>;
@@ -19,6 +56,23 @@ pub trait DocumentRepository: Send + Sync {
     // Links & Graph
     async fn add_link(&self, from_id: Uuid, to_id: Uuid) -> anyhow::Result<()>;
     async fn find_links(&self, owner_id: Uuid) -> anyhow::Result<Vec<(Uuid, Uuid)>>;
+    /// Links `from_id` to `to_id` under `relation`, replacing the metadata of an existing link
+    /// of that relation.
+    async fn add_typed_link(&self, from_id: Uuid, to_id: Uuid, relation: &str, metadata: serde_json::Value) -> anyhow::Result<DocumentLink>;
+    /// Removes the link of `relation` from `from_id` to `to_id`, or all of them when `None`.
+    /// Returns how many links were removed.
+    async fn remove_link(&self, from_id: Uuid, to_id: Uuid, relation: Option<&str>) -> anyhow::Result<u64>;
+    /// Links pointing at `to_id` from documents of the same owner, oldest first.
+    async fn find_backlinks(&self, to_id: Uuid) -> anyhow::Result<Vec<DocumentLink>>;
+    /// Documents of `owner_id` within `depth` edges of `doc_id`, following edges in either
+    /// direction, and the edges between them.
+    async fn find_neighbourhood(&self, owner_id: Uuid, doc_id: Uuid, depth: u32, traversal: Traversal) -> anyhow::Result<Subgraph>;
+    /// Every edge between documents of `owner_id`.
+    async fn find_graph_edges(&self, owner_id: Uuid, traversal: Traversal) -> anyhow::Result<Vec<GraphEdge>>;
+    /// Up to `limit` directed cycles of at most `max_len` documents, each listed once, starting
+    /// from its smallest id. The shortest come first, then cycles in order of their ids, so the
+    /// limit keeps the same cycles on every backend.
+    async fn find_cycles(&self, owner_id: Uuid, traversal: Traversal, max_len: u32, limit: i64) -> anyhow::Result<Vec<Vec<Uuid>>>;
     async fn find_children_properties(&self, parent_id: Uuid, class_filter: Option<String>) -> anyhow::Result<Vec<serde_json::Value>>;
     async fn get_aggregate_sum(&self, parent_id: Uuid, property_key: &str) -> anyhow::Result<f64>;
     
@@ -38,6 +92,13 @@ pub trait ArchetypeRepository: Send + Sync {
     async fn find_by_id(&self, id: &str) -> anyhow::Result<Option<Archetype>>;
 }
 
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create(&self, id: Uuid, username: &str, password_hash: &str) -> anyhow::Result<()>;
+    async fn find_by_username(&self, username: &str) -> anyhow::Result<Option<Credentials>>;
+    async fn update_avatar(&self, user_id: Uuid, avatar_url: Option<String>) -> anyhow::Result<()>;
+}
+
 #[async_trait]
 pub trait AuditRepository: Send + Sync {
     async fn log(&self, user_id: Option<Uuid>, resource_id: Option<Uuid>, resource_type: &str, action: &str, details: Option<String>) -> anyhow::Result<()>;
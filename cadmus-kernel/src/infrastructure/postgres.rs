@@ -1,11 +1,129 @@
 use async_trait::async_trait;
-use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
 use uuid::Uuid;
-use crate::domain::repository::{DocumentRepository, ArchetypeRepository, AuditRepository};
+use crate::domain::repository::{DocumentRepository, ArchetypeRepository, AuditRepository, UserRepository};
+use crate::modules::security::domain::Credentials;
 use crate::domain::archetypes::Archetype;
 use crate::modules::content::workspace::WorkspaceNode;
+use crate::modules::graph::domain::{DocumentLink, EdgeKind, GraphEdge, GraphNode, Subgraph, Traversal};
+use crate::modules::query::domain::{Cursor, DocumentPage, DocumentQuery, FilterOp, PropertyFilter, SortField};
+use crate::modules::trash::domain::TrashItem;
 use serde_json::json;
 
+/// Pairs of original and copied ids, from a JSON object bound to `$1`, as a common table
+/// expression named `map`.
+const ID_MAP: &str = "map AS (SELECT key::uuid AS old_id, value::uuid AS new_id FROM jsonb_each_text($1))";
+
+/// Records a tombstone for each document deleted by a common table expression named `purged`,
+/// so replicas delete them too, and selects the ids of those documents.
+const RECORD_TOMBSTONES: &str = "tombstones AS (
+        INSERT INTO sync_tombstones (kind, key, owner_id)
+        SELECT 'document', id::text, owner_id FROM purged WHERE owner_id IS NOT NULL
+        ON CONFLICT (kind, key) DO UPDATE SET deleted_at = EXCLUDED.deleted_at
+    )
+    SELECT id FROM purged";
+
+/// Edges between documents of `$1`, as common table expressions: `typed_edges` holds links when
+/// `$2` and parent/child edges when `$3`; `edges` holds each directed pair once.
+const GRAPH_EDGES: &str = r#"
+    typed_edges AS (
+        SELECT l.from_id AS a, l.to_id AS b, 'link' AS kind, l.relation FROM document_links l
+        JOIN documents f ON f.id = l.from_id
+        JOIN documents t ON t.id = l.to_id
+        WHERE $2 AND f.owner_id = $1 AND t.owner_id = $1 AND f.deleted_at IS NULL AND t.deleted_at IS NULL
+        UNION ALL
+        SELECT p.id, d.id, 'child', NULL FROM documents d
+        JOIN documents p ON p.id = d.parent_id
+        WHERE $3 AND d.owner_id = $1 AND p.owner_id = $1 AND d.deleted_at IS NULL AND p.deleted_at IS NULL
+    ),
+    edges AS (SELECT DISTINCT a, b FROM typed_edges)
+"#;
+
+/// Breadth-first walk from `$4` over `edges` in both directions, `$5` levels deep. `reached`
+/// holds every document found, at its shortest distance.
+const GRAPH_WALK: &str = r#"
+    undirected AS (SELECT a, b FROM edges UNION SELECT b, a FROM edges),
+    walk(id, depth) AS (
+        SELECT $4::UUID, 0
+        UNION
+        SELECT u.b, w.depth + 1 FROM walk w JOIN undirected u ON u.a = w.id WHERE w.depth < $5
+    ),
+    reached AS (SELECT id, MIN(depth) AS depth FROM walk GROUP BY id)
+"#;
+
+fn graph_edge((from_id, to_id, kind, relation): (Uuid, Uuid, String, Option<String>)) -> GraphEdge {
+    let kind = if kind == "child" { EdgeKind::Child } else { EdgeKind::Link };
+    GraphEdge { from_id, to_id, kind, relation }
+}
+
+/// Arguments of `translate` that fold ASCII letters to lower case and leave other letters be,
+/// as SQLite's `lower` does.
+const ASCII_CASE: &str = "'ABCDEFGHIJKLMNOPQRSTUVWXYZ', 'abcdefghijklmnopqrstuvwxyz'";
+
+/// Position of `sort_key`'s JSON type in `SortField` order, as `Cursor::rank` gives it.
+const SORT_RANK: &str = "CASE jsonb_typeof(sort_key) WHEN 'string' THEN 1 WHEN 'number' THEN 2 \
+    WHEN 'boolean' THEN 3 WHEN 'array' THEN 4 WHEN 'object' THEN 4 ELSE 0 END";
+
+/// A string `sort_key` as text in byte order, and an empty string for other types.
+const SORT_TEXT: &str = "(CASE WHEN jsonb_typeof(sort_key) = 'string' THEN sort_key #>> '{}' ELSE '' END) COLLATE \"C\"";
+
+/// A number or boolean `sort_key`, and `null` for other types, which jsonb orders like SQLite.
+const SORT_SCALAR: &str = "CASE WHEN jsonb_typeof(sort_key) IN ('number', 'boolean') THEN sort_key ELSE 'null'::jsonb END";
+
+/// Pushes the JSONB condition of a filter. `properties` is never NULL where it is used.
+fn push_filter(qb: &mut QueryBuilder<'_, Postgres>, filter: &PropertyFilter) {
+    let key = filter.key.clone();
+    let (cmp, value) = match &filter.op {
+        FilterOp::Eq(v) => {
+            qb.push("properties -> ").push_bind(key).push(" = ").push_bind(v.clone());
+            return;
+        },
+        FilterOp::Gt(v) => (">", v),
+        FilterOp::Gte(v) => (">=", v),
+        FilterOp::Lt(v) => ("<", v),
+        FilterOp::Lte(v) => ("<=", v),
+        FilterOp::Contains(text) => {
+            qb.push("(jsonb_typeof(properties -> ").push_bind(key.clone())
+                .push(") = 'string' AND strpos(translate(properties ->> ").push_bind(key)
+                .push(format!(", {ASCII_CASE}), ")).push_bind(text.to_ascii_lowercase()).push(") > 0)");
+            return;
+        },
+        FilterOp::Exists(exists) => {
+            qb.push(if *exists { "properties ? " } else { "NOT properties ? " }).push_bind(key);
+            return;
+        },
+        FilterOp::HasTag(tag) => {
+            qb.push("(jsonb_typeof(properties -> ").push_bind(key.clone())
+                .push(") = 'array' AND properties -> ").push_bind(key)
+                .push(" ? ").push_bind(tag.clone()).push(")");
+            return;
+        },
+    };
+    // jsonb orders values of different types by type, so ranges are kept to one type. Strings
+    // compare by bytes, as in the vault, rather than by the database's collation.
+    qb.push("(jsonb_typeof(properties -> ").push_bind(key.clone())
+        .push(") = jsonb_typeof(").push_bind(value.clone()).push(") AND ");
+    match value.as_str() {
+        Some(text) => qb.push("(properties ->> ").push_bind(key)
+            .push(format!(") COLLATE \"C\" {cmp} ")).push_bind(text.to_string()),
+        None => qb.push("properties -> ").push_bind(key)
+            .push(format!(" {cmp} ")).push_bind(value.clone()),
+    };
+    qb.push(")");
+}
+
+/// Pushes the sort value of a document as JSONB, `null` when the property is missing, so that
+/// keyset comparisons never meet SQL NULLs.
+fn push_sort_key(qb: &mut QueryBuilder<'_, Postgres>, field: &SortField) {
+    match field {
+        SortField::Title => qb.push("to_jsonb(title)"),
+        SortField::CreatedAt => qb.push("to_jsonb(created_at)"),
+        SortField::UpdatedAt => qb.push("to_jsonb(updated_at)"),
+        SortField::Property(key) => qb.push("COALESCE(properties -> ").push_bind(key.clone()).push(", 'null'::jsonb)"),
+    };
+}
+
 /// Implementation of DocumentRepository for PostgreSQL.
 pub struct PostgresDocumentRepository {
     pool: PgPool,
@@ -63,7 +181,7 @@ impl DocumentRepository for PostgresDocumentRepository {
             r#"
             SELECT id, title, parent_id, class_id, COALESCE(properties, '{}'::jsonb) as properties
             FROM documents
-            WHERE owner_id = $1
+            WHERE owner_id = $1 AND deleted_at IS NULL
             ORDER BY updated_at DESC
             LIMIT $2
             "#
@@ -83,7 +201,7 @@ impl DocumentRepository for PostgresDocumentRepository {
             r#"
             SELECT id, title, parent_id, class_id, COALESCE(properties, '{}'::jsonb) as properties
             FROM documents
-            WHERE owner_id = $1
+            WHERE owner_id = $1 AND deleted_at IS NULL
             ORDER BY title ASC
             "#
         )
@@ -101,7 +219,7 @@ impl DocumentRepository for PostgresDocumentRepository {
             r#"
             SELECT id, title, parent_id, class_id, COALESCE(properties, '{}'::jsonb) as properties
             FROM documents
-            WHERE id = $1
+            WHERE id = $1 AND deleted_at IS NULL
             "#
         )
         .bind(id)
@@ -112,27 +230,151 @@ impl DocumentRepository for PostgresDocumentRepository {
         Ok(node)
     }
 
-    /// Deletes a document by its ID.
-    async fn delete(&self, id: Uuid, _owner_id: Uuid) -> anyhow::Result<()> {
-        sqlx::query("DELETE FROM documents WHERE id = $1")
+    /// Compiles the query to JSONB conditions and pages by keyset on the sort value and id.
+    async fn query(&self, owner_id: Uuid, query: &DocumentQuery) -> anyhow::Result<DocumentPage> {
+        let limit = query.page_size() as usize;
+        let mut qb = QueryBuilder::<Postgres>::new(
+            "SELECT id, title, parent_id, class_id, properties, sort_key FROM (
+                SELECT id, title, parent_id, class_id, COALESCE(properties, '{}'::jsonb) AS properties, "
+        );
+        push_sort_key(&mut qb, &query.sort.field);
+        qb.push(" AS sort_key FROM documents WHERE owner_id = ").push_bind(owner_id)
+            .push(" AND deleted_at IS NULL) q WHERE true");
+        if !query.classes.is_empty() {
+            qb.push(" AND class_id = ANY(").push_bind(query.classes.clone()).push(")");
+        }
+        for filter in &query.filters {
+            qb.push(" AND ");
+            push_filter(&mut qb, filter);
+        }
+        let (cmp, order) = if query.sort.descending { ("<", "DESC") } else { (">", "ASC") };
+        // Orders by type, then strings by bytes and numbers and booleans as jsonb does, like the vault.
+        if let Some(after) = query.after()? {
+            let text = after.value.as_str().unwrap_or_default().to_string();
+            let scalar = if matches!(after.rank(), 2 | 3) { after.value.clone() } else { serde_json::Value::Null };
+            qb.push(format!(" AND ({SORT_RANK}, {SORT_TEXT}, {SORT_SCALAR}, id) {cmp} ("))
+                .push_bind(after.rank()).push(", ").push_bind(text).push(", ").push_bind(scalar)
+                .push(", ").push_bind(after.id).push(")");
+        }
+        qb.push(format!(" ORDER BY {SORT_RANK} {order}, {SORT_TEXT} {order}, {SORT_SCALAR} {order}, id {order} LIMIT "))
+            .push_bind(limit as i64 + 1);
+
+        let mut rows = qb.build_query_as::<(Uuid, String, Option<Uuid>, Option<String>, serde_json::Value, serde_json::Value)>()
+            .fetch_all(&self.pool).await?;
+        let next_cursor = if rows.len() > limit {
+            rows.truncate(limit);
+            rows.last().map(|(id, .., sort_key)| Cursor { value: sort_key.clone(), id: *id }.encode())
+        } else {
+            None
+        };
+        let documents = rows.into_iter()
+            .map(|(id, title, parent_id, class_id, properties, _)| WorkspaceNode { id, title, parent_id, class_id, properties })
+            .collect();
+        Ok(DocumentPage { documents, next_cursor })
+    }
+
+    /// Returns the owner of a document, or `None` if it does not exist.
+    async fn find_owner(&self, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let owner = sqlx::query_scalar("SELECT owner_id FROM documents WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .fetch_optional(&self.pool)
             .await?;
-        Ok(())
+        Ok(owner)
+    }
+
+    /// Returns the owner of a document outside the trash, or `None`.
+    async fn find_live_owner(&self, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let owner = sqlx::query_scalar("SELECT owner_id FROM documents WHERE id = $1 AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(owner)
+    }
+
+    /// Reparents a document, unless the new parent's lineage already contains it.
+    async fn set_parent(&self, doc_id: Uuid, parent_id: Option<Uuid>) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            r#"
+            WITH RECURSIVE lineage AS (
+                SELECT id, parent_id FROM documents WHERE id = $2
+                UNION ALL
+                SELECT d.id, d.parent_id FROM documents d
+                INNER JOIN lineage l ON d.id = l.parent_id
+            )
+            UPDATE documents SET parent_id = $2, updated_at = CURRENT_TIMESTAMP
+            WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM lineage WHERE id = $1)
+            "#
+        ).bind(doc_id).bind(parent_id).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Copies a subtree inside one transaction, remapping parents, links and content to new ids.
+    async fn duplicate(&self, id: Uuid, owner_id: Uuid, title: &str) -> anyhow::Result<HashMap<Uuid, Uuid>> {
+        let mut tx = self.start_authenticated_tx(owner_id).await?;
+        let ids: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            WITH RECURSIVE subtree AS (
+                SELECT id FROM documents WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL
+                UNION
+                SELECT d.id FROM documents d
+                INNER JOIN subtree s ON d.parent_id = s.id
+                WHERE d.owner_id = $2 AND d.deleted_at IS NULL
+            )
+            SELECT id FROM subtree
+            "#
+        ).bind(id).bind(owner_id).fetch_all(&mut *tx).await?;
+        let id_map: HashMap<Uuid, Uuid> = ids.into_iter().map(|old| (old, Uuid::new_v4())).collect();
+        if id_map.is_empty() {
+            return Ok(id_map);
+        }
+        let map = serde_json::to_value(&id_map)?;
+
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO documents (id, owner_id, parent_id, title, class_id, properties, config, is_public)
+             SELECT m.new_id, d.owner_id, COALESCE(p.new_id, d.parent_id), CASE WHEN d.id = $2 THEN $3 ELSE d.title END,
+                    d.class_id, d.properties, d.config, d.is_public
+             FROM documents d JOIN map m ON m.old_id = d.id LEFT JOIN map p ON p.old_id = d.parent_id"
+        )).bind(&map).bind(id).bind(title).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO collection_rows (document_id, data, order_index)
+             SELECT m.new_id, r.data, r.order_index FROM collection_rows r JOIN map m ON m.old_id = r.document_id"
+        )).bind(&map).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO document_links (from_id, to_id, relation, metadata)
+             SELECT f.new_id, t.new_id, l.relation, l.metadata FROM document_links l
+             JOIN map f ON f.old_id = l.from_id JOIN map t ON t.old_id = l.to_id"
+        )).bind(&map).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO document_snapshots (doc_id, data)
+             SELECT m.new_id, s.data FROM document_snapshots s JOIN map m ON m.old_id = s.doc_id"
+        )).bind(&map).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO document_updates (doc_id, data, author_id, created_at)
+             SELECT m.new_id, u.data, u.author_id, u.created_at FROM document_updates u JOIN map m ON m.old_id = u.doc_id
+             ORDER BY u.created_at, u.id"
+        )).bind(&map).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(id_map)
     }
 
     /// Retrieves system statistics for a given owner.
     async fn get_stats(&self, owner_id: Uuid) -> anyhow::Result<crate::kernel::types::SystemStats> {
-        let nodes: i32 = sqlx::query_scalar("SELECT COUNT(*)::int4 FROM documents WHERE owner_id = $1")
+        let nodes: i32 = sqlx::query_scalar("SELECT COUNT(*)::int4 FROM documents WHERE owner_id = $1 AND deleted_at IS NULL")
             .bind(owner_id).fetch_one(&self.pool).await?;
 
-        let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM document_links l JOIN documents d ON l.from_id = d.id WHERE d.owner_id = $1")
+        let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM document_links l JOIN documents d ON l.from_id = d.id JOIN documents t ON l.to_id = t.id WHERE d.owner_id = $1 AND d.deleted_at IS NULL AND t.deleted_at IS NULL")
             .bind(owner_id).fetch_one(&self.pool).await?;
 
-        let recent: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE owner_id = $1 AND updated_at > NOW() - INTERVAL '24 hours'")
+        let recent: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE owner_id = $1 AND deleted_at IS NULL AND updated_at > NOW() - INTERVAL '24 hours'")
             .bind(owner_id).fetch_one(&self.pool).await?;
 
-        let rows = sqlx::query("SELECT class_id, COUNT(*)::int4 FROM documents WHERE owner_id = $1 GROUP BY class_id")
+        let rows = sqlx::query("SELECT class_id, COUNT(*)::int4 FROM documents WHERE owner_id = $1 AND deleted_at IS NULL GROUP BY class_id")
             .bind(owner_id).fetch_all(&self.pool).await?;
 
         let mut distribution = std::collections::HashMap::new();
@@ -146,7 +388,7 @@ impl DocumentRepository for PostgresDocumentRepository {
         let orphans: i64 = sqlx::query_scalar(
             r#"
             SELECT COUNT(*) FROM documents d
-            WHERE owner_id = $1
+            WHERE owner_id = $1 AND deleted_at IS NULL
               AND NOT EXISTS (SELECT 1 FROM document_links WHERE from_id = d.id OR to_id = d.id)
               AND parent_id IS NULL
             "#
@@ -155,7 +397,7 @@ impl DocumentRepository for PostgresDocumentRepository {
         let untagged: i64 = sqlx::query_scalar(
             r#"
             SELECT COUNT(*) FROM documents 
-            WHERE owner_id = $1 
+            WHERE owner_id = $1 AND deleted_at IS NULL
               AND (
                 properties->'tags' IS NULL 
                 OR properties->'tags' = '[]'::jsonb
@@ -174,6 +416,74 @@ impl DocumentRepository for PostgresDocumentRepository {
         })
     }
 
+    /// Marks a document and its live descendants as trashed, with the document as their root.
+    /// Trashing and restoring stamp `updated_at`, so replicas pick the change up.
+    async fn trash(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<Vec<Uuid>> {
+        let mut tx = self.start_authenticated_tx(owner_id).await?;
+        let trashed = sqlx::query_scalar(
+            r#"
+            WITH RECURSIVE subtree AS (
+                SELECT id FROM documents WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL
+                UNION
+                SELECT d.id FROM documents d
+                INNER JOIN subtree s ON d.parent_id = s.id
+                WHERE d.owner_id = $2 AND d.deleted_at IS NULL
+            )
+            UPDATE documents SET deleted_at = CURRENT_TIMESTAMP, trash_root_id = $1, updated_at = CURRENT_TIMESTAMP
+            WHERE id IN (SELECT id FROM subtree)
+            RETURNING id
+            "#
+        ).bind(id).bind(owner_id).fetch_all(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(trashed)
+    }
+
+    /// Clears the trash marks of a set, detaching its root from a parent that is still trashed.
+    async fn restore(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<u64> {
+        let mut tx = self.start_authenticated_tx(owner_id).await?;
+        let result = sqlx::query("UPDATE documents SET deleted_at = NULL, trash_root_id = NULL, updated_at = CURRENT_TIMESTAMP WHERE trash_root_id = $1 AND owner_id = $2")
+            .bind(id).bind(owner_id).execute(&mut *tx).await?;
+        if result.rows_affected() > 0 {
+            sqlx::query(
+                "UPDATE documents SET parent_id = NULL, updated_at = CURRENT_TIMESTAMP WHERE id = $1 AND parent_id IS NOT NULL
+                 AND NOT EXISTS (SELECT 1 FROM documents p WHERE p.id = documents.parent_id AND p.deleted_at IS NULL)"
+            ).bind(id).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Lists the roots of a user's trashed sets with their sizes.
+    async fn find_trash(&self, owner_id: Uuid) -> anyhow::Result<Vec<TrashItem>> {
+        let rows = sqlx::query_as::<_, (Uuid, String, Option<String>, chrono::NaiveDateTime, i64)>(
+            r#"
+            SELECT d.id, d.title, d.class_id, d.deleted_at, (SELECT COUNT(*) FROM documents s WHERE s.trash_root_id = d.id)
+            FROM documents d
+            WHERE d.owner_id = $1 AND d.trash_root_id = d.id
+            ORDER BY d.deleted_at DESC, d.id
+            "#
+        ).bind(owner_id).fetch_all(&self.pool).await?;
+        Ok(rows.into_iter()
+            .map(|(id, title, class_id, deleted_at, documents)| TrashItem { id, title, class_id, deleted_at: deleted_at.and_utc(), documents: documents as u64 })
+            .collect())
+    }
+
+    /// Deletes a trashed set; foreign keys cascade to everything that refers to its documents.
+    async fn purge(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<Vec<Uuid>> {
+        let mut tx = self.start_authenticated_tx(owner_id).await?;
+        let purged = sqlx::query_scalar(&format!("WITH purged AS (DELETE FROM documents WHERE trash_root_id = $1 AND owner_id = $2 RETURNING id, owner_id), {RECORD_TOMBSTONES}"))
+            .bind(id).bind(owner_id).fetch_all(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(purged)
+    }
+
+    /// Deletes every document trashed before the cutoff.
+    async fn purge_expired(&self, before: chrono::DateTime<chrono::Utc>) -> anyhow::Result<Vec<Uuid>> {
+        let purged = sqlx::query_scalar(&format!("WITH purged AS (DELETE FROM documents WHERE deleted_at < $1 RETURNING id, owner_id), {RECORD_TOMBSTONES}"))
+            .bind(before.naive_utc()).fetch_all(&self.pool).await?;
+        Ok(purged)
+    }
+
     /// Updates a specific property of a document.
     async fn update_property(&self, doc_id: Uuid, key: &str, value: serde_json::Value) -> anyhow::Result<()> {
         tracing::info!("[SQL_EXEC] Attempting property update: doc={}, key={}", doc_id, key); // Debug log
@@ -183,7 +493,7 @@ impl DocumentRepository for PostgresDocumentRepository {
             let result = sqlx::query("UPDATE documents SET title = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2")
                 .bind(title)
                 .bind(doc_id)
-                .execute(&mut self.pool.begin().await?) // Use a transaction for the update
+                .execute(&self.pool)
                 .await?;
             tracing::debug!("[SQL_SUCCESS] Title updated. Rows affected: {}", result.rows_affected()); // Debug log
         } else {
@@ -191,7 +501,7 @@ impl DocumentRepository for PostgresDocumentRepository {
             let result = sqlx::query("UPDATE documents SET properties = COALESCE(properties, '{}'::jsonb) || $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2")
                 .bind(&json_patch)
                 .bind(doc_id)
-                .execute(&mut self.pool.begin().await?) // Use a transaction for the update
+                .execute(&self.pool)
                 .await?;
             tracing::debug!("[SQL_SUCCESS] Property '{}' merged into JSONB. Rows affected: {}", key, result.rows_affected()); // Debug log
         }
@@ -215,7 +525,8 @@ impl DocumentRepository for PostgresDocumentRepository {
     /// Searches for documents similar to a given embedding vector.
     async fn search_similar(&self, embedding: Vec<f32>, limit: i64) -> anyhow::Result<Vec<Uuid>> {
         let rows = sqlx::query_scalar(
-            "SELECT document_id FROM neural_metadata ORDER BY embedding <-> $1 LIMIT $2"
+            "SELECT m.document_id FROM neural_metadata m JOIN documents d ON d.id = m.document_id
+             WHERE d.deleted_at IS NULL ORDER BY m.embedding <-> $1 LIMIT $2"
         )
         .bind(pgvector::Vector::from(embedding))
         .bind(limit)
@@ -224,6 +535,23 @@ impl DocumentRepository for PostgresDocumentRepository {
         Ok(rows)
     }
 
+    /// Sums a numeric property over the live children of a document and the documents it links to.
+    async fn get_aggregate_sum(&self, parent_id: Uuid, property_key: &str) -> anyhow::Result<f64> {
+        let sum: f64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(CASE WHEN jsonb_typeof(properties -> $1) = 'number' THEN (properties ->> $1)::float8 END), 0)
+            FROM documents
+            WHERE (parent_id = $2 OR id IN (SELECT to_id FROM document_links WHERE from_id = $2))
+              AND deleted_at IS NULL
+            "#
+        )
+        .bind(property_key)
+        .bind(parent_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(sum)
+    }
+
     /// Adds a new link between two documents.
     async fn add_link(&self, from_id: Uuid, to_id: Uuid) -> anyhow::Result<()> {
         sqlx::query("INSERT INTO document_links (from_id, to_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
@@ -234,18 +562,115 @@ impl DocumentRepository for PostgresDocumentRepository {
     /// Finds all links for documents owned by a specific user.
     async fn find_links(&self, owner_id: Uuid) -> anyhow::Result<Vec<(Uuid, Uuid)>> {
         let rows = sqlx::query_as::<_, (Uuid, Uuid)>(
-            "SELECT l.from_id, l.to_id FROM document_links l JOIN documents d ON l.from_id = d.id WHERE d.owner_id = $1"
+            "SELECT DISTINCT l.from_id, l.to_id FROM document_links l JOIN documents d ON l.from_id = d.id JOIN documents t ON l.to_id = t.id
+             WHERE d.owner_id = $1 AND d.deleted_at IS NULL AND t.deleted_at IS NULL"
         ).bind(owner_id).fetch_all(&self.pool).await?;
         Ok(rows)
     }
 
+    /// Adds or updates a typed link between two documents.
+    async fn add_typed_link(&self, from_id: Uuid, to_id: Uuid, relation: &str, metadata: serde_json::Value) -> anyhow::Result<DocumentLink> {
+        let created_at: Option<chrono::NaiveDateTime> = sqlx::query_scalar(
+            "INSERT INTO document_links (from_id, to_id, relation, metadata) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (from_id, to_id, relation) DO UPDATE SET metadata = EXCLUDED.metadata, updated_at = CURRENT_TIMESTAMP
+             RETURNING created_at"
+        )
+        .bind(from_id).bind(to_id).bind(relation).bind(&metadata)
+        .fetch_one(&self.pool).await?;
+        Ok(DocumentLink { from_id, to_id, relation: relation.to_string(), metadata, created_at: created_at.map(|t| t.and_utc()) })
+    }
+
+    /// Removes one relation between two documents, or all of them, leaving a tombstone for each
+    /// so replicas remove them too.
+    async fn remove_link(&self, from_id: Uuid, to_id: Uuid, relation: Option<&str>) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO sync_tombstones (kind, key, owner_id)
+             SELECT 'link', l.from_id::text || ':' || l.to_id::text || ':' || l.relation, d.owner_id FROM document_links l
+             JOIN documents d ON d.id = l.from_id
+             WHERE l.from_id = $1 AND l.to_id = $2 AND ($3::TEXT IS NULL OR l.relation = $3) AND d.owner_id IS NOT NULL
+             ON CONFLICT (kind, key) DO UPDATE SET deleted_at = EXCLUDED.deleted_at"
+        )
+        .bind(from_id).bind(to_id).bind(relation)
+        .execute(&mut *tx).await?;
+        let result = sqlx::query("DELETE FROM document_links WHERE from_id = $1 AND to_id = $2 AND ($3::TEXT IS NULL OR relation = $3)")
+            .bind(from_id).bind(to_id).bind(relation)
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Finds the links pointing at a document from documents of the same owner.
+    async fn find_backlinks(&self, to_id: Uuid) -> anyhow::Result<Vec<DocumentLink>> {
+        let rows = sqlx::query_as::<_, (Uuid, Uuid, String, serde_json::Value, Option<chrono::NaiveDateTime>)>(
+            "SELECT l.from_id, l.to_id, l.relation, l.metadata, l.created_at FROM document_links l
+             JOIN documents s ON s.id = l.from_id
+             JOIN documents t ON t.id = l.to_id
+             WHERE l.to_id = $1 AND s.owner_id = t.owner_id AND s.deleted_at IS NULL AND t.deleted_at IS NULL
+             ORDER BY l.created_at, l.from_id, l.relation"
+        ).bind(to_id).fetch_all(&self.pool).await?;
+        Ok(rows.into_iter()
+            .map(|(from_id, to_id, relation, metadata, created_at)| DocumentLink { from_id, to_id, relation, metadata, created_at: created_at.map(|t| t.and_utc()) })
+            .collect())
+    }
+
+    /// Walks the graph around a document with a recursive query.
+    async fn find_neighbourhood(&self, owner_id: Uuid, doc_id: Uuid, depth: u32, traversal: Traversal) -> anyhow::Result<Subgraph> {
+        let nodes: Vec<(Uuid, i32)> = sqlx::query_as(&format!(
+            "WITH RECURSIVE {GRAPH_EDGES}, {GRAPH_WALK} SELECT id, depth FROM reached ORDER BY depth, id"
+        ))
+        .bind(owner_id).bind(traversal.follows_links()).bind(traversal.follows_hierarchy()).bind(doc_id).bind(depth as i32)
+        .fetch_all(&self.pool).await?;
+        let edges: Vec<(Uuid, Uuid, String, Option<String>)> = sqlx::query_as(&format!(
+            "WITH RECURSIVE {GRAPH_EDGES}, {GRAPH_WALK}
+             SELECT a, b, kind, relation FROM typed_edges
+             WHERE a IN (SELECT id FROM reached) AND b IN (SELECT id FROM reached)
+             ORDER BY a, b, kind, relation"
+        ))
+        .bind(owner_id).bind(traversal.follows_links()).bind(traversal.follows_hierarchy()).bind(doc_id).bind(depth as i32)
+        .fetch_all(&self.pool).await?;
+        Ok(Subgraph {
+            nodes: nodes.into_iter().map(|(id, depth)| GraphNode { id, depth: depth as u32 }).collect(),
+            edges: edges.into_iter().map(graph_edge).collect(),
+        })
+    }
+
+    /// Lists every edge of a user's graph.
+    async fn find_graph_edges(&self, owner_id: Uuid, traversal: Traversal) -> anyhow::Result<Vec<GraphEdge>> {
+        let edges: Vec<(Uuid, Uuid, String, Option<String>)> = sqlx::query_as(&format!(
+            "WITH {GRAPH_EDGES} SELECT a, b, kind, relation FROM typed_edges ORDER BY a, b, kind, relation"
+        ))
+        .bind(owner_id).bind(traversal.follows_links()).bind(traversal.follows_hierarchy())
+        .fetch_all(&self.pool).await?;
+        Ok(edges.into_iter().map(graph_edge).collect())
+    }
+
+    /// Finds directed cycles with a recursive query. A path only grows through ids greater than
+    /// its start, so each cycle is found once, from its smallest id.
+    async fn find_cycles(&self, owner_id: Uuid, traversal: Traversal, max_len: u32, limit: i64) -> anyhow::Result<Vec<Vec<Uuid>>> {
+        let cycles: Vec<Vec<Uuid>> = sqlx::query_scalar(&format!(
+            "WITH RECURSIVE {GRAPH_EDGES},
+             walk(start, id, path) AS (
+                 SELECT DISTINCT a, a, ARRAY[a] FROM edges
+                 UNION ALL
+                 SELECT w.start, e.b, w.path || e.b FROM walk w JOIN edges e ON e.a = w.id
+                 WHERE e.b > w.start AND NOT e.b = ANY(w.path) AND cardinality(w.path) < $4
+             )
+             SELECT w.path FROM walk w JOIN edges e ON e.a = w.id AND e.b = w.start
+             ORDER BY cardinality(w.path), w.path LIMIT $5"
+        ))
+        .bind(owner_id).bind(traversal.follows_links()).bind(traversal.follows_hierarchy()).bind(max_len as i32).bind(limit)
+        .fetch_all(&self.pool).await?;
+        Ok(cycles)
+    }
+
     /// Finds properties of children documents for a given parent, optionally filtered by class.
     async fn find_children_properties(&self, parent_id: Uuid, class_filter: Option<String>) -> anyhow::Result<Vec<serde_json::Value>> {
         let rows = if let Some(cf) = class_filter {
-            sqlx::query_scalar::<_, serde_json::Value>("SELECT properties FROM documents WHERE parent_id = $1 AND class_id = $2")
+            sqlx::query_scalar::<_, serde_json::Value>("SELECT properties FROM documents WHERE parent_id = $1 AND class_id = $2 AND deleted_at IS NULL")
                 .bind(parent_id).bind(cf).fetch_all(&self.pool).await?
         } else {
-            sqlx::query_scalar::<_, serde_json::Value>("SELECT properties FROM documents WHERE parent_id = $1")
+            sqlx::query_scalar::<_, serde_json::Value>("SELECT properties FROM documents WHERE parent_id = $1 AND deleted_at IS NULL")
                 .bind(parent_id).fetch_all(&self.pool).await?
         };
         Ok(rows)
@@ -355,4 +780,25 @@ impl AuditRepository for PostgresAuditRepository {
             .bind(user_id).bind(resource_id).bind(resource_type).bind(action).bind(details_json).bind(last_hash).bind(new_hash).execute(&self.pool).await?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Implementation of UserRepository for PostgreSQL.
+pub struct PostgresUserRepository { pool: PgPool }
+impl PostgresUserRepository { pub fn new(pool: PgPool) -> Self { Self { pool } } }
+#[async_trait]
+impl UserRepository for PostgresUserRepository {
+    async fn create(&self, id: Uuid, username: &str, password_hash: &str) -> anyhow::Result<()> {
+        sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)")
+            .bind(id).bind(username).bind(password_hash).execute(&self.pool).await?;
+        Ok(())
+    }
+    async fn find_by_username(&self, username: &str) -> anyhow::Result<Option<Credentials>> {
+        let row: Option<(Uuid, String, String, Option<String>)> = sqlx::query_as("SELECT id, username, password_hash, avatar_url FROM users WHERE username = $1")
+            .bind(username).fetch_optional(&self.pool).await?;
+        Ok(row.map(|(id, username, password_hash, avatar_url)| Credentials { id, username, password_hash, avatar_url }))
+    }
+    async fn update_avatar(&self, user_id: Uuid, avatar_url: Option<String>) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET avatar_url = $1 WHERE id = $2").bind(avatar_url).bind(user_id).execute(&self.pool).await?;
+        Ok(())
+    }
+}
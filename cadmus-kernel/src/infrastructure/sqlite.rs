@@ -1,11 +1,165 @@
 use async_trait::async_trait;
-use sqlx::{SqlitePool, Row};
+use std::collections::HashMap;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::TryStreamExt;
+use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool, Transaction};
+use sqlx::sqlite::SqliteRow;
+use tokio::sync::Mutex;
 use uuid::Uuid;
-use crate::domain::repository::{DocumentRepository, ArchetypeRepository};
+use crate::domain::repository::{DocumentRepository, ArchetypeRepository, AuditRepository, UserRepository};
+use crate::modules::security::domain::Credentials;
 use crate::domain::archetypes::Archetype;
+use crate::domain::logic::integrity::{AuditEntry, ChainVerification, IntegrityEngine};
+use crate::modules::intelligence::vector_store::{self, NearestNeighbours};
+use crate::shared::migrations::run_sqlite_migrations;
 use crate::modules::content::workspace::WorkspaceNode;
+use crate::modules::graph::domain::{DocumentLink, EdgeKind, GraphEdge, GraphNode, Subgraph, Traversal};
+use crate::modules::query::domain::{Cursor, DocumentPage, DocumentQuery, FilterOp, PropertyFilter, SortField};
+use crate::modules::trash::domain::TrashItem;
 use serde_json::json;
 
+/// Pairs of original and copied ids, from a JSON object bound to `?1`, as a common table
+/// expression named `map`.
+const ID_MAP: &str = "map AS (SELECT key AS old_id, value AS new_id FROM json_each(?1))";
+
+/// JSON path of a validated property key.
+fn json_path(key: &str) -> String {
+    format!("$.\"{}\"", key)
+}
+
+/// Binds a JSON scalar as the SQL value `json_extract` yields for it.
+fn push_json_value(qb: &mut QueryBuilder<'_, Sqlite>, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Bool(b) => qb.push_bind(*b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => qb.push_bind(i),
+            None => qb.push_bind(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => qb.push_bind(s.clone()),
+        other => qb.push_bind(other.to_string()),
+    };
+}
+
+/// `json_type` names of the values comparable with `value`.
+fn json_types(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Number(_) => "('integer', 'real')",
+        _ => "('text')",
+    }
+}
+
+/// Pushes the `json_extract` condition of a filter.
+fn push_filter(qb: &mut QueryBuilder<'_, Sqlite>, filter: &PropertyFilter) {
+    let path = json_path(&filter.key);
+    let (cmp, value) = match &filter.op {
+        FilterOp::Eq(serde_json::Value::Bool(b)) => {
+            qb.push("json_type(properties, ").push_bind(path).push(if *b { ") = 'true'" } else { ") = 'false'" });
+            return;
+        },
+        FilterOp::Eq(v) => ("=", v),
+        FilterOp::Gt(v) => (">", v),
+        FilterOp::Gte(v) => (">=", v),
+        FilterOp::Lt(v) => ("<", v),
+        FilterOp::Lte(v) => ("<=", v),
+        FilterOp::Contains(text) => {
+            qb.push("(json_type(properties, ").push_bind(path.clone())
+                .push(") = 'text' AND instr(lower(json_extract(properties, ").push_bind(path)
+                .push(")), ").push_bind(text.to_ascii_lowercase()).push(") > 0)");
+            return;
+        },
+        FilterOp::Exists(exists) => {
+            qb.push("json_type(properties, ").push_bind(path).push(if *exists { ") IS NOT NULL" } else { ") IS NULL" });
+            return;
+        },
+        FilterOp::HasTag(tag) => {
+            qb.push("(json_type(properties, ").push_bind(path.clone())
+                .push(") = 'array' AND EXISTS (SELECT 1 FROM json_each(properties, ").push_bind(path)
+                .push(") WHERE value = ").push_bind(tag.clone()).push("))");
+            return;
+        },
+    };
+    // SQLite orders values of different types by type, so comparisons are kept to one type.
+    qb.push("(json_type(properties, ").push_bind(path.clone())
+        .push(format!(") IN {} AND json_extract(properties, ", json_types(value))).push_bind(path)
+        .push(format!(") {cmp} "));
+    push_json_value(qb, value);
+    qb.push(")");
+}
+
+/// Pushes the JSON type of a document's sort value as `sort_type`, NULL when the property is
+/// missing, and the value as `json_extract` yields it as `sort_raw`.
+fn push_sort_key(qb: &mut QueryBuilder<'_, Sqlite>, field: &SortField) {
+    let column = match field {
+        SortField::Title => "title",
+        SortField::CreatedAt => "created_at",
+        SortField::UpdatedAt => "updated_at",
+        SortField::Property(key) => {
+            let path = json_path(key);
+            qb.push("json_type(properties, ").push_bind(path.clone())
+                .push(") AS sort_type, json_extract(properties, ").push_bind(path).push(") AS sort_raw");
+            return;
+        },
+    };
+    qb.push(format!("CASE WHEN {column} IS NULL THEN NULL ELSE 'text' END AS sort_type, {column} AS sort_raw"));
+}
+
+/// Position of `sort_type` in `SortField` order, as `Cursor::rank` gives it.
+const SORT_RANK: &str = "CASE sort_type WHEN 'text' THEN 1 WHEN 'integer' THEN 2 WHEN 'real' THEN 2 \
+    WHEN 'true' THEN 3 WHEN 'false' THEN 3 WHEN 'array' THEN 4 WHEN 'object' THEN 4 ELSE 0 END";
+
+/// The sort value within its rank: text compares by bytes, numbers and booleans (as 0 and 1)
+/// numerically, and the other types are all equal.
+const SORT_KEY: &str = "CASE WHEN sort_type IN ('text', 'integer', 'real', 'true', 'false') THEN sort_raw ELSE '' END";
+
+/// The sort value as JSON text, for cursors.
+const SORT_VALUE: &str = "CASE WHEN sort_type IS NULL OR sort_type = 'null' THEN 'null' \
+    WHEN sort_type IN ('true', 'false') THEN sort_type WHEN sort_type IN ('array', 'object') THEN sort_raw \
+    ELSE json_quote(sort_raw) END";
+
+/// Pushes the keyset condition for the documents after `after`.
+fn push_after(qb: &mut QueryBuilder<'_, Sqlite>, after: &Cursor, descending: bool) {
+    let cmp = if descending { "<" } else { ">" };
+    qb.push(format!(" AND ({SORT_RANK}, {SORT_KEY}, id) {cmp} (")).push_bind(after.rank() as i64).push(", ");
+    match after.rank() {
+        1..=3 => push_json_value(qb, &after.value),
+        _ => { qb.push_bind(""); },
+    }
+    qb.push(", ").push_bind(after.id.to_string()).push(")");
+}
+
+/// Edges between documents of `?1`, as common table expressions: `typed_edges` holds links when
+/// `?2` and parent/child edges when `?3`; `edges` holds each directed pair once.
+const GRAPH_EDGES: &str = r#"
+    typed_edges AS (
+        SELECT l.from_id AS a, l.to_id AS b, 'link' AS kind, l.relation AS relation FROM document_links l
+        JOIN documents f ON f.id = l.from_id
+        JOIN documents t ON t.id = l.to_id
+        WHERE ?2 AND f.owner_id = ?1 AND t.owner_id = ?1 AND f.deleted_at IS NULL AND t.deleted_at IS NULL
+        UNION ALL
+        SELECT p.id, d.id, 'child', NULL FROM documents d
+        JOIN documents p ON p.id = d.parent_id
+        WHERE ?3 AND d.owner_id = ?1 AND p.owner_id = ?1 AND d.deleted_at IS NULL AND p.deleted_at IS NULL
+    ),
+    edges AS (SELECT DISTINCT a, b FROM typed_edges)
+"#;
+
+/// Breadth-first walk from `?4` over `edges` in both directions, `?5` levels deep. `reached`
+/// holds every document found, at its shortest distance.
+const GRAPH_WALK: &str = r#"
+    undirected AS (SELECT a, b FROM edges UNION SELECT b, a FROM edges),
+    walk(id, depth) AS (
+        SELECT ?4, 0
+        UNION
+        SELECT u.b, w.depth + 1 FROM walk w JOIN undirected u ON u.a = w.id WHERE w.depth < ?5
+    ),
+    reached AS (SELECT id, MIN(depth) AS depth FROM walk GROUP BY id)
+"#;
+
+fn graph_edge((from_id, to_id, kind, relation): (String, String, String, Option<String>)) -> Option<GraphEdge> {
+    let kind = if kind == "child" { EdgeKind::Child } else { EdgeKind::Link };
+    Some(GraphEdge { from_id: Uuid::parse_str(&from_id).ok()?, to_id: Uuid::parse_str(&to_id).ok()?, kind, relation })
+}
+
 pub struct SqliteDocumentRepository {
     pool: SqlitePool,
 }
@@ -15,81 +169,39 @@ impl SqliteDocumentRepository {
         Self { pool }
     }
 
+    /// Creates or upgrades the vault schema through the versioned SQLite migrations.
     pub async fn initialize(&self) -> anyhow::Result<()> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS documents (
-                id TEXT PRIMARY KEY,
-                owner_id TEXT NOT NULL,
-                title TEXT NOT NULL,
-                class_id TEXT,
-                parent_id TEXT,
-                properties TEXT DEFAULT '{}' NOT NULL,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            );
-            CREATE TABLE IF NOT EXISTS document_links (
-                from_id TEXT NOT NULL,
-                to_id TEXT NOT NULL,
-                PRIMARY KEY (from_id, to_id)
-            );
-            CREATE TABLE IF NOT EXISTS document_updates (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                doc_id TEXT NOT NULL,
-                data BLOB NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            );
-            CREATE TABLE IF NOT EXISTS collection_rows (
-                id TEXT PRIMARY KEY,
-                document_id TEXT NOT NULL,
-                data TEXT DEFAULT '{}' NOT NULL,
-                order_index INTEGER,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            );
-            CREATE TABLE IF NOT EXISTS classes (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                json_schema TEXT DEFAULT '{}',
-                ui_schema TEXT DEFAULT '[]',
-                behavior_rules TEXT DEFAULT '{}',
-                icon TEXT,
-                has_collection BOOLEAN DEFAULT 0,
-                group_id TEXT DEFAULT 'primitiva',
-                required_tier TEXT DEFAULT 'Community'
-            );
-            "#
-        )
-        .execute(&self.pool)
-        .await?;
-
-        let count: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM classes").fetch_one(&self.pool).await?;
-        if count == 0 {
-            self.seed_archetypes().await?;
-        }
-
-        Ok(())
+        run_sqlite_migrations(&self.pool).await
     }
 
-    async fn seed_archetypes(&self) -> anyhow::Result<()> {
-        let archetypes = vec![
-            ("note", "Note", "{}", r#"[{"key":"sealed_at","type":"text","label":"INTEGRITY_SEAL","read_only":true}]"#, "{}", "FileText", false, "primitiva", "Community"),
-            ("project", "Project", "{}", r#"[{"key":"status","type":"badge","label":"STATE"},{"key":"progress","type":"progress","label":"COMPLETION"}]"#, "{}", "Briefcase", true, "primitiva", "Community"),
-            ("task", "Task", "{}", r#"[{"key":"status","type":"select","label":"STATUS","options":["todo","doing","done"]}]"#, "{}", "CheckSquare", false, "primitiva", "Community"),
-            ("container", "Container", "{}", "[]", "{}", "Box", false, "primitiva", "Community"),
-            ("folha", "Planilha", "{}", "[]", "{}", "Table", true, "dados", "Community"),
-            ("meeting", "Reunião", "{}", r#"[{"key":"attendees","type":"text","label":"Participantes"}]"#, "{}", "Users", false, "operacional", "PRO"),
-            ("blueprint", "Blueprint", "{}", "[]", "{}", "Zap", false, "operacional", "PRO"),
-            ("inventory", "Estoque", "{}", "[]", "{}", "Box", true, "operacional", "PRO"),
-            ("contract", "Contrato", "{}", "[]", "{}", "Shield", false, "recursos", "PRO"),
-            ("finance", "Financeiro", "{}", "[]", "{}", "Gem", true, "recursos", "PRO"),
-            ("ledger", "Ledger", "{}", r#"[{"key":"total_balance","type":"money","label":"AGGREGATE_BALANCE","read_only":true}]"#, "{}", "Table", true, "dados", "Community"),
+    /// Deletes documents and every row that refers to them. Postgres cascades these through
+    /// foreign keys; the vault has none.
+    pub(crate) async fn delete_documents(tx: &mut Transaction<'_, Sqlite>, ids: &[String]) -> anyhow::Result<u64> {
+        let ids = serde_json::to_string(ids)?;
+        let referencing = [
+            ("document_links", "from_id"),
+            ("document_links", "to_id"),
+            ("collection_rows", "document_id"),
+            ("document_updates", "doc_id"),
+            ("document_snapshots", "doc_id"),
+            ("neural_metadata", "document_id"),
         ];
-
-        for (id, name, js, ui, rules, icon, has_coll, group, tier) in archetypes {
-            sqlx::query("INSERT INTO classes (id, name, json_schema, ui_schema, behavior_rules, icon, has_collection, group_id, required_tier) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
-                .bind(id).bind(name).bind(js).bind(ui).bind(rules).bind(icon).bind(has_coll).bind(group).bind(tier)
-                .execute(&self.pool).await?;
+        for (table, column) in referencing {
+            sqlx::query(&format!("DELETE FROM {table} WHERE {column} IN (SELECT value FROM json_each(?))"))
+                .bind(&ids).execute(&mut **tx).await?;
         }
+        let result = sqlx::query("DELETE FROM documents WHERE id IN (SELECT value FROM json_each(?))")
+            .bind(&ids).execute(&mut **tx).await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Records the deletion of documents about to be purged, so replicas delete them too.
+    async fn record_tombstones(tx: &mut Transaction<'_, Sqlite>, ids: &[String]) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO sync_tombstones (kind, key, owner_id, deleted_at)
+             SELECT 'document', id, owner_id, CURRENT_TIMESTAMP FROM documents WHERE id IN (SELECT value FROM json_each(?))
+             ON CONFLICT (kind, key) DO UPDATE SET deleted_at = excluded.deleted_at"
+        ).bind(serde_json::to_string(ids)?).execute(&mut **tx).await?;
         Ok(())
     }
 }
@@ -129,7 +241,7 @@ impl DocumentRepository for SqliteDocumentRepository {
     }
 
     async fn find_recent(&self, owner_id: Uuid, limit: i64) -> anyhow::Result<Vec<WorkspaceNode>> {
-        let rows = sqlx::query("SELECT id, title, parent_id, class_id, properties FROM documents WHERE owner_id = ? ORDER BY updated_at DESC LIMIT ?")
+        let rows = sqlx::query("SELECT id, title, parent_id, class_id, properties FROM documents WHERE owner_id = ? AND deleted_at IS NULL ORDER BY updated_at DESC LIMIT ?")
             .bind(owner_id.to_string()).bind(limit).fetch_all(&self.pool).await?;
         Ok(rows.into_iter().map(|r| {
             WorkspaceNode {
@@ -143,7 +255,7 @@ impl DocumentRepository for SqliteDocumentRepository {
     }
 
     async fn find_all(&self, owner_id: Uuid) -> anyhow::Result<Vec<WorkspaceNode>> {
-        let rows = sqlx::query("SELECT id, title, parent_id, class_id, properties FROM documents WHERE owner_id = ? ORDER BY title ASC")
+        let rows = sqlx::query("SELECT id, title, parent_id, class_id, properties FROM documents WHERE owner_id = ? AND deleted_at IS NULL ORDER BY title ASC")
             .bind(owner_id.to_string()).fetch_all(&self.pool).await?;
         Ok(rows.into_iter().map(|r| {
             WorkspaceNode {
@@ -157,7 +269,7 @@ impl DocumentRepository for SqliteDocumentRepository {
     }
 
     async fn find_by_id(&self, id: Uuid) -> anyhow::Result<Option<WorkspaceNode>> {
-        let row = sqlx::query("SELECT id, title, parent_id, class_id, properties FROM documents WHERE id = ?").bind(id.to_string()).fetch_optional(&self.pool).await?;
+        let row = sqlx::query("SELECT id, title, parent_id, class_id, properties FROM documents WHERE id = ? AND deleted_at IS NULL").bind(id.to_string()).fetch_optional(&self.pool).await?;
         Ok(row.map(|r| WorkspaceNode {
             id: Uuid::parse_str(r.get(0)).unwrap_or_default(),
             title: r.get(1),
@@ -167,22 +279,143 @@ impl DocumentRepository for SqliteDocumentRepository {
         }))
     }
 
-    async fn delete(&self, id: Uuid, _owner_id: Uuid) -> anyhow::Result<()> {
-        sqlx::query("DELETE FROM documents WHERE id = ?").bind(id.to_string()).execute(&self.pool).await?;
-        Ok(())
+    async fn query(&self, owner_id: Uuid, query: &DocumentQuery) -> anyhow::Result<DocumentPage> {
+        let limit = query.page_size() as usize;
+        let mut qb = QueryBuilder::<Sqlite>::new(
+            format!("SELECT id, title, parent_id, class_id, properties, {SORT_VALUE} FROM (
+                SELECT id, title, parent_id, class_id, properties, ")
+        );
+        push_sort_key(&mut qb, &query.sort.field);
+        qb.push(" FROM documents WHERE owner_id = ").push_bind(owner_id.to_string())
+            .push(" AND deleted_at IS NULL) WHERE 1");
+        if !query.classes.is_empty() {
+            qb.push(" AND class_id IN (");
+            let mut classes = qb.separated(", ");
+            for class in &query.classes {
+                classes.push_bind(class.clone());
+            }
+            qb.push(")");
+        }
+        for filter in &query.filters {
+            qb.push(" AND ");
+            push_filter(&mut qb, filter);
+        }
+        if let Some(after) = query.after()? {
+            push_after(&mut qb, &after, query.sort.descending);
+        }
+        let order = if query.sort.descending { "DESC" } else { "ASC" };
+        qb.push(format!(" ORDER BY {SORT_RANK} {order}, {SORT_KEY} {order}, id {order} LIMIT ")).push_bind(limit as i64 + 1);
+
+        let mut rows = qb.build().fetch_all(&self.pool).await?;
+        let next_cursor = if rows.len() > limit {
+            rows.truncate(limit);
+            match rows.last() {
+                Some(r) => Some(Cursor { value: serde_json::from_str(r.get(5))?, id: Uuid::parse_str(r.get(0))? }.encode()),
+                None => None,
+            }
+        } else {
+            None
+        };
+        let documents = rows.into_iter().map(|r| WorkspaceNode {
+            id: Uuid::parse_str(r.get(0)).unwrap_or_default(),
+            title: r.get(1),
+            parent_id: r.get::<Option<String>, _>(2).and_then(|s| Uuid::parse_str(&s).ok()),
+            class_id: r.get(3),
+            properties: serde_json::from_str(&r.get::<String, _>(4)).unwrap_or(json!({})),
+        }).collect();
+        Ok(DocumentPage { documents, next_cursor })
+    }
+
+    async fn find_owner(&self, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let owner: Option<String> = sqlx::query_scalar("SELECT owner_id FROM documents WHERE id = ?")
+            .bind(id.to_string()).fetch_optional(&self.pool).await?;
+        Ok(owner.and_then(|s| Uuid::parse_str(&s).ok()))
+    }
+
+    async fn find_live_owner(&self, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let owner: Option<String> = sqlx::query_scalar("SELECT owner_id FROM documents WHERE id = ? AND deleted_at IS NULL")
+            .bind(id.to_string()).fetch_optional(&self.pool).await?;
+        Ok(owner.and_then(|s| Uuid::parse_str(&s).ok()))
+    }
+
+    async fn set_parent(&self, doc_id: Uuid, parent_id: Option<Uuid>) -> anyhow::Result<bool> {
+        let result = sqlx::query(r#"WITH RECURSIVE lineage AS (SELECT id, parent_id FROM documents WHERE id = ?1 UNION ALL SELECT d.id, d.parent_id FROM documents d JOIN lineage l ON d.id = l.parent_id) UPDATE documents SET parent_id = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2 AND NOT EXISTS (SELECT 1 FROM lineage WHERE id = ?2)"#)
+            .bind(parent_id.map(|u| u.to_string())).bind(doc_id.to_string()).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn duplicate(&self, id: Uuid, owner_id: Uuid, title: &str) -> anyhow::Result<HashMap<Uuid, Uuid>> {
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<String> = sqlx::query_scalar(
+            r#"
+            WITH RECURSIVE subtree AS (
+                SELECT id FROM documents WHERE id = ?1 AND owner_id = ?2 AND deleted_at IS NULL
+                UNION
+                SELECT d.id FROM documents d JOIN subtree s ON d.parent_id = s.id
+                WHERE d.owner_id = ?2 AND d.deleted_at IS NULL
+            )
+            SELECT id FROM subtree
+            "#
+        ).bind(id.to_string()).bind(owner_id.to_string()).fetch_all(&mut *tx).await?;
+        let id_map: HashMap<Uuid, Uuid> = ids.iter()
+            .filter_map(|old| Some((Uuid::parse_str(old).ok()?, Uuid::new_v4())))
+            .collect();
+        if id_map.is_empty() {
+            return Ok(id_map);
+        }
+        let map = serde_json::to_string(&id_map)?;
+        // Row ids have no default in the vault, so copied rows get theirs from a second map.
+        let row_ids: Vec<String> = sqlx::query_scalar(&format!(
+            "WITH {ID_MAP} SELECT r.id FROM collection_rows r JOIN map m ON m.old_id = r.document_id"
+        )).bind(&map).fetch_all(&mut *tx).await?;
+        let row_map: HashMap<String, String> = row_ids.into_iter().map(|old| (old, Uuid::new_v4().to_string())).collect();
+
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO documents (id, owner_id, parent_id, title, class_id, properties, config, is_public)
+             SELECT m.new_id, d.owner_id, COALESCE(p.new_id, d.parent_id), CASE WHEN d.id = ?2 THEN ?3 ELSE d.title END,
+                    d.class_id, d.properties, d.config, d.is_public
+             FROM documents d JOIN map m ON m.old_id = d.id LEFT JOIN map p ON p.old_id = d.parent_id"
+        )).bind(&map).bind(id.to_string()).bind(title).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "WITH {ID_MAP}, row_map AS (SELECT key AS old_id, value AS new_id FROM json_each(?2))
+             INSERT INTO collection_rows (id, document_id, data, order_index)
+             SELECT rm.new_id, m.new_id, r.data, r.order_index FROM collection_rows r
+             JOIN row_map rm ON rm.old_id = r.id JOIN map m ON m.old_id = r.document_id"
+        )).bind(&map).bind(serde_json::to_string(&row_map)?).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO document_links (from_id, to_id, relation, metadata)
+             SELECT f.new_id, t.new_id, l.relation, l.metadata FROM document_links l
+             JOIN map f ON f.old_id = l.from_id JOIN map t ON t.old_id = l.to_id"
+        )).bind(&map).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO document_snapshots (doc_id, data)
+             SELECT m.new_id, s.data FROM document_snapshots s JOIN map m ON m.old_id = s.doc_id"
+        )).bind(&map).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "WITH {ID_MAP}
+             INSERT INTO document_updates (doc_id, data, author_id, created_at)
+             SELECT m.new_id, u.data, u.author_id, u.created_at FROM document_updates u JOIN map m ON m.old_id = u.doc_id
+             ORDER BY u.created_at, u.id"
+        )).bind(&map).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(id_map)
     }
 
     async fn get_stats(&self, owner_id: Uuid) -> anyhow::Result<crate::kernel::types::SystemStats> {
-        let nodes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE owner_id = ?")
+        let nodes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE owner_id = ? AND deleted_at IS NULL")
             .bind(owner_id.to_string()).fetch_one(&self.pool).await?;
         
-        let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM document_links l JOIN documents d ON l.from_id = d.id WHERE d.owner_id = ?")
+        let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM document_links l JOIN documents d ON l.from_id = d.id JOIN documents t ON l.to_id = t.id WHERE d.owner_id = ? AND d.deleted_at IS NULL AND t.deleted_at IS NULL")
             .bind(owner_id.to_string()).fetch_one(&self.pool).await?;
 
-        let recent: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE owner_id = ? AND updated_at > datetime('now', '-1 day')")
+        let recent: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE owner_id = ? AND deleted_at IS NULL AND updated_at > datetime('now', '-1 day')")
             .bind(owner_id.to_string()).fetch_one(&self.pool).await?;
 
-        let rows = sqlx::query("SELECT class_id, COUNT(*) FROM documents WHERE owner_id = ? GROUP BY class_id")
+        let rows = sqlx::query("SELECT class_id, COUNT(*) FROM documents WHERE owner_id = ? AND deleted_at IS NULL GROUP BY class_id")
             .bind(owner_id.to_string()).fetch_all(&self.pool).await?;
 
         let mut distribution = std::collections::HashMap::new();
@@ -197,7 +430,7 @@ impl DocumentRepository for SqliteDocumentRepository {
             SELECT COUNT(*) FROM documents 
             WHERE id NOT IN (SELECT from_id FROM document_links)
               AND id NOT IN (SELECT to_id FROM document_links)
-              AND owner_id = ?
+              AND owner_id = ? AND deleted_at IS NULL
             "#
         ).bind(owner_id.to_string()).fetch_one(&self.pool).await?;
 
@@ -205,7 +438,7 @@ impl DocumentRepository for SqliteDocumentRepository {
             r#"
             SELECT COUNT(*) FROM documents 
             WHERE (json_extract(properties, '$.tags') IS NULL OR json_extract(properties, '$.tags') = '[]')
-              AND owner_id = ?
+              AND owner_id = ? AND deleted_at IS NULL
             "#
         ).bind(owner_id.to_string()).fetch_one(&self.pool).await?;
 
@@ -219,20 +452,189 @@ impl DocumentRepository for SqliteDocumentRepository {
         })
     }
 
+    async fn trash(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<Vec<Uuid>> {
+        let trashed: Vec<String> = sqlx::query_scalar(
+            r#"
+            WITH RECURSIVE subtree AS (
+                SELECT id FROM documents WHERE id = ?1 AND owner_id = ?2 AND deleted_at IS NULL
+                UNION
+                SELECT d.id FROM documents d JOIN subtree s ON d.parent_id = s.id
+                WHERE d.owner_id = ?2 AND d.deleted_at IS NULL
+            )
+            UPDATE documents SET deleted_at = CURRENT_TIMESTAMP, trash_root_id = ?1, updated_at = CURRENT_TIMESTAMP
+            WHERE id IN (SELECT id FROM subtree)
+            RETURNING id
+            "#
+        ).bind(id.to_string()).bind(owner_id.to_string()).fetch_all(&self.pool).await?;
+        Ok(trashed.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+
+    async fn restore(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query("UPDATE documents SET deleted_at = NULL, trash_root_id = NULL, updated_at = CURRENT_TIMESTAMP WHERE trash_root_id = ?1 AND owner_id = ?2")
+            .bind(id.to_string()).bind(owner_id.to_string()).execute(&mut *tx).await?;
+        if result.rows_affected() > 0 {
+            sqlx::query(
+                "UPDATE documents SET parent_id = NULL, updated_at = CURRENT_TIMESTAMP WHERE id = ?1 AND parent_id IS NOT NULL
+                 AND NOT EXISTS (SELECT 1 FROM documents p WHERE p.id = documents.parent_id AND p.deleted_at IS NULL)"
+            ).bind(id.to_string()).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn find_trash(&self, owner_id: Uuid) -> anyhow::Result<Vec<TrashItem>> {
+        let rows: Vec<(String, String, Option<String>, NaiveDateTime, i64)> = sqlx::query_as(
+            "SELECT d.id, d.title, d.class_id, d.deleted_at, (SELECT COUNT(*) FROM documents s WHERE s.trash_root_id = d.id)
+             FROM documents d
+             WHERE d.owner_id = ? AND d.trash_root_id = d.id
+             ORDER BY d.deleted_at DESC, d.id"
+        ).bind(owner_id.to_string()).fetch_all(&self.pool).await?;
+        Ok(rows.into_iter()
+            .filter_map(|(id, title, class_id, deleted_at, documents)| Some(TrashItem {
+                id: Uuid::parse_str(&id).ok()?,
+                title,
+                class_id,
+                deleted_at: deleted_at.and_utc(),
+                documents: documents as u64,
+            }))
+            .collect())
+    }
+
+    async fn purge(&self, id: Uuid, owner_id: Uuid) -> anyhow::Result<Vec<Uuid>> {
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM documents WHERE trash_root_id = ? AND owner_id = ?")
+            .bind(id.to_string()).bind(owner_id.to_string()).fetch_all(&mut *tx).await?;
+        Self::record_tombstones(&mut tx, &ids).await?;
+        Self::delete_documents(&mut tx, &ids).await?;
+        tx.commit().await?;
+        Ok(ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+
+    async fn purge_expired(&self, before: DateTime<Utc>) -> anyhow::Result<Vec<Uuid>> {
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM documents WHERE deleted_at < ?")
+            .bind(before.naive_utc()).fetch_all(&mut *tx).await?;
+        Self::record_tombstones(&mut tx, &ids).await?;
+        Self::delete_documents(&mut tx, &ids).await?;
+        tx.commit().await?;
+        Ok(ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+
     async fn add_link(&self, from_id: Uuid, to_id: Uuid) -> anyhow::Result<()> {
-        sqlx::query("INSERT OR IGNORE INTO document_links (from_id, to_id) VALUES (?, ?)")
+        sqlx::query("INSERT OR IGNORE INTO document_links (from_id, to_id, created_at) VALUES (?, ?, CURRENT_TIMESTAMP)")
             .bind(from_id.to_string()).bind(to_id.to_string()).execute(&self.pool).await?;
         Ok(())
     }
 
     async fn find_links(&self, owner_id: Uuid) -> anyhow::Result<Vec<(Uuid, Uuid)>> {
-        let rows = sqlx::query("SELECT l.from_id, l.to_id FROM document_links l JOIN documents d ON l.from_id = d.id WHERE d.owner_id = ?")
+        let rows = sqlx::query("SELECT DISTINCT l.from_id, l.to_id FROM document_links l JOIN documents d ON l.from_id = d.id JOIN documents t ON l.to_id = t.id
+             WHERE d.owner_id = ? AND d.deleted_at IS NULL AND t.deleted_at IS NULL")
             .bind(owner_id.to_string()).fetch_all(&self.pool).await?;
         Ok(rows.into_iter().filter_map(|r| Some((Uuid::parse_str(r.get(0)).ok()?, Uuid::parse_str(r.get(1)).ok()?))).collect())
     }
 
+    async fn add_typed_link(&self, from_id: Uuid, to_id: Uuid, relation: &str, metadata: serde_json::Value) -> anyhow::Result<DocumentLink> {
+        let created_at: Option<NaiveDateTime> = sqlx::query_scalar(
+            "INSERT INTO document_links (from_id, to_id, relation, metadata, created_at) VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+             ON CONFLICT (from_id, to_id, relation) DO UPDATE SET metadata = excluded.metadata, updated_at = CURRENT_TIMESTAMP
+             RETURNING created_at"
+        )
+        .bind(from_id.to_string()).bind(to_id.to_string()).bind(relation).bind(metadata.to_string())
+        .fetch_one(&self.pool).await?;
+        Ok(DocumentLink { from_id, to_id, relation: relation.to_string(), metadata, created_at: created_at.map(|t| t.and_utc()) })
+    }
+
+    async fn remove_link(&self, from_id: Uuid, to_id: Uuid, relation: Option<&str>) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO sync_tombstones (kind, key, owner_id, deleted_at)
+             SELECT 'link', l.from_id || ':' || l.to_id || ':' || l.relation, d.owner_id, CURRENT_TIMESTAMP FROM document_links l
+             JOIN documents d ON d.id = l.from_id
+             WHERE l.from_id = ?1 AND l.to_id = ?2 AND (?3 IS NULL OR l.relation = ?3) AND d.owner_id IS NOT NULL
+             ON CONFLICT (kind, key) DO UPDATE SET deleted_at = excluded.deleted_at"
+        )
+        .bind(from_id.to_string()).bind(to_id.to_string()).bind(relation)
+        .execute(&mut *tx).await?;
+        let result = sqlx::query("DELETE FROM document_links WHERE from_id = ?1 AND to_id = ?2 AND (?3 IS NULL OR relation = ?3)")
+            .bind(from_id.to_string()).bind(to_id.to_string()).bind(relation)
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn find_backlinks(&self, to_id: Uuid) -> anyhow::Result<Vec<DocumentLink>> {
+        let rows: Vec<(String, String, String, String, Option<NaiveDateTime>)> = sqlx::query_as(
+            "SELECT l.from_id, l.to_id, l.relation, l.metadata, l.created_at FROM document_links l
+             JOIN documents s ON s.id = l.from_id
+             JOIN documents t ON t.id = l.to_id
+             WHERE l.to_id = ? AND s.owner_id = t.owner_id AND s.deleted_at IS NULL AND t.deleted_at IS NULL
+             ORDER BY l.created_at, l.from_id, l.relation"
+        ).bind(to_id.to_string()).fetch_all(&self.pool).await?;
+        Ok(rows.into_iter()
+            .filter_map(|(from_id, to_id, relation, metadata, created_at)| Some(DocumentLink {
+                from_id: Uuid::parse_str(&from_id).ok()?,
+                to_id: Uuid::parse_str(&to_id).ok()?,
+                relation,
+                metadata: serde_json::from_str(&metadata).unwrap_or_else(|_| json!({})),
+                created_at: created_at.map(|t| t.and_utc()),
+            }))
+            .collect())
+    }
+
+    async fn find_neighbourhood(&self, owner_id: Uuid, doc_id: Uuid, depth: u32, traversal: Traversal) -> anyhow::Result<Subgraph> {
+        let nodes: Vec<(String, i64)> = sqlx::query_as(&format!(
+            "WITH RECURSIVE {GRAPH_EDGES}, {GRAPH_WALK} SELECT id, depth FROM reached ORDER BY depth, id"
+        ))
+        .bind(owner_id.to_string()).bind(traversal.follows_links()).bind(traversal.follows_hierarchy()).bind(doc_id.to_string()).bind(depth)
+        .fetch_all(&self.pool).await?;
+        let edges: Vec<(String, String, String, Option<String>)> = sqlx::query_as(&format!(
+            "WITH RECURSIVE {GRAPH_EDGES}, {GRAPH_WALK}
+             SELECT a, b, kind, relation FROM typed_edges
+             WHERE a IN (SELECT id FROM reached) AND b IN (SELECT id FROM reached)
+             ORDER BY a, b, kind, relation"
+        ))
+        .bind(owner_id.to_string()).bind(traversal.follows_links()).bind(traversal.follows_hierarchy()).bind(doc_id.to_string()).bind(depth)
+        .fetch_all(&self.pool).await?;
+        Ok(Subgraph {
+            nodes: nodes.into_iter().filter_map(|(id, depth)| Some(GraphNode { id: Uuid::parse_str(&id).ok()?, depth: depth as u32 })).collect(),
+            edges: edges.into_iter().filter_map(graph_edge).collect(),
+        })
+    }
+
+    async fn find_graph_edges(&self, owner_id: Uuid, traversal: Traversal) -> anyhow::Result<Vec<GraphEdge>> {
+        let edges: Vec<(String, String, String, Option<String>)> = sqlx::query_as(&format!(
+            "WITH {GRAPH_EDGES} SELECT a, b, kind, relation FROM typed_edges ORDER BY a, b, kind, relation"
+        ))
+        .bind(owner_id.to_string()).bind(traversal.follows_links()).bind(traversal.follows_hierarchy())
+        .fetch_all(&self.pool).await?;
+        Ok(edges.into_iter().filter_map(graph_edge).collect())
+    }
+
+    async fn find_cycles(&self, owner_id: Uuid, traversal: Traversal, max_len: u32, limit: i64) -> anyhow::Result<Vec<Vec<Uuid>>> {
+        // Paths are comma-separated ids; a path only grows through ids greater than its start,
+        // so each cycle is found once, from its smallest id. Ids of one length compare as text
+        // in the order Postgres compares them as uuids.
+        let paths: Vec<String> = sqlx::query_scalar(&format!(
+            "WITH RECURSIVE {GRAPH_EDGES},
+             walk(start, id, path, len) AS (
+                 SELECT DISTINCT a, a, a, 1 FROM edges
+                 UNION ALL
+                 SELECT w.start, e.b, w.path || ',' || e.b, w.len + 1 FROM walk w JOIN edges e ON e.a = w.id
+                 WHERE e.b > w.start AND instr(w.path, e.b) = 0 AND w.len < ?4
+             )
+             SELECT w.path FROM walk w JOIN edges e ON e.a = w.id AND e.b = w.start
+             ORDER BY w.len, w.path LIMIT ?5"
+        ))
+        .bind(owner_id.to_string()).bind(traversal.follows_links()).bind(traversal.follows_hierarchy()).bind(max_len).bind(limit)
+        .fetch_all(&self.pool).await?;
+        Ok(paths.into_iter()
+            .filter_map(|path| path.split(',').map(|id| Uuid::parse_str(id).ok()).collect())
+            .collect())
+    }
+
     async fn find_children_properties(&self, parent_id: Uuid, _class_filter: Option<String>) -> anyhow::Result<Vec<serde_json::Value>> {
-        let rows = sqlx::query("SELECT properties FROM documents WHERE parent_id = ?").bind(parent_id.to_string()).fetch_all(&self.pool).await?;
+        let rows = sqlx::query("SELECT properties FROM documents WHERE parent_id = ? AND deleted_at IS NULL").bind(parent_id.to_string()).fetch_all(&self.pool).await?;
         Ok(rows.into_iter().filter_map(|r| serde_json::from_str(&r.get::<String, _>(0)).ok()).collect())
     }
 
@@ -289,8 +691,8 @@ impl DocumentRepository for SqliteDocumentRepository {
             r#"
             SELECT SUM(CAST(json_extract(properties, ?) AS REAL)) 
             FROM documents 
-            WHERE parent_id = ? 
-               OR id IN (SELECT to_id FROM document_links WHERE from_id = ?)
+            WHERE (parent_id = ? OR id IN (SELECT to_id FROM document_links WHERE from_id = ?))
+              AND deleted_at IS NULL
             "#
         )
         .bind(key_path)
@@ -302,9 +704,24 @@ impl DocumentRepository for SqliteDocumentRepository {
         Ok(sum.unwrap_or(0.0))
     }
 
-    // AI/Physics Removed
-    async fn update_embedding(&self, _doc_id: Uuid, _embedding: Vec<f32>) -> anyhow::Result<()> { Ok(()) }
-    async fn search_similar(&self, _target_vector: Vec<f32>, _limit: i64) -> anyhow::Result<Vec<Uuid>> { Ok(vec![]) }
+    async fn update_embedding(&self, doc_id: Uuid, embedding: Vec<f32>) -> anyhow::Result<()> {
+        vector_store::check_dimensions(&embedding)?;
+        sqlx::query("INSERT INTO neural_metadata (document_id, embedding) VALUES (?, ?) ON CONFLICT (document_id) DO UPDATE SET embedding = excluded.embedding")
+            .bind(doc_id.to_string()).bind(vector_store::encode_embedding(&embedding))
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Documents closest to `embedding`, scanning every stored embedding in Rust.
+    async fn search_similar(&self, embedding: Vec<f32>, limit: i64) -> anyhow::Result<Vec<Uuid>> {
+        let mut nearest = NearestNeighbours::new(embedding, limit.max(0) as usize);
+        let mut rows = sqlx::query_as::<_, (String, Vec<u8>)>("SELECT m.document_id, m.embedding FROM neural_metadata m JOIN documents d ON d.id = m.document_id WHERE m.embedding IS NOT NULL AND d.deleted_at IS NULL")
+            .fetch(&self.pool);
+        while let Some((id, blob)) = rows.try_next().await? {
+            nearest.offer(Uuid::parse_str(&id)?, &vector_store::decode_embedding(&blob)?)?;
+        }
+        Ok(nearest.into_ids())
+    }
 }
 
 pub struct SqliteArchetypeRepository { pool: SqlitePool }
@@ -312,23 +729,106 @@ impl SqliteArchetypeRepository { pub fn new(pool: SqlitePool) -> Self { Self { p
 #[async_trait]
 impl ArchetypeRepository for SqliteArchetypeRepository {
     async fn find_all(&self) -> anyhow::Result<Vec<Archetype>> {
-        let rows = sqlx::query("SELECT id, name, icon, COALESCE(ui_schema, '[]'), COALESCE(behavior_rules, '{}'), group_id, required_tier FROM classes")
+        let rows = sqlx::query("SELECT id, name, icon, COALESCE(ui_schema, '[]'), COALESCE(behavior_rules, '{}'), group_id, required_tier, allowed_children FROM classes")
             .fetch_all(&self.pool).await?;
         Ok(rows.into_iter().map(|r| Archetype {
             id: r.get(0), name: r.get(1), icon: r.get(2), group_id: r.get(5), required_tier: r.get(6),
             ui_schema: serde_json::from_str(&r.get::<String, _>(3)).unwrap_or(json!([])),
             behavior_rules: serde_json::from_str(&r.get::<String, _>(4)).unwrap_or(json!({})),
-            allowed_children: None
+            allowed_children: r.get::<Option<String>, _>(7).and_then(|c| serde_json::from_str(&c).ok())
         }).collect())
     }
     async fn find_by_id(&self, id: &str) -> anyhow::Result<Option<Archetype>> {
-        let row = sqlx::query("SELECT id, name, icon, COALESCE(ui_schema, '[]'), COALESCE(behavior_rules, '{}'), group_id, required_tier FROM classes WHERE id = ?")
+        let row = sqlx::query("SELECT id, name, icon, COALESCE(ui_schema, '[]'), COALESCE(behavior_rules, '{}'), group_id, required_tier, allowed_children FROM classes WHERE id = ?")
             .bind(id).fetch_optional(&self.pool).await?;
         Ok(row.map(|r| Archetype {
             id: r.get(0), name: r.get(1), icon: r.get(2), group_id: r.get(5), required_tier: r.get(6),
             ui_schema: serde_json::from_str(&r.get::<String, _>(3)).unwrap_or(json!([])),
             behavior_rules: serde_json::from_str(&r.get::<String, _>(4)).unwrap_or(json!({})),
-            allowed_children: None
+            allowed_children: r.get::<Option<String>, _>(7).and_then(|c| serde_json::from_str(&c).ok())
         }))
     }
-}
\ No newline at end of file
+}
+pub struct SqliteUserRepository { pool: SqlitePool }
+impl SqliteUserRepository { pub fn new(pool: SqlitePool) -> Self { Self { pool } } }
+#[async_trait]
+impl UserRepository for SqliteUserRepository {
+    async fn create(&self, id: Uuid, username: &str, password_hash: &str) -> anyhow::Result<()> {
+        sqlx::query("INSERT INTO users (id, username, password_hash) VALUES (?, ?, ?)")
+            .bind(id.to_string()).bind(username).bind(password_hash).execute(&self.pool).await?;
+        Ok(())
+    }
+    async fn find_by_username(&self, username: &str) -> anyhow::Result<Option<Credentials>> {
+        let row: Option<(String, String, String, Option<String>)> = sqlx::query_as("SELECT id, username, password_hash, avatar_url FROM users WHERE username = ?")
+            .bind(username).fetch_optional(&self.pool).await?;
+        row.map(|(id, username, password_hash, avatar_url)| Ok(Credentials { id: Uuid::parse_str(&id)?, username, password_hash, avatar_url }))
+            .transpose()
+    }
+    async fn update_avatar(&self, user_id: Uuid, avatar_url: Option<String>) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET avatar_url = ? WHERE id = ?").bind(avatar_url).bind(user_id.to_string()).execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+/// Implementation of AuditRepository for the local vault. Entries are chained in insertion
+/// order (`rowid`), as timestamps only have one-second precision.
+pub struct SqliteAuditRepository {
+    pool: SqlitePool,
+    // Reading the last hash and appending the next entry must not interleave.
+    append: Mutex<()>,
+}
+
+impl SqliteAuditRepository {
+    pub fn new(pool: SqlitePool) -> Self { Self { pool, append: Mutex::new(()) } }
+
+    /// The latest `limit` entries, most recent first.
+    pub async fn recent(&self, limit: i64) -> anyhow::Result<Vec<AuditEntry>> {
+        let rows = sqlx::query(&format!("{} ORDER BY rowid DESC LIMIT ?", AUDIT_COLUMNS))
+            .bind(limit).fetch_all(&self.pool).await?;
+        rows.iter().map(audit_entry).collect()
+    }
+
+    /// Checks the whole chain, from the first entry on.
+    pub async fn verify(&self) -> anyhow::Result<ChainVerification> {
+        let rows = sqlx::query(&format!("{} ORDER BY rowid", AUDIT_COLUMNS)).fetch_all(&self.pool).await?;
+        let entries = rows.iter().map(audit_entry).collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(IntegrityEngine::verify_chain(&entries))
+    }
+}
+
+const AUDIT_COLUMNS: &str = "SELECT id, user_id, resource_id, resource_type, action, details, created_at, prev_hash, hash FROM audit_logs";
+
+fn audit_entry(r: &SqliteRow) -> anyhow::Result<AuditEntry> {
+    let uuid = |s: Option<String>| s.and_then(|s| Uuid::parse_str(&s).ok());
+    Ok(AuditEntry {
+        id: Uuid::parse_str(&r.get::<String, _>(0))?,
+        user_id: uuid(r.get(1)),
+        resource_id: uuid(r.get(2)),
+        resource_type: r.get(3),
+        action: r.get::<Option<String>, _>(4).unwrap_or_default(),
+        details: r.get::<Option<String>, _>(5).unwrap_or_else(|| "{}".to_string()),
+        created_at: r.get::<NaiveDateTime, _>(6).and_utc(),
+        prev_hash: r.get(7),
+        hash: r.get(8),
+    })
+}
+
+#[async_trait]
+impl AuditRepository for SqliteAuditRepository {
+    /// Appends an audit event to 'audit_logs', chained to the hash of the entry before it.
+    async fn log(&self, user_id: Option<Uuid>, resource_id: Option<Uuid>, resource_type: &str, action: &str, details: Option<String>) -> anyhow::Result<()> {
+        let details_json = details.unwrap_or("{}".to_string());
+        let user = user_id.map(|u| u.to_string());
+        let resource = resource_id.map(|r| r.to_string());
+
+        let _guard = self.append.lock().await;
+        let mut tx = self.pool.begin().await?;
+        let last_hash: Option<String> = sqlx::query_scalar("SELECT hash FROM audit_logs ORDER BY rowid DESC LIMIT 1").fetch_optional(&mut *tx).await?.flatten();
+        let new_hash = IntegrityEngine::calculate_audit_hash(user.as_deref(), resource.as_deref(), action, &details_json, last_hash.as_deref());
+        sqlx::query("INSERT INTO audit_logs (id, user_id, resource_id, resource_type, action, details, prev_hash, hash) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(Uuid::new_v4().to_string()).bind(user).bind(resource).bind(resource_type).bind(action).bind(details_json).bind(last_hash).bind(new_hash)
+            .execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePoolOptions};
+use sqlx::{ConnectOptions, Connection, SqlitePool};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Key derivation settings of an encrypted vault, kept in a file beside it. Holds no secret:
+/// the key only exists while the vault is unlocked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VaultHeader {
+    version: u32,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+}
+
+impl VaultHeader {
+    fn generate() -> Self {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            version: 1,
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+            salt: hex::encode(salt),
+        }
+    }
+
+    /// SQLCipher raw key, derived from the passphrase with Argon2id, as a `PRAGMA key` value.
+    fn key(&self, passphrase: &str) -> Result<Zeroizing<String>> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| anyhow::anyhow!("invalid key derivation settings: {}", e))?;
+        let salt = hex::decode(&self.salt).context("invalid vault salt")?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| anyhow::anyhow!("key derivation failed: {}", e))?;
+        Ok(Zeroizing::new(format!("\"x'{}'\"", hex::encode_upper(key.as_ref()))))
+    }
+
+    fn read(path: &Path) -> Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes).context("unreadable vault header")?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// The SQLite file of a desktop vault, encrypted with SQLCipher or not.
+///
+/// An encrypted vault is only ever opened with its key; locking it is closing its pool. Turning
+/// encryption on and changing the passphrase both export the vault into a new file under the
+/// new key and swap it in, so the file on disk is never partly re-keyed. The new header is
+/// written as pending before the swap and promoted after it, and unlocking tries both.
+pub struct VaultFile {
+    db_path: PathBuf,
+    max_connections: u32,
+}
+
+impl VaultFile {
+    pub fn new(db_path: impl Into<PathBuf>) -> Self {
+        Self { db_path: db_path.into(), max_connections: 5 }
+    }
+
+    /// Overrides the size of the pools this file is opened with.
+    pub fn with_max_connections(mut self, max_connections: u32) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.header_path().exists() || self.pending_header_path().exists()
+    }
+
+    /// Opens a vault that is not encrypted.
+    pub async fn open(&self) -> Result<SqlitePool> {
+        if self.is_encrypted() {
+            return Err(anyhow::anyhow!("vault is encrypted"));
+        }
+        Ok(SqlitePoolOptions::new().max_connections(self.max_connections).connect_with(self.options(None)).await?)
+    }
+
+    /// Opens an encrypted vault with the key derived from `passphrase`.
+    pub async fn unlock(&self, passphrase: &str) -> Result<SqlitePool> {
+        let (key, from_pending) = self.find_key(passphrase).await?;
+        let pool = SqlitePoolOptions::new()
+            .max_connections(self.max_connections)
+            .connect_with(self.options(Some(key.to_string())))
+            .await?;
+        if from_pending {
+            std::fs::rename(self.pending_header_path(), self.header_path())?;
+        }
+        Ok(pool)
+    }
+
+    /// Fails unless `passphrase` opens the vault. Usable while the vault is open.
+    pub async fn check_passphrase(&self, passphrase: &str) -> Result<()> {
+        self.find_key(passphrase).await.map(|_| ())
+    }
+
+    /// Encrypts a plaintext vault under `passphrase`. Its pools must be closed.
+    ///
+    /// The encrypted copy is a new file that replaces the plaintext one, which is unlinked, not
+    /// overwritten: its pages can survive in free blocks of the disk, in filesystem snapshots
+    /// and in backups until the space is reused. Encryption protects what is written from then
+    /// on, not copies of the disk made before.
+    pub async fn encrypt(&self, passphrase: &str) -> Result<()> {
+        if self.is_encrypted() {
+            return Err(anyhow::anyhow!("vault is already encrypted"));
+        }
+        self.reencrypt(None, passphrase).await
+    }
+
+    /// Re-keys an encrypted vault under a new passphrase. Its pools must be closed.
+    pub async fn change_passphrase(&self, old: &str, new: &str) -> Result<()> {
+        let (key, _) = self.find_key(old).await?;
+        self.reencrypt(Some(key), new).await
+    }
+
+    /// Key that opens the vault, and whether it comes from the pending header.
+    async fn find_key(&self, passphrase: &str) -> Result<(Zeroizing<String>, bool)> {
+        let candidates = [(self.header_path(), false), (self.pending_header_path(), true)];
+        for (path, pending) in candidates {
+            let Some(header) = VaultHeader::read(&path)? else { continue };
+            let key = header.key(passphrase)?;
+            let mut conn = self.options(Some(key.to_string())).connect().await?;
+            let opened = check_readable(&mut conn).await;
+            conn.close().await.ok();
+            if opened.is_ok() {
+                return Ok((key, pending));
+            }
+        }
+        match self.is_encrypted() {
+            true => Err(anyhow::anyhow!("wrong passphrase")),
+            false => Err(anyhow::anyhow!("vault is not encrypted")),
+        }
+    }
+
+    /// Copies the vault into a new file under a new key, then swaps the two.
+    async fn reencrypt(&self, current_key: Option<Zeroizing<String>>, passphrase: &str) -> Result<()> {
+        let header = VaultHeader::generate();
+        let key = header.key(passphrase)?;
+        let target = self.db_path.with_extension("rekey");
+        remove_if_exists(&target)?;
+
+        let mut conn = self.options(current_key.map(|k| k.to_string())).connect().await?;
+        check_readable(&mut conn).await?;
+        let has_cipher: Option<String> = sqlx::query_scalar("PRAGMA cipher_version").fetch_optional(&mut conn).await?;
+        if has_cipher.is_none() {
+            return Err(anyhow::anyhow!("this build cannot encrypt vaults (SQLCipher is not linked)"));
+        }
+        let attach = Zeroizing::new(format!(
+            "ATTACH DATABASE '{}' AS rekeyed KEY {}",
+            target.display().to_string().replace('\'', "''"),
+            key.as_str()
+        ));
+        sqlx::query(&attach).execute(&mut conn).await?;
+        sqlx::query("SELECT sqlcipher_export('rekeyed')").execute(&mut conn).await?;
+        sqlx::query("DETACH DATABASE rekeyed").execute(&mut conn).await?;
+        conn.close().await?;
+
+        header.write(&self.pending_header_path())?;
+        std::fs::rename(&target, &self.db_path)?;
+        for suffix in ["-wal", "-shm"] {
+            remove_if_exists(&sidecar(&self.db_path, suffix))?;
+        }
+        std::fs::rename(self.pending_header_path(), self.header_path())?;
+        Ok(())
+    }
+
+    fn options(&self, key: Option<String>) -> SqliteConnectOptions {
+        let options = SqliteConnectOptions::new()
+            .filename(&self.db_path)
+            .create_if_missing(true)
+            // Sorts and temporary tables of an unlocked vault stay off the disk.
+            .pragma("temp_store", "MEMORY");
+        match key {
+            Some(key) => options.pragma("key", key),
+            None => options,
+        }
+    }
+
+    fn header_path(&self) -> PathBuf {
+        sidecar(&self.db_path, ".vault")
+    }
+
+    fn pending_header_path(&self) -> PathBuf {
+        sidecar(&self.db_path, ".vault.pending")
+    }
+}
+
+/// Fails with "file is not a database" when the key does not match.
+async fn check_readable(conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM sqlite_master").fetch_one(conn).await?;
+    Ok(())
+}
+
+fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
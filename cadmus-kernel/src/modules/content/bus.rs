@@ -0,0 +1,237 @@
+//! Room Bus
+//!
+//! Relays the updates a `ContentRegistry` applies to the registries of peer instances, so
+//! editors connected to different API replicas share their rooms. Each instance persists only
+//! the updates it received from its own sockets, and relays them once they are written; relayed
+//! updates are applied in memory, and rooms loaded later read them from storage.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+/// Postgres channel the room updates are announced on.
+pub const ROOM_BUS_CHANNEL: &str = "cadmus_room_updates";
+
+/// Hex characters per notification. Postgres rejects payloads of 8000 bytes or more.
+const MAX_CHUNK_HEX: usize = 7000;
+
+/// Events pending delivery before the bus listener applies backpressure.
+const EVENT_BUFFER: usize = 1024;
+
+/// What a registry receives from its peers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BusEvent {
+    /// An update another instance applied to a room.
+    Update { doc_id: String, update: Vec<u8> },
+    /// The bus lost its connection and may have missed updates; resident rooms should be
+    /// brought up to date from storage.
+    Reconnected,
+}
+
+/// Transport between the room registries of several instances.
+#[async_trait]
+pub trait RoomBus: Send + Sync {
+    /// Announces an update applied locally. Peers receive it; this instance does not.
+    async fn publish(&self, doc_id: &str, update: &[u8]) -> anyhow::Result<()>;
+
+    /// Starts receiving the updates published by peer instances.
+    async fn subscribe(&self) -> anyhow::Result<mpsc::Receiver<BusEvent>>;
+}
+
+/// One notification on the Postgres channel. Updates larger than a notification allows are
+/// split into parts and reassembled by the receivers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusPayload {
+    pub origin: Uuid,
+    pub seq: u64,
+    pub doc_id: String,
+    pub part: usize,
+    pub parts: usize,
+    pub data: String,
+}
+
+impl BusPayload {
+    /// Splits an update into notification payloads.
+    pub fn split(origin: Uuid, seq: u64, doc_id: &str, update: &[u8]) -> Vec<String> {
+        let encoded = hex::encode(update);
+        let chunks: Vec<&str> = encoded.as_bytes()
+            .chunks(MAX_CHUNK_HEX)
+            // Hex is ASCII, so byte chunks are valid strings.
+            .map(|c| std::str::from_utf8(c).unwrap_or_default())
+            .collect();
+        let parts = chunks.len();
+        chunks.into_iter().enumerate()
+            .map(|(part, data)| {
+                let payload = BusPayload { origin, seq, doc_id: doc_id.to_string(), part, parts, data: data.to_string() };
+                serde_json::to_string(&payload).unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+/// Collects the parts of split updates until each one is complete.
+#[derive(Default)]
+pub struct PayloadAssembler {
+    pending: HashMap<(Uuid, u64), Vec<Option<String>>>,
+}
+
+impl PayloadAssembler {
+    /// Adds a part and returns the update once every part of it has arrived.
+    pub fn push(&mut self, payload: BusPayload) -> Option<(String, Vec<u8>)> {
+        if payload.parts <= 1 {
+            return hex::decode(&payload.data).ok().map(|u| (payload.doc_id, u));
+        }
+
+        let key = (payload.origin, payload.seq);
+        let parts = self.pending.entry(key).or_insert_with(|| vec![None; payload.parts]);
+        if let Some(slot) = parts.get_mut(payload.part) {
+            *slot = Some(payload.data);
+        }
+        if parts.iter().any(Option::is_none) {
+            return None;
+        }
+
+        let parts = self.pending.remove(&key)?;
+        let encoded: String = parts.into_iter().flatten().collect();
+        hex::decode(encoded).ok().map(|u| (payload.doc_id, u))
+    }
+
+    /// Drops partially received updates, whose remaining parts will not arrive.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Room bus over Postgres `LISTEN`/`NOTIFY`.
+pub struct PgRoomBus {
+    pool: PgPool,
+    instance_id: Uuid,
+    seq: AtomicU64,
+}
+
+impl PgRoomBus {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool, instance_id: Uuid::new_v4(), seq: AtomicU64::new(0) }
+    }
+}
+
+#[async_trait]
+impl RoomBus for PgRoomBus {
+    async fn publish(&self, doc_id: &str, update: &[u8]) -> anyhow::Result<()> {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let payloads = BusPayload::split(self.instance_id, seq, doc_id, update);
+
+        // Parts sent in one transaction are delivered together, in order, on commit.
+        let mut tx = self.pool.begin().await?;
+        for payload in payloads {
+            sqlx::query("SELECT pg_notify($1, $2)")
+                .bind(ROOM_BUS_CHANNEL)
+                .bind(payload)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> anyhow::Result<mpsc::Receiver<BusEvent>> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(ROOM_BUS_CHANNEL).await?;
+        let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+        let instance_id = self.instance_id;
+
+        tokio::spawn(async move {
+            let mut assembler = PayloadAssembler::default();
+            loop {
+                let event = match listener.try_recv().await {
+                    Ok(Some(notification)) => {
+                        let Ok(payload) = serde_json::from_str::<BusPayload>(notification.payload()) else {
+                            tracing::warn!("Content: Ignoring malformed room bus payload");
+                            continue;
+                        };
+                        if payload.origin == instance_id {
+                            continue;
+                        }
+                        match assembler.push(payload) {
+                            Some((doc_id, update)) => BusEvent::Update { doc_id, update },
+                            None => continue,
+                        }
+                    },
+                    // The connection dropped; the next call reconnects and re-listens.
+                    Ok(None) => {
+                        tracing::warn!("Content: Room bus connection lost, reconnecting");
+                        assembler.clear();
+                        BusEvent::Reconnected
+                    },
+                    Err(e) => {
+                        tracing::error!("Content: Room bus failed: {}", e);
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        continue;
+                    },
+                };
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(rx)
+    }
+}
+
+/// Room bus between registries of the same process, for tests and embedded setups.
+pub struct MemoryRoomBus {
+    tx: broadcast::Sender<(Uuid, String, Vec<u8>)>,
+    instance_id: Uuid,
+}
+
+impl MemoryRoomBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        Self { tx, instance_id: Uuid::new_v4() }
+    }
+
+    /// Another endpoint on the same bus, for a peer registry.
+    pub fn peer(&self) -> Self {
+        Self { tx: self.tx.clone(), instance_id: Uuid::new_v4() }
+    }
+}
+
+impl Default for MemoryRoomBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RoomBus for MemoryRoomBus {
+    async fn publish(&self, doc_id: &str, update: &[u8]) -> anyhow::Result<()> {
+        let _ = self.tx.send((self.instance_id, doc_id.to_string(), update.to_vec()));
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> anyhow::Result<mpsc::Receiver<BusEvent>> {
+        let mut bus_rx = self.tx.subscribe();
+        let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+        let instance_id = self.instance_id;
+
+        tokio::spawn(async move {
+            loop {
+                let event = match bus_rx.recv().await {
+                    Ok((origin, _, _)) if origin == instance_id => continue,
+                    Ok((_, doc_id, update)) => BusEvent::Update { doc_id, update },
+                    Err(broadcast::error::RecvError::Lagged(_)) => BusEvent::Reconnected,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(rx)
+    }
+}
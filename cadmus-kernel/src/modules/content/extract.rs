@@ -0,0 +1,324 @@
+//! Content Extraction
+//!
+//! Reads the Tiptap/ProseMirror tree a document keeps in its `XmlFragment` and renders it as
+//! plain text, Markdown and a heading outline, so the server can search, export and scan
+//! documents without a browser.
+
+use serde::Serialize;
+use std::sync::Arc;
+use yrs::types::text::YChange;
+use yrs::types::Attrs;
+use yrs::{Any, GetString, ReadTxn, Text, Value, Xml, XmlElementRef, XmlFragment, XmlFragmentRef, XmlNode, XmlTextRef};
+
+/// Name of the fragment Tiptap's `Collaboration` extension binds the editor to.
+pub const EDITOR_FRAGMENT: &str = "default";
+
+/// A heading of the document, in reading order.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutlineEntry {
+    pub level: u8,
+    pub text: String,
+}
+
+/// The readable content of a document, derived from its Yjs state.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DocumentContent {
+    pub text: String,
+    pub markdown: String,
+    pub outline: Vec<OutlineEntry>,
+}
+
+impl DocumentContent {
+    /// Extracts the editor content of a document. Documents without an editor fragment are empty.
+    pub fn extract<T: ReadTxn>(txn: &T) -> Self {
+        match txn.get_xml_fragment(EDITOR_FRAGMENT) {
+            Some(fragment) => Self::from_fragment(txn, &fragment),
+            None => Self::default(),
+        }
+    }
+
+    pub fn from_fragment<T: ReadTxn>(txn: &T, fragment: &XmlFragmentRef) -> Self {
+        let mut renderer = Renderer { txn, text: Vec::new(), outline: Vec::new() };
+        let blocks = renderer.blocks(children(txn, fragment));
+        Self {
+            text: renderer.text.join("\n"),
+            markdown: blocks.join("\n\n"),
+            outline: renderer.outline,
+        }
+    }
+}
+
+fn children<T: ReadTxn, F: XmlFragment>(txn: &T, parent: &F) -> Vec<XmlNode> {
+    (0..parent.len(txn)).filter_map(|i| parent.get(txn, i)).collect()
+}
+
+/// Walks the ProseMirror tree once, producing Markdown blocks while collecting text lines and headings.
+struct Renderer<'a, T: ReadTxn> {
+    txn: &'a T,
+    text: Vec<String>,
+    outline: Vec<OutlineEntry>,
+}
+
+impl<T: ReadTxn> Renderer<'_, T> {
+    fn blocks(&mut self, nodes: Vec<XmlNode>) -> Vec<String> {
+        nodes.into_iter().filter_map(|node| self.block(node)).collect()
+    }
+
+    /// Renders one block node as Markdown. Returns `None` for nodes with nothing to show.
+    fn block(&mut self, node: XmlNode) -> Option<String> {
+        let el = match node {
+            XmlNode::Element(el) => el,
+            XmlNode::Text(t) => return self.paragraph(self.inline_text(&t)),
+            XmlNode::Fragment(f) => {
+                let blocks = self.blocks(children(self.txn, &f));
+                return (!blocks.is_empty()).then(|| blocks.join("\n\n"));
+            }
+        };
+
+        match el.tag().as_ref() {
+            "paragraph" => {
+                let (markdown, plain) = self.inline(&el);
+                if !plain.trim().is_empty() {
+                    self.text.push(plain);
+                }
+                (!markdown.trim().is_empty()).then_some(markdown)
+            },
+            "heading" => {
+                let level = self.attr(&el, "level").and_then(|l| l.parse::<u8>().ok()).unwrap_or(1).clamp(1, 6);
+                let (markdown, plain) = self.inline(&el);
+                self.text.push(plain.clone());
+                self.outline.push(OutlineEntry { level, text: plain.trim().to_string() });
+                Some(format!("{} {}", "#".repeat(level as usize), markdown.trim()))
+            },
+            "bulletList" | "orderedList" | "taskList" => Some(self.list(&el)),
+            "blockquote" | "callout" | "field-alert" => {
+                let inner = match el.tag().as_ref() {
+                    "blockquote" => self.blocks(children(self.txn, &el)).join("\n\n"),
+                    _ => self.inline_block(&el),
+                };
+                Some(prefix_lines(&inner, "> ", "> "))
+            },
+            "codeBlock" => {
+                let code = plain_string(self.txn, &el);
+                self.text.push(code.clone());
+                let lang = self.attr(&el, "language").unwrap_or_default();
+                Some(format!("```{}\n{}\n```", lang, code))
+            },
+            "mermaid-block" => {
+                let code = self.attr(&el, "code").unwrap_or_default();
+                Some(format!("```mermaid\n{}\n```", code))
+            },
+            "math-block" => {
+                let latex = self.attr(&el, "latex").unwrap_or_default();
+                self.text.push(latex.clone());
+                Some(format!("$$\n{}\n$$", latex))
+            },
+            "media-node" => {
+                let url = self.attr(&el, "url").unwrap_or_default();
+                let caption = self.attr(&el, "caption").unwrap_or_default();
+                if !caption.is_empty() {
+                    self.text.push(caption.clone());
+                }
+                match self.attr(&el, "type").as_deref() {
+                    Some("image") | None => Some(format!("![{}]({})", caption, url)),
+                    _ => Some(format!("[{}]({})", if caption.is_empty() { &url } else { &caption }, url)),
+                }
+            },
+            "image" => {
+                let alt = self.attr(&el, "alt").unwrap_or_default();
+                Some(format!("![{}]({})", alt, self.attr(&el, "src").unwrap_or_default()))
+            },
+            "horizontalRule" => Some("---".to_string()),
+            "table" => Some(self.table(&el)),
+            // Unknown containers still contribute their content.
+            _ => {
+                let blocks = self.blocks(children(self.txn, &el));
+                (!blocks.is_empty()).then(|| blocks.join("\n\n"))
+            }
+        }
+    }
+
+    fn paragraph(&mut self, (markdown, plain): (String, String)) -> Option<String> {
+        if plain.trim().is_empty() {
+            return None;
+        }
+        self.text.push(plain);
+        Some(markdown)
+    }
+
+    /// Renders a node holding inline content (or paragraphs) as a single Markdown block.
+    fn inline_block(&mut self, el: &XmlElementRef) -> String {
+        let nodes = children(self.txn, el);
+        if nodes.iter().all(|n| matches!(n, XmlNode::Text(_))) {
+            let (markdown, plain) = self.inline(el);
+            self.text.push(plain);
+            markdown
+        } else {
+            self.blocks(nodes).join("\n\n")
+        }
+    }
+
+    fn list(&mut self, el: &XmlElementRef) -> String {
+        let ordered = el.tag().as_ref() == "orderedList";
+        let mut number = self.attr(el, "start").and_then(|s| s.parse::<u32>().ok()).unwrap_or(1);
+
+        let mut items = Vec::new();
+        for item in children(self.txn, el) {
+            let XmlNode::Element(item) = item else { continue };
+            let marker = if ordered {
+                let m = format!("{}. ", number);
+                number += 1;
+                m
+            } else if item.tag().as_ref() == "taskItem" {
+                let checked = self.attr(&item, "checked").is_some_and(|c| c == "true");
+                format!("- [{}] ", if checked { "x" } else { " " })
+            } else {
+                "- ".to_string()
+            };
+            let body = self.blocks(children(self.txn, &item)).join("\n");
+            let indent = " ".repeat(marker.len());
+            items.push(prefix_lines(&body, &marker, &indent));
+        }
+        items.join("\n")
+    }
+
+    fn table(&mut self, el: &XmlElementRef) -> String {
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        for row in children(self.txn, el) {
+            let XmlNode::Element(row) = row else { continue };
+            let cells = children(self.txn, &row).into_iter()
+                .filter_map(|cell| match cell {
+                    XmlNode::Element(cell) => Some(self.blocks(children(self.txn, &cell)).join(" ").replace('|', "\\|")),
+                    _ => None,
+                })
+                .collect();
+            rows.push(cells);
+        }
+
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if width == 0 {
+            return String::new();
+        }
+        let line = |cells: &[String]| {
+            let padded: Vec<&str> = (0..width).map(|i| cells.get(i).map(String::as_str).unwrap_or("")).collect();
+            format!("| {} |", padded.join(" | "))
+        };
+        let mut out = vec![line(&rows[0]), format!("|{}", " --- |".repeat(width))];
+        out.extend(rows[1..].iter().map(|r| line(r)));
+        out.join("\n")
+    }
+
+    /// Renders the inline children of an element as (Markdown, plain text).
+    fn inline(&self, el: &XmlElementRef) -> (String, String) {
+        let mut markdown = String::new();
+        let mut plain = String::new();
+        for node in children(self.txn, el) {
+            match node {
+                XmlNode::Text(t) => {
+                    let (m, p) = self.inline_text(&t);
+                    markdown.push_str(&m);
+                    plain.push_str(&p);
+                },
+                XmlNode::Element(inner) if inner.tag().as_ref() == "hardBreak" => {
+                    markdown.push_str("  \n");
+                    plain.push('\n');
+                },
+                XmlNode::Element(inner) => {
+                    let (m, p) = self.inline(&inner);
+                    markdown.push_str(&m);
+                    plain.push_str(&p);
+                },
+                XmlNode::Fragment(_) => {},
+            }
+        }
+        (markdown, plain)
+    }
+
+    fn inline_text(&self, text: &XmlTextRef) -> (String, String) {
+        let mut markdown = String::new();
+        let mut plain = String::new();
+        for chunk in text.diff(self.txn, YChange::identity) {
+            let Value::Any(Any::String(s)) = chunk.insert else { continue };
+            plain.push_str(&s);
+            markdown.push_str(&apply_marks(&s, chunk.attributes.as_deref()));
+        }
+        (markdown, plain)
+    }
+
+    fn attr(&self, el: &XmlElementRef, name: &str) -> Option<String> {
+        el.get_attribute(self.txn, name)
+    }
+}
+
+/// Wraps a text chunk in the Markdown syntax of its ProseMirror marks.
+fn apply_marks(text: &str, attrs: Option<&Attrs>) -> String {
+    let Some(attrs) = attrs else {
+        return text.to_string();
+    };
+    // Markdown markers must hug the text, so surrounding whitespace stays outside them.
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+    let lead = &text[..text.len() - text.trim_start().len()];
+    let trail = &text[text.trim_end().len()..];
+
+    let mut out = trimmed.to_string();
+    if attrs.contains_key("code") {
+        out = format!("`{}`", out);
+    }
+    if attrs.contains_key("bold") {
+        out = format!("**{}**", out);
+    }
+    if attrs.contains_key("italic") {
+        out = format!("*{}*", out);
+    }
+    if attrs.contains_key("strike") {
+        out = format!("~~{}~~", out);
+    }
+    if let Some(Any::Map(link)) = attrs.get("link")
+        && let Some(Any::String(href)) = link.get("href")
+    {
+        out = format!("[{}]({})", out, href);
+    }
+    format!("{}{}{}", lead, out, trail)
+}
+
+/// The concatenated text children of an element, without the tags `get_string` renders.
+fn plain_string<T: ReadTxn>(txn: &T, el: &XmlElementRef) -> String {
+    children(txn, el).into_iter()
+        .filter_map(|n| match n {
+            XmlNode::Text(t) => Some(t.get_string(txn)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Prefixes the first line of `body` with `first` and every following line with `rest`.
+fn prefix_lines(body: &str, first: &str, rest: &str) -> String {
+    body.lines().enumerate()
+        .map(|(i, line)| {
+            let prefix = if i == 0 { first } else { rest };
+            if line.is_empty() { prefix.trim_end().to_string() } else { format!("{}{}", prefix, line) }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Extracted content cached against the room revision it was computed from.
+#[derive(Default)]
+pub(crate) struct ContentCache {
+    entry: Option<(u64, Arc<DocumentContent>)>,
+}
+
+impl ContentCache {
+    pub(crate) fn get(&self, revision: u64) -> Option<Arc<DocumentContent>> {
+        self.entry.as_ref().filter(|(r, _)| *r == revision).map(|(_, c)| c.clone())
+    }
+
+    pub(crate) fn store(&mut self, revision: u64, content: DocumentContent) -> Arc<DocumentContent> {
+        let content = Arc::new(content);
+        self.entry = Some((revision, content.clone()));
+        content
+    }
+}
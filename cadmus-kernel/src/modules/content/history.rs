@@ -0,0 +1,142 @@
+//! Version history of collaborative documents, rebuilt from the `document_updates` log.
+//!
+//! Every row of the log is a Yjs delta with its creation time and author. Consecutive rows are
+//! grouped into editing sessions, any instant can be replayed into a document state, and a past
+//! state is restored by emitting a new forward update so the log keeps every intermediate edit.
+//! The log does not reach past the last compaction: earlier instants are refused with [`BeforeCompaction`].
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+use uuid::Uuid;
+use yrs::undo::Options as UndoOptions;
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, Options, Origin, ReadTxn, StateVector, Transact, UndoManager, Update};
+use super::storage::SHEET_SNAPSHOT_PREFIX;
+
+/// Default pause between two updates after which a new editing session starts.
+pub const DEFAULT_SESSION_GAP: Duration = Duration::from_secs(300);
+
+/// Origin of the transaction replaying later changes onto a past state, so only those are undone.
+const REVERT_ORIGIN: &str = "cadmus-history-revert";
+
+/// An instant older than the last compaction, whose state the log can no longer rebuild.
+#[derive(Debug, thiserror::Error)]
+#[error("history starts at the last compaction ({compacted_at}), {requested} is older")]
+pub struct BeforeCompaction {
+    pub requested: DateTime<Utc>,
+    pub compacted_at: DateTime<Utc>,
+}
+
+/// One row of a document's update log, without its payload.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub created_at: DateTime<Utc>,
+    pub author_id: Option<Uuid>,
+    pub size: usize,
+}
+
+/// A run of consecutive updates by the same author with no pause longer than the session gap.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocumentVersion {
+    /// Restoring to this instant reproduces the document as the session left it.
+    pub at: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
+    pub author_id: Option<Uuid>,
+    pub update_count: usize,
+    pub bytes: usize,
+    /// Marks the compacted state the log starts from; nothing older can be restored.
+    pub compacted: bool,
+}
+
+/// Groups log entries (oldest first) into sessions, split by `gap` of inactivity or a change of author.
+/// `baseline` is the time of the compacted snapshot, listed as the first version when present.
+pub fn group_sessions(baseline: Option<DateTime<Utc>>, entries: &[HistoryEntry], gap: Duration) -> Vec<DocumentVersion> {
+    let gap = chrono::Duration::from_std(gap).unwrap_or(chrono::Duration::MAX);
+    let mut versions: Vec<DocumentVersion> = baseline.into_iter()
+        .map(|at| DocumentVersion { at, started_at: at, author_id: None, update_count: 0, bytes: 0, compacted: true })
+        .collect();
+
+    for entry in entries {
+        match versions.last_mut() {
+            Some(last) if !last.compacted && last.author_id == entry.author_id && entry.created_at - last.at <= gap => {
+                last.at = entry.created_at;
+                last.update_count += 1;
+                last.bytes += entry.size;
+            },
+            _ => versions.push(DocumentVersion {
+                at: entry.created_at,
+                started_at: entry.created_at,
+                author_id: entry.author_id,
+                update_count: 1,
+                bytes: entry.size,
+                compacted: false,
+            }),
+        }
+    }
+    versions
+}
+
+/// Replays updates into a fresh document that keeps deleted content, so it can be restored later.
+/// Spreadsheet snapshots found among them are skipped.
+pub fn replay<I>(updates: I) -> Doc
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    let doc = Doc::with_options(Options { skip_gc: true, ..Options::default() });
+    {
+        let mut txn = doc.transact_mut();
+        for bytes in updates {
+            if bytes.starts_with(SHEET_SNAPSHOT_PREFIX) {
+                continue;
+            }
+            if let Ok(update) = Update::decode_v1(&bytes) {
+                txn.apply_update(update);
+            }
+        }
+    }
+    doc
+}
+
+/// Encodes the full state of a document as a single update.
+pub fn encode_state(doc: &Doc) -> Vec<u8> {
+    doc.transact().encode_state_as_update_v1(&StateVector::default())
+}
+
+/// Builds the forward update that turns `current` back into `past`.
+///
+/// The changes `current` holds beyond `past` are replayed onto a copy of `past` and undone there;
+/// the resulting deletions and re-insertions are new operations, so applying the update to any
+/// replica converges it to the past content without rewriting its history. Returns `None` when
+/// `current` holds no changes beyond `past`.
+pub fn revert_update(past: &Doc, current: &Doc) -> anyhow::Result<Option<Vec<u8>>> {
+    let (changes, current_sv, roots) = {
+        let past_sv = past.transact().state_vector();
+        let txn = current.transact();
+        let roots: Vec<String> = txn.root_refs().map(|(name, _)| name.to_string()).collect();
+        (txn.encode_state_as_update_v1(&past_sv), txn.state_vector(), roots)
+    };
+
+    let scratch = replay([encode_state(past)]);
+    // Rows rebuilt from updates have untyped roots; the undo manager only needs their branches.
+    let scopes: Vec<_> = roots.iter().map(|name| scratch.get_or_insert_array(name.as_str())).collect();
+    let Some((first, rest)) = scopes.split_first() else {
+        return Ok(None);
+    };
+
+    let origin = Origin::from(REVERT_ORIGIN);
+    let mut options = UndoOptions { capture_timeout_millis: u64::MAX, ..UndoOptions::default() };
+    options.tracked_origins.insert(origin.clone());
+    let mut undo = UndoManager::with_options(&scratch, first, options);
+    for scope in rest {
+        undo.expand_scope(scope);
+    }
+
+    scratch.transact_mut_with(origin).apply_update(Update::decode_v1(&changes)?);
+    if !undo.undo().map_err(|e| anyhow::anyhow!("revert transaction unavailable: {:?}", e))? {
+        return Ok(None);
+    }
+
+    let revert = scratch.transact().encode_state_as_update_v1(&current_sv);
+    Ok(Some(revert))
+}
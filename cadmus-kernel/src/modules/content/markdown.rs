@@ -0,0 +1,372 @@
+//! Markdown Import
+//!
+//! Parses Markdown into the Tiptap/ProseMirror tree the editor keeps in its `XmlFragment`, the
+//! reverse of [`super::extract`], so notes written elsewhere open as regular documents.
+
+use super::extract::EDITOR_FRAGMENT;
+use super::history::encode_state;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use std::collections::HashMap;
+use std::sync::Arc;
+use yrs::types::Attrs;
+use yrs::{Any, Doc, Text, Transact, TransactionMut, Xml, XmlElementPrelim, XmlFragment, XmlTextPrelim};
+
+/// Converts a Markdown body into a full Yjs state holding it as editor content.
+pub fn markdown_to_state(markdown: &str) -> Vec<u8> {
+    let doc = Doc::new();
+    let fragment = doc.get_or_insert_xml_fragment(EDITOR_FRAGMENT);
+    write_markdown(&mut doc.transact_mut(), &fragment, markdown);
+    encode_state(&doc)
+}
+
+/// Appends the blocks of `markdown` to `parent`. A leading YAML front matter block is skipped.
+pub fn write_markdown<F: XmlFragment>(txn: &mut TransactionMut, parent: &F, markdown: &str) {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_MATH
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+    let mut builder = Builder::default();
+    for event in Parser::new_ext(markdown, options) {
+        builder.event(event);
+    }
+    write_nodes(txn, parent, builder.finish());
+}
+
+/// ProseMirror marks of a run of text.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Marks {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    code: bool,
+    link: Option<String>,
+}
+
+impl Marks {
+    /// Formatting attributes as the Tiptap binding stores them: one entry per mark, holding
+    /// the mark's attributes.
+    fn attrs(&self) -> Attrs {
+        let mut attrs = Attrs::new();
+        for (name, set) in [("bold", self.bold), ("italic", self.italic), ("strike", self.strike), ("code", self.code)] {
+            if set {
+                attrs.insert(Arc::from(name), Any::Map(Arc::new(HashMap::new())));
+            }
+        }
+        if let Some(href) = &self.link {
+            let link = HashMap::from([("href".to_string(), Any::from(href.as_str()))]);
+            attrs.insert(Arc::from("link"), Any::Map(Arc::new(link)));
+        }
+        attrs
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Element { tag: &'static str, attrs: Vec<(&'static str, String)>, children: Vec<Node> },
+    Text(Vec<(String, Marks)>),
+}
+
+/// Appends a run of text, extending the preceding text node when there is one.
+fn push_text(children: &mut Vec<Node>, text: String, marks: Marks) {
+    if text.is_empty() {
+        return;
+    }
+    match children.last_mut() {
+        Some(Node::Text(chunks)) => match chunks.last_mut() {
+            Some((last, last_marks)) if *last_marks == marks => last.push_str(&text),
+            _ => chunks.push((text, marks)),
+        },
+        _ => children.push(Node::Text(vec![(text, marks)])),
+    }
+}
+
+/// An element being built. `implicit` paragraphs were opened to hold inline content that
+/// Markdown places directly in a list item, quote or cell.
+struct Frame {
+    tag: &'static str,
+    attrs: Vec<(&'static str, String)>,
+    children: Vec<Node>,
+    implicit: bool,
+}
+
+impl Frame {
+    fn new(tag: &'static str) -> Self {
+        Self { tag, attrs: Vec::new(), children: Vec::new(), implicit: false }
+    }
+}
+
+/// Block elements that hold paragraphs rather than text. The empty tag stands for the
+/// document itself and for Markdown blocks without an editor counterpart, whose content is
+/// kept in their parent.
+const CONTAINERS: &[&str] = &["", "listItem", "taskItem", "blockquote", "tableCell", "tableHeader"];
+
+/// Blocks that Markdown writes inline; alone in a paragraph they replace it.
+const EMBEDDED_BLOCKS: &[&str] = &["image", "math-block"];
+
+/// Folds the pulldown-cmark event stream into a node tree.
+struct Builder {
+    stack: Vec<Frame>,
+    marks: Marks,
+    /// Source and alt text of the image being read.
+    image: Option<(String, String)>,
+    in_table_head: bool,
+    in_metadata: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self { stack: vec![Frame::new("")], marks: Marks::default(), image: None, in_table_head: false, in_metadata: false }
+    }
+}
+
+impl Builder {
+    fn event(&mut self, event: Event) {
+        if self.in_metadata {
+            self.in_metadata = !matches!(event, Event::End(TagEnd::MetadataBlock(_)));
+            return;
+        }
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(text.into_string()),
+            Event::Code(code) => {
+                let marks = Marks { code: true, ..self.marks.clone() };
+                self.inline(text_node(code.into_string(), marks));
+            },
+            Event::InlineMath(latex) => self.text(format!("${}$", latex)),
+            Event::DisplayMath(latex) => {
+                self.inline(Node::Element { tag: "math-block", attrs: vec![("latex", latex.trim().to_string())], children: Vec::new() });
+            },
+            Event::Html(html) | Event::InlineHtml(html) => self.text(html.into_string()),
+            Event::FootnoteReference(label) => self.text(format!("[^{}]", label)),
+            Event::SoftBreak => self.text(" ".to_string()),
+            Event::HardBreak => {
+                self.inline(Node::Element { tag: "hardBreak", attrs: Vec::new(), children: Vec::new() });
+            },
+            Event::Rule => {
+                self.open("horizontalRule");
+                self.close();
+            },
+            Event::TaskListMarker(checked) => self.mark_task(checked),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::HtmlBlock => self.open("paragraph"),
+            Tag::Heading { level, .. } => {
+                self.open("heading");
+                self.attr("level", (level as u8).to_string());
+            },
+            Tag::BlockQuote(_) => self.open("blockquote"),
+            Tag::CodeBlock(kind) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or_default().to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.open("codeBlock");
+                if !language.is_empty() {
+                    self.attr("language", language);
+                }
+            },
+            Tag::List(start) => {
+                self.open(if start.is_some() { "orderedList" } else { "bulletList" });
+                if let Some(start) = start.filter(|s| *s != 1) {
+                    self.attr("start", start.to_string());
+                }
+            },
+            Tag::Item => self.open("listItem"),
+            Tag::Table(_) => self.open("table"),
+            Tag::TableHead => {
+                self.in_table_head = true;
+                self.open("tableRow");
+            },
+            Tag::TableRow => self.open("tableRow"),
+            Tag::TableCell => self.open(if self.in_table_head { "tableHeader" } else { "tableCell" }),
+            Tag::Emphasis => self.marks.italic = true,
+            Tag::Strong => self.marks.bold = true,
+            Tag::Strikethrough => self.marks.strike = true,
+            Tag::Link { dest_url, .. } => self.marks.link = Some(dest_url.into_string()),
+            Tag::Image { dest_url, .. } => self.image = Some((dest_url.into_string(), String::new())),
+            Tag::MetadataBlock(_) => self.in_metadata = true,
+            Tag::FootnoteDefinition(_) | Tag::DefinitionList | Tag::DefinitionListTitle | Tag::DefinitionListDefinition => self.open(""),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Emphasis => self.marks.italic = false,
+            TagEnd::Strong => self.marks.bold = false,
+            TagEnd::Strikethrough => self.marks.strike = false,
+            TagEnd::Link => self.marks.link = None,
+            TagEnd::Image => {
+                if let Some((src, alt)) = self.image.take() {
+                    self.inline(Node::Element { tag: "image", attrs: vec![("src", src), ("alt", alt)], children: Vec::new() });
+                }
+            },
+            TagEnd::MetadataBlock(_) => {},
+            TagEnd::TableHead => {
+                self.in_table_head = false;
+                self.close_implicit();
+                self.close();
+            },
+            _ => {
+                self.close_implicit();
+                self.close();
+            },
+        }
+    }
+
+    fn text(&mut self, text: String) {
+        match &mut self.image {
+            Some((_, alt)) => alt.push_str(&text),
+            None => self.inline(text_node(text, self.marks.clone())),
+        }
+    }
+
+    /// Adds inline content to the open block, opening a paragraph when the block only holds
+    /// other blocks.
+    fn inline(&mut self, node: Node) {
+        if CONTAINERS.contains(&self.top().tag) {
+            self.stack.push(Frame { implicit: true, ..Frame::new("paragraph") });
+        }
+        let children = &mut self.top().children;
+        match node {
+            Node::Text(chunks) => chunks.into_iter().for_each(|(text, marks)| push_text(children, text, marks)),
+            element => children.push(element),
+        }
+    }
+
+    fn open(&mut self, tag: &'static str) {
+        self.close_implicit();
+        self.stack.push(Frame::new(tag));
+    }
+
+    fn attr(&mut self, name: &'static str, value: String) {
+        self.top().attrs.push((name, value));
+    }
+
+    fn top(&mut self) -> &mut Frame {
+        self.stack.last_mut().expect("the document frame is never closed")
+    }
+
+    fn close_implicit(&mut self) {
+        while self.stack.last().is_some_and(|f| f.implicit) {
+            self.close();
+        }
+    }
+
+    /// Closes the innermost block and appends what it became to its parent.
+    fn close(&mut self) {
+        if self.stack.len() == 1 {
+            return;
+        }
+        let frame = self.stack.pop().expect("checked above");
+        let nodes = finish(frame);
+        self.top().children.extend(nodes);
+    }
+
+    /// Turns the enclosing list item into a task item, and its list into a task list.
+    fn mark_task(&mut self, checked: bool) {
+        let Some(item) = self.stack.iter().rposition(|f| f.tag == "listItem") else { return };
+        self.stack[item].tag = "taskItem";
+        self.stack[item].attrs.push(("checked", checked.to_string()));
+        if item > 0 {
+            self.stack[item - 1].tag = "taskList";
+        }
+    }
+
+    fn finish(mut self) -> Vec<Node> {
+        while self.stack.len() > 1 {
+            self.close();
+        }
+        self.stack.pop().map(|root| root.children).unwrap_or_default()
+    }
+}
+
+fn text_node(text: String, marks: Marks) -> Node {
+    Node::Text(vec![(text, marks)])
+}
+
+fn finish(frame: Frame) -> Vec<Node> {
+    let Frame { tag, attrs, children, .. } = frame;
+    match tag {
+        "" => children,
+        "paragraph" => finish_paragraph(children),
+        "codeBlock" => {
+            let mut code: String = children.into_iter()
+                .flat_map(|n| match n {
+                    Node::Text(chunks) => chunks.into_iter().map(|(t, _)| t).collect(),
+                    Node::Element { .. } => Vec::new(),
+                })
+                .collect();
+            if code.ends_with('\n') {
+                code.pop();
+            }
+            if attrs.iter().any(|(name, lang)| *name == "language" && lang == "mermaid") {
+                return vec![Node::Element { tag: "mermaid-block", attrs: vec![("code", code)], children: Vec::new() }];
+            }
+            vec![Node::Element { tag, attrs, children: vec![text_node(code, Marks::default())] }]
+        },
+        _ => vec![Node::Element { tag, attrs, children }],
+    }
+}
+
+/// A paragraph holding only an image or display math becomes that block; elsewhere those are
+/// kept as text, an image as a link to its source.
+fn finish_paragraph(children: Vec<Node>) -> Vec<Node> {
+    let embedded = |n: &Node| matches!(n, Node::Element { tag, .. } if EMBEDDED_BLOCKS.contains(tag));
+    let blank = |n: &Node| matches!(n, Node::Text(chunks) if chunks.iter().all(|(t, _)| t.trim().is_empty()));
+    if children.iter().any(embedded) && children.iter().all(|n| embedded(n) || blank(n)) {
+        return children.into_iter().filter(embedded).collect();
+    }
+
+    let mut inline = Vec::new();
+    for node in children {
+        match node {
+            Node::Element { tag: "image", attrs, .. } => {
+                let src = attr(&attrs, "src");
+                let alt = attr(&attrs, "alt");
+                let text = if alt.is_empty() { src.clone() } else { alt };
+                push_text(&mut inline, text, Marks { link: Some(src), ..Marks::default() });
+            },
+            Node::Element { tag: "math-block", attrs, .. } => {
+                push_text(&mut inline, format!("$${}$$", attr(&attrs, "latex")), Marks::default());
+            },
+            Node::Text(chunks) => chunks.into_iter().for_each(|(text, marks)| push_text(&mut inline, text, marks)),
+            element => inline.push(element),
+        }
+    }
+    if inline.is_empty() {
+        return Vec::new();
+    }
+    vec![Node::Element { tag: "paragraph", attrs: Vec::new(), children: inline }]
+}
+
+fn attr(attrs: &[(&'static str, String)], name: &str) -> String {
+    attrs.iter().find(|(n, _)| *n == name).map(|(_, v)| v.clone()).unwrap_or_default()
+}
+
+fn write_nodes<F: XmlFragment>(txn: &mut TransactionMut, parent: &F, nodes: Vec<Node>) {
+    for node in nodes {
+        match node {
+            Node::Element { tag, attrs, children } => {
+                let el = parent.push_back(txn, XmlElementPrelim::empty(tag));
+                for (name, value) in attrs {
+                    el.insert_attribute(txn, name, value);
+                }
+                write_nodes(txn, &el, children);
+            },
+            Node::Text(chunks) => {
+                let text = parent.push_back(txn, XmlTextPrelim::new(""));
+                for (chunk, marks) in chunks {
+                    // Always pass the attributes, so a plain run does not inherit the marks
+                    // of the run before it.
+                    let index = text.len(txn);
+                    text.insert_with_attributes(txn, index, &chunk, marks.attrs());
+                }
+            },
+        }
+    }
+}
@@ -1,3 +1,8 @@
+pub mod bus;
+pub mod extract;
+pub mod history;
+pub mod markdown;
 pub mod socket;
 pub mod storage;
 pub mod workspace;
+pub mod write_behind;
@@ -1,26 +1,434 @@
-use yrs::{Doc, Transact, Update};
+use yrs::{Doc, ReadTxn, StateVector, Transact, Transaction, Update};
+use yrs::block::ClientID;
 use yrs::updates::decoder::Decode;
-use std::collections::HashMap;
-use tokio::sync::{RwLock, broadcast};
-use std::sync::Arc;
+use yrs::updates::encoder::Encode;
+use y_sync::awareness::{Awareness, AwarenessUpdate};
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, RwLock, broadcast, mpsc, oneshot};
+use std::sync::{Arc, Mutex, Weak};
+use serde::Serialize;
+use uuid::Uuid;
+use super::bus::{BusEvent, RoomBus};
+use super::extract::{ContentCache, DocumentContent};
+use super::history;
 use super::storage::ContentStorage;
+use super::write_behind::{self, WriteBehind};
+
+/// Default time an unsubscribed room stays resident before it is unloaded.
+const DEFAULT_IDLE_GRACE_SECS: u64 = 300;
+
+/// Default number of messages a room buffers per subscriber before a slow one starts lagging.
+const DEFAULT_CHANNEL_CAPACITY: usize = 100;
+
+/// Default quiet period after the last update before a room's extracted content is refreshed.
+const DEFAULT_EXTRACT_DEBOUNCE_MS: u64 = 2000;
+
+/// Pause between attempts to open a write transaction while readers hold the document.
+const APPLY_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Longest a read waits for queued updates to release the document before giving up.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A read refused because an update holds the document's write transaction; retrying succeeds.
+#[derive(Debug, thiserror::Error)]
+#[error("document is busy: {0}")]
+pub struct RoomBusy(String);
+
+/// Updates queued for a room's apply task, with where to report the change they made.
+struct ApplyRequest {
+    updates: Vec<Vec<u8>>,
+    done: oneshot::Sender<Vec<u8>>,
+}
 
 pub struct Room {
     pub doc: Doc,
     pub tx: broadcast::Sender<Vec<u8>>,
+    /// Relays encoded `AwarenessUpdate`s (cursors, presence) between the room's sockets.
+    pub awareness_tx: broadcast::Sender<Vec<u8>>,
+    awareness: Mutex<Awareness>,
+    subscribers: AtomicUsize,
+    last_active: Mutex<Instant>,
+    lag_events: AtomicU64,
+    lagged_messages: AtomicU64,
+    /// Bumped by every applied update; extracted content is cached against it.
+    revision: AtomicU64,
+    content: Mutex<ContentCache>,
+    extract_pending: AtomicBool,
+    /// Feeds the task that applies updates one at a time, in arrival order.
+    apply_tx: mpsc::UnboundedSender<ApplyRequest>,
+    /// Set once the document is discarded; its sockets are told through `close_notify`.
+    closed: AtomicBool,
+    close_notify: Notify,
+}
+
+/// Client IDs whose presence appeared or disappeared after applying an awareness update.
+#[derive(Debug, Default)]
+pub struct PresenceChange {
+    pub added: Vec<ClientID>,
+    pub removed: Vec<ClientID>,
+}
+
+impl Room {
+    /// Creates a room and starts its apply task, which stops once the room is dropped.
+    fn new(doc: Doc, capacity: usize) -> Arc<Self> {
+        let (tx, _) = broadcast::channel(capacity);
+        let (awareness_tx, _) = broadcast::channel(capacity);
+        let (apply_tx, apply_rx) = mpsc::unbounded_channel();
+        let room = Arc::new(Self {
+            awareness: Mutex::new(Awareness::new(doc.clone())),
+            doc,
+            tx,
+            awareness_tx,
+            subscribers: AtomicUsize::new(0),
+            last_active: Mutex::new(Instant::now()),
+            lag_events: AtomicU64::new(0),
+            lagged_messages: AtomicU64::new(0),
+            revision: AtomicU64::new(0),
+            content: Mutex::new(ContentCache::default()),
+            extract_pending: AtomicBool::new(false),
+            apply_tx,
+            closed: AtomicBool::new(false),
+            close_notify: Notify::new(),
+        });
+        tokio::spawn(Self::run_apply(Arc::downgrade(&room), apply_rx));
+        room
+    }
+
+    /// Applies queued updates in order. A write transaction cannot open while a socket or the
+    /// extractor reads the document, so the task waits for it instead of dropping the update.
+    async fn run_apply(room: Weak<Room>, mut rx: mpsc::UnboundedReceiver<ApplyRequest>) {
+        while let Some(request) = rx.recv().await {
+            let Some(room) = room.upgrade() else {
+                break;
+            };
+            let mut waited = 0u32;
+            let diff = loop {
+                if let Ok(mut txn) = room.doc.try_transact_mut() {
+                    let before = txn.state_vector();
+                    for bytes in &request.updates {
+                        if let Ok(update) = Update::decode_v1(bytes) {
+                            txn.apply_update(update);
+                        }
+                    }
+                    break txn.encode_state_as_update_v1(&before);
+                }
+                waited += 1;
+                if waited == 1000 {
+                    tracing::warn!("Content: Update waiting on a busy document for over a second");
+                }
+                tokio::time::sleep(APPLY_RETRY_INTERVAL).await;
+            };
+            if diff != [0, 0] {
+                room.revision.fetch_add(1, Ordering::SeqCst);
+            }
+            let _ = request.done.send(diff);
+        }
+    }
+
+    /// Queues encoded updates behind those already pending and waits until they are applied.
+    /// Undecodable ones are skipped. Returns the change they made to the document, empty
+    /// (`[0, 0]`) if it already had them.
+    pub async fn apply(&self, updates: Vec<Vec<u8>>) -> anyhow::Result<Vec<u8>> {
+        let (done, applied) = oneshot::channel();
+        self.apply_tx.send(ApplyRequest { updates, done })
+            .map_err(|_| anyhow::anyhow!("room apply task has stopped"))?;
+        Ok(applied.await?)
+    }
+
+    /// Number of updates applied since the room was loaded.
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+
+    /// Text, Markdown and outline of the document, extracted again only if it changed since.
+    /// Fails with [`RoomBusy`] if updates hold the document past `READ_TIMEOUT`.
+    pub async fn content(&self) -> Result<Arc<DocumentContent>, RoomBusy> {
+        let revision = self.revision();
+        if let Some(content) = self.content.lock().unwrap().get(revision) {
+            return Ok(content);
+        }
+
+        let content = self.read(|txn| DocumentContent::extract(txn)).await?;
+        Ok(self.content.lock().unwrap().store(revision, content))
+    }
+
+    /// Reads the document, waiting while the apply task holds it for writing.
+    /// Fails with [`RoomBusy`] if it is still held after `READ_TIMEOUT`.
+    async fn read<T>(&self, read: impl FnOnce(&Transaction) -> T) -> Result<T, RoomBusy> {
+        let deadline = Instant::now() + READ_TIMEOUT;
+        loop {
+            let error = match self.doc.try_transact() {
+                Ok(txn) => return Ok(read(&txn)),
+                Err(e) => format!("{:?}", e),
+            };
+            if Instant::now() >= deadline {
+                return Err(RoomBusy(error));
+            }
+            tokio::time::sleep(APPLY_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// State vector of the document, announced to a client joining the room.
+    pub async fn state_vector(&self) -> Result<StateVector, RoomBusy> {
+        self.read(|txn| txn.state_vector()).await
+    }
+
+    /// Encodes what a client holding `since` is missing, together with the state vector it reaches.
+    /// Used to resynchronize a subscriber that fell behind the broadcast channel.
+    pub async fn diff_since(&self, since: &StateVector) -> Result<(Vec<u8>, StateVector), RoomBusy> {
+        self.read(|txn| (txn.encode_state_as_update_v1(since), txn.state_vector())).await
+    }
+
+    /// True once the room's document was trashed or purged; its sockets should leave.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Completes once the room is closed.
+    pub async fn closed(&self) {
+        loop {
+            let notified = self.close_notify.notified();
+            if self.is_closed() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.close_notify.notify_waiters();
+    }
+
+    /// Records that a subscriber fell behind and missed `skipped` broadcast messages.
+    pub fn record_lag(&self, skipped: u64) {
+        self.lag_events.fetch_add(1, Ordering::Relaxed);
+        self.lagged_messages.fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    /// Number of times a subscriber of this room lagged behind the broadcast channel.
+    pub fn lag_events(&self) -> u64 {
+        self.lag_events.load(Ordering::Relaxed)
+    }
+
+    /// Applies an awareness update sent by a client and relays it to every socket in the room.
+    pub fn apply_awareness(&self, update: AwarenessUpdate) -> anyhow::Result<PresenceChange> {
+        let encoded = update.encode_v1();
+        let change = {
+            let mut awareness = self.awareness.lock().unwrap();
+            let before: HashSet<ClientID> = awareness.clients().keys().copied().collect();
+            awareness.apply_update(update)?;
+            let after: HashSet<ClientID> = awareness.clients().keys().copied().collect();
+            PresenceChange {
+                added: after.difference(&before).copied().collect(),
+                removed: before.difference(&after).copied().collect(),
+            }
+        };
+        let _ = self.awareness_tx.send(encoded);
+        Ok(change)
+    }
+
+    /// Current presence of every client in the room, or `None` when nobody has announced any.
+    pub fn awareness_update(&self) -> Option<AwarenessUpdate> {
+        let awareness = self.awareness.lock().unwrap();
+        if awareness.clients().is_empty() {
+            return None;
+        }
+        awareness.update().ok()
+    }
+
+    /// Drops the presence of the given clients and tells the rest of the room they are gone.
+    pub fn remove_awareness(&self, clients: impl IntoIterator<Item = ClientID>) {
+        let encoded = {
+            let mut awareness = self.awareness.lock().unwrap();
+            let removed: Vec<ClientID> = clients.into_iter()
+                .filter(|id| awareness.clients().contains_key(id))
+                .collect();
+            if removed.is_empty() {
+                return;
+            }
+            for id in &removed {
+                awareness.remove_state(*id);
+            }
+            match awareness.update_with_clients(removed) {
+                Ok(update) => update.encode_v1(),
+                Err(_) => return,
+            }
+        };
+        let _ = self.awareness_tx.send(encoded);
+    }
+
+    /// Number of clients currently announcing presence in the room.
+    pub fn presence_count(&self) -> usize {
+        self.awareness.lock().unwrap().clients().len()
+    }
+
+    /// Number of sockets currently joined to this room.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.load(Ordering::SeqCst)
+    }
+
+    /// Time elapsed since the room was last joined, left or updated.
+    pub fn idle_for(&self) -> Duration {
+        self.last_active.lock().unwrap().elapsed()
+    }
+
+    fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    /// Rough resident size of the document, measured as the length of its encoded state.
+    /// Returns `None` while a write transaction holds the document.
+    pub fn estimated_memory(&self) -> Option<usize> {
+        self.doc.try_transact().ok()
+            .map(|txn| txn.encode_state_as_update_v1(&StateVector::default()).len())
+    }
+}
+
+/// A subscription to a [`Room`]. The room counts as in use until the handle is dropped.
+pub struct RoomHandle {
+    room: Arc<Room>,
+}
+
+impl Deref for RoomHandle {
+    type Target = Room;
+
+    fn deref(&self) -> &Room {
+        &self.room
+    }
+}
+
+impl Drop for RoomHandle {
+    fn drop(&mut self) {
+        self.room.subscribers.fetch_sub(1, Ordering::SeqCst);
+        self.room.touch();
+    }
+}
+
+/// Point-in-time view of a resident room, exposed for operators.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomStats {
+    pub id: String,
+    pub subscribers: usize,
+    pub presence: usize,
+    pub idle_secs: u64,
+    pub estimated_bytes: Option<usize>,
+    pub lag_events: u64,
+    pub lagged_messages: u64,
+    /// Size of the updates applied to the room but not yet written to storage.
+    pub unpersisted_bytes: usize,
+    /// Why the room's updates could not be written, while the writes are being retried.
+    pub persistence_error: Option<String>,
+}
+
+/// Point-in-time view of every resident room.
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistryStats {
+    pub room_count: usize,
+    pub total_subscribers: usize,
+    pub estimated_bytes: usize,
+    pub total_lag_events: u64,
+    pub channel_capacity: usize,
+    pub rooms: Vec<RoomStats>,
 }
 
 pub struct ContentRegistry {
     rooms: RwLock<HashMap<String, Arc<Room>>>,
     storage: Option<Arc<ContentStorage>>,
+    idle_grace: Duration,
+    channel_capacity: usize,
+    extract_debounce: Duration,
+    bus: Option<Arc<dyn RoomBus>>,
+    write_behind: Option<Arc<WriteBehind>>,
+    flush_interval: Duration,
+    max_pending_bytes: usize,
 }
 
 impl ContentRegistry {
+    /// Creates a registry. The idle grace period is read from `CONTENT_ROOM_IDLE_SECS`, the
+    /// per-room broadcast capacity from `CONTENT_ROOM_CHANNEL_CAPACITY`, the quiet period
+    /// before content is re-extracted from `CONTENT_EXTRACT_DEBOUNCE_MS`, and the write-behind
+    /// thresholds from `CONTENT_WRITE_BEHIND_MS` and `CONTENT_WRITE_BEHIND_BYTES`.
     pub fn new(storage: Option<Arc<ContentStorage>>) -> Self {
-        Self {
+        let idle_secs = std::env::var("CONTENT_ROOM_IDLE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_IDLE_GRACE_SECS);
+        let channel_capacity = std::env::var("CONTENT_ROOM_CHANNEL_CAPACITY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CHANNEL_CAPACITY);
+        let extract_debounce_ms = std::env::var("CONTENT_EXTRACT_DEBOUNCE_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_EXTRACT_DEBOUNCE_MS);
+        let flush_interval = std::env::var("CONTENT_WRITE_BEHIND_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(write_behind::DEFAULT_FLUSH_INTERVAL);
+        let max_pending_bytes = std::env::var("CONTENT_WRITE_BEHIND_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(write_behind::DEFAULT_MAX_PENDING_BYTES);
+
+        let mut registry = Self {
             rooms: RwLock::new(HashMap::new()),
             storage,
-        }
+            idle_grace: Duration::from_secs(idle_secs),
+            channel_capacity: channel_capacity.max(1),
+            extract_debounce: Duration::from_millis(extract_debounce_ms),
+            bus: None,
+            write_behind: None,
+            flush_interval,
+            max_pending_bytes,
+        };
+        registry.write_behind = registry.build_write_behind();
+        registry
+    }
+
+    /// Buffers writes to storage, if any, relaying them over the bus once written.
+    fn build_write_behind(&self) -> Option<Arc<WriteBehind>> {
+        self.storage.as_ref()
+            .map(|storage| WriteBehind::with_relay(storage.clone(), self.flush_interval, self.max_pending_bytes, self.bus.clone()))
+    }
+
+    /// Overrides how long updates are buffered and how large a document's buffer may grow
+    /// before they are written to storage.
+    pub fn with_write_behind(mut self, interval: Duration, max_pending_bytes: usize) -> Self {
+        self.flush_interval = interval;
+        self.max_pending_bytes = max_pending_bytes;
+        self.write_behind = self.build_write_behind();
+        self
+    }
+
+    /// Relays applied updates to peer instances through `bus`, once they are written to storage.
+    /// Start `run_bus` to receive theirs.
+    pub fn with_bus(mut self, bus: Arc<dyn RoomBus>) -> Self {
+        self.bus = Some(bus);
+        self.write_behind = self.build_write_behind();
+        self
+    }
+
+    /// Overrides how long a room without subscribers stays resident.
+    pub fn with_idle_grace(mut self, grace: Duration) -> Self {
+        self.idle_grace = grace;
+        self
+    }
+
+    /// Overrides the quiet period after which a changed room's content is re-extracted.
+    pub fn with_extract_debounce(mut self, debounce: Duration) -> Self {
+        self.extract_debounce = debounce;
+        self
+    }
+
+    /// Overrides how many messages a room buffers per subscriber. Applies to rooms loaded afterwards.
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = capacity.max(1);
+        self
     }
 
     pub fn get_storage(&self) -> Option<Arc<ContentStorage>> {
@@ -29,13 +437,29 @@ impl ContentRegistry {
 
     pub async fn get_room(&self, id: &str) -> Arc<Room> {
         let mut rooms = self.rooms.write().await;
+        let room = self.load_room(&mut rooms, id).await;
+        room.touch();
+        room
+    }
+
+    /// Joins a room, loading it if needed. The room is kept resident while the handle lives.
+    pub async fn join(&self, id: &str) -> RoomHandle {
+        let mut rooms = self.rooms.write().await;
+        let room = self.load_room(&mut rooms, id).await;
+        // Incremented under the write lock so `evict_idle` never unloads a room being joined.
+        room.subscribers.fetch_add(1, Ordering::SeqCst);
+        room.touch();
+        RoomHandle { room }
+    }
+
+    async fn load_room(&self, rooms: &mut HashMap<String, Arc<Room>>, id: &str) -> Arc<Room> {
         if let Some(room) = rooms.get(id) {
             return room.clone();
         }
 
         tracing::info!("Content: Initializing room '{}'...", id);
         let doc = Doc::new();
-        
+
         if let Some(storage) = &self.storage {
             let updates = storage.load_updates(id).await.unwrap_or_default();
             if !updates.is_empty() {
@@ -49,39 +473,290 @@ impl ContentRegistry {
             }
         }
 
-        let (tx, _) = broadcast::channel(100);
-        let room = Arc::new(Room { doc, tx });
+        let room = Room::new(doc, self.channel_capacity);
         rooms.insert(id.to_string(), room.clone());
         room
     }
 
-    pub async fn process_update(&self, id: &str, update: Vec<u8>) {
+    pub async fn process_update(&self, id: &str, update: Vec<u8>) -> anyhow::Result<()> {
+        self.process_update_as(id, update, None).await
+    }
+
+    /// Applies, broadcasts and persists an update, recording its author in the version history.
+    ///
+    /// Updates that do not decode are rejected before reaching anyone. Valid ones are applied
+    /// through the room's queue, so none is lost to a concurrent reader and all keep their order.
+    pub async fn process_update_as(&self, id: &str, update: Vec<u8>, author_id: Option<Uuid>) -> anyhow::Result<()> {
         if update.is_empty() || update == vec![0, 0] {
-            return;
+            return Ok(());
         }
+        Update::decode_v1(&update)
+            .map_err(|e| anyhow::anyhow!("invalid update for '{}': {}", id, e))?;
 
         let room = self.get_room(id).await;
-        
-        // 1. Apply to memory with Fail-Safe Concurrency
-        if let Ok(u) = Update::decode_v1(&update) {
-            match room.doc.try_transact_mut() {
-                Ok(mut txn) => {
-                    txn.apply_update(u);
+
+        // 1. Apply to memory, in arrival order
+        let diff = room.apply(vec![update.clone()]).await?;
+        if diff != [0, 0] {
+            self.schedule_extraction(&room);
+        }
+
+        // 2. Broadcast to other participants
+        let _ = room.tx.send(update.clone());
+
+        // 3. Queue for persistence; the write-behind relays it to peer instances once written
+        let Some(write_behind) = &self.write_behind else {
+            // 4. Without storage there is nothing for peers to load, so relay right away
+            if let Some(bus) = &self.bus
+                && let Err(e) = bus.publish(id, &update).await
+            {
+                tracing::warn!("Content: Failed to relay update for '{}': {}", id, e);
+            }
+            return Ok(());
+        };
+        // Queued under the room lock, so a document being discarded never gets an update back.
+        let _rooms = self.rooms.read().await;
+        if room.is_closed() {
+            return Err(anyhow::anyhow!("document '{}' was deleted", id));
+        }
+        write_behind.enqueue(id, update, author_id)
+    }
+
+    /// Writes every buffered update to storage now. Call before shutting down.
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        match &self.write_behind {
+            Some(write_behind) => write_behind.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the buffered updates of one document to storage now, for work that reads its
+    /// content from storage rather than from the room.
+    pub async fn flush_doc(&self, id: &str) -> anyhow::Result<()> {
+        match &self.write_behind {
+            Some(write_behind) => write_behind.flush_doc(id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Unloads the room of a trashed or purged document without writing its buffered updates,
+    /// and closes it so its sockets leave. Later updates to the same room are refused.
+    pub async fn discard(&self, id: &str) {
+        if let Some(room) = self.rooms.write().await.remove(id) {
+            room.close();
+            tracing::info!("Content: Discarded room '{}'", id);
+        }
+        if let Some(write_behind) = &self.write_behind {
+            write_behind.discard(id).await;
+        }
+    }
+
+    /// Applies an update relayed by a peer instance, which already persisted it.
+    ///
+    /// Rooms that are not resident are skipped: they read the update from storage when loaded.
+    pub async fn apply_remote(&self, id: &str, update: Vec<u8>) {
+        let Some(room) = self.rooms.read().await.get(id).cloned() else {
+            return;
+        };
+        match room.apply(vec![update.clone()]).await {
+            Ok(diff) if diff != [0, 0] => {
+                self.schedule_extraction(&room);
+                let _ = room.tx.send(update);
+            },
+            Ok(_) => {},
+            Err(e) => tracing::warn!("Content: Failed to apply relayed update for '{}': {}", id, e),
+        }
+    }
+
+    /// Re-applies the stored updates of a resident room. Updates are idempotent, so only the
+    /// ones the room missed change it; connected clients receive the resulting diff.
+    async fn resync(&self, id: &str, room: &Arc<Room>) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let updates = match storage.load_updates(id).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                tracing::error!("Content: Failed to reload '{}' from storage: {}", id, e);
+                return;
+            }
+        };
+
+        match room.apply(updates).await {
+            Ok(diff) if diff != [0, 0] => {
+                self.schedule_extraction(room);
+                let _ = room.tx.send(diff);
+            },
+            Ok(_) => {},
+            Err(e) => tracing::warn!("Content: Failed to resynchronize '{}': {}", id, e),
+        }
+    }
+
+    /// Applies the updates peer instances relay over the bus, for the lifetime of the process.
+    pub async fn run_bus(self: Arc<Self>) -> anyhow::Result<()> {
+        let Some(bus) = self.bus.clone() else {
+            return Ok(());
+        };
+        let mut events = bus.subscribe().await?;
+        while let Some(event) = events.recv().await {
+            match event {
+                BusEvent::Update { doc_id, update } => self.apply_remote(&doc_id, update).await,
+                BusEvent::Reconnected => {
+                    let rooms: Vec<(String, Arc<Room>)> = self.rooms.read().await.iter()
+                        .map(|(id, room)| (id.clone(), room.clone()))
+                        .collect();
+                    tracing::info!("Content: Room bus reconnected, resynchronizing {} rooms", rooms.len());
+                    for (id, room) in rooms {
+                        self.resync(&id, &room).await;
+                    }
                 },
-                Err(_) => {
-                    tracing::warn!("Content: Update for '{}' skipped due to lock contention.", id);
-                    // We drop the update instead of panicking. 
-                    // Yjs state vector sync will eventually catch up.
+            }
+        }
+        Ok(())
+    }
+
+    /// Refreshes the extracted content of a room once updates have settled for the debounce period.
+    fn schedule_extraction(&self, room: &Arc<Room>) {
+        if room.extract_pending.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let room = room.clone();
+        let debounce = self.extract_debounce;
+        tokio::spawn(async move {
+            loop {
+                let seen = room.revision();
+                tokio::time::sleep(debounce).await;
+                if room.revision() != seen {
+                    continue;
+                }
+                room.extract_pending.store(false, Ordering::SeqCst);
+                if let Err(e) = room.content().await {
+                    tracing::debug!("Content: Deferring extraction: {}", e);
+                    if room.extract_pending.swap(true, Ordering::SeqCst) {
+                        break;
+                    }
+                    continue;
                 }
+                break;
             }
+        });
+    }
+
+    /// Extracted text, Markdown and outline of a document, loading its room if needed.
+    pub async fn content(&self, id: &str) -> anyhow::Result<Arc<DocumentContent>> {
+        Ok(self.get_room(id).await.content().await?)
+    }
+
+    /// Brings a document back to its state as of `at`.
+    ///
+    /// The restore is a new forward update applied through the room, so connected clients
+    /// converge on it and the edits made since `at` stay in the history.
+    /// Returns `false` when nothing was written after `at`. Fails with [`RoomBusy`] if updates
+    /// hold the room past `READ_TIMEOUT`.
+    pub async fn restore(&self, id: &str, at: chrono::DateTime<chrono::Utc>, author_id: Option<Uuid>) -> anyhow::Result<bool> {
+        let storage = self.storage.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("version history requires storage"))?;
+        if let Some(write_behind) = &self.write_behind {
+            write_behind.flush_doc(id).await?;
         }
+        let past = history::replay(storage.load_updates_until(id, at).await?);
 
-        // 2. Broadcast to other participants
-        let _ = room.tx.send(update.clone());
+        let room = self.get_room(id).await;
+        let state = room.read(|txn| txn.encode_state_as_update_v1(&StateVector::default())).await?;
+        let current = history::replay([state]);
 
-        // 3. Persist to storage
-        if let Some(storage) = &self.storage {
-            let _ = storage.save_update(id, update).await;
+        match history::revert_update(&past, &current)? {
+            Some(update) => {
+                tracing::info!("Content: Restoring '{}' to its state as of {}", id, at);
+                self.process_update_as(id, update, author_id).await?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// Writes the buffered updates of a room to storage, compacting its log if it is due.
+    async fn flush_room(&self, id: &str) -> anyhow::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        if let Some(write_behind) = &self.write_behind {
+            write_behind.flush_doc(id).await?;
+        }
+        storage.compact_if_due(id).await
+    }
+
+    /// Unloads rooms that have had no subscribers for longer than the idle grace period.
+    ///
+    /// Each candidate is flushed to storage first; a room that fails to flush, or that is
+    /// joined or updated while flushing, stays resident. Returns the number of rooms unloaded.
+    pub async fn evict_idle(&self) -> usize {
+        let candidates: Vec<(String, Arc<Room>)> = self.rooms.read().await.iter()
+            .filter(|(_, room)| room.subscriber_count() == 0 && room.idle_for() >= self.idle_grace)
+            .map(|(id, room)| (id.clone(), room.clone()))
+            .collect();
+
+        let mut evicted = 0;
+        for (id, room) in candidates {
+            let flushed_at = Instant::now();
+            if let Err(e) = self.flush_room(&id).await {
+                tracing::warn!("Content: Keeping room '{}' resident, flush failed: {}", id, e);
+                continue;
+            }
+
+            let mut rooms = self.rooms.write().await;
+            let untouched = room.subscriber_count() == 0 && *room.last_active.lock().unwrap() <= flushed_at;
+            if untouched && rooms.get(&id).is_some_and(|r| Arc::ptr_eq(r, &room)) {
+                rooms.remove(&id);
+                evicted += 1;
+                tracing::info!("Content: Unloaded idle room '{}'", id);
+            }
+        }
+        evicted
+    }
+
+    /// Runs `evict_idle` periodically for the lifetime of the process.
+    pub async fn run_eviction(self: Arc<Self>) {
+        let period = (self.idle_grace / 2).max(Duration::from_secs(1));
+        loop {
+            tokio::time::sleep(period).await;
+            let evicted = self.evict_idle().await;
+            if evicted > 0 {
+                tracing::debug!("Content: Eviction pass unloaded {} rooms", evicted);
+            }
+        }
+    }
+
+    fn persistence_status(&self, id: &str) -> write_behind::PersistenceStatus {
+        self.write_behind.as_ref().map(|wb| wb.status(id)).unwrap_or_default()
+    }
+
+    /// Reports what is currently resident in memory.
+    pub async fn stats(&self) -> RegistryStats {
+        let rooms = self.rooms.read().await;
+        let mut stats: Vec<RoomStats> = rooms.iter()
+            .map(|(id, room)| (id, room, self.persistence_status(id)))
+            .map(|(id, room, status)| RoomStats {
+                id: id.clone(),
+                subscribers: room.subscriber_count(),
+                presence: room.presence_count(),
+                idle_secs: room.idle_for().as_secs(),
+                estimated_bytes: room.estimated_memory(),
+                lag_events: room.lag_events(),
+                lagged_messages: room.lagged_messages.load(Ordering::Relaxed),
+                unpersisted_bytes: status.pending_bytes,
+                persistence_error: status.error,
+            })
+            .collect();
+        stats.sort_by(|a, b| a.id.cmp(&b.id));
+
+        RegistryStats {
+            room_count: stats.len(),
+            total_subscribers: stats.iter().map(|r| r.subscribers).sum(),
+            estimated_bytes: stats.iter().filter_map(|r| r.estimated_bytes).sum(),
+            total_lag_events: stats.iter().map(|r| r.lag_events).sum(),
+            channel_capacity: self.channel_capacity,
+            rooms: stats,
         }
     }
 }
@@ -1,6 +1,23 @@
-use sqlx::{Pool, Postgres, Sqlite};
+use sqlx::{Pool, Postgres, QueryBuilder, Sqlite};
 use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use uuid::Uuid;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use yrs::{Doc, ReadTxn, StateVector, Transact, Update};
+use yrs::updates::decoder::Decode;
+use super::history::{self, BeforeCompaction, DocumentVersion, HistoryEntry};
+
+/// Prefix written by the spreadsheet editor in front of its JSON snapshots.
+/// Rows carrying it are not Yjs updates and are never folded into a compacted state.
+pub const SHEET_SNAPSHOT_PREFIX: &[u8] = b"CADMUS_SHEET_V1:";
+
+/// Default number of pending Yjs updates a document may accumulate before it is compacted.
+const DEFAULT_COMPACTION_THRESHOLD: i64 = 500;
+
+/// Largest number of ids bound into one SQLite `DELETE ... IN (...)` during compaction.
+const SQLITE_DELETE_CHUNK: usize = 500;
 
 #[derive(Clone)]
 pub enum DbPool {
@@ -8,63 +25,146 @@ pub enum DbPool {
     Sqlite(Pool<Sqlite>),
 }
 
+impl DbPool {
+    /// Runs a trivial query, to tell whether the database answers.
+    pub async fn ping(&self) -> Result<()> {
+        match self {
+            DbPool::Postgres(p) => { sqlx::query("SELECT 1").execute(p).await?; },
+            DbPool::Sqlite(p) => { sqlx::query("SELECT 1").execute(p).await?; },
+        }
+        Ok(())
+    }
+}
+
 pub struct ContentStorage {
     pub pool: DbPool,
+    compaction_threshold: i64,
+    compacting: Mutex<HashSet<Uuid>>,
 }
 
 impl ContentStorage {
     pub fn new_pg(pool: Pool<Postgres>) -> Self {
-        Self { pool: DbPool::Postgres(pool) }
+        Self::new(DbPool::Postgres(pool))
     }
 
     pub fn new_sqlite(pool: Pool<Sqlite>) -> Self {
-        Self { pool: DbPool::Sqlite(pool) }
+        Self::new(DbPool::Sqlite(pool))
+    }
+
+    /// Reads the compaction threshold from `CONTENT_COMPACTION_THRESHOLD` (0 disables compaction).
+    pub fn new(pool: DbPool) -> Self {
+        let compaction_threshold = std::env::var("CONTENT_COMPACTION_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_COMPACTION_THRESHOLD);
+
+        Self { pool, compaction_threshold, compacting: Mutex::new(HashSet::new()) }
+    }
+
+    /// Overrides the number of pending updates that triggers a compaction. 0 disables it.
+    pub fn with_compaction_threshold(mut self, threshold: i64) -> Self {
+        self.compaction_threshold = threshold;
+        self
+    }
+
+    pub fn compaction_threshold(&self) -> i64 {
+        self.compaction_threshold
     }
 
     pub async fn save_update(&self, doc_id: &str, update: Vec<u8>) -> Result<()> {
+        self.save_update_as(doc_id, update, None).await
+    }
+
+    /// Appends an update to the log, recording who wrote it for the version history.
+    pub async fn save_update_as(&self, doc_id: &str, update: Vec<u8>, author_id: Option<Uuid>) -> Result<()> {
         let uuid = Uuid::parse_str(doc_id)?;
         let len = update.len();
-        
+
         match &self.pool {
             DbPool::Postgres(p) => {
-                sqlx::query("INSERT INTO document_updates (doc_id, data) VALUES ($1, $2)")
+                sqlx::query("INSERT INTO document_updates (doc_id, data, author_id) VALUES ($1, $2, $3)")
                     .bind(uuid)
                     .bind(&update)
+                    .bind(author_id)
                     .execute(p)
                     .await?;
             },
             DbPool::Sqlite(p) => {
-                sqlx::query("INSERT INTO document_updates (doc_id, data) VALUES (?, ?)")
+                sqlx::query("INSERT INTO document_updates (doc_id, data, author_id) VALUES (?, ?, ?)")
                     .bind(uuid.to_string())
                     .bind(&update)
+                    .bind(author_id.map(|a| a.to_string()))
                     .execute(p)
                     .await?;
             }
         }
-        
+
         tracing::debug!("[Storage] COMMITTED update for {} ({} bytes)", doc_id, len);
+        self.compact_if_due(doc_id).await
+    }
+
+    /// Appends several updates of one document in a single multi-row insert.
+    pub async fn save_updates(&self, doc_id: &str, rows: &[(Vec<u8>, Option<Uuid>)]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let uuid = Uuid::parse_str(doc_id)?;
+
+        match &self.pool {
+            DbPool::Postgres(p) => {
+                let mut query = QueryBuilder::<Postgres>::new("INSERT INTO document_updates (doc_id, data, author_id) ");
+                query.push_values(rows, |mut row, (data, author_id)| {
+                    row.push_bind(uuid).push_bind(data).push_bind(*author_id);
+                });
+                query.build().execute(p).await?;
+            },
+            DbPool::Sqlite(p) => {
+                let mut query = QueryBuilder::<Sqlite>::new("INSERT INTO document_updates (doc_id, data, author_id) ");
+                query.push_values(rows, |mut row, (data, author_id)| {
+                    row.push_bind(uuid.to_string()).push_bind(data).push_bind(author_id.map(|a| a.to_string()));
+                });
+                query.build().execute(p).await?;
+            }
+        }
+
+        tracing::debug!("[Storage] COMMITTED {} updates for {}", rows.len(), doc_id);
+        self.compact_if_due(doc_id).await
+    }
+
+    /// Compacts a document once its pending updates exceed the threshold. Failures are only logged.
+    pub async fn compact_if_due(&self, doc_id: &str) -> Result<()> {
+        let uuid = Uuid::parse_str(doc_id)?;
+        if self.compaction_threshold > 0
+            && self.count_pending_updates(uuid).await? > self.compaction_threshold
+            && let Err(e) = self.compact(doc_id).await
+        {
+            tracing::warn!("[Storage] Compaction of {} failed: {}", doc_id, e);
+        }
         Ok(())
     }
 
+    /// Loads the compacted snapshot (if any) followed by the updates written after it.
     pub async fn load_updates(&self, doc_id: &str) -> Result<Vec<Vec<u8>>> {
         let uuid = Uuid::parse_str(doc_id)?;
 
-        let updates: Vec<Vec<u8>> = match &self.pool {
+        let mut updates: Vec<Vec<u8>> = self.load_compacted_state(uuid).await?.into_iter().collect();
+        let tail: Vec<Vec<u8>> = match &self.pool {
             DbPool::Postgres(p) => {
-                let rows: Vec<(Vec<u8>,)> = sqlx::query_as("SELECT data FROM document_updates WHERE doc_id = $1 ORDER BY created_at ASC")
+                let rows: Vec<(Vec<u8>,)> = sqlx::query_as("SELECT data FROM document_updates WHERE doc_id = $1 ORDER BY created_at ASC, id ASC")
                     .bind(uuid)
                     .fetch_all(p)
                     .await?;
                 rows.into_iter().map(|r| r.0).collect()
             },
             DbPool::Sqlite(p) => {
-                let rows: Vec<(Vec<u8>,)> = sqlx::query_as("SELECT data FROM document_updates WHERE doc_id = ? ORDER BY created_at ASC")
+                let rows: Vec<(Vec<u8>,)> = sqlx::query_as("SELECT data FROM document_updates WHERE doc_id = ? ORDER BY created_at ASC, id ASC")
                     .bind(uuid.to_string())
                     .fetch_all(p)
                     .await?;
                 rows.into_iter().map(|r| r.0).collect()
             }
         };
+        updates.extend(tail);
 
         tracing::info!("[Storage] LOADED {} updates for {}", updates.len(), doc_id);
         Ok(updates)
@@ -88,12 +188,16 @@ impl ContentStorage {
             }
         };
 
-        Ok(data)
+        // A fully compacted document has no tail; its latest state is the snapshot itself.
+        match data {
+            Some(d) => Ok(Some(d)),
+            None => self.load_compacted_state(uuid).await,
+        }
     }
 
     pub async fn load_latest_snapshot(&self, doc_id: &str) -> Result<Option<Vec<u8>>> {
         let uuid = Uuid::parse_str(doc_id)?;
-        let prefix = "CADMUS_SHEET_V1:".as_bytes();
+        let prefix = SHEET_SNAPSHOT_PREFIX;
 
         let data = match &self.pool {
             DbPool::Postgres(p) => {
@@ -102,7 +206,7 @@ impl ContentStorage {
                     .bind(uuid)
                     .bind(prefix)
                     // Truque de range para prefixo binário
-                    .bind([prefix, &[255u8]].concat()) 
+                    .bind([prefix, &[255u8]].concat())
                     .fetch_optional(p)
                     .await?
             },
@@ -119,4 +223,286 @@ impl ContentStorage {
 
         Ok(data)
     }
-}
\ No newline at end of file
+
+    /// Returns the compacted Yjs state of a document, if it has ever been compacted.
+    pub async fn load_compacted_state(&self, doc_id: Uuid) -> Result<Option<Vec<u8>>> {
+        let data = match &self.pool {
+            DbPool::Postgres(p) => {
+                sqlx::query_scalar::<_, Vec<u8>>("SELECT data FROM document_snapshots WHERE doc_id = $1")
+                    .bind(doc_id)
+                    .fetch_optional(p)
+                    .await?
+            },
+            DbPool::Sqlite(p) => {
+                sqlx::query_scalar::<_, Vec<u8>>("SELECT data FROM document_snapshots WHERE doc_id = ?")
+                    .bind(doc_id.to_string())
+                    .fetch_optional(p)
+                    .await?
+            }
+        };
+
+        Ok(data)
+    }
+
+    /// Time of the last compaction of a document: its history does not reach further back.
+    pub async fn load_compacted_at(&self, doc_id: Uuid) -> Result<Option<DateTime<Utc>>> {
+        let at: Option<Option<NaiveDateTime>> = match &self.pool {
+            DbPool::Postgres(p) => {
+                sqlx::query_scalar("SELECT updated_at FROM document_snapshots WHERE doc_id = $1")
+                    .bind(doc_id)
+                    .fetch_optional(p)
+                    .await?
+            },
+            DbPool::Sqlite(p) => {
+                sqlx::query_scalar("SELECT updated_at FROM document_snapshots WHERE doc_id = ?")
+                    .bind(doc_id.to_string())
+                    .fetch_optional(p)
+                    .await?
+            }
+        };
+
+        Ok(at.flatten().map(|t| t.and_utc()))
+    }
+
+    /// Lists the Yjs updates of a document, oldest first, with their time, author and size.
+    pub async fn load_history(&self, doc_id: &str) -> Result<Vec<HistoryEntry>> {
+        let uuid = Uuid::parse_str(doc_id)?;
+        let prefix = SHEET_SNAPSHOT_PREFIX;
+
+        let rows: Vec<(Option<NaiveDateTime>, Option<Uuid>, i64)> = match &self.pool {
+            DbPool::Postgres(p) => {
+                sqlx::query_as("SELECT created_at, author_id, length(data)::int8 FROM document_updates WHERE doc_id = $1 AND substring(data from 1 for $2) <> $3 ORDER BY created_at ASC, id ASC")
+                    .bind(uuid)
+                    .bind(prefix.len() as i32)
+                    .bind(prefix)
+                    .fetch_all(p)
+                    .await?
+            },
+            DbPool::Sqlite(p) => {
+                let rows: Vec<(Option<NaiveDateTime>, Option<String>, i64)> = sqlx::query_as("SELECT created_at, author_id, length(data) FROM document_updates WHERE doc_id = ? AND substr(data, 1, ?) <> ? ORDER BY created_at ASC, id ASC")
+                    .bind(uuid.to_string())
+                    .bind(prefix.len() as i32)
+                    .bind(prefix)
+                    .fetch_all(p)
+                    .await?;
+                rows.into_iter()
+                    .map(|(at, author, size)| (at, author.and_then(|a| Uuid::parse_str(&a).ok()), size))
+                    .collect()
+            }
+        };
+
+        Ok(rows.into_iter()
+            .filter_map(|(at, author_id, size)| Some(HistoryEntry { created_at: at?.and_utc(), author_id, size: size as usize }))
+            .collect())
+    }
+
+    /// Lists the versions of a document: its compacted state followed by its editing sessions.
+    pub async fn load_versions(&self, doc_id: &str, session_gap: Duration) -> Result<Vec<DocumentVersion>> {
+        let uuid = Uuid::parse_str(doc_id)?;
+        let baseline = self.load_compacted_at(uuid).await?;
+        let entries = self.load_history(doc_id).await?;
+        Ok(history::group_sessions(baseline, &entries, session_gap))
+    }
+
+    /// Loads the compacted snapshot (if any) followed by the Yjs updates written up to `at`, inclusive.
+    /// Fails with [`BeforeCompaction`] when `at` precedes the last compaction.
+    pub async fn load_updates_until(&self, doc_id: &str, at: DateTime<Utc>) -> Result<Vec<Vec<u8>>> {
+        let uuid = Uuid::parse_str(doc_id)?;
+        let prefix = SHEET_SNAPSHOT_PREFIX;
+
+        // The snapshot already holds every update folded into it, whatever their time.
+        if let Some(compacted_at) = self.load_compacted_at(uuid).await? && at < compacted_at {
+            return Err(BeforeCompaction { requested: at, compacted_at }.into());
+        }
+
+        let mut updates: Vec<Vec<u8>> = self.load_compacted_state(uuid).await?.into_iter().collect();
+        let tail: Vec<Vec<u8>> = match &self.pool {
+            DbPool::Postgres(p) => {
+                sqlx::query_scalar("SELECT data FROM document_updates WHERE doc_id = $1 AND created_at <= $2 AND substring(data from 1 for $3) <> $4 ORDER BY created_at ASC, id ASC")
+                    .bind(uuid)
+                    .bind(at.naive_utc())
+                    .bind(prefix.len() as i32)
+                    .bind(prefix)
+                    .fetch_all(p)
+                    .await?
+            },
+            DbPool::Sqlite(p) => {
+                sqlx::query_scalar("SELECT data FROM document_updates WHERE doc_id = ? AND created_at <= ? AND substr(data, 1, ?) <> ? ORDER BY created_at ASC, id ASC")
+                    .bind(uuid.to_string())
+                    .bind(at.naive_utc())
+                    .bind(prefix.len() as i32)
+                    .bind(prefix)
+                    .fetch_all(p)
+                    .await?
+            }
+        };
+        updates.extend(tail);
+        Ok(updates)
+    }
+
+    /// Rebuilds the state of a document as of `at`, encoded as a single Yjs update.
+    /// Fails with [`BeforeCompaction`] when `at` precedes the last compaction.
+    pub async fn load_state_at(&self, doc_id: &str, at: DateTime<Utc>) -> Result<Vec<u8>> {
+        let updates = self.load_updates_until(doc_id, at).await?;
+        Ok(history::encode_state(&history::replay(updates)))
+    }
+
+    /// Counts the Yjs updates of a document that have not been folded into its snapshot yet.
+    pub async fn count_pending_updates(&self, doc_id: Uuid) -> Result<i64> {
+        let prefix = SHEET_SNAPSHOT_PREFIX;
+
+        let count = match &self.pool {
+            DbPool::Postgres(p) => {
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM document_updates WHERE doc_id = $1 AND substring(data from 1 for $2) <> $3")
+                    .bind(doc_id)
+                    .bind(prefix.len() as i32)
+                    .bind(prefix)
+                    .fetch_one(p)
+                    .await?
+            },
+            DbPool::Sqlite(p) => {
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM document_updates WHERE doc_id = ? AND substr(data, 1, ?) <> ?")
+                    .bind(doc_id.to_string())
+                    .bind(prefix.len() as i32)
+                    .bind(prefix)
+                    .fetch_one(p)
+                    .await?
+            }
+        };
+
+        Ok(count)
+    }
+
+    /// Folds the snapshot and every pending Yjs update of a document into a single encoded state.
+    ///
+    /// Merged rows are removed from `document_updates`; updates written while the compaction
+    /// runs stay behind as the new tail. Spreadsheet snapshots and undecodable rows are left untouched.
+    /// Returns the number of update rows that were merged.
+    pub async fn compact(&self, doc_id: &str) -> Result<usize> {
+        let uuid = Uuid::parse_str(doc_id)?;
+
+        // Only one compaction per document at a time within this process.
+        if !self.compacting.lock().unwrap().insert(uuid) {
+            return Ok(0);
+        }
+        let result = self.compact_locked(uuid).await;
+        self.compacting.lock().unwrap().remove(&uuid);
+
+        if let Ok(merged) = result && merged > 0 {
+            tracing::info!("[Storage] COMPACTED {} updates for {}", merged, doc_id);
+        }
+        result
+    }
+
+    async fn compact_locked(&self, doc_id: Uuid) -> Result<usize> {
+        match &self.pool {
+            DbPool::Postgres(p) => {
+                let mut tx = p.begin().await?;
+                // Serializes compactions of the same document across API instances.
+                sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1::text))")
+                    .bind(doc_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                let snapshot: Option<Vec<u8>> = sqlx::query_scalar("SELECT data FROM document_snapshots WHERE doc_id = $1")
+                    .bind(doc_id)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+                let rows: Vec<(i32, Vec<u8>)> = sqlx::query_as("SELECT id, data FROM document_updates WHERE doc_id = $1 ORDER BY id ASC")
+                    .bind(doc_id)
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+                let Some((state, merged_ids)) = Self::merge_state(snapshot, rows.into_iter().map(|(id, d)| (id as i64, d))) else {
+                    return Ok(0);
+                };
+
+                sqlx::query(
+                    "INSERT INTO document_snapshots (doc_id, data, updated_at) VALUES ($1, $2, CURRENT_TIMESTAMP)
+                     ON CONFLICT (doc_id) DO UPDATE SET data = EXCLUDED.data, updated_at = EXCLUDED.updated_at"
+                )
+                .bind(doc_id)
+                .bind(&state)
+                .execute(&mut *tx)
+                .await?;
+
+                let ids: Vec<i32> = merged_ids.iter().map(|id| *id as i32).collect();
+                sqlx::query("DELETE FROM document_updates WHERE id = ANY($1)")
+                    .bind(&ids)
+                    .execute(&mut *tx)
+                    .await?;
+
+                tx.commit().await?;
+                Ok(merged_ids.len())
+            },
+            DbPool::Sqlite(p) => {
+                let mut tx = p.begin().await?;
+
+                let snapshot: Option<Vec<u8>> = sqlx::query_scalar("SELECT data FROM document_snapshots WHERE doc_id = ?")
+                    .bind(doc_id.to_string())
+                    .fetch_optional(&mut *tx)
+                    .await?;
+                let rows: Vec<(i64, Vec<u8>)> = sqlx::query_as("SELECT id, data FROM document_updates WHERE doc_id = ? ORDER BY id ASC")
+                    .bind(doc_id.to_string())
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+                let Some((state, merged_ids)) = Self::merge_state(snapshot, rows) else {
+                    return Ok(0);
+                };
+
+                sqlx::query(
+                    "INSERT INTO document_snapshots (doc_id, data, updated_at) VALUES (?, ?, CURRENT_TIMESTAMP)
+                     ON CONFLICT (doc_id) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at"
+                )
+                .bind(doc_id.to_string())
+                .bind(&state)
+                .execute(&mut *tx)
+                .await?;
+
+                // Skipped rows (sheet snapshots, undecodable updates) stay in the log, so the ids are
+                // listed rather than deleted as a range; chunked to stay under SQLite's bind limit.
+                for chunk in merged_ids.chunks(SQLITE_DELETE_CHUNK) {
+                    let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM document_updates WHERE id IN (");
+                    let mut ids = query.separated(", ");
+                    for id in chunk {
+                        ids.push_bind(*id);
+                    }
+                    query.push(")");
+                    query.build().execute(&mut *tx).await?;
+                }
+
+                tx.commit().await?;
+                Ok(merged_ids.len())
+            }
+        }
+    }
+
+    /// Applies the snapshot and the decodable updates to a scratch `Doc` and re-encodes its full state.
+    /// Returns `None` when there is nothing to merge.
+    fn merge_state(snapshot: Option<Vec<u8>>, rows: impl IntoIterator<Item = (i64, Vec<u8>)>) -> Option<(Vec<u8>, Vec<i64>)> {
+        let doc = Doc::new();
+        let mut merged_ids = Vec::new();
+        {
+            let mut txn = doc.transact_mut();
+            if let Some(u) = snapshot.and_then(|s| Update::decode_v1(&s).ok()) {
+                txn.apply_update(u);
+            }
+            for (id, data) in rows {
+                if data.starts_with(SHEET_SNAPSHOT_PREFIX) {
+                    continue;
+                }
+                if let Ok(u) = Update::decode_v1(&data) {
+                    txn.apply_update(u);
+                    merged_ids.push(id);
+                }
+            }
+        }
+
+        if merged_ids.is_empty() {
+            return None;
+        }
+        let state = doc.transact().encode_state_as_update_v1(&StateVector::default());
+        Some((state, merged_ids))
+    }
+}
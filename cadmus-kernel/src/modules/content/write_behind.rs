@@ -0,0 +1,241 @@
+//! Write-Behind Persistence
+//!
+//! Buffers the Yjs updates a room applies and writes them to `document_updates` in batches,
+//! instead of one insert per keystroke. Consecutive updates of the same author are merged into
+//! one row, so the version history keeps its authors. A document is written once its buffer
+//! reaches the size threshold or the flush interval elapses; failed writes stay buffered and
+//! are retried with backoff. Written updates are relayed to peer instances only then, so a peer
+//! that loads the room from storage never misses one it was not sent.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tokio::sync::Notify;
+use uuid::Uuid;
+use super::bus::RoomBus;
+use super::storage::ContentStorage;
+
+/// Default time an update may wait in the buffer before it is written.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Default buffered size of one document that triggers an immediate write.
+pub const DEFAULT_MAX_PENDING_BYTES: usize = 1024 * 1024;
+
+/// Longest pause between two attempts to write a failing document.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Updates of one document waiting to be written, oldest first.
+#[derive(Default)]
+struct DocBuffer {
+    rows: Vec<(Vec<u8>, Option<Uuid>)>,
+    bytes: usize,
+    failures: u32,
+    last_error: Option<String>,
+    retry_at: Option<Instant>,
+}
+
+impl DocBuffer {
+    fn push(&mut self, update: Vec<u8>, author_id: Option<Uuid>) {
+        self.bytes += update.len();
+        if let Some((last, last_author)) = self.rows.last_mut()
+            && *last_author == author_id
+            && let Ok(merged) = yrs::merge_updates_v1(&[last.as_slice(), update.as_slice()])
+        {
+            *last = merged;
+            return;
+        }
+        self.rows.push((update, author_id));
+    }
+
+    fn is_backing_off(&self, now: Instant) -> bool {
+        self.retry_at.is_some_and(|at| at > now)
+    }
+}
+
+/// What is still waiting to be written for a document.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PersistenceStatus {
+    pub pending_bytes: usize,
+    /// Error of the last failed write, cleared once a write succeeds.
+    pub error: Option<String>,
+}
+
+pub struct WriteBehind {
+    storage: Arc<ContentStorage>,
+    buffers: Mutex<HashMap<String, DocBuffer>>,
+    interval: Duration,
+    max_pending_bytes: usize,
+    wake: Notify,
+    /// Serializes writes so the rows of a document reach storage in order.
+    writing: tokio::sync::Mutex<()>,
+    started: AtomicBool,
+    /// Peer instances told about every update once it is written.
+    relay: Option<Arc<dyn RoomBus>>,
+}
+
+impl WriteBehind {
+    pub fn new(storage: Arc<ContentStorage>, interval: Duration, max_pending_bytes: usize) -> Arc<Self> {
+        Self::with_relay(storage, interval, max_pending_bytes, None)
+    }
+
+    /// Like `new`, and publishes each written update on `relay`.
+    pub fn with_relay(storage: Arc<ContentStorage>, interval: Duration, max_pending_bytes: usize, relay: Option<Arc<dyn RoomBus>>) -> Arc<Self> {
+        Arc::new(Self {
+            storage,
+            buffers: Mutex::new(HashMap::new()),
+            interval,
+            max_pending_bytes: max_pending_bytes.max(1),
+            wake: Notify::new(),
+            writing: tokio::sync::Mutex::new(()),
+            started: AtomicBool::new(false),
+            relay,
+        })
+    }
+
+    /// Buffers an update for writing. The background writer starts with the first update.
+    ///
+    /// The update is always kept; an error reports that earlier writes of the document
+    /// are failing and still being retried.
+    pub fn enqueue(self: &Arc<Self>, doc_id: &str, update: Vec<u8>, author_id: Option<Uuid>) -> anyhow::Result<()> {
+        Uuid::parse_str(doc_id)?;
+        if !self.started.swap(true, Ordering::SeqCst) {
+            tokio::spawn(Self::run(Arc::downgrade(self)));
+        }
+
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.entry(doc_id.to_string()).or_default();
+        buffer.push(update, author_id);
+        if buffer.bytes >= self.max_pending_bytes {
+            self.wake.notify_one();
+        }
+        match &buffer.last_error {
+            Some(e) => Err(anyhow::anyhow!("updates of '{}' are not persisted yet: {}", doc_id, e)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn status(&self, doc_id: &str) -> PersistenceStatus {
+        self.buffers.lock().unwrap().get(doc_id)
+            .map(|b| PersistenceStatus { pending_bytes: b.bytes, error: b.last_error.clone() })
+            .unwrap_or_default()
+    }
+
+    /// Writes everything buffered now, ignoring backoff. Used before shutdown.
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        let ids: Vec<String> = self.buffers.lock().unwrap().keys().cloned().collect();
+        let mut failed = Vec::new();
+        for id in ids {
+            if let Err(e) = self.flush_doc(&id).await {
+                failed.push(format!("{}: {}", id, e));
+            }
+        }
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!("failed to persist {} documents: {}", failed.len(), failed.join("; "))),
+        }
+    }
+
+    /// Writes the buffered updates of one document now, ignoring backoff.
+    pub async fn flush_doc(&self, doc_id: &str) -> anyhow::Result<()> {
+        let _writing = self.writing.lock().await;
+        self.write(doc_id).await
+    }
+
+    /// Drops the buffered updates of a document without writing them, once a write of them
+    /// already under way is done. For documents that no longer exist.
+    pub async fn discard(&self, doc_id: &str) {
+        let _writing = self.writing.lock().await;
+        self.buffers.lock().unwrap().remove(doc_id);
+    }
+
+    /// Writes every document that is not waiting out a retry backoff.
+    async fn flush_due(&self) {
+        let _writing = self.writing.lock().await;
+        let now = Instant::now();
+        let due: Vec<String> = self.buffers.lock().unwrap().iter()
+            .filter(|(_, b)| !b.rows.is_empty() && !b.is_backing_off(now))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in due {
+            if let Err(e) = self.write(&id).await {
+                tracing::warn!("[Storage] Write-behind of {} failed, will retry: {}", id, e);
+            }
+        }
+    }
+
+    /// Takes a document's rows out of the buffer and writes them, putting them back on failure.
+    /// Callers hold the `writing` lock.
+    async fn write(&self, doc_id: &str) -> anyhow::Result<()> {
+        let rows = match self.buffers.lock().unwrap().get_mut(doc_id) {
+            Some(buffer) if !buffer.rows.is_empty() => {
+                buffer.bytes = 0;
+                std::mem::take(&mut buffer.rows)
+            },
+            _ => return Ok(()),
+        };
+
+        let result = self.storage.save_updates(doc_id, &rows).await;
+        if result.is_ok() {
+            self.relay(doc_id, &rows).await;
+        }
+
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.entry(doc_id.to_string()).or_default();
+        match &result {
+            Ok(()) => {
+                buffer.failures = 0;
+                buffer.last_error = None;
+                buffer.retry_at = None;
+                if buffer.rows.is_empty() {
+                    buffers.remove(doc_id);
+                }
+            },
+            Err(e) => {
+                // Rows buffered while writing came later; the failed ones go back in front.
+                let newer = std::mem::replace(&mut buffer.rows, rows);
+                buffer.bytes = buffer.rows.iter().chain(&newer).map(|(u, _)| u.len()).sum();
+                buffer.rows.extend(newer);
+                buffer.failures += 1;
+                buffer.last_error = Some(e.to_string());
+                let backoff = self.interval.saturating_mul(2u32.saturating_pow(buffer.failures.min(16)));
+                buffer.retry_at = Some(Instant::now() + backoff.min(MAX_RETRY_BACKOFF));
+            }
+        }
+        result
+    }
+
+    /// Announces written rows to peer instances, which can now also read them from storage.
+    async fn relay(&self, doc_id: &str, rows: &[(Vec<u8>, Option<Uuid>)]) {
+        let Some(bus) = &self.relay else {
+            return;
+        };
+        for (update, _) in rows {
+            if let Err(e) = bus.publish(doc_id, update).await {
+                tracing::warn!("Content: Failed to relay update for '{}': {}", doc_id, e);
+            }
+        }
+    }
+
+    /// Writes due documents every interval, or sooner once one outgrows the size threshold.
+    /// Stops when the write-behind is dropped.
+    async fn run(this: Weak<Self>) {
+        while let Some(interval) = this.upgrade().map(|wb| wb.interval) {
+            let wake = async {
+                match this.upgrade() {
+                    Some(wb) => wb.wake.notified().await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {},
+                _ = wake => {},
+            }
+            let Some(wb) = this.upgrade() else {
+                break;
+            };
+            wb.flush_due().await;
+        }
+    }
+}
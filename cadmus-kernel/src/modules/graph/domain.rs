@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Relation of links made without one, such as wikilinks and edges drawn in the graph view.
+pub const DEFAULT_RELATION: &str = "link";
+
+/// Longest accepted relation name.
+pub const MAX_RELATION_LEN: usize = 64;
+
+/// A directed, typed edge between two documents. The same pair may be linked once per relation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentLink {
+    pub from_id: Uuid,
+    pub to_id: Uuid,
+    pub relation: String,
+    /// JSON object of extra data about the relation, empty when there is none.
+    pub metadata: serde_json::Value,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Deepest neighbourhood, and longest path or cycle, a traversal explores.
+pub const MAX_DEPTH: u32 = 6;
+
+/// Which edges a traversal follows: typed links, parent/child edges of the document tree, or both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Traversal {
+    Links,
+    Hierarchy,
+    #[default]
+    Both,
+}
+
+impl Traversal {
+    pub fn follows_links(self) -> bool {
+        matches!(self, Self::Links | Self::Both)
+    }
+
+    pub fn follows_hierarchy(self) -> bool {
+        matches!(self, Self::Hierarchy | Self::Both)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    Link,
+    /// From a parent to one of its children.
+    Child,
+}
+
+/// An edge of the graph. Links carry their relation; a pair linked under several relations
+/// yields one edge per relation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphEdge {
+    pub from_id: Uuid,
+    pub to_id: Uuid,
+    pub kind: EdgeKind,
+    pub relation: Option<String>,
+}
+
+/// A document reached by a traversal, with its distance in edges from the start.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: Uuid,
+    pub depth: u32,
+}
+
+/// The documents around a start document and the edges between them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Subgraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Why a link change or a traversal was refused.
+#[derive(Error, Debug)]
+pub enum GraphError {
+    #[error("document {0} not found")]
+    NotFound(Uuid),
+    #[error("document {0} belongs to another user")]
+    Forbidden(Uuid),
+    #[error("invalid relation '{0}': use 1 to 64 lowercase letters, digits or underscores")]
+    InvalidRelation(String),
+    #[error("link metadata must be a JSON object")]
+    InvalidMetadata,
+    #[error("no link from {from} to {to}")]
+    LinkNotFound { from: Uuid, to: Uuid },
+    #[error("depth must be between 1 and {MAX_DEPTH}, got {0}")]
+    InvalidDepth(u32),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl GraphError {
+    /// True for refusals caused by the request itself, as opposed to missing documents,
+    /// ownership or storage failures.
+    pub fn is_validation(&self) -> bool {
+        matches!(self, Self::InvalidRelation(_) | Self::InvalidMetadata | Self::InvalidDepth(_))
+    }
+}
+
+/// Checks a relation name: lowercase ASCII letters, digits and underscores, so relations stay
+/// comparable across clients and safe to use as keys.
+pub fn validate_relation(relation: &str) -> Result<(), GraphError> {
+    let valid = !relation.is_empty()
+        && relation.len() <= MAX_RELATION_LEN
+        && relation.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_');
+    if valid { Ok(()) } else { Err(GraphError::InvalidRelation(relation.to_string())) }
+}
@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use serde_json::Value;
+use uuid::Uuid;
+use crate::domain::repository::DocumentRepository;
+use super::domain::{validate_relation, DocumentLink, GraphError, Subgraph, Traversal, DEFAULT_RELATION, MAX_DEPTH};
+
+/// Most cycles returned by one listing.
+pub const MAX_CYCLES: i64 = 100;
+
+/// Typed links between the documents of one owner, and traversals of the graph they form with
+/// the document tree. Both ends of a link must belong to the caller, so a link never reveals or
+/// points into another user's workspace, and traversals only cross the caller's documents.
+pub struct GraphService {
+    documents: Arc<dyn DocumentRepository>,
+}
+
+impl GraphService {
+    pub fn new(documents: Arc<dyn DocumentRepository>) -> Self {
+        Self { documents }
+    }
+
+    /// Links two documents of `owner_id` under `relation` ("link" when `None`). Linking them
+    /// again under the same relation replaces its metadata.
+    pub async fn link(&self, owner_id: Uuid, from_id: Uuid, to_id: Uuid, relation: Option<String>, metadata: Option<Value>) -> Result<DocumentLink, GraphError> {
+        let relation = relation.unwrap_or_else(|| DEFAULT_RELATION.to_string());
+        validate_relation(&relation)?;
+        let metadata = metadata.unwrap_or_else(|| Value::Object(Default::default()));
+        if !metadata.is_object() {
+            return Err(GraphError::InvalidMetadata);
+        }
+        self.check_owner(owner_id, from_id).await?;
+        self.check_owner(owner_id, to_id).await?;
+        Ok(self.documents.add_typed_link(from_id, to_id, &relation, metadata).await?)
+    }
+
+    /// Removes the link of `relation` between two documents of `owner_id`, or every link
+    /// between them when `None`. Returns how many were removed.
+    pub async fn unlink(&self, owner_id: Uuid, from_id: Uuid, to_id: Uuid, relation: Option<&str>) -> Result<u64, GraphError> {
+        if let Some(relation) = relation {
+            validate_relation(relation)?;
+        }
+        self.check_owner(owner_id, from_id).await?;
+        match self.documents.remove_link(from_id, to_id, relation).await? {
+            0 => Err(GraphError::LinkNotFound { from: from_id, to: to_id }),
+            removed => Ok(removed),
+        }
+    }
+
+    /// Links pointing at a document of `owner_id`.
+    pub async fn backlinks(&self, owner_id: Uuid, doc_id: Uuid) -> Result<Vec<DocumentLink>, GraphError> {
+        self.check_owner(owner_id, doc_id).await?;
+        Ok(self.documents.find_backlinks(doc_id).await?)
+    }
+
+    /// Documents within `depth` edges of `doc_id`, in either direction, and the edges between them.
+    pub async fn neighbourhood(&self, owner_id: Uuid, doc_id: Uuid, depth: u32, traversal: Traversal) -> Result<Subgraph, GraphError> {
+        check_depth(depth)?;
+        self.check_owner(owner_id, doc_id).await?;
+        Ok(self.documents.find_neighbourhood(owner_id, doc_id, depth, traversal).await?)
+    }
+
+    /// A shortest path from `from_id` to `to_id` of at most `max_depth` edges, followed in either
+    /// direction, or `None` when there is none. Among paths of the same length the one through the
+    /// smallest ids is chosen.
+    pub async fn shortest_path(&self, owner_id: Uuid, from_id: Uuid, to_id: Uuid, traversal: Traversal, max_depth: u32) -> Result<Option<Vec<Uuid>>, GraphError> {
+        check_depth(max_depth)?;
+        self.check_owner(owner_id, from_id).await?;
+        self.check_owner(owner_id, to_id).await?;
+        let around = self.documents.find_neighbourhood(owner_id, from_id, max_depth, traversal).await?;
+
+        // The walk gives every document its distance from the start; the path is rebuilt from
+        // the target by stepping to a neighbour one edge closer each time.
+        let depths: HashMap<Uuid, u32> = around.nodes.iter().map(|n| (n.id, n.depth)).collect();
+        let mut neighbours: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        for edge in &around.edges {
+            neighbours.entry(edge.from_id).or_default().push(edge.to_id);
+            neighbours.entry(edge.to_id).or_default().push(edge.from_id);
+        }
+        let Some(&depth) = depths.get(&to_id) else {
+            return Ok(None);
+        };
+        let mut path = vec![to_id];
+        let mut current = to_id;
+        for step in (0..depth).rev() {
+            let previous = neighbours.get(&current).into_iter().flatten()
+                .filter(|n| depths.get(n) == Some(&step))
+                .min()
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("neighbourhood of {} is missing an edge to {}", from_id, current))?;
+            path.push(previous);
+            current = previous;
+        }
+        path.reverse();
+        Ok(Some(path))
+    }
+
+    /// Groups the documents of `owner_id` into connected components, edges followed in either
+    /// direction, largest first. Documents without edges are components of their own.
+    ///
+    /// Labelling components in SQL would walk from every document, so the edges are read once
+    /// and joined here instead.
+    pub async fn components(&self, owner_id: Uuid, traversal: Traversal) -> Result<Vec<Vec<Uuid>>, GraphError> {
+        let documents = self.documents.find_all(owner_id).await?;
+        let edges = self.documents.find_graph_edges(owner_id, traversal).await?;
+
+        let index: HashMap<Uuid, usize> = documents.iter().enumerate().map(|(i, d)| (d.id, i)).collect();
+        let mut roots: Vec<usize> = (0..documents.len()).collect();
+        fn find(roots: &mut [usize], mut i: usize) -> usize {
+            while roots[i] != i {
+                roots[i] = roots[roots[i]];
+                i = roots[i];
+            }
+            i
+        }
+        for edge in &edges {
+            if let (Some(&a), Some(&b)) = (index.get(&edge.from_id), index.get(&edge.to_id)) {
+                let (a, b) = (find(&mut roots, a), find(&mut roots, b));
+                roots[a.max(b)] = a.min(b);
+            }
+        }
+
+        let mut groups: BTreeMap<usize, Vec<Uuid>> = BTreeMap::new();
+        for (i, doc) in documents.iter().enumerate() {
+            let root = find(&mut roots, i);
+            groups.entry(root).or_default().push(doc.id);
+        }
+        let mut components: Vec<Vec<Uuid>> = groups.into_values()
+            .map(|mut ids| { ids.sort(); ids })
+            .collect();
+        components.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        Ok(components)
+    }
+
+    /// Directed cycles of at most `max_len` documents, shortest first, each starting from its
+    /// smallest id. Parent/child edges only point down the tree, so cycles always go through
+    /// at least one link. At most `limit` cycles are returned, capped at [`MAX_CYCLES`].
+    pub async fn cycles(&self, owner_id: Uuid, traversal: Traversal, max_len: u32, limit: i64) -> Result<Vec<Vec<Uuid>>, GraphError> {
+        check_depth(max_len)?;
+        Ok(self.documents.find_cycles(owner_id, traversal, max_len, limit.clamp(1, MAX_CYCLES)).await?)
+    }
+
+    async fn check_owner(&self, owner_id: Uuid, doc_id: Uuid) -> Result<(), GraphError> {
+        match self.documents.find_owner(doc_id).await? {
+            Some(owner) if owner == owner_id => Ok(()),
+            Some(_) => Err(GraphError::Forbidden(doc_id)),
+            None => Err(GraphError::NotFound(doc_id)),
+        }
+    }
+}
+
+fn check_depth(depth: u32) -> Result<(), GraphError> {
+    if (1..=MAX_DEPTH).contains(&depth) { Ok(()) } else { Err(GraphError::InvalidDepth(depth)) }
+}
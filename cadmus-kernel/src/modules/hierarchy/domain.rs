@@ -0,0 +1,27 @@
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Why a change to the document tree was refused.
+#[derive(Error, Debug)]
+pub enum HierarchyError {
+    #[error("document {0} not found")]
+    NotFound(Uuid),
+    #[error("document {0} belongs to another user")]
+    Forbidden(Uuid),
+    #[error("document {document} cannot be placed under {parent}, which is itself or one of its descendants")]
+    Cycle { document: Uuid, parent: Uuid },
+    #[error("class '{parent_class}' does not accept children of class '{child_class}'")]
+    ChildNotAllowed { parent_class: String, child_class: String },
+    #[error("module '{module}' refused document {document}")]
+    RejectedByModule { module: String, document: Uuid },
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl HierarchyError {
+    /// True for refusals caused by the request itself, as opposed to missing documents,
+    /// ownership or storage failures.
+    pub fn is_validation(&self) -> bool {
+        matches!(self, Self::Cycle { .. } | Self::ChildNotAllowed { .. } | Self::RejectedByModule { .. })
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::domain::archetypes::modules::ModuleRegistry;
+use crate::domain::repository::{ArchetypeRepository, DocumentRepository};
+use crate::modules::content::workspace::WorkspaceNode;
+use super::domain::HierarchyError;
+
+/// Class of documents created without one.
+const DEFAULT_CLASS: &str = "note";
+
+/// Changes to the document tree that must keep it a tree and respect the ontology: a document
+/// is never placed under itself or its descendants, and only under a parent whose archetype
+/// lists its class in `allowed_children` (an empty list accepts any class). Moves are also
+/// submitted to the module registered under the parent's class; a document being created has
+/// no id yet for the module to check. Duplicates are placed next to their original.
+///
+/// The repository does not check `allowed_children`, and three writers skip it on purpose:
+/// - `duplicate_document` puts each copy under the parent, or the copy of the parent, that
+///   already holds an original of the same class;
+/// - `PortabilityService::import` recreates a tree as it was exported, with archetypes that may
+///   have changed since;
+/// - `SyncStore::apply` writes what another replica already accepted, under its archetypes.
+pub struct HierarchyService {
+    documents: Arc<dyn DocumentRepository>,
+    archetypes: Arc<dyn ArchetypeRepository>,
+    modules: Arc<ModuleRegistry>,
+}
+
+impl HierarchyService {
+    pub fn new(documents: Arc<dyn DocumentRepository>, archetypes: Arc<dyn ArchetypeRepository>, modules: Arc<ModuleRegistry>) -> Self {
+        Self { documents, archetypes, modules }
+    }
+
+    /// Creates a document of `owner_id`, refusing a parent that is not theirs or whose
+    /// archetype does not accept the class.
+    pub async fn create_document(&self, owner_id: Uuid, title: String, class_id: Option<String>, parent_id: Option<Uuid>) -> Result<WorkspaceNode, HierarchyError> {
+        if let Some(parent_id) = parent_id {
+            let parent = self.owned(owner_id, parent_id).await?;
+            self.check_allowed_child(&parent, class_id.as_deref().unwrap_or(DEFAULT_CLASS)).await?;
+        }
+        Ok(self.documents.create(owner_id, title, class_id, parent_id).await?)
+    }
+
+    /// Moves a document of `owner_id` under `parent_id`, or to the root when `None`.
+    pub async fn move_document(&self, owner_id: Uuid, doc_id: Uuid, parent_id: Option<Uuid>) -> Result<WorkspaceNode, HierarchyError> {
+        let doc = self.owned(owner_id, doc_id).await?;
+        if doc.parent_id == parent_id {
+            return Ok(doc);
+        }
+        if let Some(parent_id) = parent_id {
+            if parent_id == doc_id {
+                return Err(HierarchyError::Cycle { document: doc_id, parent: parent_id });
+            }
+            let parent = self.owned(owner_id, parent_id).await?;
+            self.check_containment(&parent, doc.class_id.as_deref().unwrap_or(DEFAULT_CLASS), doc_id).await?;
+        }
+
+        // The repository repeats the cycle check in the update itself, so a concurrent move
+        // cannot slip a loop in between.
+        if !self.documents.set_parent(doc_id, parent_id).await? {
+            return Err(HierarchyError::Cycle { document: doc_id, parent: parent_id.unwrap_or(doc_id) });
+        }
+        tracing::info!("[Hierarchy] MOVED {} under {:?}", doc_id, parent_id);
+        Ok(WorkspaceNode { parent_id, ..doc })
+    }
+
+    /// Copies a document of `owner_id` and its descendants next to it, with their content,
+    /// collection rows and the links among them. Returns the copy of the document.
+    pub async fn duplicate_document(&self, owner_id: Uuid, doc_id: Uuid) -> Result<WorkspaceNode, HierarchyError> {
+        let doc = self.owned(owner_id, doc_id).await?;
+        // The copy shares the parent of a document already there, so containment holds.
+        let id_map = self.documents.duplicate(doc_id, owner_id, &format!("{} (copy)", doc.title)).await?;
+        let copy_id = *id_map.get(&doc_id).ok_or(HierarchyError::NotFound(doc_id))?;
+        tracing::info!("[Hierarchy] DUPLICATED {} as {} ({} documents)", doc_id, copy_id, id_map.len());
+        self.documents.find_by_id(copy_id).await?.ok_or(HierarchyError::NotFound(copy_id))
+    }
+
+    /// Ids of a live document of `owner_id` and its live descendants, the document first.
+    pub async fn subtree(&self, owner_id: Uuid, doc_id: Uuid) -> Result<Vec<Uuid>, HierarchyError> {
+        self.owned(owner_id, doc_id).await?;
+        let nodes = self.documents.find_all(owner_id).await?;
+        let mut subtree = vec![doc_id];
+        let mut next = 0;
+        while let Some(&parent) = subtree.get(next) {
+            for node in nodes.iter().filter(|n| n.parent_id == Some(parent)) {
+                if !subtree.contains(&node.id) {
+                    subtree.push(node.id);
+                }
+            }
+            next += 1;
+        }
+        Ok(subtree)
+    }
+
+    /// The document, if it exists and belongs to `owner_id`.
+    async fn owned(&self, owner_id: Uuid, doc_id: Uuid) -> Result<WorkspaceNode, HierarchyError> {
+        match self.documents.find_owner(doc_id).await? {
+            Some(owner) if owner == owner_id => {},
+            Some(_) => return Err(HierarchyError::Forbidden(doc_id)),
+            None => return Err(HierarchyError::NotFound(doc_id)),
+        }
+        self.documents.find_by_id(doc_id).await?.ok_or(HierarchyError::NotFound(doc_id))
+    }
+
+    /// Checks that `parent` may hold the document `child_id`, of `child_class`.
+    async fn check_containment(&self, parent: &WorkspaceNode, child_class: &str, child_id: Uuid) -> Result<(), HierarchyError> {
+        self.check_allowed_child(parent, child_class).await?;
+        let parent_class = parent.class_id.as_deref().unwrap_or(DEFAULT_CLASS);
+        if let Some(behavior) = self.modules.get_behavior(parent_class)
+            && !behavior.validate_integrity(&child_id).await?
+        {
+            return Err(HierarchyError::RejectedByModule { module: parent_class.to_string(), document: child_id });
+        }
+        Ok(())
+    }
+
+    /// Checks that the archetype of `parent` lists `child_class` among its allowed children.
+    async fn check_allowed_child(&self, parent: &WorkspaceNode, child_class: &str) -> Result<(), HierarchyError> {
+        let parent_class = parent.class_id.as_deref().unwrap_or(DEFAULT_CLASS);
+        let allowed = self.archetypes.find_by_id(parent_class).await?
+            .and_then(|a| a.allowed_children)
+            .unwrap_or_default();
+        if !allowed.is_empty() && !allowed.iter().any(|c| c == child_class) {
+            return Err(HierarchyError::ChildNotAllowed { parent_class: parent_class.to_string(), child_class: child_class.to_string() });
+        }
+        Ok(())
+    }
+}
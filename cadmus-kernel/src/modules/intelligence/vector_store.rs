@@ -1 +1,93 @@
-// Intelligence Vector Store
\ No newline at end of file
+// Intelligence Vector Store
+//
+// Nearest-neighbour search for backends without pgvector. Embeddings are kept as little-endian
+// f32 blobs and ranked by Euclidean distance, the `<->` operator the Postgres backend orders by.
+// A brute-force scan is exact and fast enough for the size of a local vault.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use uuid::Uuid;
+
+/// Dimensions of a document embedding, as declared by `neural_metadata.embedding`.
+pub const EMBEDDING_DIMENSIONS: usize = 384;
+
+pub fn encode_embedding(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn decode_embedding(bytes: &[u8]) -> anyhow::Result<Vec<f32>> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(anyhow::anyhow!("embedding of {} bytes is not a list of f32", bytes.len()));
+    }
+    Ok(bytes.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+}
+
+/// Rejects embeddings the Postgres column would not accept.
+pub fn check_dimensions(embedding: &[f32]) -> anyhow::Result<()> {
+    if embedding.len() != EMBEDDING_DIMENSIONS {
+        return Err(anyhow::anyhow!("expected {} dimensions, not {}", EMBEDDING_DIMENSIONS, embedding.len()));
+    }
+    Ok(())
+}
+
+pub fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+/// The `limit` embeddings closest to a query, out of those offered one by one.
+pub struct NearestNeighbours {
+    query: Vec<f32>,
+    limit: usize,
+    // Max-heap on distance: the farthest kept candidate is the first to go.
+    kept: BinaryHeap<Candidate>,
+}
+
+impl NearestNeighbours {
+    pub fn new(query: Vec<f32>, limit: usize) -> Self {
+        Self { query, limit, kept: BinaryHeap::with_capacity(limit + 1) }
+    }
+
+    pub fn offer(&mut self, id: Uuid, embedding: &[f32]) -> anyhow::Result<()> {
+        if embedding.len() != self.query.len() {
+            return Err(anyhow::anyhow!("different vector dimensions {} and {}", self.query.len(), embedding.len()));
+        }
+        if self.limit == 0 {
+            return Ok(());
+        }
+        self.kept.push(Candidate { distance: l2_distance(&self.query, embedding), id });
+        if self.kept.len() > self.limit {
+            self.kept.pop();
+        }
+        Ok(())
+    }
+
+    /// Kept ids, closest first.
+    pub fn into_ids(self) -> Vec<Uuid> {
+        self.kept.into_sorted_vec().into_iter().map(|c| c.id).collect()
+    }
+}
+
+struct Candidate {
+    distance: f32,
+    id: Uuid,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then_with(|| self.id.cmp(&other.id))
+    }
+}
@@ -3,3 +3,9 @@ pub mod content;
 pub mod physics;
 pub mod intelligence;
 pub mod configuration;
+pub mod sync;
+pub mod portability;
+pub mod hierarchy;
+pub mod graph;
+pub mod trash;
+pub mod query;
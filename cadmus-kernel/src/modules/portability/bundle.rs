@@ -0,0 +1,171 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+use crate::modules::graph::domain::DEFAULT_RELATION;
+
+/// Identifies a workspace bundle, whatever its file name.
+pub const BUNDLE_FORMAT: &str = "cadmus-workspace";
+
+/// Layout of the bundle. Raised when a field changes meaning or a section is added; a reader
+/// refuses bundles newer than itself.
+pub const BUNDLE_SCHEMA_VERSION: u32 = 1;
+
+/// Describes a bundle and lets the reader check that no section was altered or cut short.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: String,
+    pub schema_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub owner_id: Uuid,
+    pub document_count: usize,
+    pub collection_row_count: usize,
+    pub link_count: usize,
+    /// SHA-256 of each section, keyed by section name.
+    pub checksums: BTreeMap<String, String>,
+}
+
+/// A document with everything needed to recreate it. Content is the full Yjs state,
+/// hex-encoded, so it restores on any backend regardless of how the update log was compacted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleDocument {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub title: String,
+    pub class_id: Option<String>,
+    pub properties: serde_json::Value,
+    pub config: serde_json::Value,
+    pub is_public: bool,
+    pub content: Option<String>,
+    /// Latest spreadsheet snapshot, hex-encoded with its prefix, for documents that have one.
+    #[serde(default)]
+    pub sheet: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleCollectionRow {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub data: serde_json::Value,
+    pub order_index: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleLink {
+    pub from_id: Uuid,
+    pub to_id: Uuid,
+    /// Omitted for plain links, as are empty metadata, so bundles without typed links keep the
+    /// layout and checksums they had before relations existed.
+    #[serde(default = "default_relation", skip_serializing_if = "is_default_relation")]
+    pub relation: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl BundleLink {
+    /// A plain link without metadata.
+    pub fn new(from_id: Uuid, to_id: Uuid) -> Self {
+        Self { from_id, to_id, relation: default_relation(), metadata: None }
+    }
+}
+
+fn default_relation() -> String {
+    DEFAULT_RELATION.to_string()
+}
+
+fn is_default_relation(relation: &str) -> bool {
+    relation == DEFAULT_RELATION
+}
+
+/// A whole workspace in one file: the manifest and one section per table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceBundle {
+    pub manifest: BundleManifest,
+    pub documents: Vec<BundleDocument>,
+    pub collection_rows: Vec<BundleCollectionRow>,
+    pub links: Vec<BundleLink>,
+}
+
+/// What an import created. `id_map` maps the ids of the bundle to the new ones.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub documents: usize,
+    pub collection_rows: usize,
+    pub links: usize,
+    pub id_map: HashMap<Uuid, Uuid>,
+}
+
+impl WorkspaceBundle {
+    /// Builds a bundle and its manifest from its sections.
+    pub fn new(owner_id: Uuid, documents: Vec<BundleDocument>, collection_rows: Vec<BundleCollectionRow>, links: Vec<BundleLink>) -> anyhow::Result<Self> {
+        let manifest = BundleManifest {
+            format: BUNDLE_FORMAT.to_string(),
+            schema_version: BUNDLE_SCHEMA_VERSION,
+            exported_at: Utc::now(),
+            owner_id,
+            document_count: documents.len(),
+            collection_row_count: collection_rows.len(),
+            link_count: links.len(),
+            checksums: BTreeMap::new(),
+        };
+        let mut bundle = Self { manifest, documents, collection_rows, links };
+        bundle.manifest.checksums = bundle.section_checksums()?;
+        Ok(bundle)
+    }
+
+    /// Rejects bundles of another format, of a newer layout, or whose sections do not match
+    /// their manifest.
+    pub fn verify(&self) -> anyhow::Result<()> {
+        let manifest = &self.manifest;
+        if manifest.format != BUNDLE_FORMAT {
+            return Err(anyhow::anyhow!("not a workspace bundle: format '{}'", manifest.format));
+        }
+        if manifest.schema_version > BUNDLE_SCHEMA_VERSION {
+            return Err(anyhow::anyhow!(
+                "bundle schema version {} is newer than the supported {}", manifest.schema_version, BUNDLE_SCHEMA_VERSION
+            ));
+        }
+        let counts = [
+            ("documents", manifest.document_count, self.documents.len()),
+            ("collection_rows", manifest.collection_row_count, self.collection_rows.len()),
+            ("links", manifest.link_count, self.links.len()),
+        ];
+        for (section, declared, found) in counts {
+            if declared != found {
+                return Err(anyhow::anyhow!("section '{}' holds {} entries, the manifest declares {}", section, found, declared));
+            }
+        }
+        let checksums = self.section_checksums()?;
+        for (section, checksum) in &checksums {
+            if manifest.checksums.get(section) != Some(checksum) {
+                return Err(anyhow::anyhow!("checksum mismatch in section '{}'", section));
+            }
+        }
+        Ok(())
+    }
+
+    fn section_checksums(&self) -> anyhow::Result<BTreeMap<String, String>> {
+        Ok(BTreeMap::from([
+            ("documents".to_string(), checksum(&self.documents)?),
+            ("collection_rows".to_string(), checksum(&self.collection_rows)?),
+            ("links".to_string(), checksum(&self.links)?),
+        ]))
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Parses and verifies a bundle.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let bundle: Self = serde_json::from_slice(bytes)
+            .map_err(|e| anyhow::anyhow!("unreadable workspace bundle: {}", e))?;
+        bundle.verify()?;
+        Ok(bundle)
+    }
+}
+
+fn checksum<T: Serialize>(section: &T) -> anyhow::Result<String> {
+    Ok(hex::encode(Sha256::digest(serde_json::to_vec(section)?)))
+}
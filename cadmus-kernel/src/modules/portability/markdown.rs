@@ -0,0 +1,355 @@
+//! Markdown Vaults
+//!
+//! Converts between a workspace bundle and a folder of Markdown notes, as kept by Obsidian
+//! and similar tools:
+//!
+//! - Each note is a document titled after its file name. A folder is the document its notes
+//!   belong to; its own text and properties live in the sibling `Folder.md`, when there is one.
+//! - YAML front matter holds the properties. `tags` becomes a list without `#`, and two keys
+//!   are reserved: `class` sets the archetype, and `links` lists links that are not in the text.
+//! - `[[wikilinks]]` anywhere in a note become document links. A target is matched, ignoring
+//!   case, against the note's path inside the vault, then against note names.
+//!
+//! Collection rows have no place in a vault and are not exported.
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+use yrs::Transact;
+use crate::modules::content::extract::DocumentContent;
+use crate::modules::content::history;
+use crate::modules::content::markdown::markdown_to_state;
+use super::bundle::{BundleDocument, BundleLink, WorkspaceBundle};
+
+/// Class of documents created from notes that do not name one.
+const DEFAULT_CLASS: &str = "note";
+
+/// A note read from the vault, before its links are resolved.
+struct Note {
+    doc: BundleDocument,
+    /// Path inside the vault, without the extension.
+    path: String,
+    source: String,
+}
+
+/// Reads every note under `root` into a bundle owned by `owner_id`. Hidden files and folders
+/// are skipped.
+pub fn read_vault(root: &Path, owner_id: Uuid) -> Result<WorkspaceBundle> {
+    if !root.is_dir() {
+        return Err(anyhow::anyhow!("vault '{}' is not a folder", root.display()));
+    }
+    let mut notes = Vec::new();
+    read_folder(root, None, "", &mut notes)?;
+
+    let resolver = Resolver::new(notes.iter().map(|n| (n.path.as_str(), n.doc.id)));
+    let mut links = Vec::new();
+    let mut seen = HashSet::new();
+    for note in &notes {
+        for target in resolver.resolve_all(&note.source) {
+            if target != note.doc.id && seen.insert((note.doc.id, target)) {
+                links.push(BundleLink::new(note.doc.id, target));
+            }
+        }
+    }
+
+    let documents = notes.into_iter().map(|n| n.doc).collect();
+    WorkspaceBundle::new(owner_id, documents, Vec::new(), links)
+}
+
+fn read_folder(dir: &Path, parent_id: Option<Uuid>, prefix: &str, notes: &mut Vec<Note>) -> Result<()> {
+    let mut folders = Vec::new();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("cannot read '{}'", dir.display()))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            folders.push(name);
+        } else if let Some(stem) = markdown_stem(&name) {
+            files.push((stem.to_string(), name));
+        }
+    }
+    folders.sort();
+    files.sort();
+
+    for folder in &folders {
+        let path = format!("{}{}", prefix, folder);
+        let note = match files.iter().position(|(stem, _)| stem == folder) {
+            Some(i) => {
+                let (stem, name) = files.remove(i);
+                read_note(&dir.join(name), stem, path.clone(), parent_id)?
+            },
+            None => empty_note(folder.clone(), path.clone(), parent_id),
+        };
+        let id = note.doc.id;
+        notes.push(note);
+        read_folder(&dir.join(folder), Some(id), &format!("{}/", path), notes)?;
+    }
+    for (stem, name) in files {
+        let path = format!("{}{}", prefix, stem);
+        notes.push(read_note(&dir.join(name), stem, path, parent_id)?);
+    }
+    Ok(())
+}
+
+fn markdown_stem(name: &str) -> Option<&str> {
+    let (stem, ext) = name.rsplit_once('.')?;
+    (ext.eq_ignore_ascii_case("md") && !stem.is_empty()).then_some(stem)
+}
+
+fn empty_note(title: String, path: String, parent_id: Option<Uuid>) -> Note {
+    Note { doc: document(title, parent_id, Map::new(), None), path, source: String::new() }
+}
+
+fn read_note(file: &Path, title: String, path: String, parent_id: Option<Uuid>) -> Result<Note> {
+    let source = fs::read_to_string(file).with_context(|| format!("cannot read '{}'", file.display()))?;
+    let (front_matter, body) = split_front_matter(&source);
+    let properties = match front_matter {
+        Some(yaml) => parse_front_matter(yaml).with_context(|| format!("invalid front matter in '{}'", file.display()))?,
+        None => Map::new(),
+    };
+    let content = (!body.trim().is_empty()).then(|| hex::encode(markdown_to_state(body)));
+    Ok(Note { doc: document(title, parent_id, properties, content), path, source })
+}
+
+fn document(title: String, parent_id: Option<Uuid>, mut properties: Map<String, Value>, content: Option<String>) -> BundleDocument {
+    let class_id = match properties.remove("class") {
+        Some(Value::String(class)) if !class.trim().is_empty() => class.trim().to_string(),
+        _ => DEFAULT_CLASS.to_string(),
+    };
+    properties.remove("links");
+    if let Some(tags) = properties.remove("tags") {
+        properties.insert("tags".to_string(), normalize_tags(tags));
+    }
+    BundleDocument {
+        id: Uuid::new_v4(),
+        parent_id,
+        title,
+        class_id: Some(class_id),
+        properties: Value::Object(properties),
+        config: Value::Object(Map::new()),
+        is_public: false,
+        content,
+        sheet: None,
+    }
+}
+
+/// Splits a leading `---` block from the body. Returns the YAML, if any, and the rest.
+fn split_front_matter(source: &str) -> (Option<&str>, &str) {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let Some(rest) = source.strip_prefix("---\n").or_else(|| source.strip_prefix("---\r\n")) else {
+        return (None, source);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, source)
+}
+
+fn parse_front_matter(yaml: &str) -> Result<Map<String, Value>> {
+    if yaml.trim().is_empty() {
+        return Ok(Map::new());
+    }
+    match serde_json::to_value(serde_yaml::from_str::<serde_yaml::Value>(yaml)?)? {
+        Value::Object(map) => Ok(map),
+        Value::Null => Ok(Map::new()),
+        _ => Err(anyhow::anyhow!("front matter is not a mapping")),
+    }
+}
+
+/// Tags as a list of names, whether written as a list or a comma or space separated string.
+fn normalize_tags(tags: Value) -> Value {
+    let names: Vec<String> = match tags {
+        Value::Array(items) => items.into_iter()
+            .filter_map(|t| match t {
+                Value::String(s) => Some(s),
+                Value::Null => None,
+                other => Some(other.to_string()),
+            })
+            .collect(),
+        Value::String(s) => s.split([',', ' ']).map(str::to_string).collect(),
+        Value::Null => Vec::new(),
+        other => vec![other.to_string()],
+    };
+    names.iter()
+        .map(|t| t.trim().trim_start_matches('#'))
+        .filter(|t| !t.is_empty())
+        .map(|t| Value::String(t.to_string()))
+        .collect()
+}
+
+/// The targets of the `[[wikilinks]]` in `text`, without aliases and headings.
+fn wikilinks(text: &str) -> Vec<&str> {
+    let mut targets = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else { break };
+        let inner = &rest[..end];
+        if !inner.contains('\n') {
+            let target = inner.split(['|', '#']).next().unwrap_or_default().trim();
+            if !target.is_empty() {
+                targets.push(target);
+            }
+        }
+        rest = &rest[end + 2..];
+    }
+    targets
+}
+
+/// Matches wikilink targets to documents by vault path, then by note name.
+struct Resolver {
+    paths: HashMap<String, Uuid>,
+    names: HashMap<String, Uuid>,
+}
+
+impl Resolver {
+    fn new<'a>(notes: impl Iterator<Item = (&'a str, Uuid)>) -> Self {
+        let mut paths = HashMap::new();
+        let mut names = HashMap::new();
+        for (path, id) in notes {
+            let path = path.to_lowercase();
+            let name = path.rsplit('/').next().unwrap_or_default().to_string();
+            // The first note of a name wins, as notes are read in path order.
+            names.entry(name).or_insert(id);
+            paths.insert(path, id);
+        }
+        Self { paths, names }
+    }
+
+    fn resolve(&self, target: &str) -> Option<Uuid> {
+        let target = target.to_lowercase();
+        let target = target.strip_suffix(".md").unwrap_or(&target).trim_start_matches('/');
+        self.paths.get(target).or_else(|| self.names.get(target)).copied()
+    }
+
+    fn resolve_all(&self, text: &str) -> Vec<Uuid> {
+        wikilinks(text).into_iter().filter_map(|t| self.resolve(t)).collect()
+    }
+}
+
+/// Writes the documents of `bundle` under `root` as Markdown notes, in the layout
+/// `read_vault` reads. `root` must be missing or empty. Returns the number of documents written.
+pub fn write_vault(bundle: &WorkspaceBundle, root: &Path) -> Result<usize> {
+    bundle.verify()?;
+    if root.exists() && fs::read_dir(root)?.next().is_some() {
+        return Err(anyhow::anyhow!("'{}' is not empty", root.display()));
+    }
+    fs::create_dir_all(root)?;
+
+    let ids: HashSet<Uuid> = bundle.documents.iter().map(|d| d.id).collect();
+    let mut children: HashMap<Option<Uuid>, Vec<&BundleDocument>> = HashMap::new();
+    for doc in &bundle.documents {
+        let parent = doc.parent_id.filter(|p| ids.contains(p));
+        children.entry(parent).or_default().push(doc);
+    }
+
+    // Name every document first, so links can point at notes not yet written.
+    let mut paths: HashMap<Uuid, String> = HashMap::new();
+    let mut pending = vec![(None, String::new())];
+    while let Some((parent, prefix)) = pending.pop() {
+        let mut taken = HashSet::new();
+        for doc in children.get(&parent).into_iter().flatten() {
+            let name = unique_name(&doc.title, &mut taken);
+            let path = format!("{}{}", prefix, name);
+            pending.push((Some(doc.id), format!("{}/", path)));
+            paths.insert(doc.id, path);
+        }
+    }
+
+    let resolver = Resolver::new(paths.iter().map(|(id, path)| (path.as_str(), *id)));
+    let mut targets: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for link in &bundle.links {
+        let listed = targets.entry(link.from_id).or_default();
+        // Notes link to one another once, whatever the relations between their documents.
+        if paths.contains_key(&link.to_id) && !listed.contains(&link.to_id) {
+            listed.push(link.to_id);
+        }
+    }
+
+    for doc in &bundle.documents {
+        let path = &paths[&doc.id];
+        let body = match &doc.content {
+            Some(state) => {
+                let state = hex::decode(state).with_context(|| format!("invalid content for '{}'", doc.title))?;
+                let ydoc = history::replay(vec![state]);
+                DocumentContent::extract(&ydoc.transact()).markdown
+            },
+            None => String::new(),
+        };
+
+        let mut front_matter = match &doc.properties {
+            Value::Object(map) => map.clone(),
+            _ => Map::new(),
+        };
+        if let Some(class) = doc.class_id.as_deref().filter(|c| *c != DEFAULT_CLASS) {
+            front_matter.insert("class".to_string(), Value::String(class.to_string()));
+        }
+        let in_body: HashSet<Uuid> = resolver.resolve_all(&body).into_iter().collect();
+        let extra: Vec<Value> = targets.get(&doc.id).into_iter().flatten()
+            .filter(|t| !in_body.contains(t))
+            .map(|t| Value::String(format!("[[{}]]", paths[t])))
+            .collect();
+        if !extra.is_empty() {
+            front_matter.insert("links".to_string(), Value::Array(extra));
+        }
+
+        let file = root.join(format!("{}.md", path));
+        if children.contains_key(&Some(doc.id)) {
+            fs::create_dir_all(root.join(path))?;
+            if body.trim().is_empty() && front_matter.is_empty() {
+                continue;
+            }
+        }
+        if let Some(folder) = file.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        fs::write(&file, render_note(&front_matter, &body)?).with_context(|| format!("cannot write '{}'", file.display()))?;
+    }
+
+    tracing::info!("[Portability] WROTE {} notes to {}", bundle.documents.len(), root.display());
+    Ok(bundle.documents.len())
+}
+
+fn render_note(front_matter: &Map<String, Value>, body: &str) -> Result<String> {
+    let mut note = String::new();
+    if !front_matter.is_empty() {
+        note.push_str("---\n");
+        note.push_str(&serde_yaml::to_string(front_matter)?);
+        note.push_str("---\n");
+        if !body.is_empty() {
+            note.push('\n');
+        }
+    }
+    if !body.is_empty() {
+        note.push_str(body);
+        note.push('\n');
+    }
+    Ok(note)
+}
+
+/// A file name for `title` that no sibling uses yet, compared ignoring case.
+fn unique_name(title: &str, taken: &mut HashSet<String>) -> String {
+    let cleaned: String = title.chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '-' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    let base = if cleaned.is_empty() { "Untitled" } else { cleaned };
+
+    let mut name = base.to_string();
+    let mut n = 2;
+    while !taken.insert(name.to_lowercase()) {
+        name = format!("{} {}", base, n);
+        n += 1;
+    }
+    name
+}
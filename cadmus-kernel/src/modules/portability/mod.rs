@@ -0,0 +1,3 @@
+pub mod bundle;
+pub mod markdown;
+pub mod service;
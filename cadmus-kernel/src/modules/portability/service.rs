@@ -0,0 +1,338 @@
+use anyhow::Result;
+use sqlx::{Postgres, Sqlite, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use serde_json::json;
+use uuid::Uuid;
+use crate::modules::content::history;
+use crate::modules::content::storage::{ContentStorage, DbPool, SHEET_SNAPSHOT_PREFIX};
+use crate::modules::sync::store::SyncStore;
+use super::bundle::{BundleCollectionRow, BundleDocument, BundleLink, ImportReport, WorkspaceBundle};
+use super::markdown;
+
+type PgDocumentRow = (Uuid, Option<Uuid>, String, Option<String>, Option<serde_json::Value>, serde_json::Value, Option<bool>);
+type SqliteDocumentRow = (String, Option<String>, String, Option<String>, String, Option<String>, Option<bool>);
+type Sections = (Vec<BundleDocument>, Vec<BundleCollectionRow>, Vec<BundleLink>);
+
+/// Moves a user's workspace in and out of a `WorkspaceBundle`, on either backend.
+///
+/// An import never overwrites anything: every document and collection row gets a new id, and
+/// parents, links and rows are remapped to them, so a bundle can be imported next to the
+/// workspace it came from, or twice. The whole import is one transaction.
+pub struct PortabilityService {
+    pool: DbPool,
+    storage: ContentStorage,
+}
+
+impl PortabilityService {
+    pub fn new(pool: DbPool) -> Self {
+        Self { storage: ContentStorage::new(pool.clone()), pool }
+    }
+
+    /// Reads the documents of `owner_id`, their rows and content, and the links between them.
+    pub async fn export(&self, owner_id: Uuid) -> Result<WorkspaceBundle> {
+        let (mut documents, collection_rows, links) = match &self.pool {
+            DbPool::Postgres(p) => {
+                let mut tx = SyncStore::authenticated_tx(p, owner_id).await?;
+                let sections = Self::pg_read(&mut tx, owner_id).await?;
+                tx.commit().await?;
+                sections
+            },
+            DbPool::Sqlite(p) => {
+                let mut tx = p.begin().await?;
+                let sections = Self::sqlite_read(&mut tx, owner_id).await?;
+                tx.commit().await?;
+                sections
+            }
+        };
+
+        for doc in &mut documents {
+            let updates: Vec<Vec<u8>> = self.storage.load_updates(&doc.id.to_string()).await?
+                .into_iter()
+                .filter(|u| !u.starts_with(SHEET_SNAPSHOT_PREFIX))
+                .collect();
+            if !updates.is_empty() {
+                doc.content = Some(hex::encode(history::encode_state(&history::replay(updates))));
+            }
+            doc.sheet = self.storage.load_latest_snapshot(&doc.id.to_string()).await?.map(hex::encode);
+        }
+
+        tracing::info!("[Portability] EXPORTED {} documents for {}", documents.len(), owner_id);
+        WorkspaceBundle::new(owner_id, documents, collection_rows, links)
+    }
+
+    async fn pg_read(tx: &mut Transaction<'_, Postgres>, owner_id: Uuid) -> Result<Sections> {
+        let rows: Vec<PgDocumentRow> = sqlx::query_as(
+            "SELECT id, parent_id, title, class_id, properties, config, is_public FROM documents
+             WHERE owner_id = $1 AND deleted_at IS NULL ORDER BY created_at, id"
+        )
+        .bind(owner_id)
+        .fetch_all(&mut **tx)
+        .await?;
+        let documents = rows.into_iter()
+            .map(|(id, parent_id, title, class_id, properties, config, is_public)| BundleDocument {
+                id, parent_id, title, class_id,
+                properties: properties.unwrap_or_default(),
+                config,
+                is_public: is_public.unwrap_or(false),
+                content: None,
+                sheet: None,
+            })
+            .collect();
+
+        let rows: Vec<(Uuid, Uuid, serde_json::Value, i32)> = sqlx::query_as(
+            "SELECT r.id, r.document_id, r.data, r.order_index FROM collection_rows r
+             JOIN documents d ON d.id = r.document_id
+             WHERE d.owner_id = $1 AND d.deleted_at IS NULL ORDER BY r.document_id, r.order_index, r.id"
+        )
+        .bind(owner_id)
+        .fetch_all(&mut **tx)
+        .await?;
+        let collection_rows = rows.into_iter()
+            .map(|(id, document_id, data, order_index)| BundleCollectionRow { id, document_id, data, order_index: Some(order_index as i64) })
+            .collect();
+
+        let rows: Vec<(Uuid, Uuid, String, serde_json::Value)> = sqlx::query_as(
+            "SELECT l.from_id, l.to_id, l.relation, l.metadata FROM document_links l
+             JOIN documents f ON f.id = l.from_id
+             JOIN documents t ON t.id = l.to_id
+             WHERE f.owner_id = $1 AND t.owner_id = $1 AND f.deleted_at IS NULL AND t.deleted_at IS NULL ORDER BY l.from_id, l.to_id, l.relation"
+        )
+        .bind(owner_id)
+        .fetch_all(&mut **tx)
+        .await?;
+        let links = rows.into_iter()
+            .map(|(from_id, to_id, relation, metadata)| BundleLink { from_id, to_id, relation, metadata: non_empty(metadata) })
+            .collect();
+
+        Ok((documents, collection_rows, links))
+    }
+
+    async fn sqlite_read(tx: &mut Transaction<'_, Sqlite>, owner_id: Uuid) -> Result<Sections> {
+        let rows: Vec<SqliteDocumentRow> = sqlx::query_as(
+            "SELECT id, parent_id, title, class_id, properties, config, is_public FROM documents
+             WHERE owner_id = ? AND deleted_at IS NULL ORDER BY created_at, id"
+        )
+        .bind(owner_id.to_string())
+        .fetch_all(&mut **tx)
+        .await?;
+        let documents = rows.into_iter()
+            .filter_map(|(id, parent_id, title, class_id, properties, config, is_public)| Some(BundleDocument {
+                id: Uuid::parse_str(&id).ok()?,
+                parent_id: parent_id.and_then(|p| Uuid::parse_str(&p).ok()),
+                title,
+                class_id,
+                properties: serde_json::from_str(&properties).unwrap_or_default(),
+                config: config.and_then(|c| serde_json::from_str(&c).ok()).unwrap_or_else(|| serde_json::json!({})),
+                is_public: is_public.unwrap_or(false),
+                content: None,
+                sheet: None,
+            }))
+            .collect();
+
+        let rows: Vec<(String, String, String, Option<i64>)> = sqlx::query_as(
+            "SELECT r.id, r.document_id, r.data, r.order_index FROM collection_rows r
+             JOIN documents d ON d.id = r.document_id
+             WHERE d.owner_id = ? AND d.deleted_at IS NULL ORDER BY r.document_id, r.order_index, r.id"
+        )
+        .bind(owner_id.to_string())
+        .fetch_all(&mut **tx)
+        .await?;
+        let collection_rows = rows.into_iter()
+            .filter_map(|(id, document_id, data, order_index)| Some(BundleCollectionRow {
+                id: Uuid::parse_str(&id).ok()?,
+                document_id: Uuid::parse_str(&document_id).ok()?,
+                data: serde_json::from_str(&data).unwrap_or_default(),
+                order_index,
+            }))
+            .collect();
+
+        let rows: Vec<(String, String, String, String)> = sqlx::query_as(
+            "SELECT l.from_id, l.to_id, l.relation, l.metadata FROM document_links l
+             JOIN documents f ON f.id = l.from_id
+             JOIN documents t ON t.id = l.to_id
+             WHERE f.owner_id = ?1 AND t.owner_id = ?1 AND f.deleted_at IS NULL AND t.deleted_at IS NULL ORDER BY l.from_id, l.to_id, l.relation"
+        )
+        .bind(owner_id.to_string())
+        .fetch_all(&mut **tx)
+        .await?;
+        let links = rows.into_iter()
+            .filter_map(|(from_id, to_id, relation, metadata)| Some(BundleLink {
+                from_id: Uuid::parse_str(&from_id).ok()?,
+                to_id: Uuid::parse_str(&to_id).ok()?,
+                relation,
+                metadata: non_empty(serde_json::from_str(&metadata).unwrap_or_default()),
+            }))
+            .collect();
+
+        Ok((documents, collection_rows, links))
+    }
+
+    /// Recreates a verified bundle in the workspace of `owner_id`, under new ids.
+    ///
+    /// Parents that are not in the bundle become roots, and links to documents that are not in
+    /// it are dropped. Collection rows of unknown documents make the bundle invalid. The tree is
+    /// kept as exported, without checking `allowed_children` against the current archetypes.
+    pub async fn import(&self, owner_id: Uuid, bundle: &WorkspaceBundle) -> Result<ImportReport> {
+        bundle.verify()?;
+        let id_map: HashMap<Uuid, Uuid> = bundle.documents.iter().map(|d| (d.id, Uuid::new_v4())).collect();
+        if id_map.len() != bundle.documents.len() {
+            return Err(anyhow::anyhow!("bundle holds the same document twice"));
+        }
+        if let Some(row) = bundle.collection_rows.iter().find(|r| !id_map.contains_key(&r.document_id)) {
+            return Err(anyhow::anyhow!("collection row '{}' belongs to a document missing from the bundle", row.id));
+        }
+
+        let documents: Vec<BundleDocument> = bundle.documents.iter()
+            .map(|d| BundleDocument {
+                id: id_map[&d.id],
+                parent_id: d.parent_id.and_then(|p| id_map.get(&p).copied()),
+                ..d.clone()
+            })
+            .collect();
+        let collection_rows: Vec<BundleCollectionRow> = bundle.collection_rows.iter()
+            .map(|r| BundleCollectionRow { id: Uuid::new_v4(), document_id: id_map[&r.document_id], ..r.clone() })
+            .collect();
+        let mut seen = HashSet::new();
+        let links: Vec<BundleLink> = bundle.links.iter()
+            .filter_map(|l| Some(BundleLink { from_id: *id_map.get(&l.from_id)?, to_id: *id_map.get(&l.to_id)?, ..l.clone() }))
+            .filter(|l| seen.insert((l.from_id, l.to_id, l.relation.clone())))
+            .collect();
+        let contents = documents.iter()
+            .map(|d| Ok((d.id, decode_hex(&d.content)?, decode_hex(&d.sheet)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        match &self.pool {
+            DbPool::Postgres(p) => {
+                let mut tx = SyncStore::authenticated_tx(p, owner_id).await?;
+                for d in &documents {
+                    sqlx::query(
+                        "INSERT INTO documents (id, owner_id, parent_id, title, class_id, properties, config, is_public)
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+                    )
+                    .bind(d.id)
+                    .bind(owner_id)
+                    .bind(d.parent_id)
+                    .bind(&d.title)
+                    .bind(&d.class_id)
+                    .bind(&d.properties)
+                    .bind(&d.config)
+                    .bind(d.is_public)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                for r in &collection_rows {
+                    sqlx::query(
+                        "INSERT INTO collection_rows (id, document_id, data, order_index)
+                         VALUES ($1, $2, $3, COALESCE($4, nextval(pg_get_serial_sequence('collection_rows', 'order_index'))))"
+                    )
+                    .bind(r.id)
+                    .bind(r.document_id)
+                    .bind(&r.data)
+                    .bind(r.order_index.map(|i| i as i32))
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                for l in &links {
+                    sqlx::query("INSERT INTO document_links (from_id, to_id, relation, metadata) VALUES ($1, $2, $3, $4)")
+                        .bind(l.from_id)
+                        .bind(l.to_id)
+                        .bind(&l.relation)
+                        .bind(l.metadata.clone().unwrap_or_else(|| json!({})))
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                for (doc_id, content, sheet) in &contents {
+                    for data in [content, sheet].into_iter().flatten() {
+                        sqlx::query("INSERT INTO document_updates (doc_id, data, author_id) VALUES ($1, $2, $3)")
+                            .bind(doc_id)
+                            .bind(data)
+                            .bind(owner_id)
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                }
+                tx.commit().await?;
+            },
+            DbPool::Sqlite(p) => {
+                let mut tx = p.begin().await?;
+                for d in &documents {
+                    sqlx::query(
+                        "INSERT INTO documents (id, owner_id, parent_id, title, class_id, properties, config, is_public)
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+                    )
+                    .bind(d.id.to_string())
+                    .bind(owner_id.to_string())
+                    .bind(d.parent_id.map(|id| id.to_string()))
+                    .bind(&d.title)
+                    .bind(&d.class_id)
+                    .bind(d.properties.to_string())
+                    .bind(d.config.to_string())
+                    .bind(d.is_public)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                for r in &collection_rows {
+                    sqlx::query("INSERT INTO collection_rows (id, document_id, data, order_index) VALUES (?, ?, ?, ?)")
+                        .bind(r.id.to_string())
+                        .bind(r.document_id.to_string())
+                        .bind(r.data.to_string())
+                        .bind(r.order_index)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                for l in &links {
+                    sqlx::query("INSERT INTO document_links (from_id, to_id, relation, metadata) VALUES (?, ?, ?, ?)")
+                        .bind(l.from_id.to_string())
+                        .bind(l.to_id.to_string())
+                        .bind(&l.relation)
+                        .bind(l.metadata.as_ref().map_or_else(|| "{}".to_string(), |m| m.to_string()))
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                for (doc_id, content, sheet) in &contents {
+                    for data in [content, sheet].into_iter().flatten() {
+                        sqlx::query("INSERT INTO document_updates (doc_id, data, author_id) VALUES (?, ?, ?)")
+                            .bind(doc_id.to_string())
+                            .bind(data)
+                            .bind(owner_id.to_string())
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                }
+                tx.commit().await?;
+            }
+        }
+
+        tracing::info!("[Portability] IMPORTED {} documents for {}", documents.len(), owner_id);
+        Ok(ImportReport { documents: documents.len(), collection_rows: collection_rows.len(), links: links.len(), id_map })
+    }
+
+    /// Imports the Markdown vault at `root`; see [`super::markdown`] for how notes map to documents.
+    pub async fn import_markdown(&self, owner_id: Uuid, root: &Path) -> Result<ImportReport> {
+        let root = root.to_path_buf();
+        let bundle = tokio::task::spawn_blocking(move || markdown::read_vault(&root, owner_id)).await??;
+        self.import(owner_id, &bundle).await
+    }
+
+    /// Writes the workspace of `owner_id` as a Markdown vault under `root`, which must be
+    /// missing or empty. Returns the number of documents written.
+    pub async fn export_markdown(&self, owner_id: Uuid, root: &Path) -> Result<usize> {
+        let bundle = self.export(owner_id).await?;
+        let root = root.to_path_buf();
+        tokio::task::spawn_blocking(move || markdown::write_vault(&bundle, &root)).await?
+    }
+}
+
+fn decode_hex(data: &Option<String>) -> Result<Option<Vec<u8>>> {
+    data.as_deref().map(|d| hex::decode(d).map_err(|e| anyhow::anyhow!("invalid content encoding: {}", e))).transpose()
+}
+
+/// Link metadata as stored in a bundle: left out when there is none.
+fn non_empty(metadata: serde_json::Value) -> Option<serde_json::Value> {
+    match &metadata {
+        serde_json::Value::Object(map) if map.is_empty() => None,
+        serde_json::Value::Null => None,
+        _ => Some(metadata),
+    }
+}
@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use uuid::Uuid;
+use crate::modules::content::workspace::WorkspaceNode;
+
+/// Documents per page of a query that does not ask for a size.
+pub const DEFAULT_LIMIT: u32 = 50;
+
+/// Largest page a query may ask for.
+pub const MAX_LIMIT: u32 = 500;
+
+/// Most filters one query may combine.
+pub const MAX_FILTERS: usize = 32;
+
+/// Longest accepted property key.
+pub const MAX_KEY_LEN: usize = 64;
+
+/// A listing of the caller's documents, shared by the API and the desktop app. A document is
+/// listed when its class is one of `classes` and every filter holds; results come in `sort`
+/// order, a page at a time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocumentQuery {
+    /// Classes to list; any class when empty.
+    pub classes: Vec<String>,
+    pub filters: Vec<PropertyFilter>,
+    pub sort: Sort,
+    /// Documents per page, `DEFAULT_LIMIT` when `None`.
+    pub limit: Option<u32>,
+    /// `next_cursor` of the previous page, to list the documents after it.
+    pub cursor: Option<String>,
+}
+
+/// A condition on the property `key`, e.g. `{"key": "priority", "op": "gte", "value": 2}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyFilter {
+    pub key: String,
+    #[serde(flatten)]
+    pub op: FilterOp,
+}
+
+/// How a property is compared. Equality and ranges only hold between values of the same JSON
+/// type, so a property holding the string "4" is never greater than the number 3. Strings
+/// compare by bytes, whatever the database's collation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum FilterOp {
+    /// Equal to a string, number or boolean.
+    Eq(Value),
+    /// Greater than a string or number.
+    Gt(Value),
+    Gte(Value),
+    Lt(Value),
+    Lte(Value),
+    /// A string containing this text, ignoring ASCII case.
+    Contains(String),
+    /// Set (`true`) or missing (`false`). A property set to null is set.
+    Exists(bool),
+    /// An array holding this string, as `tags` does.
+    HasTag(String),
+}
+
+/// What documents are ordered by. Values are ordered by JSON type first: missing or null, then
+/// strings, numbers, booleans, and last arrays and objects, which are not ordered among
+/// themselves. Strings, titles included, are ordered by bytes, so both backends list documents
+/// the same way.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    Title,
+    CreatedAt,
+    UpdatedAt,
+    Property(String),
+}
+
+/// Order of a listing. Documents with the same sort value are ordered by id, so pages never
+/// overlap.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sort {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+/// A page of a listing. `next_cursor` is `None` on the last page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentPage {
+    pub documents: Vec<WorkspaceNode>,
+    pub next_cursor: Option<String>,
+}
+
+/// Position after the last document of a page: its sort value, as JSON, and its id. Clients
+/// only see it encoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub value: Value,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        hex::encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, QueryError> {
+        hex::decode(cursor).ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or(QueryError::InvalidCursor)
+    }
+
+    /// Position of the value's JSON type in `SortField` order, as the repositories rank it.
+    pub fn rank(&self) -> i32 {
+        match self.value {
+            Value::Null => 0,
+            Value::String(_) => 1,
+            Value::Number(_) => 2,
+            Value::Bool(_) => 3,
+            Value::Array(_) | Value::Object(_) => 4,
+        }
+    }
+}
+
+/// Why a query was refused.
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("invalid property key '{0}': use 1 to 64 letters, digits, underscores or hyphens")]
+    InvalidKey(String),
+    #[error("filter on '{key}' cannot compare with {value}")]
+    InvalidValue { key: String, value: Value },
+    #[error("a query combines at most {MAX_FILTERS} filters, got {0}")]
+    TooManyFilters(usize),
+    #[error("limit must be between 1 and {MAX_LIMIT}, got {0}")]
+    InvalidLimit(u32),
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl QueryError {
+    /// True for refusals caused by the query itself, as opposed to storage failures.
+    pub fn is_validation(&self) -> bool {
+        !matches!(self, Self::Internal(_))
+    }
+}
+
+impl DocumentQuery {
+    /// Checks the keys, the values compared with, the limit and the cursor. Repositories only
+    /// run validated queries, which keeps keys safe to embed in JSON paths.
+    pub fn validate(&self) -> Result<(), QueryError> {
+        if self.filters.len() > MAX_FILTERS {
+            return Err(QueryError::TooManyFilters(self.filters.len()));
+        }
+        for filter in &self.filters {
+            validate_key(&filter.key)?;
+            let valid = match &filter.op {
+                FilterOp::Eq(v) => v.is_string() || v.is_number() || v.is_boolean(),
+                FilterOp::Gt(v) | FilterOp::Gte(v) | FilterOp::Lt(v) | FilterOp::Lte(v) => v.is_string() || v.is_number(),
+                FilterOp::Contains(_) | FilterOp::Exists(_) | FilterOp::HasTag(_) => true,
+            };
+            if !valid {
+                return Err(QueryError::InvalidValue { key: filter.key.clone(), value: filter.op.value() });
+            }
+        }
+        if let SortField::Property(key) = &self.sort.field {
+            validate_key(key)?;
+        }
+        if let Some(limit) = self.limit
+            && !(1..=MAX_LIMIT).contains(&limit)
+        {
+            return Err(QueryError::InvalidLimit(limit));
+        }
+        self.after()?;
+        Ok(())
+    }
+
+    pub fn page_size(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT)
+    }
+
+    /// The decoded cursor, if the query continues a listing.
+    pub fn after(&self) -> Result<Option<Cursor>, QueryError> {
+        self.cursor.as_deref().map(Cursor::decode).transpose()
+    }
+}
+
+impl FilterOp {
+    /// The value the property is compared with.
+    pub fn value(&self) -> Value {
+        match self {
+            Self::Eq(v) | Self::Gt(v) | Self::Gte(v) | Self::Lt(v) | Self::Lte(v) => v.clone(),
+            Self::Contains(s) | Self::HasTag(s) => Value::String(s.clone()),
+            Self::Exists(b) => Value::Bool(*b),
+        }
+    }
+}
+
+/// Checks a property key: ASCII letters, digits, underscores and hyphens.
+pub fn validate_key(key: &str) -> Result<(), QueryError> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    if valid { Ok(()) } else { Err(QueryError::InvalidKey(key.to_string())) }
+}
@@ -0,0 +1,23 @@
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::domain::repository::DocumentRepository;
+use super::domain::{DocumentPage, DocumentQuery, QueryError};
+
+/// Filtered, sorted and paginated listings of a user's live documents. Filters compile to
+/// JSONB operators on Postgres and `json_extract` on SQLite; pages continue from a cursor
+/// rather than an offset, so documents created or deleted meanwhile do not shift them.
+pub struct QueryService {
+    documents: Arc<dyn DocumentRepository>,
+}
+
+impl QueryService {
+    pub fn new(documents: Arc<dyn DocumentRepository>) -> Self {
+        Self { documents }
+    }
+
+    /// A page of the documents of `owner_id` matching `query`.
+    pub async fn query(&self, owner_id: Uuid, query: &DocumentQuery) -> Result<DocumentPage, QueryError> {
+        query.validate()?;
+        Ok(self.documents.query(owner_id, query).await?)
+    }
+}
@@ -10,6 +10,29 @@ pub struct User {
     pub token: Option<String>,
 }
 
+/// A user as stored, with the hash their password is checked against.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub id: Uuid,
+    pub username: String,
+    pub password_hash: String,
+    pub avatar_url: Option<String>,
+}
+
+/// A validated token: who it belongs to and when it stops being accepted.
+#[derive(Debug, Clone, Copy)]
+pub struct Session {
+    pub user_id: Uuid,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Session {
+    /// Time left before the token expires, `None` if it never does.
+    pub fn remaining(&self) -> Option<std::time::Duration> {
+        self.expires_at.map(|exp| (exp - chrono::Utc::now()).to_std().unwrap_or_default())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PasskeyCredential {
     pub id: Uuid,
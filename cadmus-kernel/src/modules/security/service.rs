@@ -14,29 +14,22 @@ use pasetors::keys::SymmetricKey;
 use pasetors::version4::V4;
 use pasetors::claims::Claims;
 use serde_json::json;
-use super::domain::{LoginRequest, RegisterRequest, User};
+use super::domain::{LoginRequest, RegisterRequest, Session, User};
+use crate::domain::repository::UserRepository;
 use crate::shared::errors::KernelError;
-use sqlx::PgPool;
+use std::sync::Arc;
 use uuid::Uuid;
 
 /// Service responsible for identity management and cryptographic operations.
 pub struct SecurityService {
-    db: PgPool,
+    users: Arc<dyn UserRepository>,
     key: SymmetricKey<V4>,
 }
 
-#[derive(sqlx::FromRow)]
-struct UserRecord {
-    id: Uuid,
-    username: String,
-    password_hash: String,
-    avatar_url: Option<String>,
-}
-
 impl SecurityService {
     /// Initializes a new SecurityService. 
     /// Expects PASETO_SECRET environment variable for token encryption.
-    pub fn new(db: PgPool) -> Self {
+    pub fn new(users: Arc<dyn UserRepository>) -> Self {
         let secret = std::env::var("PASETO_SECRET").unwrap_or_else(|_| "emergency_fallback_secret_32bytes_!!".to_string());
         let mut key_bytes = [0u8; 32];
         let secret_bytes = secret.as_bytes();
@@ -44,7 +37,7 @@ impl SecurityService {
         key_bytes[..len].copy_from_slice(&secret_bytes[..len]);
         
         let key = SymmetricKey::<V4>::from(&key_bytes).unwrap();
-        Self { db, key }
+        Self { users, key }
     }
 
     /// Generates a signed Paseto V4 local token for a given user ID.
@@ -62,6 +55,12 @@ impl SecurityService {
 
     /// Validates a Paseto token and extracts the contained user ID.
     pub fn validate_token(&self, token: &str) -> Result<Uuid, KernelError> {
+        self.validate_session(token).map(|session| session.user_id)
+    }
+
+    /// Validates a Paseto token and returns its user ID together with its expiration,
+    /// for long-lived connections that must end when the token does.
+    pub fn validate_session(&self, token: &str) -> Result<Session, KernelError> {
         use pasetors::token::UntrustedToken;
         use pasetors::claims::ClaimsValidationRules;
 
@@ -77,7 +76,12 @@ impl SecurityService {
             .map_err(|_| KernelError::AuthError)?;
 
         let uid_str = payload["user_id"].as_str().ok_or(KernelError::AuthError)?;
-        Uuid::parse_str(uid_str).map_err(|_| KernelError::AuthError)
+        let user_id = Uuid::parse_str(uid_str).map_err(|_| KernelError::AuthError)?;
+        let expires_at = payload["exp"].as_str()
+            .and_then(|exp| chrono::DateTime::parse_from_rfc3339(exp).ok())
+            .map(|exp| exp.with_timezone(&chrono::Utc));
+
+        Ok(Session { user_id, expires_at })
     }
 
     /// Registers a new operator in the system.
@@ -90,14 +94,8 @@ impl SecurityService {
 
         let user_id = Uuid::new_v4();
 
-        sqlx::query(
-            "INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)"
-        )
-        .bind(user_id)
-        .bind(&req.username)
-        .bind(password_hash)
-        .execute(&self.db)
-        .await?;
+        self.users.create(user_id, &req.username, &password_hash).await
+            .map_err(|e| KernelError::Internal(e.to_string()))?;
 
         Ok(User {
             id: user_id,
@@ -109,12 +107,8 @@ impl SecurityService {
 
     /// Authenticates an operator using username and password.
     pub async fn login(&self, req: LoginRequest) -> Result<User, KernelError> {
-        let record: Option<UserRecord> = sqlx::query_as(
-            "SELECT id, username, password_hash, avatar_url FROM users WHERE username = $1"
-        )
-        .bind(&req.username)
-        .fetch_optional(&self.db)
-        .await?;
+        let record = self.users.find_by_username(&req.username).await
+            .map_err(|e| KernelError::Internal(e.to_string()))?;
 
         let record = record.ok_or(KernelError::AuthError)?;
 
@@ -134,14 +128,8 @@ impl SecurityService {
 
     /// Updates the operator profile metadata.
     pub async fn update_profile(&self, user_id: Uuid, avatar_url: Option<String>) -> Result<(), KernelError> {
-        sqlx::query(
-            "UPDATE users SET avatar_url = $1 WHERE id = $2"
-        )
-        .bind(avatar_url)
-        .bind(user_id)
-        .execute(&self.db)
-        .await
-        .map_err(|e| KernelError::Internal(e.to_string()))?;
+        self.users.update_avatar(user_id, avatar_url).await
+            .map_err(|e| KernelError::Internal(e.to_string()))?;
         
         Ok(())
     }
@@ -0,0 +1,206 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::modules::graph::domain::DEFAULT_RELATION;
+
+/// Position in a table ordered by `(timestamp, key)`. Rows sharing a timestamp are told apart
+/// by their key, so a page boundary never skips or repeats one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mark {
+    pub at: DateTime<Utc>,
+    pub key: String,
+}
+
+impl Mark {
+    /// Time and key to read after: the mark itself, or the start of the table.
+    pub fn start_of(mark: &Option<Mark>) -> (DateTime<Utc>, String) {
+        match mark {
+            Some(m) => (m.at, m.key.clone()),
+            None => (DateTime::<Utc>::UNIX_EPOCH, String::new()),
+        }
+    }
+}
+
+/// How far one side has read the changes of the other. Opaque to the reader, which only
+/// stores it and hands it back on the next pull.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncCursor {
+    pub documents: Option<Mark>,
+    pub collection_rows: Option<Mark>,
+    pub links: Option<Mark>,
+    pub tombstones: Option<Mark>,
+    /// Highest `document_updates.id` seen.
+    pub updates: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentRecord {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub title: String,
+    pub class_id: Option<String>,
+    pub parent_id: Option<Uuid>,
+    pub properties: serde_json::Value,
+    pub updated_at: DateTime<Utc>,
+    /// Set while the document is in the trash. Missing from replicas older than the trash.
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Document whose deletion put this one in the trash.
+    #[serde(default)]
+    pub trash_root_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollectionRowRecord {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub data: serde_json::Value,
+    pub order_index: Option<i64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkRecord {
+    pub from_id: Uuid,
+    pub to_id: Uuid,
+    /// Missing from replicas older than typed links, whose links are all plain ones.
+    #[serde(default = "default_relation")]
+    pub relation: String,
+    #[serde(default = "empty_object")]
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    /// Missing from replicas older than link updates, whose links never change once made.
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl LinkRecord {
+    /// Time the link or its metadata last changed, which orders links in a `Mark`.
+    pub fn changed_at(&self) -> DateTime<Utc> {
+        self.updated_at.unwrap_or(self.created_at)
+    }
+
+    /// Key of the link in a `Mark`.
+    pub fn key(&self) -> String {
+        format!("{}:{}:{}", self.from_id, self.to_id, self.relation)
+    }
+
+    /// Splits a key into its source, target and relation. Keys written before typed links
+    /// have no relation and sort before every link of the pair.
+    pub fn parse_key(key: &str) -> (Uuid, Uuid, String) {
+        let mut parts = key.splitn(3, ':');
+        let mut id = || parts.next().and_then(|s| Uuid::parse_str(s).ok()).unwrap_or_default();
+        let (from, to) = (id(), id());
+        (from, to, parts.next().unwrap_or_default().to_string())
+    }
+}
+
+/// What a tombstone records the deletion of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TombstoneKind {
+    Document,
+    Link,
+}
+
+impl TombstoneKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TombstoneKind::Document => "document",
+            TombstoneKind::Link => "link",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "document" => Some(TombstoneKind::Document),
+            "link" => Some(TombstoneKind::Link),
+            _ => None,
+        }
+    }
+}
+
+/// A row deleted on one side, to be deleted on the other. Deleting again only moves the time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub kind: TombstoneKind,
+    /// Key of the deleted row: the id of a document, or the `LinkRecord::key` of a link.
+    pub key: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
+fn default_relation() -> String {
+    DEFAULT_RELATION.to_string()
+}
+
+fn empty_object() -> serde_json::Value {
+    serde_json::Value::Object(Default::default())
+}
+
+/// What a replica has exchanged with one remote: how far it read the remote's changes, and
+/// how far it read its own changes to send them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplicaState {
+    pub pulled: SyncCursor,
+    pub pushed: SyncCursor,
+}
+
+/// A page of changes read from one side, and the cursor to read the next one from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub documents: Vec<DocumentRecord>,
+    pub collection_rows: Vec<CollectionRowRecord>,
+    pub links: Vec<LinkRecord>,
+    /// Absent from change sets sent by replicas that predate tombstones.
+    #[serde(default)]
+    pub tombstones: Vec<Tombstone>,
+    /// Documents whose Yjs content changed. Content is reconciled through state vectors,
+    /// not by copying update rows.
+    pub updated_docs: Vec<Uuid>,
+    pub cursor: SyncCursor,
+    /// Another page is waiting behind this one.
+    pub has_more: bool,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        !self.has_rows() && self.updated_docs.is_empty()
+    }
+
+    /// Whether there are rows or deletions to write on the other side.
+    pub fn has_rows(&self) -> bool {
+        !self.documents.is_empty() || !self.collection_rows.is_empty() || !self.links.is_empty() || !self.tombstones.is_empty()
+    }
+}
+
+/// Rows written by a push. Rows that lost to a newer version, or that belong to someone
+/// else, are ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PushResult {
+    pub accepted: usize,
+    pub ignored: usize,
+}
+
+/// Request: the Yjs state vector of the reader. Response: what the reader was missing and
+/// the state vector of the answering side, so the reader can send back what it is missing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocExchange {
+    pub state_vector: Vec<u8>,
+    #[serde(default)]
+    pub update: Vec<u8>,
+}
+
+/// Who a sync token belongs to: a replica syncs that user's workspace and no other.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SyncSession {
+    pub user_id: Uuid,
+}
+
+/// Outcome of one replication pass.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub pulled: usize,
+    pub pushed: usize,
+    pub ignored: usize,
+    pub docs_reconciled: usize,
+}
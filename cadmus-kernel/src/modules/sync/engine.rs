@@ -0,0 +1,177 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use yrs::{ReadTxn, StateVector, Transact};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use crate::modules::content::history;
+use crate::modules::content::storage::ContentStorage;
+use super::domain::{ChangeSet, DocExchange, PushResult, SyncCursor, SyncReport};
+use super::service::{DEFAULT_SETTLE, SyncService};
+use super::store::SyncStore;
+
+/// Rows per table asked for or sent in one request.
+pub const DEFAULT_PAGE_SIZE: i64 = 200;
+
+/// The server a replica syncs with.
+#[async_trait]
+pub trait SyncRemote: Send + Sync {
+    /// Name the replica keeps its cursors under, e.g. the server URL.
+    fn name(&self) -> String;
+
+    async fn pull(&self, cursor: &SyncCursor, limit: i64) -> Result<ChangeSet>;
+
+    async fn push(&self, changes: &ChangeSet) -> Result<PushResult>;
+
+    async fn exchange(&self, doc_id: Uuid, state_vector: Vec<u8>) -> Result<DocExchange>;
+
+    async fn push_update(&self, doc_id: Uuid, update: Vec<u8>) -> Result<()>;
+}
+
+/// Calls a `SyncService` of the same process, for tests and embedded setups.
+pub struct InProcessRemote {
+    service: Arc<SyncService>,
+    user_id: Uuid,
+}
+
+impl InProcessRemote {
+    pub fn new(service: Arc<SyncService>, user_id: Uuid) -> Self {
+        Self { service, user_id }
+    }
+}
+
+#[async_trait]
+impl SyncRemote for InProcessRemote {
+    fn name(&self) -> String {
+        "in-process".to_string()
+    }
+
+    async fn pull(&self, cursor: &SyncCursor, limit: i64) -> Result<ChangeSet> {
+        self.service.pull(self.user_id, cursor, limit).await
+    }
+
+    async fn push(&self, changes: &ChangeSet) -> Result<PushResult> {
+        self.service.push(self.user_id, changes).await
+    }
+
+    async fn exchange(&self, doc_id: Uuid, state_vector: Vec<u8>) -> Result<DocExchange> {
+        self.service.exchange(self.user_id, doc_id, &state_vector).await
+    }
+
+    async fn push_update(&self, doc_id: Uuid, update: Vec<u8>) -> Result<()> {
+        self.service.push_update(self.user_id, doc_id, update).await
+    }
+}
+
+/// Client side of replication: brings a local vault and its remote to the same state.
+///
+/// Rows are exchanged both ways from resumable cursors, saved after every page, so an
+/// interrupted sync continues where it stopped. Documents are written before their rows and
+/// links. Document content is reconciled through Yjs state vectors rather than by copying
+/// update rows, which makes a pass idempotent and indifferent to compaction on either side.
+pub struct ReplicationEngine {
+    store: SyncStore,
+    storage: Arc<ContentStorage>,
+    remote: Arc<dyn SyncRemote>,
+    owner_id: Uuid,
+    page_size: i64,
+    settle: Duration,
+}
+
+impl ReplicationEngine {
+    pub fn new(store: SyncStore, storage: Arc<ContentStorage>, remote: Arc<dyn SyncRemote>, owner_id: Uuid) -> Self {
+        Self { store, storage, remote, owner_id, page_size: DEFAULT_PAGE_SIZE, settle: DEFAULT_SETTLE }
+    }
+
+    /// Overrides how many rows per table are exchanged per request.
+    pub fn with_page_size(mut self, page_size: i64) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Overrides how old a local row must be before it is sent.
+    pub fn with_settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    /// Runs one full pass: pull, push, then reconcile the content of every document changed on
+    /// either side since the last pass.
+    pub async fn sync_once(&self) -> Result<SyncReport> {
+        let remote = self.remote.name();
+        let mut state = self.store.load_state(&remote).await?;
+        let mut report = SyncReport::default();
+        let mut changed_docs = BTreeSet::new();
+
+        // 1. Remote changes, page by page
+        loop {
+            let page = self.remote.pull(&state.pulled, self.page_size).await?;
+            let applied = self.store.apply(self.owner_id, &page, None).await?;
+            report.pulled += applied.accepted;
+            changed_docs.extend(page.updated_docs.iter().copied());
+            state.pulled = page.cursor;
+            self.store.save_state(&remote, &state).await?;
+            if !page.has_more {
+                break;
+            }
+        }
+
+        // 2. Local changes, page by page. Rows pulled above come back here once; the remote
+        // ignores them as they are not newer than its own.
+        loop {
+            let page = self.store.changes_since(self.owner_id, &state.pushed, self.page_size, self.settle).await?;
+            if page.has_rows() {
+                let pushed = self.remote.push(&page).await?;
+                report.pushed += pushed.accepted;
+                report.ignored += pushed.ignored;
+            }
+            changed_docs.extend(page.updated_docs.iter().copied());
+            // Content reconciled below writes local updates; they are seen, and found to be
+            // empty, on the next pass.
+            state.pushed = page.cursor;
+            if !page.has_more {
+                break;
+            }
+            self.store.save_state(&remote, &state).await?;
+        }
+
+        // 3. Content of the documents changed on either side
+        for doc_id in changed_docs {
+            match self.reconcile(doc_id).await {
+                Ok(()) => report.docs_reconciled += 1,
+                Err(e) => tracing::warn!("[Sync] Could not reconcile content of {}: {}", doc_id, e),
+            }
+        }
+        self.store.save_state(&remote, &state).await?;
+
+        tracing::info!("[Sync] Pulled {}, pushed {} ({} ignored), reconciled {} documents", report.pulled, report.pushed, report.ignored, report.docs_reconciled);
+        Ok(report)
+    }
+
+    /// Exchanges with the remote what each side is missing of a document's content.
+    async fn reconcile(&self, doc_id: Uuid) -> Result<()> {
+        let id = doc_id.to_string();
+        let local = history::replay(self.storage.load_updates(&id).await?);
+        let state_vector = local.transact().state_vector().encode_v1();
+
+        let answer = self.remote.exchange(doc_id, state_vector).await?;
+        let remote_sv = StateVector::decode_v1(&answer.state_vector)
+            .map_err(|e| anyhow::anyhow!("invalid state vector from remote: {}", e))?;
+
+        let missing_remotely = local.transact().encode_state_as_update_v1(&remote_sv);
+        if !is_empty_update(&missing_remotely) {
+            self.remote.push_update(doc_id, missing_remotely).await?;
+        }
+        if !is_empty_update(&answer.update) {
+            self.storage.save_update(&id, answer.update).await?;
+        }
+        Ok(())
+    }
+}
+
+fn is_empty_update(update: &[u8]) -> bool {
+    update.is_empty() || update == [0, 0]
+}
@@ -0,0 +1,4 @@
+pub mod domain;
+pub mod engine;
+pub mod service;
+pub mod store;
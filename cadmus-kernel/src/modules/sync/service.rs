@@ -0,0 +1,72 @@
+use anyhow::Result;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use yrs::StateVector;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use crate::modules::content::socket::ContentRegistry;
+use super::domain::{ChangeSet, DocExchange, PushResult, SyncCursor};
+use super::store::SyncStore;
+
+/// Default age a row must reach before it is handed to a reader.
+pub const DEFAULT_SETTLE: Duration = Duration::from_secs(5);
+
+/// Largest page a reader may ask for.
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Server side of replication: hands out the changes of a user's workspace and takes in the
+/// changes of their replicas. Document content goes through the registry, so connected
+/// editors see what a replica brings in.
+pub struct SyncService {
+    store: SyncStore,
+    registry: Arc<ContentRegistry>,
+    settle: Duration,
+}
+
+impl SyncService {
+    pub fn new(store: SyncStore, registry: Arc<ContentRegistry>) -> Self {
+        Self { store, registry, settle: DEFAULT_SETTLE }
+    }
+
+    /// Overrides how old a row must be before it is handed out.
+    pub fn with_settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    /// Changes of `user_id`'s workspace after `cursor`, at most `limit` rows per table.
+    pub async fn pull(&self, user_id: Uuid, cursor: &SyncCursor, limit: i64) -> Result<ChangeSet> {
+        self.store.changes_since(user_id, cursor, limit.clamp(1, MAX_PAGE_SIZE), self.settle).await
+    }
+
+    /// Writes the changes of a replica. Content is not taken from here, see `push_update`.
+    pub async fn push(&self, user_id: Uuid, changes: &ChangeSet) -> Result<PushResult> {
+        self.store.apply(user_id, changes, Some(Utc::now())).await
+    }
+
+    /// Answers a replica's state vector with what it is missing of a document, and the state
+    /// vector of the server so the replica can send back what the server is missing.
+    pub async fn exchange(&self, user_id: Uuid, doc_id: Uuid, state_vector: &[u8]) -> Result<DocExchange> {
+        self.ensure_owned(user_id, doc_id).await?;
+        let since = StateVector::decode_v1(state_vector)
+            .map_err(|e| anyhow::anyhow!("invalid state vector: {}", e))?;
+        let room = self.registry.get_room(&doc_id.to_string()).await;
+        let (update, state_vector) = room.diff_since(&since).await?;
+        Ok(DocExchange { state_vector: state_vector.encode_v1(), update })
+    }
+
+    /// Applies what a replica had of a document that the server did not.
+    pub async fn push_update(&self, user_id: Uuid, doc_id: Uuid, update: Vec<u8>) -> Result<()> {
+        self.ensure_owned(user_id, doc_id).await?;
+        self.registry.process_update_as(&doc_id.to_string(), update, Some(user_id)).await
+    }
+
+    async fn ensure_owned(&self, user_id: Uuid, doc_id: Uuid) -> Result<()> {
+        match self.store.is_owned(user_id, doc_id).await? {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!("document '{}' not found", doc_id)),
+        }
+    }
+}
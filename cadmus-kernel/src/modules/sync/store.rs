@@ -0,0 +1,736 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{Acquire, Postgres, QueryBuilder, Sqlite, Transaction};
+use std::collections::HashSet;
+use std::time::Duration;
+use uuid::Uuid;
+use crate::infrastructure::sqlite::SqliteDocumentRepository;
+use crate::modules::content::storage::DbPool;
+use super::domain::{ChangeSet, CollectionRowRecord, DocumentRecord, LinkRecord, Mark, PushResult, ReplicaState, SyncCursor, Tombstone, TombstoneKind};
+
+/// Reads and writes the replicated tables of one database, on either side of a sync.
+///
+/// Rows are read in `(timestamp, key)` order and only once they are older than the settle
+/// window, so a transaction that commits a little after stamping its rows is not skipped.
+pub struct SyncStore {
+    pool: DbPool,
+}
+
+type PgDocumentRow = (Uuid, Option<Uuid>, String, Option<String>, Option<Uuid>, Option<serde_json::Value>, Option<NaiveDateTime>, Option<NaiveDateTime>, Option<Uuid>);
+type SqliteDocumentRow = (String, String, String, Option<String>, Option<String>, String, Option<NaiveDateTime>, Option<NaiveDateTime>, Option<String>);
+type PgCollectionRow = (Uuid, Uuid, serde_json::Value, i32, Option<DateTime<Utc>>);
+type SqliteCollectionRow = (String, String, String, Option<i64>, Option<NaiveDateTime>);
+type PgLinkRow = (Uuid, Uuid, String, serde_json::Value, Option<NaiveDateTime>, NaiveDateTime);
+type SqliteLinkRow = (String, String, String, String, Option<NaiveDateTime>, NaiveDateTime);
+
+impl SyncStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Reads up to `limit` rows per table changed after `cursor` by documents of `owner_id`.
+    ///
+    /// Documents referenced by the returned rows and links are included even when they changed
+    /// later, so the reader never receives a row before the document it belongs to.
+    pub async fn changes_since(&self, owner_id: Uuid, cursor: &SyncCursor, limit: i64, settle: Duration) -> Result<ChangeSet> {
+        let until = Utc::now() - chrono::Duration::from_std(settle)?;
+        let limit = limit.max(1);
+
+        let mut changes = match &self.pool {
+            DbPool::Postgres(p) => {
+                let mut tx = Self::authenticated_tx(p, owner_id).await?;
+                let changes = Self::pg_changes(&mut tx, owner_id, cursor, limit, until).await?;
+                tx.commit().await?;
+                changes
+            },
+            DbPool::Sqlite(p) => {
+                let mut conn = p.acquire().await?;
+                Self::sqlite_changes(&mut conn, owner_id, cursor, limit, until).await?
+            }
+        };
+
+        let known: HashSet<Uuid> = changes.documents.iter().map(|d| d.id).collect();
+        let referenced: Vec<Uuid> = changes.collection_rows.iter().map(|r| r.document_id)
+            .chain(changes.links.iter().flat_map(|l| [l.from_id, l.to_id]))
+            .filter(|id| !known.contains(id))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        if !referenced.is_empty() {
+            changes.documents.extend(self.documents_by_id(owner_id, &referenced).await?);
+        }
+        Ok(changes)
+    }
+
+    async fn pg_changes(tx: &mut Transaction<'_, Postgres>, owner_id: Uuid, cursor: &SyncCursor, limit: i64, until: DateTime<Utc>) -> Result<ChangeSet> {
+        let mut changes = ChangeSet { cursor: cursor.clone(), ..Default::default() };
+
+        let (at, key) = Mark::start_of(&cursor.documents);
+        let rows: Vec<PgDocumentRow> = sqlx::query_as(
+            "SELECT id, owner_id, title, class_id, parent_id, properties, updated_at, deleted_at, trash_root_id FROM documents
+             WHERE owner_id = $1 AND updated_at <= $2 AND (updated_at, id) > ($3, $4)
+             ORDER BY updated_at, id LIMIT $5"
+        )
+        .bind(owner_id)
+        .bind(until.naive_utc())
+        .bind(at.naive_utc())
+        .bind(Uuid::parse_str(&key).unwrap_or_default())
+        .bind(limit)
+        .fetch_all(&mut **tx)
+        .await?;
+        changes.has_more |= rows.len() as i64 == limit;
+        changes.documents = rows.into_iter().filter_map(Self::pg_document).collect();
+        if let Some(last) = changes.documents.last() {
+            changes.cursor.documents = Some(Mark { at: last.updated_at, key: last.id.to_string() });
+        }
+
+        let (at, key) = Mark::start_of(&cursor.collection_rows);
+        let rows: Vec<PgCollectionRow> = sqlx::query_as(
+            "SELECT r.id, r.document_id, r.data, r.order_index, r.updated_at FROM collection_rows r
+             JOIN documents d ON d.id = r.document_id
+             WHERE d.owner_id = $1 AND r.updated_at <= $2 AND (r.updated_at, r.id) > ($3, $4)
+             ORDER BY r.updated_at, r.id LIMIT $5"
+        )
+        .bind(owner_id)
+        .bind(until)
+        .bind(at)
+        .bind(Uuid::parse_str(&key).unwrap_or_default())
+        .bind(limit)
+        .fetch_all(&mut **tx)
+        .await?;
+        changes.has_more |= rows.len() as i64 == limit;
+        changes.collection_rows = rows.into_iter()
+            .filter_map(|(id, document_id, data, order_index, updated_at)| Some(CollectionRowRecord {
+                id, document_id, data, order_index: Some(order_index as i64), updated_at: updated_at?,
+            }))
+            .collect();
+        if let Some(last) = changes.collection_rows.last() {
+            changes.cursor.collection_rows = Some(Mark { at: last.updated_at, key: last.id.to_string() });
+        }
+
+        let (at, key) = Mark::start_of(&cursor.links);
+        let (from, to, relation) = LinkRecord::parse_key(&key);
+        let rows: Vec<PgLinkRow> = sqlx::query_as(
+            "SELECT l.from_id, l.to_id, l.relation, l.metadata, l.created_at, l.updated_at FROM document_links l
+             JOIN documents d ON d.id = l.from_id
+             WHERE d.owner_id = $1 AND l.updated_at <= $2 AND (l.updated_at, l.from_id, l.to_id, l.relation) > ($3, $4, $5, $6)
+             ORDER BY l.updated_at, l.from_id, l.to_id, l.relation LIMIT $7"
+        )
+        .bind(owner_id)
+        .bind(until.naive_utc())
+        .bind(at.naive_utc())
+        .bind(from)
+        .bind(to)
+        .bind(relation)
+        .bind(limit)
+        .fetch_all(&mut **tx)
+        .await?;
+        changes.has_more |= rows.len() as i64 == limit;
+        changes.links = rows.into_iter()
+            .map(|(from_id, to_id, relation, metadata, created_at, updated_at)| LinkRecord {
+                from_id, to_id, relation, metadata,
+                created_at: created_at.unwrap_or(updated_at).and_utc(),
+                updated_at: Some(updated_at.and_utc()),
+            })
+            .collect();
+        if let Some(last) = changes.links.last() {
+            changes.cursor.links = Some(Mark { at: last.changed_at(), key: last.key() });
+        }
+
+        let (at, key) = Mark::start_of(&cursor.tombstones);
+        let rows: Vec<(String, String, NaiveDateTime)> = sqlx::query_as(
+            "SELECT kind, key, deleted_at FROM sync_tombstones
+             WHERE owner_id = $1 AND deleted_at <= $2 AND (deleted_at, key) > ($3, $4)
+             ORDER BY deleted_at, key LIMIT $5"
+        )
+        .bind(owner_id)
+        .bind(until.naive_utc())
+        .bind(at.naive_utc())
+        .bind(&key)
+        .bind(limit)
+        .fetch_all(&mut **tx)
+        .await?;
+        changes.has_more |= rows.len() as i64 == limit;
+        Self::read_tombstones(&mut changes, rows);
+
+        let rows: Vec<(Uuid, i64)> = sqlx::query_as(
+            "SELECT u.doc_id, MAX(u.id)::int8 FROM document_updates u
+             JOIN documents d ON d.id = u.doc_id
+             WHERE d.owner_id = $1 AND u.id > $2 AND u.created_at <= $3
+             GROUP BY u.doc_id"
+        )
+        .bind(owner_id)
+        .bind(cursor.updates as i32)
+        .bind(until.naive_utc())
+        .fetch_all(&mut **tx)
+        .await?;
+        changes.cursor.updates = rows.iter().map(|(_, id)| *id).fold(cursor.updates, i64::max);
+        changes.updated_docs = rows.into_iter().map(|(id, _)| id).collect();
+
+        Ok(changes)
+    }
+
+    async fn sqlite_changes(conn: &mut sqlx::SqliteConnection, owner_id: Uuid, cursor: &SyncCursor, limit: i64, until: DateTime<Utc>) -> Result<ChangeSet> {
+        let mut changes = ChangeSet { cursor: cursor.clone(), ..Default::default() };
+
+        let (at, key) = Mark::start_of(&cursor.documents);
+        let rows: Vec<SqliteDocumentRow> = sqlx::query_as(
+            "SELECT id, owner_id, title, class_id, parent_id, properties, updated_at, deleted_at, trash_root_id FROM documents
+             WHERE owner_id = ? AND updated_at <= ? AND (updated_at, id) > (?, ?)
+             ORDER BY updated_at, id LIMIT ?"
+        )
+        .bind(owner_id.to_string())
+        .bind(until.naive_utc())
+        .bind(at.naive_utc())
+        .bind(&key)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        changes.has_more |= rows.len() as i64 == limit;
+        changes.documents = rows.into_iter().filter_map(Self::sqlite_document).collect();
+        if let Some(last) = changes.documents.last() {
+            changes.cursor.documents = Some(Mark { at: last.updated_at, key: last.id.to_string() });
+        }
+
+        let (at, key) = Mark::start_of(&cursor.collection_rows);
+        let rows: Vec<SqliteCollectionRow> = sqlx::query_as(
+            "SELECT r.id, r.document_id, r.data, r.order_index, r.updated_at FROM collection_rows r
+             JOIN documents d ON d.id = r.document_id
+             WHERE d.owner_id = ? AND r.updated_at <= ? AND (r.updated_at, r.id) > (?, ?)
+             ORDER BY r.updated_at, r.id LIMIT ?"
+        )
+        .bind(owner_id.to_string())
+        .bind(until.naive_utc())
+        .bind(at.naive_utc())
+        .bind(&key)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        changes.has_more |= rows.len() as i64 == limit;
+        changes.collection_rows = rows.into_iter()
+            .filter_map(|(id, document_id, data, order_index, updated_at)| Some(CollectionRowRecord {
+                id: Uuid::parse_str(&id).ok()?,
+                document_id: Uuid::parse_str(&document_id).ok()?,
+                data: serde_json::from_str(&data).unwrap_or_default(),
+                order_index,
+                updated_at: updated_at?.and_utc(),
+            }))
+            .collect();
+        if let Some(last) = changes.collection_rows.last() {
+            changes.cursor.collection_rows = Some(Mark { at: last.updated_at, key: last.id.to_string() });
+        }
+
+        let (at, key) = Mark::start_of(&cursor.links);
+        let (from, to, relation) = LinkRecord::parse_key(&key);
+        let rows: Vec<SqliteLinkRow> = sqlx::query_as(
+            "SELECT l.from_id, l.to_id, l.relation, l.metadata, l.created_at, l.updated_at FROM document_links l
+             JOIN documents d ON d.id = l.from_id
+             WHERE d.owner_id = ? AND l.updated_at <= ? AND (l.updated_at, l.from_id, l.to_id, l.relation) > (?, ?, ?, ?)
+             ORDER BY l.updated_at, l.from_id, l.to_id, l.relation LIMIT ?"
+        )
+        .bind(owner_id.to_string())
+        .bind(until.naive_utc())
+        .bind(at.naive_utc())
+        .bind(from.to_string())
+        .bind(to.to_string())
+        .bind(relation)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        changes.has_more |= rows.len() as i64 == limit;
+        changes.links = rows.into_iter()
+            .map(Self::sqlite_link)
+            .filter_map(Result::transpose)
+            .collect::<Result<_>>()?;
+        if let Some(last) = changes.links.last() {
+            changes.cursor.links = Some(Mark { at: last.changed_at(), key: last.key() });
+        }
+
+        let (at, key) = Mark::start_of(&cursor.tombstones);
+        let rows: Vec<(String, String, NaiveDateTime)> = sqlx::query_as(
+            "SELECT kind, key, deleted_at FROM sync_tombstones
+             WHERE owner_id = ? AND deleted_at <= ? AND (deleted_at, key) > (?, ?)
+             ORDER BY deleted_at, key LIMIT ?"
+        )
+        .bind(owner_id.to_string())
+        .bind(until.naive_utc())
+        .bind(at.naive_utc())
+        .bind(&key)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+        changes.has_more |= rows.len() as i64 == limit;
+        Self::read_tombstones(&mut changes, rows);
+
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT u.doc_id, MAX(u.id) FROM document_updates u
+             JOIN documents d ON d.id = u.doc_id
+             WHERE d.owner_id = ? AND u.id > ? AND u.created_at <= ?
+             GROUP BY u.doc_id"
+        )
+        .bind(owner_id.to_string())
+        .bind(cursor.updates)
+        .bind(until.naive_utc())
+        .fetch_all(&mut *conn)
+        .await?;
+        changes.cursor.updates = rows.iter().map(|(_, id)| *id).fold(cursor.updates, i64::max);
+        changes.updated_docs = rows.into_iter().filter_map(|(id, _)| Uuid::parse_str(&id).ok()).collect();
+
+        Ok(changes)
+    }
+
+    /// Moves the tombstone cursor past every row read, including kinds this build does not know.
+    fn read_tombstones(changes: &mut ChangeSet, rows: Vec<(String, String, NaiveDateTime)>) {
+        if let Some((_, key, deleted_at)) = rows.last() {
+            changes.cursor.tombstones = Some(Mark { at: deleted_at.and_utc(), key: key.clone() });
+        }
+        changes.tombstones = rows.into_iter()
+            .filter_map(|(kind, key, deleted_at)| Some(Tombstone { kind: TombstoneKind::parse(&kind)?, key, deleted_at: deleted_at.and_utc() }))
+            .collect();
+    }
+
+    /// Reads the documents of `owner_id` with the given ids.
+    pub async fn documents_by_id(&self, owner_id: Uuid, ids: &[Uuid]) -> Result<Vec<DocumentRecord>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        match &self.pool {
+            DbPool::Postgres(p) => {
+                let mut tx = Self::authenticated_tx(p, owner_id).await?;
+                let rows: Vec<PgDocumentRow> = sqlx::query_as(
+                    "SELECT id, owner_id, title, class_id, parent_id, properties, updated_at, deleted_at, trash_root_id FROM documents WHERE owner_id = $1 AND id = ANY($2)"
+                )
+                .bind(owner_id)
+                .bind(ids)
+                .fetch_all(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                Ok(rows.into_iter().filter_map(Self::pg_document).collect())
+            },
+            DbPool::Sqlite(p) => {
+                let mut query = QueryBuilder::<Sqlite>::new("SELECT id, owner_id, title, class_id, parent_id, properties, updated_at, deleted_at, trash_root_id FROM documents WHERE owner_id = ");
+                query.push_bind(owner_id.to_string()).push(" AND id IN (");
+                let mut separated = query.separated(", ");
+                for id in ids {
+                    separated.push_bind(id.to_string());
+                }
+                query.push(")");
+                let rows: Vec<SqliteDocumentRow> = query.build_query_as().fetch_all(p).await?;
+                Ok(rows.into_iter().filter_map(Self::sqlite_document).collect())
+            }
+        }
+    }
+
+    /// Whether `doc_id` exists and belongs to `owner_id`.
+    pub async fn is_owned(&self, owner_id: Uuid, doc_id: Uuid) -> Result<bool> {
+        Ok(!self.documents_by_id(owner_id, &[doc_id]).await?.is_empty())
+    }
+
+    /// Writes a change set received from the other side, documents first.
+    ///
+    /// Documents and collection rows are last-writer-wins on `updated_at`: a row only replaces
+    /// an older version of itself. Links are too, on the time they last changed. Tombstones come
+    /// last and delete what they cover, and a tombstoned document is never written again.
+    /// Everything is written as owned by `owner_id`, and rows of other owners are left alone.
+    /// Parents are not checked against `allowed_children`: the other side accepted them already.
+    /// When `received_at` is set, accepted rows are stamped with it instead of their own time,
+    /// so readers whose cursor already passed that time still see them.
+    pub async fn apply(&self, owner_id: Uuid, changes: &ChangeSet, received_at: Option<DateTime<Utc>>) -> Result<PushResult> {
+        let mut result = PushResult::default();
+        match &self.pool {
+            DbPool::Postgres(p) => {
+                let mut tx = Self::authenticated_tx(p, owner_id).await?;
+                for doc in &changes.documents {
+                    let stamp = received_at.unwrap_or(doc.updated_at);
+                    let query = sqlx::query(
+                        "INSERT INTO documents (id, owner_id, title, class_id, parent_id, properties, updated_at, deleted_at, trash_root_id)
+                         SELECT $1, $2, $3, $4, $5, $6, $7, $10, $11
+                         WHERE NOT EXISTS (SELECT 1 FROM sync_tombstones WHERE kind = 'document' AND key = $9)
+                         ON CONFLICT (id) DO UPDATE SET title = EXCLUDED.title, class_id = EXCLUDED.class_id,
+                             parent_id = EXCLUDED.parent_id, properties = EXCLUDED.properties, updated_at = EXCLUDED.updated_at,
+                             deleted_at = EXCLUDED.deleted_at, trash_root_id = EXCLUDED.trash_root_id
+                         WHERE documents.owner_id = EXCLUDED.owner_id AND (documents.updated_at IS NULL OR documents.updated_at < $8)"
+                    )
+                    .bind(doc.id)
+                    .bind(owner_id)
+                    .bind(&doc.title)
+                    .bind(&doc.class_id)
+                    .bind(doc.parent_id)
+                    .bind(&doc.properties)
+                    .bind(stamp.naive_utc())
+                    .bind(doc.updated_at.naive_utc())
+                    .bind(doc.id.to_string())
+                    .bind(doc.deleted_at.map(|t| t.naive_utc()))
+                    .bind(doc.trash_root_id);
+                    result.count(Self::pg_write(&mut tx, query).await);
+                }
+                for row in &changes.collection_rows {
+                    let stamp = received_at.unwrap_or(row.updated_at);
+                    let query = sqlx::query(
+                        "INSERT INTO collection_rows (id, document_id, data, order_index, updated_at)
+                         SELECT $1, $2, $3, COALESCE($4, nextval(pg_get_serial_sequence('collection_rows', 'order_index'))), $5
+                         WHERE EXISTS (SELECT 1 FROM documents WHERE id = $2 AND owner_id = $6)
+                         ON CONFLICT (id) DO UPDATE SET data = EXCLUDED.data, order_index = EXCLUDED.order_index, updated_at = EXCLUDED.updated_at
+                         WHERE collection_rows.document_id = EXCLUDED.document_id AND (collection_rows.updated_at IS NULL OR collection_rows.updated_at < $7)"
+                    )
+                    .bind(row.id)
+                    .bind(row.document_id)
+                    .bind(&row.data)
+                    .bind(row.order_index.map(|i| i as i32))
+                    .bind(stamp)
+                    .bind(owner_id)
+                    .bind(row.updated_at);
+                    result.count(Self::pg_write(&mut tx, query).await);
+                }
+                for link in &changes.links {
+                    let stamp = received_at.unwrap_or(link.changed_at());
+                    let query = sqlx::query(
+                        "INSERT INTO document_links (from_id, to_id, relation, metadata, created_at, updated_at)
+                         SELECT $1, $2, $3, $4, $5, $6 WHERE EXISTS (SELECT 1 FROM documents WHERE id = $1 AND owner_id = $7)
+                             AND NOT EXISTS (SELECT 1 FROM sync_tombstones WHERE kind = 'link' AND key = $9 AND deleted_at >= $8)
+                         ON CONFLICT (from_id, to_id, relation) DO UPDATE SET metadata = EXCLUDED.metadata, updated_at = EXCLUDED.updated_at
+                         WHERE document_links.updated_at < $8"
+                    )
+                    .bind(link.from_id)
+                    .bind(link.to_id)
+                    .bind(&link.relation)
+                    .bind(&link.metadata)
+                    .bind(link.created_at.naive_utc())
+                    .bind(stamp.naive_utc())
+                    .bind(owner_id)
+                    .bind(link.changed_at().naive_utc())
+                    .bind(link.key());
+                    result.count(Self::pg_write(&mut tx, query).await);
+                }
+                for tombstone in &changes.tombstones {
+                    let stamp = received_at.unwrap_or(tombstone.deleted_at);
+                    result.count(Self::pg_bury(&mut tx, owner_id, tombstone, stamp).await);
+                }
+                tx.commit().await?;
+            },
+            DbPool::Sqlite(p) => {
+                let mut tx = p.begin().await?;
+                for doc in &changes.documents {
+                    let stamp = received_at.unwrap_or(doc.updated_at);
+                    let query = sqlx::query(
+                        "INSERT INTO documents (id, owner_id, title, class_id, parent_id, properties, updated_at, deleted_at, trash_root_id)
+                         SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?9, ?10
+                         WHERE NOT EXISTS (SELECT 1 FROM sync_tombstones WHERE kind = 'document' AND key = ?1)
+                         ON CONFLICT (id) DO UPDATE SET title = excluded.title, class_id = excluded.class_id,
+                             parent_id = excluded.parent_id, properties = excluded.properties, updated_at = excluded.updated_at,
+                             deleted_at = excluded.deleted_at, trash_root_id = excluded.trash_root_id
+                         WHERE documents.owner_id = excluded.owner_id AND (documents.updated_at IS NULL OR documents.updated_at < ?8)"
+                    )
+                    .bind(doc.id.to_string())
+                    .bind(owner_id.to_string())
+                    .bind(&doc.title)
+                    .bind(&doc.class_id)
+                    .bind(doc.parent_id.map(|id| id.to_string()))
+                    .bind(doc.properties.to_string())
+                    .bind(stamp.naive_utc())
+                    .bind(doc.updated_at.naive_utc())
+                    .bind(doc.deleted_at.map(|t| t.naive_utc()))
+                    .bind(doc.trash_root_id.map(|id| id.to_string()));
+                    result.count(Self::sqlite_write(&mut tx, query).await);
+                }
+                for row in &changes.collection_rows {
+                    let stamp = received_at.unwrap_or(row.updated_at);
+                    let query = sqlx::query(
+                        "INSERT INTO collection_rows (id, document_id, data, order_index, updated_at)
+                         SELECT ?1, ?2, ?3, ?4, ?5 WHERE EXISTS (SELECT 1 FROM documents WHERE id = ?2 AND owner_id = ?6)
+                         ON CONFLICT (id) DO UPDATE SET data = excluded.data, order_index = excluded.order_index, updated_at = excluded.updated_at
+                         WHERE collection_rows.document_id = excluded.document_id AND (collection_rows.updated_at IS NULL OR collection_rows.updated_at < ?7)"
+                    )
+                    .bind(row.id.to_string())
+                    .bind(row.document_id.to_string())
+                    .bind(row.data.to_string())
+                    .bind(row.order_index)
+                    .bind(stamp.naive_utc())
+                    .bind(owner_id.to_string())
+                    .bind(row.updated_at.naive_utc());
+                    result.count(Self::sqlite_write(&mut tx, query).await);
+                }
+                for link in &changes.links {
+                    let stamp = received_at.unwrap_or(link.changed_at());
+                    let query = sqlx::query(
+                        "INSERT INTO document_links (from_id, to_id, relation, metadata, created_at, updated_at)
+                         SELECT ?1, ?2, ?3, ?4, ?5, ?6 WHERE EXISTS (SELECT 1 FROM documents WHERE id = ?1 AND owner_id = ?7)
+                             AND NOT EXISTS (SELECT 1 FROM sync_tombstones WHERE kind = 'link' AND key = ?9 AND deleted_at >= ?8)
+                         ON CONFLICT (from_id, to_id, relation) DO UPDATE SET metadata = excluded.metadata, updated_at = excluded.updated_at
+                         WHERE document_links.updated_at < ?8"
+                    )
+                    .bind(link.from_id.to_string())
+                    .bind(link.to_id.to_string())
+                    .bind(&link.relation)
+                    .bind(link.metadata.to_string())
+                    .bind(link.created_at.naive_utc())
+                    .bind(stamp.naive_utc())
+                    .bind(owner_id.to_string())
+                    .bind(link.changed_at().naive_utc())
+                    .bind(link.key());
+                    result.count(Self::sqlite_write(&mut tx, query).await);
+                }
+                for tombstone in &changes.tombstones {
+                    let stamp = received_at.unwrap_or(tombstone.deleted_at);
+                    result.count(Self::sqlite_bury(&mut tx, owner_id, tombstone, stamp).await);
+                }
+                tx.commit().await?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Runs one write in a savepoint, so a row rejected by a constraint or a row-level policy
+    /// is ignored without aborting the rest of the change set.
+    async fn pg_write(tx: &mut Transaction<'_, Postgres>, query: sqlx::query::Query<'_, Postgres, sqlx::postgres::PgArguments>) -> bool {
+        let Ok(mut savepoint) = tx.begin().await else {
+            return false;
+        };
+        match query.execute(&mut *savepoint).await {
+            Ok(done) => savepoint.commit().await.is_ok() && done.rows_affected() > 0,
+            Err(e) => {
+                tracing::debug!("[Sync] Ignoring rejected row: {}", e);
+                let _ = savepoint.rollback().await;
+                false
+            }
+        }
+    }
+
+    async fn sqlite_write<'q>(tx: &mut Transaction<'_, Sqlite>, query: sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>>) -> bool {
+        let Ok(mut savepoint) = tx.begin().await else {
+            return false;
+        };
+        match query.execute(&mut *savepoint).await {
+            Ok(done) => savepoint.commit().await.is_ok() && done.rows_affected() > 0,
+            Err(e) => {
+                tracing::debug!("[Sync] Ignoring rejected row: {}", e);
+                let _ = savepoint.rollback().await;
+                false
+            }
+        }
+    }
+
+    /// Records a tombstone from the other side and deletes what it covers, in one savepoint.
+    /// A tombstone for the same key that is at least as recent is kept, and nothing is deleted;
+    /// neither is a link changed after the deletion.
+    async fn pg_bury(tx: &mut Transaction<'_, Postgres>, owner_id: Uuid, tombstone: &Tombstone, stamp: DateTime<Utc>) -> bool {
+        let Ok(mut savepoint) = tx.begin().await else {
+            return false;
+        };
+        let buried = async {
+            let recorded = sqlx::query(
+                "INSERT INTO sync_tombstones (kind, key, owner_id, deleted_at) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (kind, key) DO UPDATE SET deleted_at = EXCLUDED.deleted_at
+                 WHERE sync_tombstones.owner_id = EXCLUDED.owner_id AND sync_tombstones.deleted_at < $5"
+            )
+            .bind(tombstone.kind.as_str())
+            .bind(&tombstone.key)
+            .bind(owner_id)
+            .bind(stamp.naive_utc())
+            .bind(tombstone.deleted_at.naive_utc())
+            .execute(&mut *savepoint)
+            .await?
+            .rows_affected() > 0;
+            if recorded {
+                match tombstone.kind {
+                    TombstoneKind::Document => {
+                        sqlx::query("DELETE FROM documents WHERE id = $1 AND owner_id = $2")
+                            .bind(Uuid::parse_str(&tombstone.key)?)
+                            .bind(owner_id)
+                            .execute(&mut *savepoint)
+                            .await?;
+                    }
+                    TombstoneKind::Link => {
+                        let (from, to, relation) = LinkRecord::parse_key(&tombstone.key);
+                        sqlx::query(
+                            "DELETE FROM document_links WHERE from_id = $1 AND to_id = $2 AND relation = $3 AND updated_at <= $4
+                             AND EXISTS (SELECT 1 FROM documents WHERE id = $1 AND owner_id = $5)"
+                        )
+                        .bind(from)
+                        .bind(to)
+                        .bind(relation)
+                        .bind(tombstone.deleted_at.naive_utc())
+                        .bind(owner_id)
+                        .execute(&mut *savepoint)
+                        .await?;
+                    }
+                }
+            }
+            anyhow::Ok(recorded)
+        }.await;
+        match buried {
+            Ok(recorded) => savepoint.commit().await.is_ok() && recorded,
+            Err(e) => {
+                tracing::debug!("[Sync] Ignoring rejected tombstone: {}", e);
+                let _ = savepoint.rollback().await;
+                false
+            }
+        }
+    }
+
+    async fn sqlite_bury(tx: &mut Transaction<'_, Sqlite>, owner_id: Uuid, tombstone: &Tombstone, stamp: DateTime<Utc>) -> bool {
+        let Ok(mut savepoint) = tx.begin().await else {
+            return false;
+        };
+        let buried = async {
+            let recorded = sqlx::query(
+                "INSERT INTO sync_tombstones (kind, key, owner_id, deleted_at) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (kind, key) DO UPDATE SET deleted_at = excluded.deleted_at
+                 WHERE sync_tombstones.owner_id = excluded.owner_id AND sync_tombstones.deleted_at < ?5"
+            )
+            .bind(tombstone.kind.as_str())
+            .bind(&tombstone.key)
+            .bind(owner_id.to_string())
+            .bind(stamp.naive_utc())
+            .bind(tombstone.deleted_at.naive_utc())
+            .execute(&mut *savepoint)
+            .await?
+            .rows_affected() > 0;
+            if recorded {
+                match tombstone.kind {
+                    TombstoneKind::Document => {
+                        let owned: Vec<String> = sqlx::query_scalar("SELECT id FROM documents WHERE id = ? AND owner_id = ?")
+                            .bind(&tombstone.key)
+                            .bind(owner_id.to_string())
+                            .fetch_all(&mut *savepoint)
+                            .await?;
+                        SqliteDocumentRepository::delete_documents(&mut savepoint, &owned).await?;
+                    }
+                    TombstoneKind::Link => {
+                        let (from, to, relation) = LinkRecord::parse_key(&tombstone.key);
+                        sqlx::query(
+                            "DELETE FROM document_links WHERE from_id = ?1 AND to_id = ?2 AND relation = ?3 AND updated_at <= ?4
+                             AND EXISTS (SELECT 1 FROM documents WHERE id = ?1 AND owner_id = ?5)"
+                        )
+                        .bind(from.to_string())
+                        .bind(to.to_string())
+                        .bind(relation)
+                        .bind(tombstone.deleted_at.naive_utc())
+                        .bind(owner_id.to_string())
+                        .execute(&mut *savepoint)
+                        .await?;
+                    }
+                }
+            }
+            anyhow::Ok(recorded)
+        }.await;
+        match buried {
+            Ok(recorded) => savepoint.commit().await.is_ok() && recorded,
+            Err(e) => {
+                tracing::debug!("[Sync] Ignoring rejected tombstone: {}", e);
+                let _ = savepoint.rollback().await;
+                false
+            }
+        }
+    }
+
+    /// Loads what this replica has exchanged with `remote` so far.
+    pub async fn load_state(&self, remote: &str) -> Result<ReplicaState> {
+        let state: Option<String> = match &self.pool {
+            DbPool::Postgres(p) => {
+                sqlx::query_scalar("SELECT state FROM sync_state WHERE remote = $1")
+                    .bind(remote)
+                    .fetch_optional(p)
+                    .await?
+            },
+            DbPool::Sqlite(p) => {
+                sqlx::query_scalar("SELECT state FROM sync_state WHERE remote = ?")
+                    .bind(remote)
+                    .fetch_optional(p)
+                    .await?
+            }
+        };
+        Ok(state.map(|s| serde_json::from_str(&s)).transpose()?.unwrap_or_default())
+    }
+
+    pub async fn save_state(&self, remote: &str, state: &ReplicaState) -> Result<()> {
+        let state = serde_json::to_string(state)?;
+        match &self.pool {
+            DbPool::Postgres(p) => {
+                sqlx::query(
+                    "INSERT INTO sync_state (remote, state, updated_at) VALUES ($1, $2, CURRENT_TIMESTAMP)
+                     ON CONFLICT (remote) DO UPDATE SET state = EXCLUDED.state, updated_at = EXCLUDED.updated_at"
+                )
+                .bind(remote)
+                .bind(state)
+                .execute(p)
+                .await?;
+            },
+            DbPool::Sqlite(p) => {
+                sqlx::query(
+                    "INSERT INTO sync_state (remote, state, updated_at) VALUES (?, ?, CURRENT_TIMESTAMP)
+                     ON CONFLICT (remote) DO UPDATE SET state = excluded.state, updated_at = excluded.updated_at"
+                )
+                .bind(remote)
+                .bind(state)
+                .execute(p)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens a transaction the `documents` row-level policies evaluate as `user_id`.
+    pub(crate) async fn authenticated_tx(pool: &sqlx::PgPool, user_id: Uuid) -> Result<Transaction<'static, Postgres>> {
+        let mut tx = pool.begin().await?;
+        sqlx::query(&format!("SET LOCAL app.current_user_id = '{}'", user_id))
+            .execute(&mut *tx)
+            .await?;
+        Ok(tx)
+    }
+
+    fn pg_document((id, owner_id, title, class_id, parent_id, properties, updated_at, deleted_at, trash_root_id): PgDocumentRow) -> Option<DocumentRecord> {
+        Some(DocumentRecord {
+            id,
+            owner_id: owner_id?,
+            title,
+            class_id,
+            parent_id,
+            properties: properties.unwrap_or_default(),
+            updated_at: updated_at?.and_utc(),
+            deleted_at: deleted_at.map(|t| t.and_utc()),
+            trash_root_id,
+        })
+    }
+
+    /// A link whose metadata is not JSON fails the read rather than being skipped, which would
+    /// move the cursor past it and never replicate it.
+    fn sqlite_link((from_id, to_id, relation, metadata, created_at, updated_at): SqliteLinkRow) -> Result<Option<LinkRecord>> {
+        let (Ok(from_id), Ok(to_id)) = (Uuid::parse_str(&from_id), Uuid::parse_str(&to_id)) else {
+            return Ok(None);
+        };
+        let metadata = serde_json::from_str(&metadata)
+            .with_context(|| format!("link {}:{}:{} has invalid metadata", from_id, to_id, relation))?;
+        Ok(Some(LinkRecord {
+            from_id,
+            to_id,
+            relation,
+            metadata,
+            created_at: created_at.unwrap_or(updated_at).and_utc(),
+            updated_at: Some(updated_at.and_utc()),
+        }))
+    }
+
+    fn sqlite_document((id, owner_id, title, class_id, parent_id, properties, updated_at, deleted_at, trash_root_id): SqliteDocumentRow) -> Option<DocumentRecord> {
+        Some(DocumentRecord {
+            id: Uuid::parse_str(&id).ok()?,
+            owner_id: Uuid::parse_str(&owner_id).ok()?,
+            title,
+            class_id,
+            parent_id: parent_id.and_then(|p| Uuid::parse_str(&p).ok()),
+            properties: serde_json::from_str(&properties).unwrap_or_default(),
+            updated_at: updated_at?.and_utc(),
+            deleted_at: deleted_at.map(|t| t.and_utc()),
+            trash_root_id: trash_root_id.and_then(|r| Uuid::parse_str(&r).ok()),
+        })
+    }
+}
+
+impl PushResult {
+    fn count(&mut self, accepted: bool) {
+        match accepted {
+            true => self.accepted += 1,
+            false => self.ignored += 1,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Days a document stays in the trash before it is purged for good.
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// A document the user deleted. Its descendants went to the trash with it and come back with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub id: Uuid,
+    pub title: String,
+    pub class_id: Option<String>,
+    pub deleted_at: DateTime<Utc>,
+    /// Documents in the set, the deleted one included.
+    pub documents: u64,
+}
+
+/// Why a trash operation was refused.
+#[derive(Error, Debug)]
+pub enum TrashError {
+    #[error("document {0} not found")]
+    NotFound(Uuid),
+    #[error("document {0} belongs to another user")]
+    Forbidden(Uuid),
+    #[error("document {0} is already in the trash")]
+    AlreadyTrashed(Uuid),
+    #[error("document {0} is not in the trash")]
+    NotTrashed(Uuid),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use crate::domain::repository::DocumentRepository;
+use crate::modules::content::socket::ContentRegistry;
+use super::domain::{TrashError, TrashItem, DEFAULT_RETENTION_DAYS};
+
+/// How often `run_purge` looks for documents past the retention period.
+const PURGE_PERIOD: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Deletion of documents through a trash. Deleting a document moves it and its descendants out
+/// of sight; the set can be restored as a whole until it is purged, by its owner or once it has
+/// been in the trash longer than the retention period. Purging removes the documents together
+/// with their content, links, collection rows and embeddings.
+///
+/// With a registry, the rooms of trashed and purged documents are unloaded and their sockets
+/// closed, so no update reaches a document after it is deleted.
+pub struct TrashService {
+    documents: Arc<dyn DocumentRepository>,
+    registry: Option<Arc<ContentRegistry>>,
+    retention: Duration,
+}
+
+impl TrashService {
+    pub fn new(documents: Arc<dyn DocumentRepository>) -> Self {
+        Self { documents, registry: None, retention: Duration::days(DEFAULT_RETENTION_DAYS) }
+    }
+
+    /// Unloads the rooms of the documents this service deletes from `registry`.
+    pub fn with_registry(mut self, registry: Arc<ContentRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Moves a document of `owner_id` and its descendants to the trash. Returns how many
+    /// documents were trashed.
+    pub async fn trash(&self, owner_id: Uuid, doc_id: Uuid) -> Result<u64, TrashError> {
+        self.check_owner(owner_id, doc_id).await?;
+        let trashed = self.documents.trash(doc_id, owner_id).await?;
+        if trashed.is_empty() {
+            return Err(TrashError::AlreadyTrashed(doc_id));
+        }
+        if let Some(registry) = &self.registry {
+            for id in trashed.iter().map(Uuid::to_string) {
+                // Edits made before the deletion come back with a restore.
+                if let Err(e) = registry.flush_doc(&id).await {
+                    tracing::warn!("[Trash] Dropping unwritten edits of trashed {}: {}", id, e);
+                }
+                registry.discard(&id).await;
+            }
+        }
+        tracing::info!("[Trash] TRASHED {} ({} documents)", doc_id, trashed.len());
+        Ok(trashed.len() as u64)
+    }
+
+    /// Brings back a document deleted by `owner_id` with the descendants deleted along with it.
+    pub async fn restore(&self, owner_id: Uuid, doc_id: Uuid) -> Result<u64, TrashError> {
+        self.check_owner(owner_id, doc_id).await?;
+        let restored = self.documents.restore(doc_id, owner_id).await?;
+        if restored == 0 {
+            return Err(TrashError::NotTrashed(doc_id));
+        }
+        tracing::info!("[Trash] RESTORED {} ({} documents)", doc_id, restored);
+        Ok(restored)
+    }
+
+    /// Documents `owner_id` has deleted, most recent first.
+    pub async fn list(&self, owner_id: Uuid) -> Result<Vec<TrashItem>, TrashError> {
+        Ok(self.documents.find_trash(owner_id).await?)
+    }
+
+    /// Deletes a trashed document of `owner_id` and its trashed descendants for good.
+    pub async fn purge(&self, owner_id: Uuid, doc_id: Uuid) -> Result<u64, TrashError> {
+        self.check_owner(owner_id, doc_id).await?;
+        let purged = self.documents.purge(doc_id, owner_id).await?;
+        if purged.is_empty() {
+            return Err(TrashError::NotTrashed(doc_id));
+        }
+        self.discard(&purged).await;
+        tracing::info!("[Trash] PURGED {} ({} documents)", doc_id, purged.len());
+        Ok(purged.len() as u64)
+    }
+
+    /// Purges everything that has outlived the retention period, for every user.
+    pub async fn purge_expired(&self) -> Result<u64, TrashError> {
+        let purged = self.documents.purge_expired(Utc::now() - self.retention).await?;
+        if !purged.is_empty() {
+            self.discard(&purged).await;
+            tracing::info!("[Trash] PURGED {} expired documents", purged.len());
+        }
+        Ok(purged.len() as u64)
+    }
+
+    /// Unloads the rooms of purged documents, dropping updates that can no longer be written.
+    async fn discard(&self, ids: &[Uuid]) {
+        if let Some(registry) = &self.registry {
+            for id in ids {
+                registry.discard(&id.to_string()).await;
+            }
+        }
+    }
+
+    /// Runs `purge_expired` periodically for the lifetime of the process.
+    pub async fn run_purge(self: Arc<Self>) {
+        loop {
+            if let Err(e) = self.purge_expired().await {
+                tracing::error!("[Trash] Purge of expired documents failed: {}", e);
+            }
+            tokio::time::sleep(PURGE_PERIOD).await;
+        }
+    }
+
+    async fn check_owner(&self, owner_id: Uuid, doc_id: Uuid) -> Result<(), TrashError> {
+        match self.documents.find_owner(doc_id).await? {
+            Some(owner) if owner == owner_id => Ok(()),
+            Some(_) => Err(TrashError::Forbidden(doc_id)),
+            None => Err(TrashError::NotFound(doc_id)),
+        }
+    }
+}
@@ -2,64 +2,143 @@
 //!
 //! Provides the primary connection pool and the global system state (CoreState).
 
-use sqlx::PgPool;
 use std::sync::Arc;
-use crate::infrastructure::postgres::{PostgresDocumentRepository, PostgresArchetypeRepository, PostgresAuditRepository};
+use std::str::FromStr;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use crate::domain::repository::{ArchetypeRepository, AuditRepository, DocumentRepository, UserRepository};
+use crate::infrastructure::postgres::{PostgresDocumentRepository, PostgresArchetypeRepository, PostgresAuditRepository, PostgresUserRepository};
+use crate::infrastructure::sqlite::{SqliteDocumentRepository, SqliteArchetypeRepository, SqliteAuditRepository, SqliteUserRepository};
 use crate::modules::content::socket::ContentRegistry;
+use crate::modules::content::storage::DbPool;
+use crate::modules::graph::service::GraphService;
+use crate::modules::hierarchy::service::HierarchyService;
+use crate::modules::portability::service::PortabilityService;
+use crate::modules::query::service::QueryService;
+use crate::modules::sync::service::SyncService;
+use crate::modules::sync::store::SyncStore;
+use crate::modules::trash::service::TrashService;
 use crate::modules::security::service::SecurityService;
 use crate::domain::archetypes::modules::ModuleRegistry;
+use crate::shared::migrations::{run_migrations, run_sqlite_migrations};
 
 /// CoreState serves as the central dependency injector for the system.
 /// It encapsulates database connections and service handles required across modules.
+/// Repositories are chosen by the backend of the pool, so handlers never depend on it.
 pub struct CoreState {
-    pub pool: PgPool,
-    pub documents: Arc<PostgresDocumentRepository>,
-    pub archetypes: Arc<PostgresArchetypeRepository>,
-    pub audit: Arc<PostgresAuditRepository>,
+    pub pool: DbPool,
+    pub documents: Arc<dyn DocumentRepository>,
+    pub archetypes: Arc<dyn ArchetypeRepository>,
+    pub audit: Arc<dyn AuditRepository>,
     pub security: Arc<SecurityService>,
     pub modules: Arc<ModuleRegistry>,
+    pub hierarchy: Arc<HierarchyService>,
+    pub graph: Arc<GraphService>,
+    pub trash: Arc<TrashService>,
+    pub query: Arc<QueryService>,
     pub registry: Arc<ContentRegistry>,
+    pub sync: Arc<SyncService>,
+    pub portability: Arc<PortabilityService>,
 }
 
 impl CoreState {
     /// Creates a new CoreState instance with initialized repositories and services.
-    pub fn new(pool: PgPool, registry: Arc<ContentRegistry>) -> Self {
+    pub fn new(pool: DbPool, registry: Arc<ContentRegistry>) -> Self {
+        match pool.clone() {
+            DbPool::Postgres(p) => Self::with_repositories(
+                pool,
+                registry,
+                Arc::new(PostgresDocumentRepository::new(p.clone())),
+                Arc::new(PostgresArchetypeRepository::new(p.clone())),
+                Arc::new(PostgresAuditRepository::new(p.clone())),
+                Arc::new(PostgresUserRepository::new(p)),
+            ),
+            DbPool::Sqlite(p) => Self::with_repositories(
+                pool,
+                registry,
+                Arc::new(SqliteDocumentRepository::new(p.clone())),
+                Arc::new(SqliteArchetypeRepository::new(p.clone())),
+                Arc::new(SqliteAuditRepository::new(p.clone())),
+                Arc::new(SqliteUserRepository::new(p)),
+            ),
+        }
+    }
+
+    fn with_repositories(
+        pool: DbPool,
+        registry: Arc<ContentRegistry>,
+        documents: Arc<dyn DocumentRepository>,
+        archetypes: Arc<dyn ArchetypeRepository>,
+        audit: Arc<dyn AuditRepository>,
+        users: Arc<dyn UserRepository>,
+    ) -> Self {
+        let modules = Arc::new(ModuleRegistry::new());
         Self {
-            pool: pool.clone(),
-            documents: Arc::new(PostgresDocumentRepository::new(pool.clone())),
-            archetypes: Arc::new(PostgresArchetypeRepository::new(pool.clone())),
-            audit: Arc::new(PostgresAuditRepository::new(pool.clone())),
-            security: Arc::new(SecurityService::new(pool)),
-            modules: Arc::new(ModuleRegistry::new()),
+            hierarchy: Arc::new(HierarchyService::new(documents.clone(), archetypes.clone(), modules.clone())),
+            graph: Arc::new(GraphService::new(documents.clone())),
+            trash: Arc::new(TrashService::new(documents.clone()).with_registry(registry.clone())),
+            query: Arc::new(QueryService::new(documents.clone())),
+            documents,
+            archetypes,
+            audit,
+            security: Arc::new(SecurityService::new(users)),
+            modules,
+            sync: Arc::new(SyncService::new(SyncStore::new(pool.clone()), registry.clone())),
+            portability: Arc::new(PortabilityService::new(pool.clone())),
             registry,
+            pool,
         }
     }
 }
 
-/// Database connection wrapper for PostgreSQL.
+/// Database connection wrapper, over PostgreSQL or SQLite.
 pub struct Db {
-    pub pool: PgPool,
+    pub pool: DbPool,
 }
 
 impl Db {
     /// Establishes a new connection pool to the database using the provided URL.
+    /// `postgres://` and `postgresql://` URLs select PostgreSQL, `sqlite:` URLs SQLite.
     pub async fn new(url: &str) -> Result<Self, sqlx::Error> {
-        tracing::info!("Initializing Database Pool (Postgres)...");
-        
         let max_connections = std::env::var("DB_MAX_CONNECTIONS")
             .ok()
             .and_then(|v| v.parse().ok())
             .unwrap_or(10);
 
-        let pool = sqlx::postgres::PgPoolOptions::new()
-            .max_connections(max_connections)
-            .min_connections(1)
-            .acquire_timeout(std::time::Duration::from_secs(30))
-            .idle_timeout(std::time::Duration::from_secs(60))
-            .connect(url)
-            .await?;
-        
-        tracing::info!("Database Pool established with {} max connections.", max_connections);
+        let pool = if url.starts_with("sqlite:") {
+            tracing::info!("Initializing Database Pool (SQLite)...");
+            // Every connection to an in-memory database would open a database of its own.
+            let max_connections = if url.contains(":memory:") { 1 } else { max_connections };
+            let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+            let pool = SqlitePoolOptions::new()
+                .max_connections(max_connections)
+                .acquire_timeout(std::time::Duration::from_secs(30))
+                .connect_with(options)
+                .await?;
+            tracing::info!("Database Pool established with {} max connections.", max_connections);
+            DbPool::Sqlite(pool)
+        } else if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            tracing::info!("Initializing Database Pool (Postgres)...");
+            let pool = sqlx::postgres::PgPoolOptions::new()
+                .max_connections(max_connections)
+                .min_connections(1)
+                .acquire_timeout(std::time::Duration::from_secs(30))
+                .idle_timeout(std::time::Duration::from_secs(60))
+                .connect(url)
+                .await?;
+            tracing::info!("Database Pool established with {} max connections.", max_connections);
+            DbPool::Postgres(pool)
+        } else {
+            return Err(sqlx::Error::Configuration(format!("unsupported database URL scheme: {}", url.split(':').next().unwrap_or(url)).into()));
+        };
+
         Ok(Self { pool })
     }
+
+    /// Applies the migrations of the backend: `migrations` or `migrations_sqlite`.
+    pub async fn migrate(&self) -> anyhow::Result<()> {
+        match &self.pool {
+            DbPool::Postgres(p) => run_migrations(p).await,
+            DbPool::Sqlite(p) => run_sqlite_migrations(p).await,
+        }
+    }
 }
@@ -1,6 +1,11 @@
-use sqlx::PgPool;
+use sqlx::{PgPool, SqlitePool};
 use anyhow::Result;
+use std::collections::BTreeMap;
 use tracing::{info, error};
+use crate::modules::content::storage::DbPool;
+
+/// Column name to logical type, per table.
+pub type LogicalSchema = BTreeMap<String, BTreeMap<String, String>>;
 
 pub async fn run_migrations(pool: &PgPool) -> Result<()> {
     info!("Starting Sovereign Migration Runner...");
@@ -8,7 +13,7 @@ pub async fn run_migrations(pool: &PgPool) -> Result<()> {
     // Usamos o migrator nativo do sqlx que gerencia a tabela _sqlx_migrations
     // Isso garante que novas migrações sejam detectadas automaticamente
     let migrator = sqlx::migrate!("./migrations");
-    
+
     match migrator.run(pool).await {
         Ok(_) => {
             info!("Database Migrations: ALL_SYSTEMS_GO (Applied Successfully)");
@@ -19,4 +24,91 @@ pub async fn run_migrations(pool: &PgPool) -> Result<()> {
             Err(anyhow::anyhow!("Failed to run migrations: {}", e))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Migrates a local vault with `migrations_sqlite`, the SQLite counterpart of `migrations`.
+/// Every schema change lands in both directories; `logical_schema` tells whether they agree.
+pub async fn run_sqlite_migrations(pool: &SqlitePool) -> Result<()> {
+    upgrade_legacy_vault(pool).await?;
+
+    match sqlx::migrate!("./migrations_sqlite").run(pool).await {
+        Ok(_) => {
+            info!("Vault Migrations: ALL_SYSTEMS_GO (Applied Successfully)");
+            Ok(())
+        },
+        Err(e) => {
+            error!("CRITICAL_MIGRATION_FAILURE: {}", e);
+            Err(anyhow::anyhow!("Failed to run vault migrations: {}", e))
+        }
+    }
+}
+
+/// Vaults created before migrations were tracked may lack columns added in place since.
+/// They are added here, so the baseline migration finds the schema it describes.
+async fn upgrade_legacy_vault(pool: &SqlitePool) -> Result<()> {
+    let tracked: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'")
+        .fetch_one(pool).await?;
+    let legacy: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'documents'")
+        .fetch_one(pool).await?;
+    if tracked || !legacy {
+        return Ok(());
+    }
+
+    if !has_column(pool, "document_updates", "author_id").await? {
+        sqlx::query("ALTER TABLE document_updates ADD COLUMN author_id TEXT").execute(pool).await?;
+    }
+    // A column added later cannot default to the current time, so existing links are stamped once.
+    if !has_column(pool, "document_links", "created_at").await? {
+        sqlx::query("ALTER TABLE document_links ADD COLUMN created_at DATETIME").execute(pool).await?;
+        sqlx::query("UPDATE document_links SET created_at = CURRENT_TIMESTAMP").execute(pool).await?;
+    }
+    info!("Legacy vault prepared for versioned migrations");
+    Ok(())
+}
+
+async fn has_column(pool: &SqlitePool, table: &str, column: &str) -> Result<bool> {
+    Ok(sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table).bind(column)
+        .fetch_one(pool).await?)
+}
+
+/// Tables and columns of a database, with each column type reduced to the family both backends
+/// share (`text`, `integer`, `real`, `boolean`, `timestamp`, `blob`). Two databases migrated to
+/// the same version must return the same schema.
+pub async fn logical_schema(pool: &DbPool) -> Result<LogicalSchema> {
+    let columns: Vec<(String, String, String)> = match pool {
+        DbPool::Postgres(p) => {
+            sqlx::query_as(
+                "SELECT table_name::TEXT, column_name::TEXT, data_type::TEXT FROM information_schema.columns
+                 WHERE table_schema = 'public' AND table_name <> '_sqlx_migrations'"
+            )
+            .fetch_all(p).await?
+        }
+        DbPool::Sqlite(p) => {
+            sqlx::query_as(
+                "SELECT m.name, c.name, c.type FROM sqlite_master m JOIN pragma_table_info(m.name) c
+                 WHERE m.type = 'table' AND m.name NOT IN ('_sqlx_migrations', 'sqlite_sequence')"
+            )
+            .fetch_all(p).await?
+        }
+    };
+
+    let mut schema = LogicalSchema::new();
+    for (table, column, data_type) in columns {
+        schema.entry(table).or_default().insert(column, type_family(&data_type).to_string());
+    }
+    Ok(schema)
+}
+
+fn type_family(data_type: &str) -> &'static str {
+    let data_type = data_type.to_ascii_lowercase();
+    match data_type.as_str() {
+        "integer" | "bigint" | "smallint" => "integer",
+        "real" | "double precision" => "real",
+        "boolean" => "boolean",
+        "bytea" | "blob" | "user-defined" => "blob",
+        t if t.starts_with("timestamp") || t == "datetime" || t == "date" => "timestamp",
+        // uuid, character varying, text, jsonb and arrays are all text to SQLite
+        _ => "text",
+    }
+}
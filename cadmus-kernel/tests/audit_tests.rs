@@ -0,0 +1,50 @@
+use cadmus_kernel::domain::repository::AuditRepository;
+use cadmus_kernel::infrastructure::sqlite::{SqliteAuditRepository, SqliteDocumentRepository};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+async fn sqlite_audit() -> (SqlitePool, SqliteAuditRepository) {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.expect("Failed to initialize schema");
+    (pool.clone(), SqliteAuditRepository::new(pool))
+}
+
+#[tokio::test]
+async fn test_local_audit_chain_detects_tampering() {
+    let (pool, audit) = sqlite_audit().await;
+    let user_id = Uuid::new_v4();
+    let doc_id = Uuid::new_v4();
+
+    // 1. Entries written within the same second still chain in the order they were made.
+    audit.log(Some(user_id), None, "Auth", "LOGIN", None).await.unwrap();
+    audit.log(Some(user_id), Some(doc_id), "Document", "DELETE", Some(r#"{"title":"Plan"}"#.into())).await.unwrap();
+    audit.log(None, Some(doc_id), "Content", "RESTORE_VERSION", None).await.unwrap();
+
+    let recent = audit.recent(10).await.unwrap();
+    let actions: Vec<_> = recent.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(actions, ["RESTORE_VERSION", "DELETE", "LOGIN"]);
+    assert_eq!(recent[0].prev_hash, recent[1].hash);
+    assert_eq!(recent[2].prev_hash, None);
+
+    let report = audit.verify().await.unwrap();
+    assert!(report.valid);
+    assert_eq!(report.checked, 3);
+
+    // 2. Editing an entry in place breaks the chain at that entry.
+    sqlx::query("UPDATE audit_logs SET details = '{\"title\":\"Other\"}' WHERE action = 'DELETE'")
+        .execute(&pool).await.unwrap();
+    let report = audit.verify().await.unwrap();
+    assert!(!report.valid);
+    assert_eq!(report.checked, 1);
+    assert_eq!(report.broken_at, Some(recent[1].id));
+
+    // 3. So does removing one: the next entry no longer points at its predecessor.
+    sqlx::query("DELETE FROM audit_logs WHERE action = 'DELETE'").execute(&pool).await.unwrap();
+    let report = audit.verify().await.unwrap();
+    assert_eq!(report.broken_at, Some(recent[0].id));
+}
@@ -0,0 +1,75 @@
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::bus::{BusPayload, MemoryRoomBus, PayloadAssembler};
+use cadmus_kernel::modules::content::socket::ContentRegistry;
+use cadmus_kernel::modules::content::storage::ContentStorage;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact};
+
+#[test]
+fn test_large_updates_are_split_and_reassembled() {
+    let origin = Uuid::new_v4();
+    let update: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let payloads = BusPayload::split(origin, 7, "doc-1", &update);
+    assert!(payloads.len() > 1);
+    assert!(payloads.iter().all(|p| p.len() < 8000));
+
+    // Parts may interleave with other updates; each completes only once all its parts arrived.
+    let mut assembler = PayloadAssembler::default();
+    let small = BusPayload::split(origin, 8, "doc-2", &[1, 2, 3]);
+    let mut parsed: Vec<BusPayload> = payloads.iter().map(|p| serde_json::from_str(p).unwrap()).collect();
+    parsed.reverse();
+    let last = parsed.pop().unwrap();
+    for part in parsed {
+        assert!(assembler.push(part).is_none());
+    }
+    assert_eq!(assembler.push(serde_json::from_str(&small[0]).unwrap()), Some(("doc-2".to_string(), vec![1, 2, 3])));
+    assert_eq!(assembler.push(last), Some(("doc-1".to_string(), update)));
+}
+
+#[tokio::test]
+async fn test_updates_fan_out_to_peer_instances_without_double_persisting() {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.expect("Failed to initialize schema");
+    let storage = Arc::new(ContentStorage::new_sqlite(pool.clone()));
+    let doc_id = Uuid::new_v4().to_string();
+
+    // Two API replicas sharing one database and one bus.
+    let bus = MemoryRoomBus::new();
+    let peer_bus = bus.peer();
+    let first = Arc::new(ContentRegistry::new(Some(storage.clone())).with_bus(Arc::new(bus)));
+    let second = Arc::new(ContentRegistry::new(Some(storage.clone())).with_bus(Arc::new(peer_bus)));
+    tokio::spawn(first.clone().run_bus());
+    tokio::spawn(second.clone().run_bus());
+    tokio::task::yield_now().await;
+
+    let watcher = second.join(&doc_id).await;
+    let mut rx = watcher.tx.subscribe();
+
+    let client = Doc::new();
+    client.get_or_insert_text("content").push(&mut client.transact_mut(), "Sovereign");
+    let update = client.transact().encode_state_as_update_v1(&StateVector::default());
+    first.process_update_as(&doc_id, update.clone(), None).await.unwrap();
+
+    let relayed = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await
+        .expect("Peer room did not receive the update")
+        .unwrap();
+    assert_eq!(relayed, update);
+    // Relayed only once written, so a peer loading the room from storage already finds it.
+    assert_eq!(storage.load_updates(&doc_id).await.unwrap().len(), 1);
+    let text = watcher.doc.get_or_insert_text("content");
+    assert_eq!(text.get_string(&watcher.doc.transact()), "Sovereign");
+
+    // Only the instance the edit arrived on stored it, and nothing echoed back to it.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    first.flush().await.unwrap();
+    second.flush().await.unwrap();
+    assert_eq!(storage.load_updates(&doc_id).await.unwrap().len(), 1);
+    assert_eq!(first.get_room(&doc_id).await.revision(), 1);
+}
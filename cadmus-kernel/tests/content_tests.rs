@@ -1,5 +1,10 @@
 use cadmus_kernel::infrastructure::postgres::PostgresDocumentRepository;
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::shared::migrations::logical_schema;
 use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::modules::content::storage::DbPool;
+use cadmus_kernel::modules::sync::domain::{ChangeSet, CollectionRowRecord, DocumentRecord, LinkRecord, SyncCursor, Tombstone, TombstoneKind};
+use cadmus_kernel::modules::sync::store::SyncStore;
 use sqlx::postgres::PgPoolOptions;
 use std::env;
 use uuid::Uuid;
@@ -40,8 +45,8 @@ async fn test_document_lifecycle() {
     let found = found.unwrap();
     assert_eq!(found.properties["content"], "Hello Cadmus");
 
-    // 5. Delete
-    repo.delete(doc.id, user_id).await.expect("Failed to delete doc");
+    // 5. Trash
+    assert_eq!(repo.trash(doc.id, user_id).await.expect("Failed to trash doc"), [doc.id]);
     let missing = repo.find_by_id(doc.id).await.expect("Fail");
     assert!(missing.is_none());
 }
@@ -56,12 +61,115 @@ async fn test_embedding_search() {
 
     sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)").bind(user_id).bind(format!("ai_{}", user_id)).bind("hash").execute(&pool).await.ok();
 
+    check_embedding_search(&repo, user_id).await;
+}
+
+#[tokio::test]
+async fn test_embedding_search_sqlite() {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    let repo = SqliteDocumentRepository::new(pool);
+    repo.initialize().await.expect("Failed to initialize vault");
+
+    check_embedding_search(&repo, Uuid::new_v4()).await;
+}
+
+/// Behaviour both backends share: nearest first by Euclidean distance, 384 dimensions.
+async fn check_embedding_search(repo: &dyn DocumentRepository, user_id: Uuid) {
     let doc = repo.create(user_id, "AI Doc".to_string(), None, None).await.expect("Fail");
-    
+
     let vector = vec![0.1; 384]; // 384 dimensions as defined in migration
     repo.update_embedding(doc.id, vector.clone()).await.expect("Failed to upsert embedding");
 
-    let results = repo.search_similar(vector, 1).await.expect("Search failed");
+    let results = repo.search_similar(vector.clone(), 1).await.expect("Search failed");
     assert_eq!(results.len(), 1);
     assert_eq!(results[0], doc.id);
+
+    // Far away documents rank behind close ones, and moving an embedding moves its rank.
+    let near = repo.create(user_id, "Near".to_string(), None, None).await.expect("Fail");
+    let far = repo.create(user_id, "Far".to_string(), None, None).await.expect("Fail");
+    repo.update_embedding(near.id, vec![0.12; 384]).await.expect("Failed to upsert embedding");
+    repo.update_embedding(far.id, vec![-5.0; 384]).await.expect("Failed to upsert embedding");
+    let results = repo.search_similar(vec![0.1; 384], 1000).await.expect("Search failed");
+    let rank = |id| results.iter().position(|r| *r == id).unwrap();
+    assert!(rank(doc.id) < rank(near.id) && rank(near.id) < rank(far.id));
+
+    repo.update_embedding(far.id, vec![0.11; 384]).await.expect("Failed to upsert embedding");
+    let results = repo.search_similar(vec![0.1; 384], 1000).await.expect("Search failed");
+    let rank = |id| results.iter().position(|r| *r == id).unwrap();
+    assert!(rank(far.id) < rank(near.id));
+
+    assert!(repo.update_embedding(doc.id, vec![0.1; 3]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_sync_store_round_trip() {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let pool = PgPoolOptions::new().max_connections(2).connect(&db_url).await.expect("Fail");
+    let store = SyncStore::new(DbPool::Postgres(pool.clone()));
+    let user_id = Uuid::new_v4();
+
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)").bind(user_id).bind(format!("sync_{}", user_id)).bind("hash").execute(&pool).await.ok();
+
+    // 1. A replica pushes a document, a row and a link written while offline.
+    let edited_at = chrono::Utc::now() - chrono::Duration::hours(1);
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+    let document = |id, title: &str| DocumentRecord {
+        id, owner_id: user_id, title: title.into(), class_id: Some("note".into()), parent_id: None,
+        properties: serde_json::json!({"tags": ["field"]}), updated_at: edited_at, deleted_at: None, trash_root_id: None,
+    };
+    let changes = ChangeSet {
+        documents: vec![document(a, "Offline A"), document(b, "Offline B")],
+        collection_rows: vec![CollectionRowRecord { id: Uuid::new_v4(), document_id: a, data: serde_json::json!({"qty": 2}), order_index: None, updated_at: edited_at }],
+        links: vec![
+            LinkRecord { from_id: a, to_id: b, relation: "link".into(), metadata: serde_json::json!({}), created_at: edited_at, updated_at: None },
+            LinkRecord { from_id: a, to_id: b, relation: "cites".into(), metadata: serde_json::json!({"page": 4}), created_at: edited_at, updated_at: None },
+        ],
+        ..Default::default()
+    };
+    let pushed = store.apply(user_id, &changes, Some(chrono::Utc::now())).await.expect("Push failed");
+    assert_eq!((pushed.accepted, pushed.ignored), (5, 0));
+
+    // 2. Pushing the same versions again changes nothing.
+    let again = store.apply(user_id, &changes, Some(chrono::Utc::now())).await.expect("Push failed");
+    assert_eq!((again.accepted, again.ignored), (0, 5));
+
+    // 3. Another replica reads everything back, stamped with the time it arrived.
+    let page = store.changes_since(user_id, &SyncCursor::default(), 100, std::time::Duration::ZERO).await.expect("Pull failed");
+    assert_eq!(page.documents.len(), 2);
+    assert!(page.documents.iter().all(|d| d.updated_at > edited_at));
+    assert_eq!(page.collection_rows[0].data["qty"], 2);
+    assert_eq!(page.links.len(), 2);
+    let cites = page.links.iter().find(|l| l.relation == "cites").expect("Typed link was not replicated");
+    assert_eq!(cites.metadata, serde_json::json!({"page": 4}));
+    let next = store.changes_since(user_id, &page.cursor, 100, std::time::Duration::ZERO).await.expect("Pull failed");
+    assert!(next.is_empty());
+
+    // 4. A link edited later and a link removed later both reach the next reader.
+    let plain = page.links.iter().find(|l| l.relation == "link").unwrap();
+    let edited = LinkRecord { metadata: serde_json::json!({"page": 5}), updated_at: Some(chrono::Utc::now()), ..cites.clone() };
+    let removed = Tombstone { kind: TombstoneKind::Link, key: plain.key(), deleted_at: chrono::Utc::now() };
+    let changes = ChangeSet { links: vec![edited], tombstones: vec![removed], ..Default::default() };
+    let pushed = store.apply(user_id, &changes, Some(chrono::Utc::now())).await.expect("Push failed");
+    assert_eq!((pushed.accepted, pushed.ignored), (2, 0));
+    let later = store.changes_since(user_id, &page.cursor, 100, std::time::Duration::ZERO).await.expect("Pull failed");
+    assert_eq!(later.links.len(), 1);
+    assert_eq!(later.links[0].metadata, serde_json::json!({"page": 5}));
+    assert_eq!(later.tombstones.len(), 1);
+    let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM document_links WHERE from_id = $1").bind(a).fetch_one(&pool).await.unwrap();
+    assert_eq!(left, 1);
+}
+
+#[tokio::test]
+async fn test_sqlite_vault_matches_postgres_schema() {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for integration tests");
+    let pg = PgPoolOptions::new().max_connections(1).connect(&db_url).await.expect("Failed to connect to test database");
+    let sqlite = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    SqliteDocumentRepository::new(sqlite.clone()).initialize().await.expect("Failed to initialize vault");
+
+    // Every table and column of the server exists in a vault, with a compatible type.
+    let server = logical_schema(&DbPool::Postgres(pg)).await.expect("Failed to read Postgres schema");
+    let vault = logical_schema(&DbPool::Sqlite(sqlite)).await.expect("Failed to read SQLite schema");
+    assert_eq!(vault, server);
 }
@@ -0,0 +1,109 @@
+use cadmus_kernel::modules::content::extract::{DocumentContent, OutlineEntry, EDITOR_FRAGMENT};
+use cadmus_kernel::modules::content::socket::ContentRegistry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use yrs::types::Attrs;
+use yrs::{Any, Doc, ReadTxn, Text, Transact, Xml, XmlElementPrelim, XmlFragment, XmlTextPrelim};
+
+/// Builds a document the way Tiptap's collaboration binding lays it out.
+fn editor_doc() -> Doc {
+    let doc = Doc::new();
+    let body = doc.get_or_insert_xml_fragment(EDITOR_FRAGMENT);
+    let mut txn = doc.transact_mut();
+
+    let h = body.push_back(&mut txn, XmlElementPrelim::empty("heading"));
+    h.insert_attribute(&mut txn, "level", "1");
+    h.push_back(&mut txn, XmlTextPrelim::new("Launch plan"));
+
+    let p = body.push_back(&mut txn, XmlElementPrelim::empty("paragraph"));
+    let t = p.push_back(&mut txn, XmlTextPrelim::new("Ship the kernel via the site"));
+    t.format(&mut txn, 9, 6, Attrs::from([(Arc::from("bold"), Any::Bool(true))]));
+    let href = HashMap::from([("href".to_string(), Any::from("https://cadmus.dev"))]);
+    t.format(&mut txn, 20, 8, Attrs::from([(Arc::from("link"), Any::from(href))]));
+
+    let h2 = body.push_back(&mut txn, XmlElementPrelim::empty("heading"));
+    h2.insert_attribute(&mut txn, "level", "2");
+    h2.push_back(&mut txn, XmlTextPrelim::new("Tasks"));
+
+    let list = body.push_back(&mut txn, XmlElementPrelim::empty("bulletList"));
+    for item in ["Write docs", "Cut release"] {
+        let li = list.push_back(&mut txn, XmlElementPrelim::empty("listItem"));
+        let p = li.push_back(&mut txn, XmlElementPrelim::empty("paragraph"));
+        p.push_back(&mut txn, XmlTextPrelim::new(item));
+    }
+
+    let code = body.push_back(&mut txn, XmlElementPrelim::empty("codeBlock"));
+    code.insert_attribute(&mut txn, "language", "rust");
+    code.push_back(&mut txn, XmlTextPrelim::new("fn main() {}"));
+    drop(txn);
+    doc
+}
+
+#[test]
+fn test_extracts_text_markdown_and_outline() {
+    let doc = editor_doc();
+    let content = DocumentContent::extract(&doc.transact());
+
+    assert_eq!(content.outline, vec![
+        OutlineEntry { level: 1, text: "Launch plan".into() },
+        OutlineEntry { level: 2, text: "Tasks".into() },
+    ]);
+    assert_eq!(
+        content.text,
+        "Launch plan\nShip the kernel via the site\nTasks\nWrite docs\nCut release\nfn main() {}"
+    );
+    assert_eq!(
+        content.markdown,
+        "# Launch plan\n\n\
+         Ship the **kernel** via [the site](https://cadmus.dev)\n\n\
+         ## Tasks\n\n\
+         - Write docs\n- Cut release\n\n\
+         ```rust\nfn main() {}\n```"
+    );
+
+    // Documents rebuilt from the update log have untyped roots; they extract the same way.
+    let replica = Doc::new();
+    let update = doc.transact().encode_state_as_update_v1(&Default::default());
+    replica.transact_mut().apply_update(yrs::updates::decoder::Decode::decode_v1(&update).unwrap());
+    assert_eq!(DocumentContent::extract(&replica.transact()), content);
+}
+
+#[tokio::test]
+async fn test_room_content_refreshes_after_updates_settle() {
+    let registry = ContentRegistry::new(None).with_extract_debounce(Duration::from_millis(20));
+    let doc = editor_doc();
+    let update = doc.transact().encode_state_as_update_v1(&Default::default());
+
+    let room = registry.get_room("doc-1").await;
+    assert!(registry.content("doc-1").await.unwrap().outline.is_empty());
+
+    registry.process_update_as("doc-1", update, None).await.unwrap();
+    assert_eq!(room.revision(), 1);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The debounced refresh already cached the result for this revision.
+    let first = room.content().await.unwrap();
+    let again = registry.content("doc-1").await.unwrap();
+    assert!(Arc::ptr_eq(&first, &again));
+    assert_eq!(first.outline.len(), 2);
+}
+
+#[tokio::test]
+async fn test_room_content_waits_for_a_busy_document() {
+    let registry = ContentRegistry::new(None);
+    let room = registry.get_room("doc-1").await;
+
+    let (held, holding) = std::sync::mpsc::channel();
+    let writer = {
+        let doc = room.doc.clone();
+        std::thread::spawn(move || {
+            let _txn = doc.transact_mut();
+            held.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+        })
+    };
+    holding.recv().unwrap();
+    assert!(registry.content("doc-1").await.is_ok());
+    writer.join().unwrap();
+}
@@ -0,0 +1,183 @@
+use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::infrastructure::postgres::PostgresDocumentRepository;
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::graph::domain::{EdgeKind, GraphError, Traversal};
+use cadmus_kernel::modules::graph::service::GraphService;
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+async fn sqlite_repo() -> Arc<SqliteDocumentRepository> {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    let repo = SqliteDocumentRepository::new(pool);
+    repo.initialize().await.unwrap();
+    Arc::new(repo)
+}
+
+/// Relates two documents in several ways, reads the backlinks and unlinks them again.
+async fn check_typed_links(documents: Arc<dyn DocumentRepository>, owner_id: Uuid) {
+    let service = GraphService::new(documents.clone());
+    let spec = documents.create(owner_id, "Spec".into(), None, None).await.unwrap();
+    let paper = documents.create(owner_id, "Paper".into(), None, None).await.unwrap();
+    let build = documents.create(owner_id, "Build".into(), None, None).await.unwrap();
+
+    let plain = service.link(owner_id, paper.id, spec.id, None, None).await.expect("Failed to link");
+    assert_eq!((plain.relation.as_str(), &plain.metadata), ("link", &json!({})));
+    service.link(owner_id, paper.id, spec.id, Some("cites".into()), Some(json!({ "page": 3 }))).await.unwrap();
+    service.link(owner_id, build.id, spec.id, Some("depends_on".into()), None).await.unwrap();
+    // Linking again under the same relation replaces its metadata.
+    let cites = service.link(owner_id, paper.id, spec.id, Some("cites".into()), Some(json!({ "page": 7 }))).await.unwrap();
+    assert_eq!(cites.metadata, json!({ "page": 7 }));
+
+    let backlinks = service.backlinks(owner_id, spec.id).await.expect("Failed to read backlinks");
+    assert_eq!(backlinks.len(), 3);
+    assert!(backlinks.iter().all(|l| l.to_id == spec.id && l.created_at.is_some()));
+    let cited = backlinks.iter().find(|l| l.relation == "cites").expect("Missing typed link");
+    assert_eq!((cited.from_id, &cited.metadata), (paper.id, &json!({ "page": 7 })));
+    // The graph view still sees each pair once.
+    assert_eq!(documents.find_links(owner_id).await.unwrap().len(), 2);
+
+    assert_eq!(service.unlink(owner_id, paper.id, spec.id, Some("cites")).await.unwrap(), 1);
+    assert!(matches!(service.unlink(owner_id, paper.id, spec.id, Some("cites")).await, Err(GraphError::LinkNotFound { .. })));
+    assert_eq!(service.unlink(owner_id, build.id, spec.id, None).await.unwrap(), 1);
+    let remaining = service.backlinks(owner_id, spec.id).await.unwrap();
+    assert_eq!(remaining.iter().map(|l| (l.from_id, l.relation.as_str())).collect::<Vec<_>>(), vec![(paper.id, "link")]);
+}
+
+/// Traverses a tree of three documents, a cycle of links hanging off it, an isolated document
+/// and a document linked to itself.
+async fn check_traversals(documents: Arc<dyn DocumentRepository>, owner_id: Uuid) {
+    let service = GraphService::new(documents.clone());
+    let root = documents.create(owner_id, "Root".into(), None, None).await.unwrap();
+    let left = documents.create(owner_id, "Left".into(), None, Some(root.id)).await.unwrap();
+    let right = documents.create(owner_id, "Right".into(), None, Some(root.id)).await.unwrap();
+    let x = documents.create(owner_id, "X".into(), None, None).await.unwrap();
+    let y = documents.create(owner_id, "Y".into(), None, None).await.unwrap();
+    let lone = documents.create(owner_id, "Lone".into(), None, None).await.unwrap();
+    let echo = documents.create(owner_id, "Echo".into(), None, None).await.unwrap();
+    service.link(owner_id, left.id, x.id, Some("cites".into()), None).await.unwrap();
+    service.link(owner_id, x.id, y.id, None, None).await.unwrap();
+    service.link(owner_id, y.id, left.id, Some("depends_on".into()), None).await.unwrap();
+    service.link(owner_id, echo.id, echo.id, None, None).await.unwrap();
+
+    // 1. Neighbourhoods grow by one edge per level, in either direction.
+    let near = service.neighbourhood(owner_id, root.id, 1, Traversal::Both).await.expect("Failed to walk");
+    let mut ids: Vec<(Uuid, u32)> = near.nodes.iter().map(|n| (n.id, n.depth)).collect();
+    ids.sort();
+    let mut expected = vec![(root.id, 0), (left.id, 1), (right.id, 1)];
+    expected.sort();
+    assert_eq!(ids, expected);
+    assert!(near.edges.iter().all(|e| e.kind == EdgeKind::Child && e.from_id == root.id));
+    let wider = service.neighbourhood(owner_id, root.id, 2, Traversal::Both).await.unwrap();
+    assert_eq!(wider.nodes.len(), 5);
+    assert!(wider.nodes.iter().any(|n| n.id == y.id && n.depth == 2));
+    assert!(wider.edges.iter().any(|e| e.from_id == y.id && e.relation.as_deref() == Some("depends_on")));
+    let links_only = service.neighbourhood(owner_id, root.id, 3, Traversal::Links).await.unwrap();
+    assert_eq!(links_only.nodes.len(), 1);
+
+    // 2. Shortest paths follow edges backwards too, and stop at the depth limit.
+    let path = service.shortest_path(owner_id, root.id, y.id, Traversal::Both, 6).await.unwrap();
+    assert_eq!(path, Some(vec![root.id, left.id, y.id]));
+    assert_eq!(service.shortest_path(owner_id, root.id, y.id, Traversal::Links, 6).await.unwrap(), None);
+    assert_eq!(service.shortest_path(owner_id, right.id, x.id, Traversal::Both, 2).await.unwrap(), None);
+    assert_eq!(service.shortest_path(owner_id, right.id, x.id, Traversal::Both, 3).await.unwrap().map(|p| p.len()), Some(4));
+    assert_eq!(service.shortest_path(owner_id, lone.id, lone.id, Traversal::Both, 1).await.unwrap(), Some(vec![lone.id]));
+
+    // 3. Components, largest first.
+    let components = service.components(owner_id, Traversal::Both).await.unwrap();
+    let mut tree = vec![root.id, left.id, right.id, x.id, y.id];
+    tree.sort();
+    assert_eq!(components.len(), 3);
+    assert_eq!(components[0], tree);
+    assert_eq!(service.components(owner_id, Traversal::Hierarchy).await.unwrap().len(), 5);
+
+    // 4. Cycles follow edge direction and start from their smallest id.
+    let mut ring = vec![left.id, x.id, y.id];
+    let smallest = ring.iter().enumerate().min_by_key(|(_, id)| **id).unwrap().0;
+    ring.rotate_left(smallest);
+    assert_eq!(service.cycles(owner_id, Traversal::Both, 6, 10).await.unwrap(), vec![vec![echo.id], ring]);
+    assert_eq!(service.cycles(owner_id, Traversal::Both, 6, 1).await.unwrap(), vec![vec![echo.id]]);
+    assert_eq!(service.cycles(owner_id, Traversal::Links, 2, 10).await.unwrap(), vec![vec![echo.id]]);
+    assert!(service.cycles(owner_id, Traversal::Hierarchy, 6, 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_sqlite_graph_traversals() {
+    check_traversals(sqlite_repo().await, Uuid::new_v4()).await;
+}
+
+#[tokio::test]
+async fn test_traversals_are_bounded_and_owner_checked() {
+    let documents = sqlite_repo().await;
+    let service = GraphService::new(documents.clone());
+    let (owner_id, stranger) = (Uuid::new_v4(), Uuid::new_v4());
+    let mine = documents.create(owner_id, "Mine".into(), None, None).await.unwrap();
+    let theirs = documents.create(stranger, "Theirs".into(), None, Some(mine.id)).await.unwrap();
+
+    for depth in [0, 7] {
+        let err = service.neighbourhood(owner_id, mine.id, depth, Traversal::Both).await.unwrap_err();
+        assert!(matches!(err, GraphError::InvalidDepth(_)) && err.is_validation());
+    }
+    assert!(matches!(service.neighbourhood(stranger, mine.id, 2, Traversal::Both).await, Err(GraphError::Forbidden(_))));
+    assert!(matches!(service.shortest_path(owner_id, mine.id, theirs.id, Traversal::Both, 2).await, Err(GraphError::Forbidden(_))));
+    // A child filed under another user's document is not an edge of either graph.
+    let around = service.neighbourhood(owner_id, mine.id, 2, Traversal::Both).await.unwrap();
+    assert_eq!((around.nodes.len(), around.edges.len()), (1, 0));
+}
+
+#[tokio::test]
+async fn test_sqlite_typed_links_and_backlinks() {
+    check_typed_links(sqlite_repo().await, Uuid::new_v4()).await;
+}
+
+#[tokio::test]
+async fn test_links_are_validated_and_owner_checked() {
+    let documents = sqlite_repo().await;
+    let service = GraphService::new(documents.clone());
+    let (owner_id, stranger) = (Uuid::new_v4(), Uuid::new_v4());
+    let mine = documents.create(owner_id, "Mine".into(), None, None).await.unwrap();
+    let other = documents.create(owner_id, "Other".into(), None, None).await.unwrap();
+    let theirs = documents.create(stranger, "Theirs".into(), None, None).await.unwrap();
+
+    for relation in ["", "Depends On", "cites;--", &"x".repeat(65)] {
+        let err = service.link(owner_id, mine.id, other.id, Some(relation.to_string()), None).await.unwrap_err();
+        assert!(matches!(err, GraphError::InvalidRelation(_)), "Expected '{}' to be refused, got {:?}", relation, err);
+        assert!(err.is_validation());
+    }
+    let err = service.link(owner_id, mine.id, other.id, None, Some(json!(["page", 3]))).await.unwrap_err();
+    assert!(matches!(err, GraphError::InvalidMetadata));
+
+    assert!(matches!(service.link(owner_id, mine.id, theirs.id, None, None).await, Err(GraphError::Forbidden(_))));
+    assert!(matches!(service.link(stranger, mine.id, theirs.id, None, None).await, Err(GraphError::Forbidden(_))));
+    assert!(matches!(service.link(owner_id, mine.id, Uuid::new_v4(), None, None).await, Err(GraphError::NotFound(_))));
+    assert!(matches!(service.backlinks(stranger, mine.id).await, Err(GraphError::Forbidden(_))));
+
+    // Links made across owners before these checks existed stay out of backlinks.
+    documents.add_link(theirs.id, mine.id).await.unwrap();
+    assert!(service.backlinks(owner_id, mine.id).await.unwrap().is_empty());
+    assert!(matches!(service.unlink(owner_id, theirs.id, mine.id, None).await, Err(GraphError::Forbidden(_))));
+}
+
+#[tokio::test]
+async fn test_postgres_typed_links_and_traversals() {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for integration tests");
+    let pool = PgPoolOptions::new().max_connections(1).connect(&db_url).await.expect("Failed to connect to test database");
+    let owner_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)")
+        .bind(owner_id)
+        .bind(format!("user_{}", owner_id))
+        .bind("hash")
+        .execute(&pool).await.unwrap();
+
+    let documents: Arc<dyn DocumentRepository> = Arc::new(PostgresDocumentRepository::new(pool.clone()));
+    check_typed_links(documents.clone(), owner_id).await;
+    sqlx::query("DELETE FROM documents WHERE owner_id = $1").bind(owner_id).execute(&pool).await.unwrap();
+    check_traversals(documents, owner_id).await;
+
+    sqlx::query("DELETE FROM documents WHERE owner_id = $1").bind(owner_id).execute(&pool).await.ok();
+    sqlx::query("DELETE FROM users WHERE id = $1").bind(owner_id).execute(&pool).await.ok();
+}
@@ -0,0 +1,250 @@
+use async_trait::async_trait;
+use cadmus_kernel::domain::archetypes::SovereignBehavior;
+use cadmus_kernel::domain::archetypes::modules::ModuleRegistry;
+use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::infrastructure::postgres::{PostgresArchetypeRepository, PostgresDocumentRepository};
+use cadmus_kernel::infrastructure::sqlite::{SqliteArchetypeRepository, SqliteDocumentRepository};
+use cadmus_kernel::modules::content::markdown::markdown_to_state;
+use cadmus_kernel::modules::content::socket::ContentRegistry;
+use cadmus_kernel::modules::content::storage::{ContentStorage, DbPool};
+use cadmus_kernel::modules::hierarchy::domain::HierarchyError;
+use cadmus_kernel::modules::hierarchy::service::HierarchyService;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+async fn sqlite_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.unwrap();
+    pool
+}
+
+fn sqlite_service(pool: &SqlitePool, modules: ModuleRegistry) -> (Arc<SqliteDocumentRepository>, HierarchyService) {
+    let documents = Arc::new(SqliteDocumentRepository::new(pool.clone()));
+    let archetypes = Arc::new(SqliteArchetypeRepository::new(pool.clone()));
+    (documents.clone(), HierarchyService::new(documents, archetypes, Arc::new(modules)))
+}
+
+/// A module that refuses every document.
+struct Refusing;
+
+#[async_trait]
+impl SovereignBehavior for Refusing {
+    async fn on_child_change(&self, _parent_id: &Uuid, _child_id: &Uuid, _key: &str, _value: &Value) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn validate_integrity(&self, _document_id: &Uuid) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Moves a document around a three-level tree and checks that cycles are refused.
+async fn check_moves(documents: &dyn DocumentRepository, service: &HierarchyService, owner_id: Uuid) {
+    let root = documents.create(owner_id, "Root".into(), Some("container".into()), None).await.unwrap();
+    let child = documents.create(owner_id, "Child".into(), Some("container".into()), Some(root.id)).await.unwrap();
+    let grandchild = documents.create(owner_id, "Grandchild".into(), Some("note".into()), Some(child.id)).await.unwrap();
+
+    let moved = service.move_document(owner_id, grandchild.id, Some(root.id)).await.expect("Failed to move");
+    assert_eq!(moved.parent_id, Some(root.id));
+    assert_eq!(documents.find_by_id(grandchild.id).await.unwrap().unwrap().parent_id, Some(root.id));
+
+    service.move_document(owner_id, child.id, None).await.expect("Failed to move to the root");
+    assert_eq!(documents.find_by_id(child.id).await.unwrap().unwrap().parent_id, None);
+    service.move_document(owner_id, child.id, Some(root.id)).await.unwrap();
+
+    for parent in [root.id, child.id] {
+        let err = service.move_document(owner_id, root.id, Some(parent)).await.unwrap_err();
+        assert!(matches!(err, HierarchyError::Cycle { .. }), "Expected a cycle, got {:?}", err);
+    }
+    // The repository refuses cycles on its own as well.
+    assert!(!documents.set_parent(root.id, Some(child.id)).await.unwrap());
+    assert_eq!(documents.find_by_id(root.id).await.unwrap().unwrap().parent_id, None);
+}
+
+/// Duplicates a two-level tree with content, a collection row, an internal link and a link
+/// leaving it.
+async fn check_duplicate(documents: &dyn DocumentRepository, service: &HierarchyService, storage: &ContentStorage, owner_id: Uuid) {
+    let folder = documents.create(owner_id, "Folder".into(), None, None).await.unwrap();
+    let root = documents.create(owner_id, "Template".into(), Some("project".into()), Some(folder.id)).await.unwrap();
+    let child = documents.create(owner_id, "Agenda".into(), Some("note".into()), Some(root.id)).await.unwrap();
+    let outside = documents.create(owner_id, "Outside".into(), None, None).await.unwrap();
+    documents.update_property(child.id, "status", json!("draft")).await.unwrap();
+    documents.add_typed_link(child.id, root.id, "part_of", json!({"weight": 2})).await.unwrap();
+    documents.add_link(child.id, outside.id).await.unwrap();
+    let row = documents.add_collection_row(root.id).await.unwrap();
+    documents.update_collection_cell(root.id, Uuid::parse_str(row["id"].as_str().unwrap()).unwrap(), "item", json!("Budget")).await.unwrap();
+    storage.save_update(&child.id.to_string(), markdown_to_state("Minutes")).await.unwrap();
+
+    let before = documents.find_all(owner_id).await.unwrap().len();
+    let copy = service.duplicate_document(owner_id, root.id).await.expect("Failed to duplicate");
+    assert_ne!(copy.id, root.id);
+    assert_eq!((copy.title.as_str(), copy.parent_id), ("Template (copy)", Some(folder.id)));
+
+    let nodes = documents.find_all(owner_id).await.unwrap();
+    assert_eq!(nodes.len(), before + 2);
+    let copy_child = nodes.iter().find(|n| n.parent_id == Some(copy.id)).expect("The child was not copied");
+    assert_eq!((copy_child.title.as_str(), &copy_child.properties["status"]), ("Agenda", &json!("draft")));
+    assert_eq!(storage.load_updates(&copy_child.id.to_string()).await.unwrap(), storage.load_updates(&child.id.to_string()).await.unwrap());
+
+    let rows = documents.get_collection_rows(copy.id).await.unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["item"], "Budget");
+    assert_ne!(rows[0]["id"], row["id"]);
+
+    // The internal link follows the copies; the one leaving the tree stays with the original.
+    let backlinks = documents.find_backlinks(copy.id).await.unwrap();
+    assert_eq!(backlinks.len(), 1);
+    assert_eq!((backlinks[0].from_id, backlinks[0].relation.as_str()), (copy_child.id, "part_of"));
+    assert_eq!(backlinks[0].metadata, json!({"weight": 2}));
+    assert_eq!(documents.find_backlinks(outside.id).await.unwrap().len(), 1);
+
+    let err = service.duplicate_document(Uuid::new_v4(), root.id).await.unwrap_err();
+    assert!(matches!(err, HierarchyError::Forbidden(_)));
+}
+
+#[tokio::test]
+async fn test_sqlite_move_and_cycles() {
+    let pool = sqlite_pool().await;
+    let (documents, service) = sqlite_service(&pool, ModuleRegistry::new());
+    check_moves(documents.as_ref(), &service, Uuid::new_v4()).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_duplicate_subtree() {
+    let pool = sqlite_pool().await;
+    let (documents, service) = sqlite_service(&pool, ModuleRegistry::new());
+    check_duplicate(documents.as_ref(), &service, &ContentStorage::new(DbPool::Sqlite(pool.clone())), Uuid::new_v4()).await;
+}
+
+#[tokio::test]
+async fn test_duplicate_copies_content_still_buffered() {
+    let pool = sqlite_pool().await;
+    let (documents, service) = sqlite_service(&pool, ModuleRegistry::new());
+    let storage = Arc::new(ContentStorage::new(DbPool::Sqlite(pool.clone())));
+    let registry = ContentRegistry::new(Some(storage.clone())).with_write_behind(Duration::from_secs(3600), usize::MAX);
+    let owner_id = Uuid::new_v4();
+    let root = documents.create(owner_id, "Template".into(), None, None).await.unwrap();
+    let child = documents.create(owner_id, "Agenda".into(), None, Some(root.id)).await.unwrap();
+    registry.process_update(&child.id.to_string(), markdown_to_state("Minutes")).await.unwrap();
+    assert!(storage.load_updates(&child.id.to_string()).await.unwrap().is_empty());
+
+    // As the duplicate route does: write what the subtree has buffered, then copy from storage.
+    let subtree = service.subtree(owner_id, root.id).await.unwrap();
+    assert_eq!(subtree, vec![root.id, child.id]);
+    for id in subtree {
+        registry.flush_doc(&id.to_string()).await.unwrap();
+    }
+    let copy = service.duplicate_document(owner_id, root.id).await.unwrap();
+    let copy_child = documents.find_all(owner_id).await.unwrap().into_iter().find(|n| n.parent_id == Some(copy.id)).unwrap();
+    assert_eq!(storage.load_updates(&copy_child.id.to_string()).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_move_respects_allowed_children() {
+    let pool = sqlite_pool().await;
+    let (documents, service) = sqlite_service(&pool, ModuleRegistry::new());
+    let owner_id = Uuid::new_v4();
+    let project = documents.create(owner_id, "Project".into(), Some("project".into()), None).await.unwrap();
+    let task = documents.create(owner_id, "Task".into(), Some("task".into()), None).await.unwrap();
+    let profile = documents.create(owner_id, "Profile".into(), Some("profile".into()), None).await.unwrap();
+    let sheet = documents.create(owner_id, "Sheet".into(), Some("folha".into()), None).await.unwrap();
+
+    service.move_document(owner_id, task.id, Some(project.id)).await.expect("Tasks belong in projects");
+    let err = service.move_document(owner_id, profile.id, Some(project.id)).await.unwrap_err();
+    assert!(matches!(err, HierarchyError::ChildNotAllowed { .. }), "Expected a containment error, got {:?}", err);
+    assert!(err.is_validation());
+    assert_eq!(documents.find_by_id(profile.id).await.unwrap().unwrap().parent_id, None);
+
+    // Classes with an empty list accept anything.
+    service.move_document(owner_id, profile.id, Some(sheet.id)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_move_consults_the_parent_module() {
+    let pool = sqlite_pool().await;
+    let mut modules = ModuleRegistry::new();
+    modules.register("container", Arc::new(Refusing));
+    let (documents, service) = sqlite_service(&pool, modules);
+    let owner_id = Uuid::new_v4();
+    let vault = documents.create(owner_id, "Vault".into(), Some("container".into()), None).await.unwrap();
+    let note = documents.create(owner_id, "Note".into(), Some("note".into()), None).await.unwrap();
+
+    let err = service.move_document(owner_id, note.id, Some(vault.id)).await.unwrap_err();
+    assert!(matches!(err, HierarchyError::RejectedByModule { .. }), "Expected a module refusal, got {:?}", err);
+    assert_eq!(documents.find_by_id(note.id).await.unwrap().unwrap().parent_id, None);
+}
+
+#[tokio::test]
+async fn test_move_checks_ownership() {
+    let pool = sqlite_pool().await;
+    let (documents, service) = sqlite_service(&pool, ModuleRegistry::new());
+    let (owner_id, stranger) = (Uuid::new_v4(), Uuid::new_v4());
+    let mine = documents.create(owner_id, "Mine".into(), None, None).await.unwrap();
+    let theirs = documents.create(stranger, "Theirs".into(), None, None).await.unwrap();
+
+    assert!(matches!(service.move_document(stranger, mine.id, None).await, Err(HierarchyError::Forbidden(_))));
+    assert!(matches!(service.move_document(owner_id, mine.id, Some(theirs.id)).await, Err(HierarchyError::Forbidden(_))));
+    assert!(matches!(service.move_document(owner_id, Uuid::new_v4(), None).await, Err(HierarchyError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_postgres_move_and_containment() {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for integration tests");
+    let pool = PgPoolOptions::new().max_connections(1).connect(&db_url).await.expect("Failed to connect to test database");
+    let owner_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)")
+        .bind(owner_id)
+        .bind(format!("user_{}", owner_id))
+        .bind("hash")
+        .execute(&pool).await.unwrap();
+
+    let documents = Arc::new(PostgresDocumentRepository::new(pool.clone()));
+    let archetypes = Arc::new(PostgresArchetypeRepository::new(pool.clone()));
+    let service = HierarchyService::new(documents.clone(), archetypes, Arc::new(ModuleRegistry::new()));
+    check_moves(documents.as_ref(), &service, owner_id).await;
+    check_duplicate(documents.as_ref(), &service, &ContentStorage::new(DbPool::Postgres(pool.clone())), owner_id).await;
+
+    // Notes only hold notes, canvases and media.
+    let note = documents.create(owner_id, "Note".into(), Some("note".into()), None).await.unwrap();
+    let project = documents.create(owner_id, "Project".into(), Some("project".into()), None).await.unwrap();
+    let err = service.move_document(owner_id, project.id, Some(note.id)).await.unwrap_err();
+    assert!(matches!(err, HierarchyError::ChildNotAllowed { .. }), "Expected a containment error, got {:?}", err);
+    let err = service.create_document(owner_id, "Project".into(), Some("project".into()), Some(note.id)).await.unwrap_err();
+    assert!(matches!(err, HierarchyError::ChildNotAllowed { .. }), "Expected a containment error, got {:?}", err);
+    service.create_document(owner_id, "Draft".into(), None, Some(note.id)).await.expect("Notes hold notes");
+
+    sqlx::query("DELETE FROM documents WHERE owner_id = $1").bind(owner_id).execute(&pool).await.ok();
+    sqlx::query("DELETE FROM users WHERE id = $1").bind(owner_id).execute(&pool).await.ok();
+}
+
+#[tokio::test]
+async fn test_create_respects_allowed_children() {
+    let pool = sqlite_pool().await;
+    let (documents, service) = sqlite_service(&pool, ModuleRegistry::new());
+    let owner_id = Uuid::new_v4();
+    let task = service.create_document(owner_id, "Task".into(), Some("task".into()), None).await.unwrap();
+
+    let checklist = service.create_document(owner_id, "Steps".into(), Some("checklist".into()), Some(task.id)).await.unwrap();
+    assert_eq!(checklist.parent_id, Some(task.id));
+    // Documents without a class are notes, which tasks accept.
+    service.create_document(owner_id, "Remark".into(), None, Some(task.id)).await.unwrap();
+
+    let err = service.create_document(owner_id, "Subproject".into(), Some("project".into()), Some(task.id)).await.unwrap_err();
+    match &err {
+        HierarchyError::ChildNotAllowed { parent_class, child_class } => assert_eq!((parent_class.as_str(), child_class.as_str()), ("task", "project")),
+        other => panic!("Expected a containment error, got {:?}", other),
+    }
+    assert!(err.is_validation());
+    assert_eq!(documents.find_all(owner_id).await.unwrap().len(), 3);
+
+    let stranger = Uuid::new_v4();
+    let err = service.create_document(stranger, "Intruder".into(), None, Some(task.id)).await.unwrap_err();
+    assert!(matches!(err, HierarchyError::Forbidden(_)));
+}
@@ -0,0 +1,159 @@
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::history::{group_sessions, BeforeCompaction, replay, revert_update, HistoryEntry, DEFAULT_SESSION_GAP};
+use cadmus_kernel::modules::content::socket::ContentRegistry;
+use cadmus_kernel::modules::content::storage::ContentStorage;
+use chrono::{Duration as ChronoDuration, TimeZone, Utc};
+use sqlx::sqlite::SqlitePoolOptions;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+use yrs::types::ToJson;
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, GetString, Map, Text, Transact, Update, XmlElementPrelim, XmlFragment, XmlTextPrelim};
+
+/// A client document that records every update it emits, like the editor does over the socket.
+fn recording_doc() -> (Doc, Arc<Mutex<Vec<Vec<u8>>>>) {
+    let doc = Doc::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let sink = log.clone();
+    let sub = doc.observe_update_v1(move |_, e| sink.lock().unwrap().push(e.update.clone())).unwrap();
+    std::mem::forget(sub);
+    (doc, log)
+}
+
+#[test]
+fn test_revert_restores_past_content_as_forward_update() {
+    let (client, log) = recording_doc();
+    let text = client.get_or_insert_text("title");
+    let props = client.get_or_insert_map("properties");
+    let body = client.get_or_insert_xml_fragment("default");
+
+    text.push(&mut client.transact_mut(), "Quarterly plan");
+    props.insert(&mut client.transact_mut(), "status", "draft");
+    {
+        let mut txn = client.transact_mut();
+        let p = body.push_back(&mut txn, XmlElementPrelim::empty("paragraph"));
+        p.push_back(&mut txn, XmlTextPrelim::new("First draft"));
+    }
+    let past_len = log.lock().unwrap().len();
+    let expected_title = text.get_string(&client.transact());
+    let expected_body = body.get_string(&client.transact());
+    let expected_props = props.to_json(&client.transact());
+
+    // Later edits: rewrite the title, drop and add properties, replace the body.
+    text.remove_range(&mut client.transact_mut(), 0, 9);
+    text.push(&mut client.transact_mut(), " (final)");
+    props.remove(&mut client.transact_mut(), "status");
+    props.insert(&mut client.transact_mut(), "owner", "ops");
+    {
+        let mut txn = client.transact_mut();
+        body.remove_range(&mut txn, 0, 1);
+        let p = body.push_back(&mut txn, XmlElementPrelim::empty("heading"));
+        p.push_back(&mut txn, XmlTextPrelim::new("Rewritten"));
+    }
+    client.get_or_insert_text("notes").push(&mut client.transact_mut(), "scratch");
+
+    let updates = log.lock().unwrap().clone();
+    let past = replay(updates[..past_len].to_vec());
+    let current = replay(updates.clone());
+    let revert = revert_update(&past, &current).unwrap().expect("Expected a revert update");
+
+    client.transact_mut().apply_update(Update::decode_v1(&revert).unwrap());
+    assert_eq!(text.get_string(&client.transact()), expected_title);
+    assert_eq!(body.get_string(&client.transact()), expected_body);
+    assert_eq!(props.to_json(&client.transact()), expected_props);
+    assert_eq!(client.get_or_insert_text("notes").get_string(&client.transact()), "");
+
+    // The revert is a plain forward update: any replica holding the later edits converges too.
+    current.transact_mut().apply_update(Update::decode_v1(&revert).unwrap());
+    assert_eq!(current.get_or_insert_text("title").get_string(&current.transact()), expected_title);
+}
+
+#[test]
+fn test_sessions_split_by_gap_and_author() {
+    let alice = Some(Uuid::new_v4());
+    let bob = Some(Uuid::new_v4());
+    let t0 = Utc.with_ymd_and_hms(2026, 10, 18, 9, 0, 0).unwrap();
+    let entry = |mins: i64, author_id| HistoryEntry { created_at: t0 + ChronoDuration::minutes(mins), author_id, size: 10 };
+
+    let entries = vec![entry(0, alice), entry(2, alice), entry(4, alice), entry(5, bob), entry(30, bob)];
+    let versions = group_sessions(Some(t0 - ChronoDuration::days(1)), &entries, Duration::from_secs(600));
+
+    assert_eq!(versions.len(), 4);
+    assert!(versions[0].compacted);
+    assert_eq!((versions[1].author_id, versions[1].update_count, versions[1].bytes), (alice, 3, 30));
+    assert_eq!(versions[1].started_at, t0);
+    assert_eq!(versions[1].at, t0 + ChronoDuration::minutes(4));
+    assert_eq!((versions[2].author_id, versions[2].update_count), (bob, 1));
+    assert_eq!(versions[3].at, t0 + ChronoDuration::minutes(30));
+}
+
+#[tokio::test]
+async fn test_registry_restore_keeps_history() {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.expect("Failed to initialize schema");
+    let storage = Arc::new(ContentStorage::new_sqlite(pool.clone()));
+    let registry = ContentRegistry::new(Some(storage.clone()));
+    let doc_id = Uuid::new_v4().to_string();
+    let author = Some(Uuid::new_v4());
+
+    // 1. A first session, written an hour ago.
+    let (client, log) = recording_doc();
+    let text = client.get_or_insert_text("content");
+    text.push(&mut client.transact_mut(), "Sovereign");
+    let updates: Vec<Vec<u8>> = log.lock().unwrap().drain(..).collect();
+    for u in updates {
+        registry.process_update_as(&doc_id, u, author).await.unwrap();
+    }
+    registry.flush().await.unwrap();
+    let an_hour_ago = Utc::now() - ChronoDuration::hours(1);
+    sqlx::query("UPDATE document_updates SET created_at = ?").bind(an_hour_ago.naive_utc()).execute(&pool).await.unwrap();
+
+    // 2. A later session rewrites the text.
+    text.remove_range(&mut client.transact_mut(), 0, 9);
+    text.push(&mut client.transact_mut(), "Rewritten");
+    let updates: Vec<Vec<u8>> = log.lock().unwrap().drain(..).collect();
+    for u in updates {
+        registry.process_update_as(&doc_id, u, author).await.unwrap();
+    }
+    registry.flush().await.unwrap();
+
+    let versions = storage.load_versions(&doc_id, DEFAULT_SESSION_GAP).await.unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].author_id, author);
+    let past = replay([storage.load_state_at(&doc_id, versions[0].at).await.unwrap()]);
+    assert_eq!(past.get_or_insert_text("content").get_string(&past.transact()), "Sovereign");
+
+    // 3. Restoring appends a forward update and converges the live room, waiting for an update
+    //    that holds the document instead of failing.
+    let logged = storage.load_history(&doc_id).await.unwrap().len();
+    let room = registry.get_room(&doc_id).await;
+    let (held, holding) = std::sync::mpsc::channel();
+    let writer = {
+        let doc = room.doc.clone();
+        std::thread::spawn(move || {
+            let _txn = doc.transact_mut();
+            held.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+        })
+    };
+    holding.recv().unwrap();
+    assert!(registry.restore(&doc_id, versions[0].at, author).await.unwrap());
+    writer.join().unwrap();
+    registry.flush().await.unwrap();
+    assert_eq!(room.doc.get_or_insert_text("content").get_string(&room.doc.transact()), "Sovereign");
+    assert_eq!(storage.load_history(&doc_id).await.unwrap().len(), logged + 1);
+
+    // 4. Restoring to the present is a no-op.
+    assert!(!registry.restore(&doc_id, Utc::now() + ChronoDuration::minutes(1), author).await.unwrap());
+
+    // 5. Compaction folds the log into the snapshot: older instants are refused, not replayed partially.
+    storage.compact(&doc_id).await.unwrap();
+    let err = storage.load_state_at(&doc_id, an_hour_ago).await.unwrap_err();
+    assert!(err.is::<BeforeCompaction>(), "Expected a refusal, got {:?}", err);
+    assert!(registry.restore(&doc_id, an_hour_ago, author).await.unwrap_err().is::<BeforeCompaction>());
+}
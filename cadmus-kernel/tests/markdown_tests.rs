@@ -0,0 +1,165 @@
+use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::extract::DocumentContent;
+use cadmus_kernel::modules::content::history::replay;
+use cadmus_kernel::modules::content::markdown::markdown_to_state;
+use cadmus_kernel::modules::content::storage::{ContentStorage, DbPool};
+use cadmus_kernel::modules::portability::service::PortabilityService;
+use serde_json::json;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use yrs::Transact;
+
+fn render(markdown: &str) -> DocumentContent {
+    let doc = replay(vec![markdown_to_state(markdown)]);
+    DocumentContent::extract(&doc.transact())
+}
+
+/// A fresh folder under the system temp dir, removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("cadmus_vault_{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn write(&self, relative: &str, contents: &str) {
+        let path = self.0.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+async fn sqlite_service() -> (PortabilityService, SqliteDocumentRepository, ContentStorage) {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    let repo = SqliteDocumentRepository::new(pool.clone());
+    repo.initialize().await.unwrap();
+    let pool = DbPool::Sqlite(pool);
+    (PortabilityService::new(pool.clone()), repo, ContentStorage::new(pool))
+}
+
+async fn markdown_of(storage: &ContentStorage, doc_id: Uuid) -> String {
+    let doc = replay(storage.load_updates(&doc_id.to_string()).await.unwrap());
+    DocumentContent::extract(&doc.transact()).markdown
+}
+
+#[test]
+fn test_markdown_survives_the_editor_tree() {
+    let markdown = "\
+# Plan
+
+Ship the **kernel** via [the site](https://cadmus.dev) with `code`.
+
+- Write docs
+- Cut release
+  1. Tag
+  2. Publish
+
+> Quoted
+
+- [x] Done
+- [ ] Open
+
+```rust
+fn main() {}
+```
+
+```mermaid
+graph TD
+```
+
+$$
+e = mc^2
+$$
+
+![Logo](logo.png)
+
+| Name | Value |
+| --- | --- |
+| a | 1 |
+
+---";
+    assert_eq!(render(markdown).markdown, markdown);
+}
+
+#[test]
+fn test_markdown_without_content_is_empty() {
+    let content = render("---\ntags: [a]\n---\n");
+    assert_eq!(content, DocumentContent::default());
+}
+
+#[tokio::test]
+async fn test_vault_imports_hierarchy_properties_and_links() {
+    let vault = TempDir::new();
+    vault.write("Projects.md", "---\nclass: project\nstatus: active\n---\nAll projects.\n");
+    vault.write("Projects/Launch.md", "---\ntags: \"#release, q3\"\n---\nSee [[ideas|the ideas]] and [[Projects/Missing]].\n");
+    vault.write("Archive/Ideas.md", "Back to [[Launch#Goals]].\n");
+    vault.write(".obsidian/workspace.md", "ignored");
+    vault.write("notes.txt", "ignored");
+
+    let (service, repo, storage) = sqlite_service().await;
+    let owner_id = Uuid::new_v4();
+    let report = service.import_markdown(owner_id, &vault.0).await.expect("Failed to import vault");
+    assert_eq!((report.documents, report.links), (4, 2));
+
+    let nodes = repo.find_all(owner_id).await.unwrap();
+    let find = |title: &str| nodes.iter().find(|n| n.title == title).unwrap_or_else(|| panic!("missing {}", title)).id;
+    let (projects, launch, archive, ideas) = (find("Projects"), find("Launch"), find("Archive"), find("Ideas"));
+
+    let projects_doc = repo.find_by_id(projects).await.unwrap().unwrap();
+    assert_eq!(projects_doc.class_id.as_deref(), Some("project"));
+    assert_eq!(projects_doc.properties, json!({ "status": "active" }));
+    let launch_doc = repo.find_by_id(launch).await.unwrap().unwrap();
+    assert_eq!(launch_doc.parent_id, Some(projects));
+    assert_eq!(launch_doc.properties["tags"], json!(["release", "q3"]));
+    assert_eq!(repo.find_by_id(ideas).await.unwrap().unwrap().parent_id, Some(archive));
+
+    let links = repo.find_links(owner_id).await.unwrap();
+    assert!(links.contains(&(launch, ideas)));
+    assert!(links.contains(&(ideas, launch)));
+    assert_eq!(markdown_of(&storage, projects).await, "All projects.");
+    assert_eq!(markdown_of(&storage, launch).await, "See [[ideas|the ideas]] and [[Projects/Missing]].");
+}
+
+#[tokio::test]
+async fn test_vault_export_round_trips() {
+    let (service, repo, storage) = sqlite_service().await;
+    let owner_id = Uuid::new_v4();
+    let folder = repo.create(owner_id, "Area: Work".to_string(), Some("note".to_string()), None).await.unwrap();
+    let first = repo.create(owner_id, "Meeting".to_string(), Some("note".to_string()), Some(folder.id)).await.unwrap();
+    let second = repo.create(owner_id, "Meeting".to_string(), Some("note".to_string()), Some(folder.id)).await.unwrap();
+    repo.update_property(first.id, "tags", json!(["work"])).await.unwrap();
+    repo.add_link(first.id, second.id).await.unwrap();
+    storage.save_update(&second.id.to_string(), markdown_to_state("Agenda for **today**")).await.unwrap();
+
+    let vault = TempDir::new();
+    let root = vault.0.join("export");
+    assert_eq!(service.export_markdown(owner_id, &root).await.expect("Failed to export"), 3);
+    assert!(service.export_markdown(owner_id, &root).await.is_err(), "Export must not write into a used folder");
+
+    // Folders without text or properties get no note of their own, and namesakes are numbered.
+    assert!(!root.join("Area- Work.md").exists());
+    let notes = ["Meeting", "Meeting 2"].map(|name| read(&root, &format!("Area- Work/{}.md", name)));
+    let tagged = notes.iter().position(|n| n.starts_with("---\n")).expect("No note carries the tags");
+    let other_name = ["Meeting", "Meeting 2"][1 - tagged];
+    assert_eq!(notes[tagged], format!("---\nlinks:\n- '[[Area- Work/{}]]'\ntags:\n- work\n---\n", other_name));
+    assert_eq!(notes[1 - tagged], "Agenda for **today**\n");
+
+    let other = Uuid::new_v4();
+    let report = service.import_markdown(other, &root).await.unwrap();
+    assert_eq!((report.documents, report.links), (3, 1));
+}
+
+fn read(root: &Path, relative: &str) -> String {
+    fs::read_to_string(root.join(relative)).unwrap_or_else(|_| panic!("missing {}", relative))
+}
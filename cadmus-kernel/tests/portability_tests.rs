@@ -0,0 +1,125 @@
+use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::infrastructure::postgres::PostgresDocumentRepository;
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::history::replay;
+use cadmus_kernel::modules::content::storage::{ContentStorage, DbPool};
+use cadmus_kernel::modules::portability::bundle::WorkspaceBundle;
+use cadmus_kernel::modules::portability::service::PortabilityService;
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::env;
+use uuid::Uuid;
+use yrs::{GetString, ReadTxn, Text, Transact};
+
+async fn sqlite_pool() -> DbPool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.expect("Failed to initialize schema");
+    DbPool::Sqlite(pool)
+}
+
+fn repository(pool: &DbPool) -> Box<dyn DocumentRepository> {
+    match pool {
+        DbPool::Postgres(p) => Box::new(PostgresDocumentRepository::new(p.clone())),
+        DbPool::Sqlite(p) => Box::new(SqliteDocumentRepository::new(p.clone())),
+    }
+}
+
+async fn write_text(storage: &ContentStorage, doc_id: Uuid, text: &str) {
+    let doc = replay(Vec::<Vec<u8>>::new());
+    doc.get_or_insert_text("content").push(&mut doc.transact_mut(), text);
+    let update = doc.transact().encode_state_as_update_v1(&Default::default());
+    storage.save_update(&doc_id.to_string(), update).await.unwrap();
+}
+
+async fn read_text(storage: &ContentStorage, doc_id: Uuid) -> String {
+    let doc = replay(storage.load_updates(&doc_id.to_string()).await.unwrap());
+    doc.get_or_insert_text("content").get_string(&doc.transact())
+}
+
+/// A project with a child task, a plain and a typed link between them, a collection row and some text.
+async fn seed_workspace(pool: &DbPool, owner_id: Uuid) -> (Uuid, Uuid) {
+    let repo = repository(pool);
+    let project = repo.create(owner_id, "Project".to_string(), Some("note".to_string()), None).await.unwrap();
+    let task = repo.create(owner_id, "Task".to_string(), Some("note".to_string()), Some(project.id)).await.unwrap();
+    repo.update_property(task.id, "status", json!("open")).await.unwrap();
+    repo.update_property(task.id, "estimate", json!(0.1)).await.unwrap();
+    repo.add_link(task.id, project.id).await.unwrap();
+    repo.add_typed_link(project.id, task.id, "depends_on", json!({ "blocking": true })).await.unwrap();
+    repo.add_collection_row(project.id).await.unwrap();
+    write_text(&ContentStorage::new(pool.clone()), task.id, "Ship the exporter").await;
+    (project.id, task.id)
+}
+
+/// Exports from `source`, imports into `target` and checks the copy is whole but distinct.
+async fn check_round_trip(source: &DbPool, target: &DbPool, source_owner: Uuid, target_owner: Uuid) {
+    let (project, task) = seed_workspace(source, source_owner).await;
+
+    let bundle = PortabilityService::new(source.clone()).export(source_owner).await.expect("Failed to export");
+    assert_eq!(bundle.manifest.document_count, 2);
+    let bundle = WorkspaceBundle::from_bytes(&bundle.to_bytes().unwrap()).expect("Bundle does not survive a round trip");
+
+    let report = PortabilityService::new(target.clone()).import(target_owner, &bundle).await.expect("Failed to import");
+    assert_eq!((report.documents, report.collection_rows, report.links), (2, 1, 2));
+    let (new_project, new_task) = (report.id_map[&project], report.id_map[&task]);
+    assert_ne!(new_project, project);
+
+    let repo = repository(target);
+    let imported = repo.find_by_id(new_task).await.unwrap().expect("Task was not imported");
+    assert_eq!(imported.parent_id, Some(new_project));
+    assert_eq!(imported.properties["status"], "open");
+    assert_eq!(imported.properties["estimate"], 0.1);
+    assert_eq!(repo.find_owner(new_task).await.unwrap(), Some(target_owner));
+    assert!(repo.find_links(target_owner).await.unwrap().contains(&(new_task, new_project)));
+    let backlinks = repo.find_backlinks(new_task).await.unwrap();
+    assert_eq!(backlinks.iter().map(|l| (l.from_id, l.relation.as_str(), &l.metadata)).collect::<Vec<_>>(), vec![(new_project, "depends_on", &json!({ "blocking": true }))]);
+    assert_eq!(repo.get_collection_rows(new_project).await.unwrap().len(), 1);
+    assert_eq!(read_text(&ContentStorage::new(target.clone()), new_task).await, "Ship the exporter");
+}
+
+#[tokio::test]
+async fn test_sqlite_workspace_round_trip() {
+    let (source, target) = (sqlite_pool().await, sqlite_pool().await);
+    check_round_trip(&source, &target, Uuid::new_v4(), Uuid::new_v4()).await;
+}
+
+#[tokio::test]
+async fn test_postgres_workspace_moves_to_sqlite() {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for integration tests");
+    let pool = PgPoolOptions::new().max_connections(1).connect(&db_url).await.expect("Failed to connect to test database");
+    let owner_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)")
+        .bind(owner_id)
+        .bind(format!("user_{}", owner_id))
+        .bind("hash")
+        .execute(&pool).await.unwrap();
+
+    check_round_trip(&DbPool::Postgres(pool.clone()), &sqlite_pool().await, owner_id, Uuid::new_v4()).await;
+
+    sqlx::query("DELETE FROM users WHERE id = $1").bind(owner_id).execute(&pool).await.ok();
+}
+
+#[tokio::test]
+async fn test_tampered_bundle_is_rejected() {
+    let pool = sqlite_pool().await;
+    let owner_id = Uuid::new_v4();
+    seed_workspace(&pool, owner_id).await;
+    let service = PortabilityService::new(pool);
+
+    let mut bundle = service.export(owner_id).await.unwrap();
+    bundle.documents[0].title = "Renamed".to_string();
+    assert!(service.import(owner_id, &bundle).await.unwrap_err().to_string().contains("checksum"));
+
+    let mut bundle = service.export(owner_id).await.unwrap();
+    bundle.manifest.schema_version += 1;
+    assert!(WorkspaceBundle::from_bytes(&bundle.to_bytes().unwrap()).is_err());
+
+    let mut bundle = service.export(owner_id).await.unwrap();
+    bundle.links.clear();
+    assert!(bundle.verify().is_err());
+}
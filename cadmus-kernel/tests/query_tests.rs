@@ -0,0 +1,235 @@
+use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::infrastructure::postgres::PostgresDocumentRepository;
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::query::domain::{DocumentQuery, FilterOp, PropertyFilter, QueryError, Sort, SortField};
+use cadmus_kernel::modules::query::service::QueryService;
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+async fn sqlite_repo() -> Arc<SqliteDocumentRepository> {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    let repo = SqliteDocumentRepository::new(pool);
+    repo.initialize().await.unwrap();
+    Arc::new(repo)
+}
+
+fn filter(key: &str, op: FilterOp) -> PropertyFilter {
+    PropertyFilter { key: key.into(), op }
+}
+
+/// Titles of every page of `query`, one page at a time.
+async fn pages(service: &QueryService, owner_id: Uuid, mut query: DocumentQuery) -> Vec<Vec<String>> {
+    let mut pages = Vec::new();
+    loop {
+        let page = service.query(owner_id, &query).await.expect("Failed to query");
+        pages.push(page.documents.into_iter().map(|d| d.title).collect());
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => return pages,
+        }
+    }
+}
+
+async fn titles(service: &QueryService, owner_id: Uuid, query: DocumentQuery) -> Vec<String> {
+    pages(service, owner_id, query).await.concat()
+}
+
+/// Filters, sorts and pages through five documents, one of them trashed.
+async fn check_queries(documents: Arc<dyn DocumentRepository>, owner_id: Uuid) {
+    let service = QueryService::new(documents.clone());
+    let props = [
+        ("Alpha", "task", json!({"priority": 3, "status": "open", "tags": ["urgent", "home"], "note": "Buy Milk"})),
+        ("Beta", "task", json!({"priority": 1, "status": "done", "tags": ["work"]})),
+        ("Gamma", "project", json!({"priority": 2, "tags": ["urgent"]})),
+        ("Delta", "note", json!({"priority": "5", "tags": "urgent"})),
+        ("Epsilon", "task", json!({"flag": true})),
+        ("Trashed", "task", json!({"priority": 3, "status": "open"})),
+    ];
+    for (title, class, properties) in props {
+        let doc = documents.create(owner_id, title.into(), Some(class.into()), None).await.unwrap();
+        for (key, value) in properties.as_object().unwrap() {
+            documents.update_property(doc.id, key, value.clone()).await.unwrap();
+        }
+        if title == "Trashed" {
+            documents.trash(doc.id, owner_id).await.unwrap();
+        }
+    }
+
+    // 1. Classes and filters.
+    let by = |classes: &[&str], filters: Vec<PropertyFilter>| DocumentQuery {
+        classes: classes.iter().map(|c| c.to_string()).collect(),
+        filters,
+        ..Default::default()
+    };
+    assert_eq!(titles(&service, owner_id, by(&[], vec![])).await, ["Alpha", "Beta", "Delta", "Epsilon", "Gamma"]);
+    assert_eq!(titles(&service, owner_id, by(&["task"], vec![])).await, ["Alpha", "Beta", "Epsilon"]);
+    assert_eq!(titles(&service, owner_id, by(&["task", "project"], vec![filter("priority", FilterOp::Lt(json!(3)))])).await, ["Beta", "Gamma"]);
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("status", FilterOp::Eq(json!("open")))])).await, ["Alpha"]);
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("flag", FilterOp::Eq(json!(true)))])).await, ["Epsilon"]);
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("priority", FilterOp::Eq(json!(2.0)))])).await, ["Gamma"]);
+    // The string "5" is not a number, so it is neither equal to nor greater than one.
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("priority", FilterOp::Gte(json!(2)))])).await, ["Alpha", "Gamma"]);
+    assert!(titles(&service, owner_id, by(&[], vec![filter("priority", FilterOp::Eq(json!(5)))])).await.is_empty());
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("priority", FilterOp::Gt(json!("4")))])).await, ["Delta"]);
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("note", FilterOp::Contains("milk".into()))])).await, ["Alpha"]);
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("priority", FilterOp::Exists(false))])).await, ["Epsilon"]);
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("priority", FilterOp::Exists(true))])).await.len(), 4);
+    // Delta's tags are a string, not a list.
+    assert_eq!(titles(&service, owner_id, by(&[], vec![filter("tags", FilterOp::HasTag("urgent".into()))])).await, ["Alpha", "Gamma"]);
+    assert_eq!(
+        titles(&service, owner_id, by(&[], vec![filter("tags", FilterOp::HasTag("urgent".into())), filter("priority", FilterOp::Lte(json!(2)))])).await,
+        ["Gamma"]
+    );
+
+    // 2. Pages cover the listing once, in order.
+    let paged = |field: SortField, descending: bool, limit: u32| DocumentQuery {
+        classes: vec!["task".into()],
+        sort: Sort { field, descending },
+        limit: Some(limit),
+        ..Default::default()
+    };
+    let priority = || SortField::Property("priority".into());
+    // Epsilon has no priority: first in ascending order, last in descending order.
+    assert_eq!(pages(&service, owner_id, paged(priority(), false, 1)).await, [vec!["Epsilon"], vec!["Beta"], vec!["Alpha"]]);
+    assert_eq!(pages(&service, owner_id, paged(priority(), true, 1)).await, [vec!["Alpha"], vec!["Beta"], vec!["Epsilon"]]);
+    assert_eq!(pages(&service, owner_id, paged(SortField::Title, true, 2)).await, [vec!["Epsilon", "Beta"], vec!["Alpha"]]);
+    assert_eq!(pages(&service, owner_id, paged(SortField::CreatedAt, false, 2)).await.concat().len(), 3);
+    let all = DocumentQuery { limit: Some(2), ..Default::default() };
+    assert_eq!(pages(&service, owner_id, all).await, [vec!["Alpha", "Beta"], vec!["Delta", "Epsilon"], vec!["Gamma"]]);
+
+    // 3. Another user sees none of it.
+    assert!(service.query(Uuid::new_v4(), &DocumentQuery::default()).await.unwrap().documents.is_empty());
+}
+
+/// Mixed types, non-ASCII text and case, which both backends must order and compare alike.
+async fn check_parity(documents: Arc<dyn DocumentRepository>, owner_id: Uuid) {
+    let service = QueryService::new(documents.clone());
+    let values = [
+        ("missing", None), ("null", Some(json!(null))), ("lower", Some(json!("b"))), ("upper", Some(json!("B"))),
+        ("umlaut", Some(json!("ä"))), ("ten", Some(json!(10))), ("half", Some(json!(2.5))), ("true", Some(json!(true))),
+        ("false", Some(json!(false))), ("list", Some(json!([1]))), ("object", Some(json!({"a": 1}))),
+    ];
+    for (title, value) in values {
+        let doc = documents.create(owner_id, title.into(), Some("mixed".into()), None).await.unwrap();
+        if let Some(value) = value {
+            documents.update_property(doc.id, "rank", value).await.unwrap();
+        }
+    }
+    for title in ["apple", "Banana", "Ähre"] {
+        let doc = documents.create(owner_id, title.into(), Some("fruit".into()), None).await.unwrap();
+        documents.update_property(doc.id, "note", json!("Élan Clair")).await.unwrap();
+    }
+
+    // 1. Types in order, strings by bytes; documents of the same rank and value by id.
+    let sorted = |class: &str, field: SortField, descending: bool, limit: u32| DocumentQuery {
+        classes: vec![class.into()],
+        sort: Sort { field, descending },
+        limit: Some(limit),
+        ..Default::default()
+    };
+    let rank = || SortField::Property("rank".into());
+    let listed = titles(&service, owner_id, sorted("mixed", rank(), false, 500)).await;
+    let mut groups = vec![listed[..2].to_vec(), listed[2..9].to_vec(), listed[9..].to_vec()];
+    groups[0].sort();
+    groups[2].sort();
+    assert_eq!(groups, [
+        vec!["missing", "null"],
+        vec!["upper", "lower", "umlaut", "half", "ten", "false", "true"],
+        vec!["list", "object"],
+    ]);
+    assert_eq!(titles(&service, owner_id, sorted("mixed", rank(), false, 1)).await, listed);
+    let mut reversed = listed.clone();
+    reversed.reverse();
+    assert_eq!(titles(&service, owner_id, sorted("mixed", rank(), true, 3)).await, reversed);
+    assert_eq!(pages(&service, owner_id, sorted("fruit", SortField::Title, false, 2)).await, [vec!["Banana", "apple"], vec!["Ähre"]]);
+
+    // 2. Ranges compare strings by bytes; only ASCII letters match either case.
+    let fruit = |op: FilterOp| DocumentQuery { classes: vec!["fruit".into()], filters: vec![filter("note", op)], ..Default::default() };
+    assert_eq!(titles(&service, owner_id, fruit(FilterOp::Contains("CLAIR".into()))).await.len(), 3);
+    assert_eq!(titles(&service, owner_id, fruit(FilterOp::Contains("Élan".into()))).await.len(), 3);
+    assert!(titles(&service, owner_id, fruit(FilterOp::Contains("élan".into()))).await.is_empty());
+    let above = DocumentQuery { classes: vec!["mixed".into()], filters: vec![filter("rank", FilterOp::Gt(json!("a")))], ..Default::default() };
+    assert_eq!(titles(&service, owner_id, above).await, ["lower", "umlaut"]);
+}
+
+#[tokio::test]
+async fn test_sqlite_query_filters_and_pages() {
+    let documents = sqlite_repo().await;
+    let owner_id = Uuid::new_v4();
+    check_queries(documents.clone(), owner_id).await;
+    check_parity(documents, owner_id).await;
+}
+
+#[tokio::test]
+async fn test_query_validation() {
+    let service = QueryService::new(sqlite_repo().await);
+    let owner_id = Uuid::new_v4();
+    let with = |filters: Vec<PropertyFilter>| DocumentQuery { filters, ..Default::default() };
+
+    let err = service.query(owner_id, &with(vec![filter("a\".b", FilterOp::Exists(true))])).await.unwrap_err();
+    assert!(matches!(err, QueryError::InvalidKey(_)), "Expected an invalid key, got {:?}", err);
+    let err = service.query(owner_id, &with(vec![filter("tags", FilterOp::Eq(json!(["a"])))])).await.unwrap_err();
+    assert!(matches!(err, QueryError::InvalidValue { .. }), "Expected an invalid value, got {:?}", err);
+    let err = service.query(owner_id, &with(vec![filter("done", FilterOp::Gt(json!(true)))])).await.unwrap_err();
+    assert!(matches!(err, QueryError::InvalidValue { .. }), "Expected an invalid value, got {:?}", err);
+    let sorted = DocumentQuery { sort: Sort { field: SortField::Property(String::new()), descending: false }, ..Default::default() };
+    assert!(matches!(service.query(owner_id, &sorted).await, Err(QueryError::InvalidKey(_))));
+    let empty = DocumentQuery { limit: Some(0), ..Default::default() };
+    assert!(matches!(service.query(owner_id, &empty).await, Err(QueryError::InvalidLimit(0))));
+    let resumed = DocumentQuery { cursor: Some("not a cursor".into()), ..Default::default() };
+    let err = service.query(owner_id, &resumed).await.unwrap_err();
+    assert!(matches!(err, QueryError::InvalidCursor));
+    assert!(err.is_validation());
+}
+
+#[test]
+fn test_query_json_shape() {
+    let query: DocumentQuery = serde_json::from_value(json!({
+        "classes": ["task"],
+        "filters": [
+            {"key": "priority", "op": "gte", "value": 2},
+            {"key": "tags", "op": "has_tag", "value": "urgent"},
+            {"key": "due", "op": "exists", "value": true}
+        ],
+        "sort": {"field": {"property": "priority"}, "descending": true},
+        "limit": 10
+    })).unwrap();
+    assert_eq!(query, DocumentQuery {
+        classes: vec!["task".into()],
+        filters: vec![
+            filter("priority", FilterOp::Gte(json!(2))),
+            filter("tags", FilterOp::HasTag("urgent".into())),
+            filter("due", FilterOp::Exists(true)),
+        ],
+        sort: Sort { field: SortField::Property("priority".into()), descending: true },
+        limit: Some(10),
+        cursor: None,
+    });
+    // Everything is optional; the default lists by title.
+    let query: DocumentQuery = serde_json::from_value(json!({"sort": {"field": "updated_at"}})).unwrap();
+    assert_eq!(query.sort, Sort { field: SortField::UpdatedAt, descending: false });
+    assert!(query.filters.is_empty() && query.limit.is_none());
+}
+
+#[tokio::test]
+async fn test_postgres_query_filters_and_pages() {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for integration tests");
+    let pool = PgPoolOptions::new().max_connections(1).connect(&db_url).await.expect("Failed to connect to test database");
+    let owner_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)")
+        .bind(owner_id)
+        .bind(format!("user_{}", owner_id))
+        .bind("hash")
+        .execute(&pool).await.unwrap();
+
+    check_queries(Arc::new(PostgresDocumentRepository::new(pool.clone())), owner_id).await;
+    check_parity(Arc::new(PostgresDocumentRepository::new(pool.clone())), owner_id).await;
+
+    sqlx::query("DELETE FROM documents WHERE owner_id = $1").bind(owner_id).execute(&pool).await.ok();
+    sqlx::query("DELETE FROM users WHERE id = $1").bind(owner_id).execute(&pool).await.ok();
+}
@@ -0,0 +1,202 @@
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::socket::ContentRegistry;
+use cadmus_kernel::modules::content::storage::ContentStorage;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use y_sync::awareness::Awareness;
+use tokio::sync::broadcast::error::RecvError;
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update};
+
+async fn sqlite_registry(grace: Duration) -> ContentRegistry {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.expect("Failed to initialize schema");
+    ContentRegistry::new(Some(Arc::new(ContentStorage::new_sqlite(pool)))).with_idle_grace(grace)
+}
+
+fn text_update(content: &str) -> Vec<u8> {
+    let doc = Doc::new();
+    let text = doc.get_or_insert_text("content");
+    text.push(&mut doc.transact_mut(), content);
+    doc.transact().encode_diff_v1(&Default::default())
+}
+
+#[tokio::test]
+async fn test_room_eviction_respects_subscribers() {
+    let registry = sqlite_registry(Duration::ZERO).await;
+    let doc_id = Uuid::new_v4().to_string();
+
+    // 1. A joined room is never unloaded.
+    let handle = registry.join(&doc_id).await;
+    registry.process_update(&doc_id, text_update("Sovereign")).await.unwrap();
+    assert_eq!(registry.evict_idle().await, 0);
+
+    let stats = registry.stats().await;
+    assert_eq!(stats.room_count, 1);
+    assert_eq!(stats.rooms[0].subscribers, 1);
+    assert!(stats.rooms[0].estimated_bytes.unwrap() > 0);
+
+    // 2. Once the last subscriber leaves, the room is flushed and unloaded.
+    drop(handle);
+    assert_eq!(registry.evict_idle().await, 1);
+    assert_eq!(registry.stats().await.room_count, 0);
+    // Below the compaction threshold the log is kept as written, so its history survives.
+    let storage = registry.get_storage().unwrap();
+    assert_eq!(storage.load_history(&doc_id).await.unwrap().len(), 1);
+    assert!(storage.load_compacted_state(Uuid::parse_str(&doc_id).unwrap()).await.unwrap().is_none());
+
+    // 3. Reloading restores the flushed state.
+    let room = registry.join(&doc_id).await;
+    let text = room.doc.get_or_insert_text("content");
+    assert_eq!(text.get_string(&room.doc.transact()), "Sovereign");
+}
+
+#[tokio::test]
+async fn test_room_stays_resident_within_grace_period() {
+    let registry = sqlite_registry(Duration::from_secs(3600)).await;
+    let doc_id = Uuid::new_v4().to_string();
+
+    drop(registry.join(&doc_id).await);
+    assert_eq!(registry.evict_idle().await, 0);
+    assert_eq!(registry.stats().await.rooms[0].subscribers, 0);
+}
+
+#[tokio::test]
+async fn test_presence_expires_when_removed() {
+    let registry = sqlite_registry(Duration::from_secs(3600)).await;
+    let room = registry.join(&Uuid::new_v4().to_string()).await;
+    let mut relayed = room.awareness_tx.subscribe();
+
+    // 1. A client announces its cursor.
+    let mut client = Awareness::new(Doc::new());
+    client.set_local_state(r#"{"user":"ada","cursor":3}"#);
+    let change = room.apply_awareness(client.update().unwrap()).unwrap();
+    assert_eq!(change.added, vec![client.client_id()]);
+    assert_eq!(room.presence_count(), 1);
+    assert!(relayed.recv().await.is_ok());
+    assert!(room.awareness_update().is_some());
+
+    // 2. Its socket closes: presence is dropped and the removal is relayed.
+    room.remove_awareness(change.added);
+    assert_eq!(room.presence_count(), 0);
+    assert!(relayed.recv().await.is_ok());
+    assert!(room.awareness_update().is_none());
+}
+
+#[tokio::test]
+async fn test_lagging_subscriber_catches_up_from_diff() {
+    let registry = sqlite_registry(Duration::from_secs(3600)).await.with_channel_capacity(2);
+    let doc_id = Uuid::new_v4().to_string();
+    let room = registry.join(&doc_id).await;
+    let mut rx = room.tx.subscribe();
+
+    // 1. A slow client joins empty, then misses part of a paste-heavy burst.
+    let client = Doc::new();
+    let client_sv = client.transact().state_vector();
+    for word in ["alpha ", "beta ", "gamma ", "delta "] {
+        let doc = Doc::new();
+        doc.get_or_insert_text("content").push(&mut doc.transact_mut(), word);
+        registry.process_update(&doc_id, doc.transact().encode_diff_v1(&StateVector::default())).await.unwrap();
+    }
+    let Err(RecvError::Lagged(skipped)) = rx.recv().await else {
+        panic!("Expected the subscriber to lag behind a channel of capacity 2");
+    };
+    assert_eq!(skipped, 2);
+
+    // 2. The diff from its last known state vector brings it level with the room. It waits for
+    // a write transaction in progress instead of failing.
+    room.record_lag(skipped);
+    let doc = room.doc.clone();
+    let (held_tx, held_rx) = std::sync::mpsc::channel();
+    let writer = std::thread::spawn(move || {
+        let _txn = doc.transact_mut();
+        held_tx.send(()).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+    });
+    held_rx.recv().unwrap();
+    let (diff, room_sv) = room.diff_since(&client_sv).await.unwrap();
+    writer.join().unwrap();
+    client.transact_mut().apply_update(Update::decode_v1(&diff).unwrap());
+    assert_eq!(client.transact().state_vector(), room_sv);
+    let expected = room.doc.get_or_insert_text("content").get_string(&room.doc.transact());
+    assert_eq!(client.get_or_insert_text("content").get_string(&client.transact()), expected);
+
+    // 3. The lag is visible to operators.
+    let stats = registry.stats().await;
+    assert_eq!(stats.channel_capacity, 2);
+    assert_eq!(stats.total_lag_events, 1);
+    assert_eq!(stats.rooms[0].lagged_messages, 2);
+}
+
+#[tokio::test]
+async fn test_updates_wait_for_readers_and_invalid_ones_are_rejected() {
+    let registry = Arc::new(sqlite_registry(Duration::from_secs(3600)).await);
+    let doc_id = Uuid::new_v4().to_string();
+    let room = registry.join(&doc_id).await;
+
+    let client = Doc::new();
+    let text = client.get_or_insert_text("content");
+    let mut updates = Vec::new();
+    for word in ["Sove", "reign"] {
+        let sv = client.transact().state_vector();
+        text.push(&mut client.transact_mut(), word);
+        updates.push(client.transact().encode_diff_v1(&sv));
+    }
+
+    // 1. A reader holds the document: updates queue up behind it instead of being dropped.
+    let reader = room.doc.transact();
+    let pending = {
+        let registry = registry.clone();
+        let doc_id = doc_id.clone();
+        tokio::spawn(async move {
+            for update in updates {
+                registry.process_update(&doc_id, update).await.unwrap();
+            }
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!pending.is_finished());
+    drop(reader);
+    pending.await.unwrap();
+
+    let content = room.doc.get_or_insert_text("content");
+    assert_eq!(content.get_string(&room.doc.transact()), "Sovereign");
+    assert_eq!(room.revision(), 2);
+
+    // 2. An update that does not decode is neither broadcast nor persisted.
+    let mut rx = room.tx.subscribe();
+    assert!(registry.process_update(&doc_id, vec![0xFF; 8]).await.is_err());
+    assert!(rx.try_recv().is_err());
+    registry.flush().await.unwrap();
+    let storage = registry.get_storage().unwrap();
+    assert_eq!(storage.load_updates(&doc_id).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_discarded_room_closes_and_drops_buffered_updates() {
+    let registry = sqlite_registry(Duration::from_secs(3600)).await.with_write_behind(Duration::from_secs(3600), usize::MAX);
+    let doc_id = Uuid::new_v4().to_string();
+    let room = registry.get_room(&doc_id).await;
+    registry.process_update(&doc_id, text_update("Gone")).await.unwrap();
+    assert!(registry.stats().await.rooms[0].unpersisted_bytes > 0);
+
+    // 1. Sockets waiting on the room learn that it closed.
+    let closed = {
+        let room = room.clone();
+        tokio::spawn(async move { room.closed().await })
+    };
+    registry.discard(&doc_id).await;
+    tokio::time::timeout(Duration::from_secs(1), closed).await.expect("Room was not closed").unwrap();
+    assert!(room.is_closed());
+
+    // 2. The room is unloaded and its buffered update is never written.
+    assert_eq!(registry.stats().await.room_count, 0);
+    registry.flush().await.unwrap();
+    assert!(registry.get_storage().unwrap().load_updates(&doc_id).await.unwrap().is_empty());
+}
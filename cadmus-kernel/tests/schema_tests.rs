@@ -0,0 +1,100 @@
+use cadmus_kernel::domain::repository::{ArchetypeRepository, DocumentRepository};
+use cadmus_kernel::infrastructure::sqlite::{SqliteArchetypeRepository, SqliteDocumentRepository};
+use cadmus_kernel::modules::content::storage::DbPool;
+use cadmus_kernel::shared::migrations::logical_schema;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+async fn memory_pool() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite")
+}
+
+/// Schema of desktop vaults created before update authors and link times were recorded.
+const LEGACY_SCHEMA: &str = r#"
+    CREATE TABLE documents (
+        id TEXT PRIMARY KEY,
+        owner_id TEXT NOT NULL,
+        title TEXT NOT NULL,
+        class_id TEXT,
+        parent_id TEXT,
+        properties TEXT DEFAULT '{}' NOT NULL,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE TABLE document_links (from_id TEXT NOT NULL, to_id TEXT NOT NULL, PRIMARY KEY (from_id, to_id));
+    CREATE TABLE document_updates (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        doc_id TEXT NOT NULL,
+        data BLOB NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE TABLE collection_rows (
+        id TEXT PRIMARY KEY,
+        document_id TEXT NOT NULL,
+        data TEXT DEFAULT '{}' NOT NULL,
+        order_index INTEGER,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE TABLE classes (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        json_schema TEXT DEFAULT '{}',
+        ui_schema TEXT DEFAULT '[]',
+        behavior_rules TEXT DEFAULT '{}',
+        icon TEXT,
+        has_collection BOOLEAN DEFAULT 0,
+        group_id TEXT DEFAULT 'primitiva',
+        required_tier TEXT DEFAULT 'Community'
+    );
+    INSERT INTO classes (id, name, icon) VALUES ('note', 'Note', 'FileText');
+"#;
+
+#[tokio::test]
+async fn test_legacy_vault_migrates_to_the_fresh_schema() {
+    let fresh = memory_pool().await;
+    SqliteDocumentRepository::new(fresh.clone()).initialize().await.expect("Failed to initialize fresh vault");
+
+    // 1. A vault from before versioned migrations, holding data.
+    let legacy = memory_pool().await;
+    sqlx::raw_sql(LEGACY_SCHEMA).execute(&legacy).await.unwrap();
+    let owner_id = Uuid::new_v4();
+    let (from, to) = (Uuid::new_v4(), Uuid::new_v4());
+    for (id, title) in [(from, "Plan"), (to, "Draft")] {
+        sqlx::query("INSERT INTO documents (id, owner_id, title, class_id) VALUES (?, ?, ?, 'note')")
+            .bind(id.to_string()).bind(owner_id.to_string()).bind(title)
+            .execute(&legacy).await.unwrap();
+    }
+    sqlx::query("INSERT INTO document_links (from_id, to_id) VALUES (?, ?)")
+        .bind(from.to_string()).bind(to.to_string())
+        .execute(&legacy).await.unwrap();
+
+    // 2. Upgrading it yields the schema of a new vault, and running again changes nothing.
+    let repo = SqliteDocumentRepository::new(legacy.clone());
+    repo.initialize().await.expect("Failed to migrate legacy vault");
+    repo.initialize().await.expect("Migrations are not idempotent");
+    assert_eq!(
+        logical_schema(&DbPool::Sqlite(legacy.clone())).await.unwrap(),
+        logical_schema(&DbPool::Sqlite(fresh)).await.unwrap()
+    );
+
+    // 3. Its data survived, and new columns were filled in.
+    assert_eq!(repo.find_all(owner_id).await.unwrap().len(), 2);
+    assert_eq!(repo.find_links(owner_id).await.unwrap(), vec![(from, to)]);
+    let unstamped: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE created_at IS NULL OR config IS NULL")
+        .fetch_one(&legacy).await.unwrap();
+    assert_eq!(unstamped, 0);
+    let unstamped: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM document_links WHERE created_at IS NULL")
+        .fetch_one(&legacy).await.unwrap();
+    assert_eq!(unstamped, 0);
+
+    // 4. Archetypes carry the children they accept, as on the server.
+    let archetypes = SqliteArchetypeRepository::new(legacy);
+    let note = archetypes.find_by_id("note").await.unwrap().unwrap();
+    assert_eq!(note.allowed_children, Some(vec!["note".to_string(), "canvas".to_string(), "media".to_string()]));
+    assert!(archetypes.find_by_id("profile").await.unwrap().is_some());
+}
@@ -1,20 +1,14 @@
+use cadmus_kernel::infrastructure::postgres::PostgresUserRepository;
+use cadmus_kernel::modules::content::socket::ContentRegistry;
 use cadmus_kernel::modules::security::service::SecurityService;
-use cadmus_kernel::modules::security::domain::{LoginRequest, RegisterRequest};
+use cadmus_kernel::modules::security::domain::{LoginRequest, RegisterRequest, User};
+use cadmus_kernel::shared::database::{CoreState, Db};
 use sqlx::postgres::PgPoolOptions;
 use std::env;
+use std::sync::Arc;
 
-#[tokio::test]
-async fn test_security_flow_full() {
-    dotenvy::dotenv().ok();
-    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for integration tests");
-    let pool = PgPoolOptions::new()
-        .max_connections(1)
-        .connect(&db_url)
-        .await
-        .expect("Failed to connect to test database");
-
-    let service = SecurityService::new(pool.clone());
-
+/// Registers, logs in and checks tokens, whatever the storage behind the service.
+async fn check_security_flow(service: &SecurityService) -> User {
     // 1. Register
     let username = format!("test_user_{}", uuid::Uuid::new_v4());
     let reg_req = RegisterRequest {
@@ -39,6 +33,11 @@ async fn test_security_flow_full() {
     let validated_id = service.validate_token(&token).expect("Failed to validate token");
     assert_eq!(validated_id, user.id);
 
+    let session = service.validate_session(&token).expect("Failed to validate session");
+    assert_eq!(session.user_id, user.id);
+    let remaining = session.remaining().expect("Token should carry an expiration");
+    assert!(remaining > std::time::Duration::from_secs(29 * 24 * 3600));
+
     // 4. Invalid Login
     let wrong_login = LoginRequest {
         username: username.clone(),
@@ -47,6 +46,43 @@ async fn test_security_flow_full() {
     let result = service.login(wrong_login).await;
     assert!(result.is_err());
 
+    user
+}
+
+#[tokio::test]
+async fn test_security_flow_full() {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for integration tests");
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&db_url)
+        .await
+        .expect("Failed to connect to test database");
+
+    let service = SecurityService::new(Arc::new(PostgresUserRepository::new(pool.clone())));
+    let user = check_security_flow(&service).await;
+
     // Cleanup (optional, depends on test DB strategy)
     sqlx::query("DELETE FROM users WHERE id = $1").bind(user.id).execute(&pool).await.ok();
 }
+
+#[tokio::test]
+async fn test_security_flow_on_sqlite_core_state() {
+    let db = Db::new("sqlite::memory:").await.expect("Failed to open in-memory SQLite");
+    db.migrate().await.expect("Failed to migrate");
+    let state = CoreState::new(db.pool.clone(), Arc::new(ContentRegistry::new(None)));
+
+    let user = check_security_flow(&state.security).await;
+
+    // The rest of the state runs on the same database.
+    let doc = state.documents.create(user.id, "Vault".to_string(), Some("note".to_string()), None).await.unwrap();
+    assert_eq!(state.documents.find_owner(doc.id).await.unwrap(), Some(user.id));
+    assert!(state.archetypes.find_by_id("note").await.unwrap().is_some());
+    state.audit.log(Some(user.id), Some(doc.id), "document", "create", None).await.unwrap();
+    state.pool.ping().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_db_rejects_unknown_schemes() {
+    assert!(Db::new("mysql://localhost/cadmus").await.is_err());
+}
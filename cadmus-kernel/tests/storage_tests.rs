@@ -0,0 +1,64 @@
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::storage::ContentStorage;
+use sqlx::sqlite::SqlitePoolOptions;
+use uuid::Uuid;
+use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update};
+use yrs::updates::decoder::Decode;
+
+async fn sqlite_storage(threshold: i64) -> ContentStorage {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.expect("Failed to initialize schema");
+    ContentStorage::new_sqlite(pool).with_compaction_threshold(threshold)
+}
+
+#[tokio::test]
+async fn test_compaction_keeps_snapshot_plus_tail() {
+    let storage = sqlite_storage(5).await;
+    let doc_id = Uuid::new_v4().to_string();
+
+    // 1. Write 8 single-character edits; the 6th crosses the threshold.
+    let source = Doc::new();
+    let text = source.get_or_insert_text("content");
+    for i in 0..8 {
+        let sv = source.transact().state_vector();
+        text.push(&mut source.transact_mut(), &i.to_string());
+        let update = source.transact().encode_diff_v1(&sv);
+        storage.save_update(&doc_id, update).await.expect("Failed to save update");
+    }
+
+    // 2. Snapshot + 2 tail updates remain.
+    let uuid = Uuid::parse_str(&doc_id).unwrap();
+    assert!(storage.load_compacted_state(uuid).await.unwrap().is_some());
+    assert_eq!(storage.count_pending_updates(uuid).await.unwrap(), 2);
+
+    // 3. Replaying snapshot + tail restores the full document.
+    let restored = Doc::new();
+    let restored_text = restored.get_or_insert_text("content");
+    {
+        let mut txn = restored.transact_mut();
+        for u in storage.load_updates(&doc_id).await.unwrap() {
+            txn.apply_update(Update::decode_v1(&u).unwrap());
+        }
+    }
+    assert_eq!(restored_text.get_string(&restored.transact()), "01234567");
+    assert_eq!(
+        restored.transact().encode_state_as_update_v1(&StateVector::default()),
+        source.transact().encode_state_as_update_v1(&StateVector::default())
+    );
+}
+
+#[tokio::test]
+async fn test_compaction_skips_sheet_snapshots() {
+    let storage = sqlite_storage(0).await;
+    let doc_id = Uuid::new_v4().to_string();
+
+    let sheet = b"CADMUS_SHEET_V1:[{\"name\":\"Sheet1\"}]".to_vec();
+    storage.save_update(&doc_id, sheet.clone()).await.unwrap();
+
+    assert_eq!(storage.compact(&doc_id).await.unwrap(), 0);
+    assert_eq!(storage.load_latest_snapshot(&doc_id).await.unwrap(), Some(sheet));
+}
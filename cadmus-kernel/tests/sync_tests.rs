@@ -0,0 +1,285 @@
+use async_trait::async_trait;
+use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::history::replay;
+use cadmus_kernel::modules::content::socket::ContentRegistry;
+use cadmus_kernel::modules::content::storage::{ContentStorage, DbPool};
+use cadmus_kernel::modules::sync::domain::{ChangeSet, DocExchange, PushResult, SyncCursor};
+use cadmus_kernel::modules::sync::engine::{InProcessRemote, ReplicationEngine, SyncRemote};
+use cadmus_kernel::modules::sync::service::SyncService;
+use cadmus_kernel::modules::sync::store::SyncStore;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use yrs::{GetString, ReadTxn, Text, Transact};
+
+/// Rows are only exchanged once they are older than this; longer than SQLite's one-second timestamps.
+const SETTLE: Duration = Duration::from_millis(1100);
+
+async fn sqlite_vault() -> (SqlitePool, SqliteDocumentRepository, Arc<ContentStorage>) {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+    let repo = SqliteDocumentRepository::new(pool.clone());
+    repo.initialize().await.expect("Failed to initialize schema");
+    (pool.clone(), repo, Arc::new(ContentStorage::new_sqlite(pool)))
+}
+
+struct Server {
+    repo: SqliteDocumentRepository,
+    registry: Arc<ContentRegistry>,
+    service: Arc<SyncService>,
+}
+
+async fn server() -> Server {
+    let (pool, repo, storage) = sqlite_vault().await;
+    let registry = Arc::new(ContentRegistry::new(Some(storage)));
+    let service = Arc::new(SyncService::new(SyncStore::new(DbPool::Sqlite(pool)), registry.clone()).with_settle(SETTLE));
+    Server { repo, registry, service }
+}
+
+fn engine(pool: &SqlitePool, storage: &Arc<ContentStorage>, remote: Arc<dyn SyncRemote>, user_id: Uuid) -> ReplicationEngine {
+    ReplicationEngine::new(SyncStore::new(DbPool::Sqlite(pool.clone())), storage.clone(), remote, user_id)
+        .with_settle(SETTLE)
+}
+
+/// Text of a document as the local vault stores it.
+async fn local_text(storage: &ContentStorage, doc_id: Uuid) -> String {
+    let doc = replay(storage.load_updates(&doc_id.to_string()).await.unwrap());
+    doc.get_or_insert_text("content").get_string(&doc.transact())
+}
+
+/// Appends to the text of a document in the local vault, as the desktop editor does offline.
+async fn local_edit(storage: &ContentStorage, doc_id: Uuid, suffix: &str) {
+    let doc = replay(storage.load_updates(&doc_id.to_string()).await.unwrap());
+    let sv = doc.transact().state_vector();
+    doc.get_or_insert_text("content").push(&mut doc.transact_mut(), suffix);
+    let update = doc.transact().encode_diff_v1(&sv);
+    storage.save_update(&doc_id.to_string(), update).await.unwrap();
+}
+
+async fn server_edit(registry: &ContentRegistry, doc_id: Uuid, suffix: &str) {
+    let room = registry.get_room(&doc_id.to_string()).await;
+    let (state, sv) = room.diff_since(&Default::default()).await.unwrap();
+    let doc = replay([state]);
+    doc.get_or_insert_text("content").push(&mut doc.transact_mut(), suffix);
+    let update = doc.transact().encode_diff_v1(&sv);
+    registry.process_update(&doc_id.to_string(), update).await.unwrap();
+    registry.flush().await.unwrap();
+}
+
+async fn server_text(registry: &ContentRegistry, doc_id: Uuid) -> String {
+    let (state, _) = registry.get_room(&doc_id.to_string()).await.diff_since(&Default::default()).await.unwrap();
+    let doc = replay([state]);
+    doc.get_or_insert_text("content").get_string(&doc.transact())
+}
+
+/// Counts the rows a remote hands out, to tell a resumed sync from one starting over.
+struct CountingRemote {
+    inner: InProcessRemote,
+    pulled_rows: AtomicUsize,
+}
+
+#[async_trait]
+impl SyncRemote for CountingRemote {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn pull(&self, cursor: &SyncCursor, limit: i64) -> anyhow::Result<ChangeSet> {
+        let page = self.inner.pull(cursor, limit).await?;
+        self.pulled_rows.fetch_add(page.documents.len() + page.collection_rows.len() + page.links.len(), Ordering::SeqCst);
+        Ok(page)
+    }
+
+    async fn push(&self, changes: &ChangeSet) -> anyhow::Result<PushResult> {
+        self.inner.push(changes).await
+    }
+
+    async fn exchange(&self, doc_id: Uuid, state_vector: Vec<u8>) -> anyhow::Result<DocExchange> {
+        self.inner.exchange(doc_id, state_vector).await
+    }
+
+    async fn push_update(&self, doc_id: Uuid, update: Vec<u8>) -> anyhow::Result<()> {
+        self.inner.push_update(doc_id, update).await
+    }
+}
+
+#[tokio::test]
+async fn test_offline_edits_on_both_sides_converge() {
+    let user_id = Uuid::new_v4();
+    let server = server().await;
+    let (pool, repo, storage) = sqlite_vault().await;
+    let engine = engine(&pool, &storage, Arc::new(InProcessRemote::new(server.service.clone(), user_id)), user_id);
+
+    // 1. Each side creates documents while apart.
+    let shared = server.repo.create(user_id, "Roadmap".into(), None, None).await.unwrap();
+    server_edit(&server.registry, shared.id, "Ship sync").await;
+    let field = repo.create(user_id, "Field notes".into(), Some("project".into()), None).await.unwrap();
+    let sketch = repo.create(user_id, "Sketch".into(), None, Some(field.id)).await.unwrap();
+    repo.add_link(field.id, sketch.id).await.unwrap();
+    let row = repo.add_collection_row(field.id).await.unwrap();
+    repo.update_collection_cell(field.id, Uuid::parse_str(row["id"].as_str().unwrap()).unwrap(), "status", "open".into()).await.unwrap();
+    local_edit(&storage, field.id, "Observed").await;
+    tokio::time::sleep(SETTLE).await;
+
+    let report = engine.sync_once().await.unwrap();
+    assert_eq!(report.pulled, 1);
+    assert_eq!(report.pushed, 4);
+
+    // 2. Both now hold every document, row, link and text.
+    assert_eq!(repo.find_all(user_id).await.unwrap().len(), 3);
+    assert_eq!(server.repo.find_all(user_id).await.unwrap().len(), 3);
+    assert_eq!(server.repo.find_links(user_id).await.unwrap(), vec![(field.id, sketch.id)]);
+    assert_eq!(server.repo.get_collection_rows(field.id).await.unwrap()[0]["status"], "open");
+    assert_eq!(local_text(&storage, shared.id).await, "Ship sync");
+    assert_eq!(server_text(&server.registry, field.id).await, "Observed");
+
+    // 3. Concurrent edits to the same text merge instead of overwriting each other.
+    server_edit(&server.registry, shared.id, " today").await;
+    local_edit(&storage, shared.id, " safely").await;
+    tokio::time::sleep(SETTLE).await;
+    engine.sync_once().await.unwrap();
+
+    let merged = server_text(&server.registry, shared.id).await;
+    assert_eq!(local_text(&storage, shared.id).await, merged);
+    assert!(merged.contains(" today") && merged.contains(" safely"));
+}
+
+#[tokio::test]
+async fn test_sync_resumes_from_its_cursor_and_keeps_the_latest_write() {
+    let user_id = Uuid::new_v4();
+    let server = server().await;
+    let (pool, repo, storage) = sqlite_vault().await;
+    let remote = Arc::new(CountingRemote {
+        inner: InProcessRemote::new(server.service.clone(), user_id),
+        pulled_rows: AtomicUsize::new(0),
+    });
+
+    let mut ids = Vec::new();
+    for title in ["Alpha", "Beta", "Gamma"] {
+        ids.push(server.repo.create(user_id, title.into(), None, None).await.unwrap().id);
+    }
+    tokio::time::sleep(SETTLE).await;
+
+    // 1. Pulled one row per request; the cursor is kept in the vault.
+    let report = engine(&pool, &storage, remote.clone(), user_id).with_page_size(1).sync_once().await.unwrap();
+    assert_eq!(report.pulled, 3);
+    assert_eq!(remote.pulled_rows.load(Ordering::SeqCst), 3);
+
+    // 2. A new engine on the same vault, as after a restart, only reads what changed since.
+    server.repo.update_property(ids[0], "title", "Alpha (server)".into()).await.unwrap();
+    tokio::time::sleep(SETTLE).await;
+    repo.update_property(ids[0], "title", "Alpha (desktop)".into()).await.unwrap();
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote.clone(), user_id).sync_once().await.unwrap();
+    assert_eq!(remote.pulled_rows.load(Ordering::SeqCst), 4);
+
+    // 3. The desktop renamed last, so its title wins on both sides.
+    assert_eq!(repo.find_by_id(ids[0]).await.unwrap().unwrap().title, "Alpha (desktop)");
+    assert_eq!(server.repo.find_by_id(ids[0]).await.unwrap().unwrap().title, "Alpha (desktop)");
+}
+
+#[tokio::test]
+async fn test_purged_documents_are_deleted_on_the_other_side_and_stay_deleted() {
+    let user_id = Uuid::new_v4();
+    let server = server().await;
+    let (pool, repo, storage) = sqlite_vault().await;
+    let remote: Arc<dyn SyncRemote> = Arc::new(InProcessRemote::new(server.service.clone(), user_id));
+
+    let draft = server.repo.create(user_id, "Draft".into(), None, None).await.unwrap();
+    let kept = server.repo.create(user_id, "Kept".into(), None, None).await.unwrap();
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote.clone(), user_id).sync_once().await.unwrap();
+    assert!(repo.find_by_id(draft.id).await.unwrap().is_some());
+
+    // 1. Purged on the desktop, the document is deleted on the server by the next sync.
+    repo.trash(draft.id, user_id).await.unwrap();
+    assert_eq!(repo.purge(draft.id, user_id).await.unwrap(), [draft.id]);
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote.clone(), user_id).sync_once().await.unwrap();
+    assert!(server.repo.find_by_id(draft.id).await.unwrap().is_none());
+    assert!(server.repo.find_by_id(kept.id).await.unwrap().is_some());
+
+    // 2. A replica that still has the document cannot bring it back.
+    let (stale_pool, stale_repo, stale_storage) = sqlite_vault().await;
+    stale_repo.create(user_id, "Seed".into(), None, None).await.unwrap();
+    let mut documents = SyncStore::new(DbPool::Sqlite(pool.clone())).documents_by_id(user_id, &[kept.id]).await.unwrap();
+    documents[0].id = draft.id;
+    let resurrect = ChangeSet { documents, ..Default::default() };
+    SyncStore::new(DbPool::Sqlite(stale_pool.clone())).apply(user_id, &resurrect, None).await.unwrap();
+    assert_eq!(remote.push(&resurrect).await.unwrap().accepted, 0);
+    assert!(server.repo.find_by_id(draft.id).await.unwrap().is_none());
+    tokio::time::sleep(SETTLE).await;
+    engine(&stale_pool, &stale_storage, remote.clone(), user_id).sync_once().await.unwrap();
+    assert!(server.repo.find_by_id(draft.id).await.unwrap().is_none());
+    assert!(stale_repo.find_by_id(draft.id).await.unwrap().is_none());
+
+    // 3. Syncing again changes nothing on the desktop.
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote, user_id).sync_once().await.unwrap();
+    assert!(repo.find_by_id(draft.id).await.unwrap().is_none());
+    assert!(repo.find_by_id(kept.id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_link_edits_and_removals_replicate() {
+    let user_id = Uuid::new_v4();
+    let server = server().await;
+    let (pool, repo, storage) = sqlite_vault().await;
+    let remote: Arc<dyn SyncRemote> = Arc::new(InProcessRemote::new(server.service.clone(), user_id));
+
+    let paper = server.repo.create(user_id, "Paper".into(), None, None).await.unwrap();
+    let spec = server.repo.create(user_id, "Spec".into(), None, None).await.unwrap();
+    server.repo.add_typed_link(paper.id, spec.id, "cites", serde_json::json!({ "page": 3 })).await.unwrap();
+    server.repo.add_typed_link(paper.id, spec.id, "link", serde_json::json!({})).await.unwrap();
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote.clone(), user_id).sync_once().await.unwrap();
+    assert_eq!(repo.find_backlinks(spec.id).await.unwrap().len(), 2);
+
+    // 1. Relinking with new metadata and unlinking on the server both reach the desktop.
+    server.repo.add_typed_link(paper.id, spec.id, "cites", serde_json::json!({ "page": 7 })).await.unwrap();
+    assert_eq!(server.repo.remove_link(paper.id, spec.id, Some("link")).await.unwrap(), 1);
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote.clone(), user_id).sync_once().await.unwrap();
+    let links = repo.find_backlinks(spec.id).await.unwrap();
+    assert_eq!(links.len(), 1);
+    assert_eq!((links[0].relation.as_str(), &links[0].metadata), ("cites", &serde_json::json!({ "page": 7 })));
+
+    // 2. A link whose metadata cannot be read stops the sync instead of being skipped.
+    sqlx::query("UPDATE document_links SET metadata = 'not json', updated_at = CURRENT_TIMESTAMP").execute(&pool).await.unwrap();
+    tokio::time::sleep(SETTLE).await;
+    assert!(engine(&pool, &storage, remote, user_id).sync_once().await.is_err());
+}
+
+#[tokio::test]
+async fn test_trash_and_restore_replicate() {
+    let user_id = Uuid::new_v4();
+    let server = server().await;
+    let (pool, repo, storage) = sqlite_vault().await;
+    let remote: Arc<dyn SyncRemote> = Arc::new(InProcessRemote::new(server.service.clone(), user_id));
+
+    let folder = server.repo.create(user_id, "Folder".into(), None, None).await.unwrap();
+    let child = server.repo.create(user_id, "Child".into(), None, Some(folder.id)).await.unwrap();
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote.clone(), user_id).sync_once().await.unwrap();
+
+    // 1. Trashed on the server, the set is in the desktop's trash after the next sync.
+    assert_eq!(server.repo.trash(folder.id, user_id).await.unwrap().len(), 2);
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote.clone(), user_id).sync_once().await.unwrap();
+    let trash = repo.find_trash(user_id).await.unwrap();
+    assert_eq!((trash.len(), trash[0].id, trash[0].documents), (1, folder.id, 2));
+
+    // 2. Restored on the desktop, it leaves the server's trash.
+    assert_eq!(repo.restore(folder.id, user_id).await.unwrap(), 2);
+    tokio::time::sleep(SETTLE).await;
+    engine(&pool, &storage, remote, user_id).sync_once().await.unwrap();
+    assert!(server.repo.find_trash(user_id).await.unwrap().is_empty());
+    assert_eq!(server.repo.find_by_id(child.id).await.unwrap().unwrap().parent_id, Some(folder.id));
+}
@@ -0,0 +1,160 @@
+use chrono::{Duration, Utc};
+use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::infrastructure::postgres::PostgresDocumentRepository;
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::markdown::markdown_to_state;
+use cadmus_kernel::modules::content::socket::ContentRegistry;
+use cadmus_kernel::modules::content::storage::{ContentStorage, DbPool};
+use cadmus_kernel::modules::trash::domain::TrashError;
+use cadmus_kernel::modules::trash::service::TrashService;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+async fn sqlite_setup() -> (Arc<SqliteDocumentRepository>, ContentStorage) {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    let repo = SqliteDocumentRepository::new(pool.clone());
+    repo.initialize().await.unwrap();
+    (Arc::new(repo), ContentStorage::new(DbPool::Sqlite(pool)))
+}
+
+/// Trashes, restores and purges a three-level tree that has a link, a collection row and
+/// content hanging off it.
+async fn check_trash(documents: Arc<dyn DocumentRepository>, storage: &ContentStorage, owner_id: Uuid) {
+    let service = TrashService::new(documents.clone());
+    let root = documents.create(owner_id, "Root".into(), None, None).await.unwrap();
+    let child = documents.create(owner_id, "Child".into(), None, Some(root.id)).await.unwrap();
+    let leaf = documents.create(owner_id, "Leaf".into(), None, Some(child.id)).await.unwrap();
+    let outside = documents.create(owner_id, "Outside".into(), None, None).await.unwrap();
+    documents.add_link(outside.id, child.id).await.unwrap();
+    documents.add_collection_row(child.id).await.unwrap();
+    storage.save_update(&leaf.id.to_string(), markdown_to_state("Draft")).await.unwrap();
+
+    // 1. Trashing takes the subtree out of every listing.
+    assert_eq!(service.trash(owner_id, root.id).await.expect("Failed to trash"), 3);
+    assert_eq!(documents.find_all(owner_id).await.unwrap().iter().map(|n| n.id).collect::<Vec<_>>(), vec![outside.id]);
+    assert!(documents.find_by_id(leaf.id).await.unwrap().is_none());
+    assert_eq!(documents.find_live_owner(leaf.id).await.unwrap(), None);
+    assert_eq!(documents.find_owner(leaf.id).await.unwrap(), Some(owner_id));
+    assert!(documents.find_links(owner_id).await.unwrap().is_empty());
+    assert_eq!(documents.get_stats(owner_id).await.unwrap().nodes, 1);
+    let trash = service.list(owner_id).await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!((trash[0].id, trash[0].title.as_str(), trash[0].documents), (root.id, "Root", 3));
+    for id in [root.id, leaf.id] {
+        assert!(matches!(service.trash(owner_id, id).await, Err(TrashError::AlreadyTrashed(_))));
+    }
+    // Only the document the user deleted can be restored.
+    assert!(matches!(service.restore(owner_id, child.id).await, Err(TrashError::NotTrashed(_))));
+
+    // 2. Restoring brings the whole set back where it was.
+    assert_eq!(service.restore(owner_id, root.id).await.expect("Failed to restore"), 3);
+    assert_eq!(documents.find_by_id(leaf.id).await.unwrap().unwrap().parent_id, Some(child.id));
+    assert_eq!(documents.find_live_owner(leaf.id).await.unwrap(), Some(owner_id));
+    assert_eq!(documents.find_links(owner_id).await.unwrap(), vec![(outside.id, child.id)]);
+    assert!(service.list(owner_id).await.unwrap().is_empty());
+
+    // 3. A set restored while its parent is still trashed moves to the root.
+    assert_eq!(service.trash(owner_id, child.id).await.unwrap(), 2);
+    assert_eq!(service.trash(owner_id, root.id).await.unwrap(), 1);
+    assert_eq!(service.list(owner_id).await.unwrap().len(), 2);
+    assert_eq!(service.restore(owner_id, child.id).await.unwrap(), 2);
+    assert_eq!(documents.find_by_id(child.id).await.unwrap().unwrap().parent_id, None);
+    assert_eq!(documents.find_by_id(leaf.id).await.unwrap().unwrap().parent_id, Some(child.id));
+
+    // 4. Purging removes the set with everything that refers to it.
+    assert!(matches!(service.purge(owner_id, child.id).await, Err(TrashError::NotTrashed(_))));
+    service.trash(owner_id, child.id).await.unwrap();
+    assert_eq!(service.purge(owner_id, child.id).await.expect("Failed to purge"), 2);
+    assert!(documents.find_owner(leaf.id).await.unwrap().is_none());
+    assert!(storage.load_updates(&leaf.id.to_string()).await.unwrap().is_empty());
+    assert!(documents.get_collection_rows(child.id).await.unwrap().is_empty());
+    assert_eq!(documents.remove_link(outside.id, child.id, None).await.unwrap(), 0);
+    assert!(matches!(service.restore(owner_id, child.id).await, Err(TrashError::NotFound(_))));
+
+    // 5. Expired trash goes for good, fresh trash stays.
+    assert_eq!(service.purge_expired().await.unwrap(), 0);
+    assert_eq!(service.list(owner_id).await.unwrap().len(), 1);
+    // The purge covers every user, so other tests sharing the database may add to the count.
+    assert!(documents.purge_expired(Utc::now() + Duration::minutes(1)).await.unwrap().contains(&root.id));
+    assert!(documents.find_owner(root.id).await.unwrap().is_none());
+    assert!(service.list(owner_id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_sqlite_trash_restore_and_purge() {
+    let (documents, storage) = sqlite_setup().await;
+    check_trash(documents, &storage, Uuid::new_v4()).await;
+}
+
+#[tokio::test]
+async fn test_trash_checks_ownership() {
+    let (documents, _) = sqlite_setup().await;
+    let service = TrashService::new(documents.clone());
+    let (owner_id, stranger) = (Uuid::new_v4(), Uuid::new_v4());
+    let mine = documents.create(owner_id, "Mine".into(), None, None).await.unwrap();
+    let theirs = documents.create(stranger, "Theirs".into(), None, Some(mine.id)).await.unwrap();
+
+    assert!(matches!(service.trash(stranger, mine.id).await, Err(TrashError::Forbidden(_))));
+    assert!(matches!(service.trash(owner_id, Uuid::new_v4()).await, Err(TrashError::NotFound(_))));
+    assert!(documents.find_by_id(mine.id).await.unwrap().is_some());
+
+    // Another user's document filed under mine is not mine to delete.
+    assert_eq!(service.trash(owner_id, mine.id).await.unwrap(), 1);
+    assert!(documents.find_by_id(theirs.id).await.unwrap().is_some());
+    assert!(service.list(stranger).await.unwrap().is_empty());
+    assert!(matches!(service.restore(stranger, mine.id).await, Err(TrashError::Forbidden(_))));
+    assert!(matches!(service.purge(stranger, mine.id).await, Err(TrashError::Forbidden(_))));
+    assert!(documents.purge(mine.id, stranger).await.unwrap().is_empty());
+    assert_eq!(service.list(owner_id).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_trash_and_purge_unload_rooms() {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    let documents = Arc::new(SqliteDocumentRepository::new(pool.clone()));
+    documents.initialize().await.unwrap();
+    let registry = Arc::new(ContentRegistry::new(Some(Arc::new(ContentStorage::new_sqlite(pool))))
+        .with_write_behind(std::time::Duration::from_secs(3600), usize::MAX));
+    let service = TrashService::new(documents.clone()).with_registry(registry.clone());
+    let owner_id = Uuid::new_v4();
+    let doc = documents.create(owner_id, "Draft".into(), None, None).await.unwrap();
+    let doc_id = doc.id.to_string();
+    let storage = registry.get_storage().unwrap();
+
+    // 1. Trashing writes what the room buffered, so a restore brings it back, and unloads the room.
+    let room = registry.get_room(&doc_id).await;
+    registry.process_update(&doc_id, markdown_to_state("Kept")).await.unwrap();
+    service.trash(owner_id, doc.id).await.unwrap();
+    assert!(room.is_closed());
+    assert_eq!(registry.stats().await.room_count, 0);
+    assert_eq!(storage.load_updates(&doc_id).await.unwrap().len(), 1);
+
+    // 2. An update that arrives late is dropped by the purge rather than written after it.
+    registry.process_update(&doc_id, markdown_to_state("Late")).await.unwrap();
+    service.purge(owner_id, doc.id).await.unwrap();
+    assert_eq!(registry.stats().await.room_count, 0);
+    registry.flush().await.unwrap();
+    assert!(storage.load_updates(&doc_id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_postgres_trash_restore_and_purge() {
+    dotenvy::dotenv().ok();
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set for integration tests");
+    let pool = PgPoolOptions::new().max_connections(1).connect(&db_url).await.expect("Failed to connect to test database");
+    let owner_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)")
+        .bind(owner_id)
+        .bind(format!("user_{}", owner_id))
+        .bind("hash")
+        .execute(&pool).await.unwrap();
+
+    let documents: Arc<dyn DocumentRepository> = Arc::new(PostgresDocumentRepository::new(pool.clone()));
+    check_trash(documents, &ContentStorage::new(DbPool::Postgres(pool.clone())), owner_id).await;
+
+    sqlx::query("DELETE FROM documents WHERE owner_id = $1").bind(owner_id).execute(&pool).await.ok();
+    sqlx::query("DELETE FROM users WHERE id = $1").bind(owner_id).execute(&pool).await.ok();
+}
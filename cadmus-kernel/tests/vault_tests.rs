@@ -0,0 +1,73 @@
+#![cfg(feature = "sqlcipher")]
+
+use cadmus_kernel::domain::repository::DocumentRepository;
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::infrastructure::vault::VaultFile;
+use cadmus_kernel::modules::content::storage::ContentStorage;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+fn vault_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cadmus-vault-{}-{}", name, Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("cadmus_local.db")
+}
+
+fn on_disk(path: &PathBuf, needle: &str) -> bool {
+    std::fs::read(path).unwrap().windows(needle.len()).any(|w| w == needle.as_bytes())
+}
+
+#[tokio::test]
+async fn test_encrypted_vault_opens_only_with_its_passphrase() {
+    let path = vault_path("encrypt");
+    let file = VaultFile::new(&path);
+    let owner = Uuid::new_v4();
+
+    // 1. A plaintext vault with a document and its content.
+    let pool = file.open().await.unwrap();
+    let repo = SqliteDocumentRepository::new(pool.clone());
+    repo.initialize().await.unwrap();
+    let doc = repo.create(owner, "Quarterly secrets".into(), None, None).await.unwrap();
+    repo.update_property(doc.id, "codename", "bluebird".into()).await.unwrap();
+    ContentStorage::new_sqlite(pool.clone()).save_update(&doc.id.to_string(), b"plaintext-update".to_vec()).await.unwrap();
+    pool.close().await;
+    assert!(on_disk(&path, "Quarterly secrets"));
+
+    // 2. Once encrypted, nothing of it is readable on disk, nor without the passphrase.
+    file.encrypt("correct horse").await.unwrap();
+    assert!(file.is_encrypted());
+    for needle in ["Quarterly secrets", "bluebird", "plaintext-update", "SQLite format 3"] {
+        assert!(!on_disk(&path, needle), "{} found on disk", needle);
+    }
+    assert!(file.open().await.is_err());
+    assert!(file.unlock("wrong horse").await.is_err());
+
+    // 3. Unlocked, it holds everything it held before.
+    let pool = file.unlock("correct horse").await.unwrap();
+    let repo = SqliteDocumentRepository::new(pool.clone());
+    repo.initialize().await.unwrap();
+    let found = repo.find_by_id(doc.id).await.unwrap().unwrap();
+    assert_eq!(found.properties["codename"], "bluebird");
+    assert_eq!(ContentStorage::new_sqlite(pool.clone()).load_updates(&doc.id.to_string()).await.unwrap(), vec![b"plaintext-update".to_vec()]);
+    pool.close().await;
+}
+
+#[tokio::test]
+async fn test_changing_the_passphrase_rekeys_the_vault() {
+    let path = vault_path("rekey");
+    let file = VaultFile::new(&path);
+    let pool = file.open().await.unwrap();
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.unwrap();
+    pool.close().await;
+    file.encrypt("first").await.unwrap();
+
+    assert!(file.change_passphrase("wrong", "second").await.is_err());
+    file.change_passphrase("first", "second").await.unwrap();
+
+    assert!(file.unlock("first").await.is_err());
+    let pool = file.unlock("second").await.unwrap();
+    let repo = SqliteDocumentRepository::new(pool.clone());
+    repo.initialize().await.unwrap();
+    assert!(repo.find_all(Uuid::new_v4()).await.unwrap().is_empty());
+    pool.close().await;
+}
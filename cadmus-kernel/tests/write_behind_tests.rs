@@ -0,0 +1,103 @@
+use cadmus_kernel::infrastructure::sqlite::SqliteDocumentRepository;
+use cadmus_kernel::modules::content::history::replay;
+use cadmus_kernel::modules::content::storage::ContentStorage;
+use cadmus_kernel::modules::content::write_behind::WriteBehind;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+
+async fn sqlite_storage() -> (SqlitePool, Arc<ContentStorage>) {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory SQLite");
+    SqliteDocumentRepository::new(pool.clone()).initialize().await.expect("Failed to initialize schema");
+    (pool.clone(), Arc::new(ContentStorage::new_sqlite(pool).with_compaction_threshold(0)))
+}
+
+/// Incremental updates of a client typing `words` one at a time.
+fn typing(words: &[&str]) -> Vec<Vec<u8>> {
+    let doc = Doc::new();
+    let text = doc.get_or_insert_text("content");
+    words.iter()
+        .map(|word| {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), word);
+            doc.transact().encode_diff_v1(&sv)
+        })
+        .collect()
+}
+
+async fn row_count(pool: &SqlitePool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM document_updates").fetch_one(pool).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_updates_are_merged_per_author_and_flushed_in_one_batch() {
+    let (pool, storage) = sqlite_storage().await;
+    let write_behind = WriteBehind::new(storage.clone(), Duration::from_secs(3600), usize::MAX);
+    let doc_id = Uuid::new_v4().to_string();
+    let (alice, bob) = (Some(Uuid::new_v4()), Some(Uuid::new_v4()));
+
+    let updates = typing(&["Sov", "er", "eign", " notes"]);
+    for update in &updates[..3] {
+        write_behind.enqueue(&doc_id, update.clone(), alice).unwrap();
+    }
+    write_behind.enqueue(&doc_id, updates[3].clone(), bob).unwrap();
+    assert_eq!(row_count(&pool).await, 0);
+    assert!(write_behind.status(&doc_id).pending_bytes > 0);
+
+    // One row per run of the same author, and the merged rows replay to the same text.
+    write_behind.flush().await.unwrap();
+    let history = storage.load_history(&doc_id).await.unwrap();
+    assert_eq!(history.iter().map(|e| e.author_id).collect::<Vec<_>>(), vec![alice, bob]);
+    let doc = replay(storage.load_updates(&doc_id).await.unwrap());
+    assert_eq!(doc.get_or_insert_text("content").get_string(&doc.transact()), "Sovereign notes");
+    assert_eq!(write_behind.status(&doc_id).pending_bytes, 0);
+}
+
+#[tokio::test]
+async fn test_updates_are_written_after_the_interval_or_size_threshold() {
+    let (pool, storage) = sqlite_storage().await;
+    let doc_id = Uuid::new_v4().to_string();
+
+    let by_time = WriteBehind::new(storage.clone(), Duration::from_millis(20), usize::MAX);
+    by_time.enqueue(&doc_id, typing(&["Sovereign"]).remove(0), None).unwrap();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(row_count(&pool).await, 1);
+
+    let by_size = WriteBehind::new(storage.clone(), Duration::from_secs(3600), 1);
+    by_size.enqueue(&doc_id, typing(&["Sovereign"]).remove(0), None).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(row_count(&pool).await, 2);
+}
+
+#[tokio::test]
+async fn test_failed_writes_are_kept_reported_and_retried() {
+    let (pool, storage) = sqlite_storage().await;
+    let write_behind = WriteBehind::new(storage.clone(), Duration::from_secs(3600), usize::MAX);
+    let doc_id = Uuid::new_v4().to_string();
+    let updates = typing(&["Sovereign", " notes"]);
+
+    // 1. The log is unavailable: the write fails and the update stays buffered.
+    sqlx::query("ALTER TABLE document_updates RENAME TO document_updates_offline").execute(&pool).await.unwrap();
+    write_behind.enqueue(&doc_id, updates[0].clone(), None).unwrap();
+    assert!(write_behind.flush().await.is_err());
+    let status = write_behind.status(&doc_id);
+    assert!(status.error.is_some());
+    assert!(status.pending_bytes > 0);
+
+    // 2. Later updates are still accepted, and the failure is reported to the caller.
+    assert!(write_behind.enqueue(&doc_id, updates[1].clone(), None).is_err());
+
+    // 3. Once storage recovers, everything is written in order and the error clears.
+    sqlx::query("ALTER TABLE document_updates_offline RENAME TO document_updates").execute(&pool).await.unwrap();
+    write_behind.flush().await.unwrap();
+    assert_eq!(write_behind.status(&doc_id), Default::default());
+    let doc = replay(storage.load_updates(&doc_id).await.unwrap());
+    assert_eq!(doc.get_or_insert_text("content").get_string(&doc.transact()), "Sovereign notes");
+}
@@ -1,130 +1,358 @@
-use cadmus_kernel::domain::repository::{DocumentRepository, ArchetypeRepository};
-use cadmus_kernel::infrastructure::sqlite::{SqliteDocumentRepository, SqliteArchetypeRepository};
-use cadmus_kernel::modules::content::storage::ContentStorage;
-use sqlx::sqlite::SqlitePoolOptions;
+use cadmus_kernel::domain::logic::integrity::{AuditEntry, ChainVerification};
+use cadmus_kernel::domain::repository::{DocumentRepository, ArchetypeRepository, AuditRepository};
+use cadmus_kernel::modules::content::history::{self, DocumentVersion, DEFAULT_SESSION_GAP};
+use cadmus_kernel::modules::content::storage::DbPool;
+use cadmus_kernel::modules::graph::domain::DocumentLink;
+use cadmus_kernel::modules::query::domain::{DocumentPage, DocumentQuery};
+use cadmus_kernel::modules::trash::domain::TrashItem;
+use cadmus_kernel::modules::portability::bundle::{ImportReport, WorkspaceBundle};
+use cadmus_kernel::modules::portability::service::PortabilityService;
+use cadmus_kernel::modules::sync::domain::SyncReport;
+use cadmus_kernel::modules::sync::engine::ReplicationEngine;
+use cadmus_kernel::modules::sync::store::SyncStore;
+use chrono::{DateTime, Utc};
 use tauri::{State, Manager};
+use std::path::Path;
 use std::sync::Arc;
 use uuid::Uuid;
 use serde_json::json;
 
+mod sync;
+mod vault;
+
 struct AppState {
-    doc_repo: Arc<SqliteDocumentRepository>,
-    arch_repo: Arc<SqliteArchetypeRepository>,
-    storage: Arc<ContentStorage>,
+    vault: vault::VaultState,
 }
 
 #[tauri::command]
 async fn push_update(state: State<'_, AppState>, doc_id: String, update: Vec<u8>) -> Result<(), String> {
-    state.storage.save_update(&doc_id, update).await.map_err(|e| e.to_string())
+    let vault = state.vault.get().await?;
+    vault.storage.save_update(&doc_id, update).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn get_updates(state: State<'_, AppState>, doc_id: String) -> Result<Vec<Vec<u8>>, String> {
-    state.storage.load_updates(&doc_id).await.map_err(|e| e.to_string())
+    let vault = state.vault.get().await?;
+    vault.storage.load_updates(&doc_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn get_latest_update(state: State<'_, AppState>, doc_id: String) -> Result<Option<Vec<u8>>, String> {
-    state.storage.load_latest_update(&doc_id).await.map_err(|e| e.to_string())
+    let vault = state.vault.get().await?;
+    vault.storage.load_latest_update(&doc_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_history(state: State<'_, AppState>, doc_id: String, gap_secs: Option<u64>) -> Result<Vec<DocumentVersion>, String> {
+    let vault = state.vault.get().await?;
+    let gap = gap_secs.map(std::time::Duration::from_secs).unwrap_or(DEFAULT_SESSION_GAP);
+    vault.storage.load_versions(&doc_id, gap).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_state_at(state: State<'_, AppState>, doc_id: String, at: DateTime<Utc>) -> Result<Vec<u8>, String> {
+    let vault = state.vault.get().await?;
+    vault.storage.load_state_at(&doc_id, at).await.map_err(|e| e.to_string())
+}
+
+/// Appends the update that brings the document back to its state as of `at` and returns it,
+/// so the open editor can apply it; `None` when nothing was written since.
+#[tauri::command]
+async fn restore_version(state: State<'_, AppState>, doc_id: String, at: DateTime<Utc>, user_id: Option<String>) -> Result<Option<Vec<u8>>, String> {
+    let vault = state.vault.get().await?;
+    let author = user_id.and_then(|s| Uuid::parse_str(&s).ok());
+    let past = history::replay(vault.storage.load_updates_until(&doc_id, at).await.map_err(|e| e.to_string())?);
+    let current = history::replay(vault.storage.load_updates(&doc_id).await.map_err(|e| e.to_string())?);
+    let Some(update) = history::revert_update(&past, &current).map_err(|e| e.to_string())? else {
+        return Ok(None);
+    };
+    vault.storage.save_update_as(&doc_id, update.clone(), author).await.map_err(|e| e.to_string())?;
+    let _ = vault.audit.log(author, Uuid::parse_str(&doc_id).ok(), "Content", "RESTORE_VERSION", Some(at.to_rfc3339())).await;
+    Ok(Some(update))
 }
 
 #[tauri::command]
 async fn get_recent_docs(state: State<'_, AppState>, user_id: String, limit: i64) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
     let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
-    let docs = state.doc_repo.find_recent(uid, limit).await.map_err(|e| e.to_string())?;
+    let docs = vault.doc_repo.find_recent(uid, limit).await.map_err(|e| e.to_string())?;
     Ok(json!(docs))
 }
 
 #[tauri::command]
 async fn get_all_docs(state: State<'_, AppState>, user_id: String) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
     let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
-    let docs = state.doc_repo.find_all(uid).await.map_err(|e| e.to_string())?;
+    let docs = vault.doc_repo.find_all(uid).await.map_err(|e| e.to_string())?;
     Ok(json!(docs))
 }
 
+/// Lists the documents matching a query, a page at a time, as the API does.
+#[tauri::command]
+async fn query_docs(state: State<'_, AppState>, user_id: String, query: DocumentQuery) -> Result<DocumentPage, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    vault.query.query(uid, &query).await.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn create_doc(state: State<'_, AppState>, user_id: String, title: String, class_id: Option<String>, parent_id: Option<String>) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
     let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
-    let pid = parent_id.and_then(|s| Uuid::parse_str(&s).ok());
-    let node = state.doc_repo.create(uid, title, class_id, pid).await.map_err(|e| e.to_string())?;
+    let pid = parent_id.map(|s| Uuid::parse_str(&s)).transpose().map_err(|e| e.to_string())?;
+    let node = vault.hierarchy.create_document(uid, title, class_id, pid).await.map_err(|e| e.to_string())?;
     Ok(json!(node))
 }
 
+/// Moves a document and its descendants to the trash.
 #[tauri::command]
 async fn delete_doc(state: State<'_, AppState>, doc_id: String, user_id: String) -> Result<(), String> {
+    let vault = state.vault.get().await?;
+    let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let trashed = vault.trash.trash(uid, did).await.map_err(|e| e.to_string())?;
+    let _ = vault.audit.log(Some(uid), Some(did), "Document", "TRASH", Some(trashed.to_string())).await;
+    Ok(())
+}
+
+/// Lists the documents the user has deleted, most recent first.
+#[tauri::command]
+async fn list_trash(state: State<'_, AppState>, user_id: String) -> Result<Vec<TrashItem>, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    vault.trash.list(uid).await.map_err(|e| e.to_string())
+}
+
+/// Brings a deleted document back with the descendants deleted along with it.
+#[tauri::command]
+async fn restore_doc(state: State<'_, AppState>, doc_id: String, user_id: String) -> Result<u64, String> {
+    let vault = state.vault.get().await?;
     let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
     let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
-    state.doc_repo.delete(did, uid).await.map_err(|e| e.to_string())
+    let restored = vault.trash.restore(uid, did).await.map_err(|e| e.to_string())?;
+    let _ = vault.audit.log(Some(uid), Some(did), "Document", "RESTORE", Some(restored.to_string())).await;
+    Ok(restored)
+}
+
+/// Deletes a trashed document and its trashed descendants for good.
+#[tauri::command]
+async fn purge_doc(state: State<'_, AppState>, doc_id: String, user_id: String) -> Result<u64, String> {
+    let vault = state.vault.get().await?;
+    let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let purged = vault.trash.purge(uid, did).await.map_err(|e| e.to_string())?;
+    let _ = vault.audit.log(Some(uid), Some(did), "Document", "PURGE", Some(purged.to_string())).await;
+    Ok(purged)
+}
+
+/// Moves a document under `parent_id`, or to the root when it is `None`.
+#[tauri::command]
+async fn move_doc(state: State<'_, AppState>, doc_id: String, user_id: String, parent_id: Option<String>) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
+    let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let pid = parent_id.map(|s| Uuid::parse_str(&s)).transpose().map_err(|e| e.to_string())?;
+    let node = vault.hierarchy.move_document(uid, did, pid).await.map_err(|e| e.to_string())?;
+    let _ = vault.audit.log(Some(uid), Some(did), "Document", "MOVE", pid.map(|p| p.to_string())).await;
+    Ok(json!(node))
+}
+
+/// Copies a document and its descendants next to it.
+#[tauri::command]
+async fn duplicate_doc(state: State<'_, AppState>, doc_id: String, user_id: String) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
+    let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let copy = vault.hierarchy.duplicate_document(uid, did).await.map_err(|e| e.to_string())?;
+    let _ = vault.audit.log(Some(uid), Some(did), "Document", "DUPLICATE", Some(copy.id.to_string())).await;
+    Ok(json!(copy))
 }
 
 #[tauri::command]
 async fn get_archetypes(state: State<'_, AppState>) -> Result<serde_json::Value, String> {
-    let archs = state.arch_repo.find_all().await.map_err(|e| e.to_string())?;
+    let vault = state.vault.get().await?;
+    let archs = vault.arch_repo.find_all().await.map_err(|e| e.to_string())?;
     Ok(json!(archs))
 }
 
 #[tauri::command]
 async fn get_system_stats(state: State<'_, AppState>, user_id: String) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
     let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
-    let stats = state.doc_repo.get_stats(uid).await.map_err(|e| e.to_string())?;
+    let stats = vault.doc_repo.get_stats(uid).await.map_err(|e| e.to_string())?;
     Ok(json!(stats))
 }
 
 #[tauri::command]
 async fn update_doc_property(state: State<'_, AppState>, doc_id: String, key: String, value: serde_json::Value, _user_id: Option<String>) -> Result<(), String> {
+    let vault = state.vault.get().await?;
     let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
-    state.doc_repo.update_property(did, &key, value).await.map_err(|e| e.to_string())
+    vault.doc_repo.update_property(did, &key, value).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn get_collection(state: State<'_, AppState>, doc_id: String) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
     let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
-    let rows = state.doc_repo.get_collection_rows(did).await.map_err(|e| e.to_string())?;
+    let rows = vault.doc_repo.get_collection_rows(did).await.map_err(|e| e.to_string())?;
     Ok(json!({ "rows": rows, "columns": [] }))
 }
 
 #[tauri::command]
 async fn update_collection_cell(state: State<'_, AppState>, doc_id: String, row_id: String, col_id: String, value: serde_json::Value) -> Result<(), String> {
+    let vault = state.vault.get().await?;
     let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
     let rid = Uuid::parse_str(&row_id).map_err(|e| e.to_string())?;
-    state.doc_repo.update_collection_cell(did, rid, &col_id, value).await.map_err(|e| e.to_string())
+    vault.doc_repo.update_collection_cell(did, rid, &col_id, value).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn add_collection_row(state: State<'_, AppState>, doc_id: String) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
     let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
-    let row = state.doc_repo.add_collection_row(did).await.map_err(|e| e.to_string())?;
+    let row = vault.doc_repo.add_collection_row(did).await.map_err(|e| e.to_string())?;
     Ok(json!(row))
 }
 
 #[tauri::command]
 async fn get_tags(state: State<'_, AppState>, doc_id: String) -> Result<Vec<String>, String> {
+    let vault = state.vault.get().await?;
     let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
-    state.doc_repo.get_tags_with_inheritance(did).await.map_err(|e| e.to_string())
+    vault.doc_repo.get_tags_with_inheritance(did).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn get_links(state: State<'_, AppState>, user_id: String) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
     let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
-    let links = state.doc_repo.find_links(uid).await.map_err(|e| e.to_string())?;
+    let links = vault.doc_repo.find_links(uid).await.map_err(|e| e.to_string())?;
     Ok(json!(links))
 }
 
+/// Links two documents of the user under `relation`, a plain "link" when it is `None`.
 #[tauri::command]
-async fn create_link(state: State<'_, AppState>, from_id: String, to_id: String) -> Result<(), String> {
+async fn create_link(state: State<'_, AppState>, user_id: String, from_id: String, to_id: String, relation: Option<String>, metadata: Option<serde_json::Value>) -> Result<DocumentLink, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
     let fid = Uuid::parse_str(&from_id).map_err(|e| e.to_string())?;
     let tid = Uuid::parse_str(&to_id).map_err(|e| e.to_string())?;
-    state.doc_repo.add_link(fid, tid).await.map_err(|e| e.to_string())
+    vault.graph.link(uid, fid, tid, relation, metadata).await.map_err(|e| e.to_string())
+}
+
+/// Removes the link of `relation` between two documents, or every link between them when it is `None`.
+#[tauri::command]
+async fn delete_link(state: State<'_, AppState>, user_id: String, from_id: String, to_id: String, relation: Option<String>) -> Result<u64, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let fid = Uuid::parse_str(&from_id).map_err(|e| e.to_string())?;
+    let tid = Uuid::parse_str(&to_id).map_err(|e| e.to_string())?;
+    vault.graph.unlink(uid, fid, tid, relation.as_deref()).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_backlinks(state: State<'_, AppState>, user_id: String, doc_id: String) -> Result<Vec<DocumentLink>, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
+    vault.graph.backlinks(uid, did).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn get_doc(state: State<'_, AppState>, doc_id: String) -> Result<serde_json::Value, String> {
+    let vault = state.vault.get().await?;
     let did = Uuid::parse_str(&doc_id).map_err(|e| e.to_string())?;
-    let doc = state.doc_repo.find_by_id(did).await.map_err(|e| e.to_string())?
+    let doc = vault.doc_repo.find_by_id(did).await.map_err(|e| e.to_string())?
         .ok_or("DOC_NOT_FOUND".to_string())?;
     Ok(json!(doc))
 }
 
+/// Replicates the local vault with a cadmus-api server, resuming from the last synced point.
+#[tauri::command]
+async fn sync_now(state: State<'_, AppState>, server_url: String, token: String) -> Result<SyncReport, String> {
+    let vault = state.vault.get().await?;
+    let remote = Arc::new(sync::HttpSyncRemote::new(&server_url, token));
+    let uid = remote.session().await.map_err(|e| e.to_string())?.user_id;
+    let engine = ReplicationEngine::new(SyncStore::new(DbPool::Sqlite(vault.pool.clone())), vault.storage.clone(), remote, uid);
+    engine.sync_once().await.map_err(|e| e.to_string())
+}
+
+/// Writes the user's workspace, content included, to a bundle file at `path`.
+#[tauri::command]
+async fn export_workspace(state: State<'_, AppState>, user_id: String, path: String) -> Result<usize, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let bundle = PortabilityService::new(DbPool::Sqlite(vault.pool.clone())).export(uid).await.map_err(|e| e.to_string())?;
+    let bytes = bundle.to_bytes().map_err(|e| e.to_string())?;
+    std::fs::write(&path, bytes).map_err(|e| e.to_string())?;
+    Ok(bundle.manifest.document_count)
+}
+
+/// Recreates the bundle file at `path` in the user's workspace, under new ids.
+#[tauri::command]
+async fn import_workspace(state: State<'_, AppState>, user_id: String, path: String) -> Result<ImportReport, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+    let bundle = WorkspaceBundle::from_bytes(&bytes).map_err(|e| e.to_string())?;
+    PortabilityService::new(DbPool::Sqlite(vault.pool.clone())).import(uid, &bundle).await.map_err(|e| e.to_string())
+}
+
+/// Imports the folder of Markdown notes at `path` into the user's workspace.
+#[tauri::command]
+async fn import_markdown_vault(state: State<'_, AppState>, user_id: String, path: String) -> Result<ImportReport, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    PortabilityService::new(DbPool::Sqlite(vault.pool.clone())).import_markdown(uid, Path::new(&path)).await.map_err(|e| e.to_string())
+}
+
+/// Writes the user's workspace as Markdown notes into the empty folder at `path`.
+#[tauri::command]
+async fn export_markdown_vault(state: State<'_, AppState>, user_id: String, path: String) -> Result<usize, String> {
+    let vault = state.vault.get().await?;
+    let uid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    PortabilityService::new(DbPool::Sqlite(vault.pool.clone())).export_markdown(uid, Path::new(&path)).await.map_err(|e| e.to_string())
+}
+
+/// Latest entries of the local audit trail, most recent first.
+#[tauri::command]
+async fn get_audit_log(state: State<'_, AppState>, limit: Option<i64>) -> Result<Vec<AuditEntry>, String> {
+    let vault = state.vault.get().await?;
+    vault.audit.recent(limit.unwrap_or(100)).await.map_err(|e| e.to_string())
+}
+
+/// Recomputes the hash chain of the local audit trail and reports the first broken entry.
+#[tauri::command]
+async fn verify_audit_chain(state: State<'_, AppState>) -> Result<ChainVerification, String> {
+    let vault = state.vault.get().await?;
+    vault.audit.verify().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn vault_status(state: State<'_, AppState>) -> Result<vault::VaultStatus, String> {
+    Ok(state.vault.status().await)
+}
+
+#[tauri::command]
+async fn unlock_vault(state: State<'_, AppState>, passphrase: String) -> Result<(), String> {
+    state.vault.unlock(&passphrase).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn lock_vault(state: State<'_, AppState>) -> Result<(), String> {
+    state.vault.lock().await.map_err(|e| e.to_string())
+}
+
+/// Encrypts a plaintext vault; from then on it opens locked.
+#[tauri::command]
+async fn encrypt_vault(state: State<'_, AppState>, passphrase: String) -> Result<(), String> {
+    state.vault.encrypt(&passphrase).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn change_vault_passphrase(state: State<'_, AppState>, old_passphrase: String, new_passphrase: String) -> Result<(), String> {
+    state.vault.change_passphrase(&old_passphrase, &new_passphrase).await.map_err(|e| e.to_string())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -134,30 +362,14 @@ pub fn run() {
                 std::fs::create_dir_all(&app_data_dir).expect("failed to create app data dir");
             }
             let db_path = app_data_dir.join("cadmus_local.db");
-            let db_url = format!("sqlite:{}?mode=rwc", db_path.display());
-
-            let pool = tauri::async_runtime::block_on(async {
-                SqlitePoolOptions::new()
-                    .max_connections(5)
-                    .connect(&db_url)
-                    .await
-                    .expect("Failed to connect to local SQLite")
-            });
-
-            let doc_repo = Arc::new(SqliteDocumentRepository::new(pool.clone()));
-            let arch_repo = Arc::new(SqliteArchetypeRepository::new(pool.clone()));
-            let storage = Arc::new(ContentStorage::new_sqlite(pool.clone()));
-
-            // Initialize DB tables
-            tauri::async_runtime::block_on(async {
+            // An encrypted vault stays locked until `unlock_vault`; one that fails to open is
+            // reported by `vault_status`.
+            let vault = tauri::async_runtime::block_on(async {
                 println!("Cadmus Kernel: Initializing Local Storage at {}...", db_path.display());
-                match doc_repo.initialize().await {
-                    Ok(_) => println!("Cadmus Kernel: Database Ready & Seeded."),
-                    Err(e) => eprintln!("Cadmus Kernel: Initialization Failed: {}", e),
-                }
+                vault::VaultState::start(db_path).await
             });
 
-            app.manage(AppState { doc_repo, arch_repo, storage });
+            app.manage(AppState { vault });
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -165,10 +377,19 @@ pub fn run() {
             push_update,
             get_updates,
             get_latest_update,
+            get_history,
+            get_state_at,
+            restore_version,
             get_recent_docs,
             get_all_docs,
+            query_docs,
             create_doc,
             delete_doc,
+            list_trash,
+            restore_doc,
+            purge_doc,
+            move_doc,
+            duplicate_doc,
             get_archetypes,
             get_system_stats,
             update_doc_property,
@@ -177,7 +398,21 @@ pub fn run() {
             add_collection_row,
             get_tags,
             get_links,
-            create_link
+            create_link,
+            delete_link,
+            get_backlinks,
+            sync_now,
+            export_workspace,
+            import_workspace,
+            import_markdown_vault,
+            export_markdown_vault,
+            get_audit_log,
+            verify_audit_chain,
+            vault_status,
+            unlock_vault,
+            lock_vault,
+            encrypt_vault,
+            change_vault_passphrase
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use cadmus_kernel::modules::sync::domain::{ChangeSet, DocExchange, PushResult, SyncCursor, SyncSession};
+use cadmus_kernel::modules::sync::engine::SyncRemote;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use uuid::Uuid;
+
+/// The cadmus-api server a desktop vault replicates with, reached over its `/api/v1/sync` routes.
+pub struct HttpSyncRemote {
+    client: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl HttpSyncRemote {
+    pub fn new(server_url: &str, token: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: server_url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    /// Asks the server whose token this is; a vault only syncs the workspace of that user.
+    pub async fn session(&self) -> anyhow::Result<SyncSession> {
+        let response = self.client.get(format!("{}/api/v1/sync/session", self.base_url))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("sync session failed with {}: {}", status, text));
+        }
+        Ok(response.json().await?)
+    }
+
+    async fn post<B: Serialize, R: DeserializeOwned>(&self, path: &str, body: &B) -> anyhow::Result<R> {
+        let response = self.client.post(format!("{}/api/v1/sync{}", self.base_url, path))
+            .bearer_auth(&self.token)
+            .json(body)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("sync request {} failed with {}: {}", path, status, text));
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl SyncRemote for HttpSyncRemote {
+    fn name(&self) -> String {
+        self.base_url.clone()
+    }
+
+    async fn pull(&self, cursor: &SyncCursor, limit: i64) -> anyhow::Result<ChangeSet> {
+        self.post("/pull", &json!({ "cursor": cursor, "limit": limit })).await
+    }
+
+    async fn push(&self, changes: &ChangeSet) -> anyhow::Result<PushResult> {
+        self.post("/push", changes).await
+    }
+
+    async fn exchange(&self, doc_id: Uuid, state_vector: Vec<u8>) -> anyhow::Result<DocExchange> {
+        self.post(&format!("/docs/{}/exchange", doc_id), &json!({ "state_vector": state_vector })).await
+    }
+
+    async fn push_update(&self, doc_id: Uuid, update: Vec<u8>) -> anyhow::Result<()> {
+        let response = self.client.post(format!("{}/api/v1/sync/docs/{}/update", self.base_url, doc_id))
+            .bearer_auth(&self.token)
+            .json(&json!({ "update": update }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("sync update of {} failed with {}: {}", doc_id, status, text));
+        }
+        Ok(())
+    }
+}
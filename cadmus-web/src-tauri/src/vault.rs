@@ -0,0 +1,156 @@
+use cadmus_kernel::infrastructure::sqlite::{SqliteArchetypeRepository, SqliteAuditRepository, SqliteDocumentRepository};
+use cadmus_kernel::domain::archetypes::modules::ModuleRegistry;
+use cadmus_kernel::infrastructure::vault::VaultFile;
+use cadmus_kernel::modules::content::storage::ContentStorage;
+use cadmus_kernel::modules::graph::service::GraphService;
+use cadmus_kernel::modules::hierarchy::service::HierarchyService;
+use cadmus_kernel::modules::query::service::QueryService;
+use cadmus_kernel::modules::trash::service::TrashService;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Repositories over an unlocked vault. Dropped, and its pool closed, when the vault is locked.
+pub struct OpenVault {
+    pub doc_repo: Arc<SqliteDocumentRepository>,
+    pub arch_repo: Arc<SqliteArchetypeRepository>,
+    pub audit: Arc<SqliteAuditRepository>,
+    pub hierarchy: Arc<HierarchyService>,
+    pub graph: Arc<GraphService>,
+    pub trash: Arc<TrashService>,
+    pub query: Arc<QueryService>,
+    pub storage: Arc<ContentStorage>,
+    pub pool: SqlitePool,
+}
+
+impl OpenVault {
+    async fn new(pool: SqlitePool) -> anyhow::Result<Self> {
+        let doc_repo = Arc::new(SqliteDocumentRepository::new(pool.clone()));
+        doc_repo.initialize().await?;
+        let arch_repo = Arc::new(SqliteArchetypeRepository::new(pool.clone()));
+        // The app is not running long enough for a timer; expired trash goes when the vault opens.
+        let trash = Arc::new(TrashService::new(doc_repo.clone()));
+        trash.purge_expired().await?;
+        Ok(Self {
+            hierarchy: Arc::new(HierarchyService::new(doc_repo.clone(), arch_repo.clone(), Arc::new(ModuleRegistry::new()))),
+            graph: Arc::new(GraphService::new(doc_repo.clone())),
+            trash,
+            query: Arc::new(QueryService::new(doc_repo.clone())),
+            doc_repo,
+            arch_repo,
+            audit: Arc::new(SqliteAuditRepository::new(pool.clone())),
+            storage: Arc::new(ContentStorage::new_sqlite(pool.clone())),
+            pool,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultStatus {
+    pub encrypted: bool,
+    pub unlocked: bool,
+    /// Why the vault failed to open when the app started, while it is still closed.
+    pub error: Option<String>,
+}
+
+/// The local vault, open or locked. Commands reach the data through `get`, which fails while
+/// the vault is locked, so nothing is read or written without the key.
+pub struct VaultState {
+    file: VaultFile,
+    open: RwLock<Option<Arc<OpenVault>>>,
+    failure: Option<String>,
+}
+
+impl VaultState {
+    /// Opens a plaintext vault right away; an encrypted one waits for `unlock`. A vault that
+    /// fails to open stays closed, and the failure is reported by `get` and `status` instead
+    /// of stopping the app.
+    pub async fn start(db_path: PathBuf) -> Self {
+        let file = VaultFile::new(db_path);
+        let mut open = None;
+        let mut failure = None;
+        if !file.is_encrypted() {
+            let opened = match file.open().await {
+                Ok(pool) => OpenVault::new(pool).await,
+                Err(e) => Err(e),
+            };
+            match opened {
+                Ok(vault) => open = Some(Arc::new(vault)),
+                Err(e) => {
+                    eprintln!("Cadmus Kernel: Failed to open local vault: {:#}", e);
+                    failure = Some(format!("{:#}", e));
+                }
+            }
+        }
+        Self { file, open: RwLock::new(open), failure }
+    }
+
+    pub async fn get(&self) -> Result<Arc<OpenVault>, String> {
+        if let Some(vault) = self.open.read().await.clone() {
+            return Ok(vault);
+        }
+        Err(match &self.failure {
+            Some(e) => format!("VAULT_UNAVAILABLE: {}", e),
+            None => "VAULT_LOCKED".to_string(),
+        })
+    }
+
+    pub async fn status(&self) -> VaultStatus {
+        let unlocked = self.open.read().await.is_some();
+        VaultStatus {
+            encrypted: self.file.is_encrypted(),
+            unlocked,
+            error: if unlocked { None } else { self.failure.clone() },
+        }
+    }
+
+    pub async fn unlock(&self, passphrase: &str) -> anyhow::Result<()> {
+        let mut open = self.open.write().await;
+        if open.is_none() {
+            let pool = self.file.unlock(passphrase).await?;
+            *open = Some(Arc::new(OpenVault::new(pool).await?));
+        }
+        Ok(())
+    }
+
+    /// Closes the pool; the key goes with it.
+    pub async fn lock(&self) -> anyhow::Result<()> {
+        if !self.file.is_encrypted() {
+            return Err(anyhow::anyhow!("vault is not encrypted"));
+        }
+        if let Some(vault) = self.open.write().await.take() {
+            vault.pool.close().await;
+        }
+        Ok(())
+    }
+
+    /// Turns encryption on for a plaintext vault, which stays open under the new key.
+    pub async fn encrypt(&self, passphrase: &str) -> anyhow::Result<()> {
+        let mut open = self.open.write().await;
+        if let Some(vault) = open.take() {
+            vault.pool.close().await;
+        }
+        let encrypted = self.file.encrypt(passphrase).await;
+        let pool = match encrypted {
+            Ok(()) => self.file.unlock(passphrase).await?,
+            Err(_) => self.file.open().await?,
+        };
+        *open = Some(Arc::new(OpenVault::new(pool).await?));
+        encrypted
+    }
+
+    /// Re-keys the vault under a new passphrase; it stays open, now under the new key.
+    pub async fn change_passphrase(&self, old: &str, new: &str) -> anyhow::Result<()> {
+        self.file.check_passphrase(old).await?;
+        let mut open = self.open.write().await;
+        if let Some(vault) = open.take() {
+            vault.pool.close().await;
+        }
+        let changed = self.file.change_passphrase(old, new).await;
+        let pool = self.file.unlock(if changed.is_ok() { new } else { old }).await?;
+        *open = Some(Arc::new(OpenVault::new(pool).await?));
+        changed
+    }
+}
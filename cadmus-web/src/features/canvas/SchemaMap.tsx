@@ -226,7 +226,7 @@ export function SchemaMap() {
       <ReactFlow
         nodes={nodes}
         edges={edges}
-        onConnect={(p) => { if (p.source && p.target) { createLink(p.source, p.target); setEdges((eds) => addEdge({ ...p, type: 'kinetic', animated: true }, eds)); }}}
+        onConnect={(p) => { if (user && p.source && p.target) { createLink(user.id, p.source, p.target).catch(console.error); setEdges((eds) => addEdge({ ...p, type: 'kinetic', animated: true }, eds)); }}}
         nodeTypes={nodeTypes}
         edgeTypes={edgeTypes}
         fitView
@@ -4,6 +4,7 @@ import { WebsocketProvider } from 'y-websocket';
 import { IndexeddbPersistence } from 'y-indexeddb';
 import { isTauri } from '../../../kernel/tauri_bridge';
 import { invoke } from '@tauri-apps/api/core';
+import { useAuthStore } from '../../auth/authStore';
 
 // Use environment variable for WS base URL, fallback for development/Tauri
 const WS_URL = import.meta.env.VITE_WS_URL || 'ws://127.0.0.1:3000/api/v1/content/ws/doc';
@@ -14,9 +15,11 @@ export function useYjsSync(docId: string) {
   const { ydoc, provider } = useMemo(() => {
     const ydoc = new Y.Doc();
     
-    // Only create provider if we are in browser/cloud mode
+    // Only create provider if we are in browser/cloud mode.
+    // Browsers cannot set headers on a WebSocket upgrade, so the session token rides in the URL.
+    const token = useAuthStore.getState().user?.token;
     const provider = !isTauri() 
-        ? new WebsocketProvider(WS_URL, docId, ydoc) 
+        ? new WebsocketProvider(WS_URL, docId, ydoc, { params: token ? { token } : {} }) 
         : null;
     
     // Local persistence (Browser side)
@@ -1,8 +1,8 @@
-import { IDataService, Archetype, FieldDefinition, CollectionData, DocumentMeta, WorkspaceNode } from './IDataService';
+import { IDataService, Archetype, FieldDefinition, CollectionData, DocumentMeta, WorkspaceNode, DocumentVersion } from './IDataService';
 import { HttpDataService } from './services/HttpDataService';
 import { TauriDataService } from './services/TauriDataService';
 
-export type { Archetype, FieldDefinition, CollectionData, DocumentMeta, WorkspaceNode };
+export type { Archetype, FieldDefinition, CollectionData, DocumentMeta, WorkspaceNode, DocumentVersion };
 
 // Detecta se está rodando no Tauri
 const isTauri = () => {
@@ -32,6 +32,7 @@ export const dataService = {
     async getAllDocs(userId: string) { return this.getService().getAllDocs(userId); },
     async createDoc(userId: string, title: string, classId?: string, parentId?: string) { return this.getService().createDoc(userId, title, classId, parentId); },
     async deleteDoc(docId: string, userId: string) { return this.getService().deleteDoc(docId, userId); },
+    async moveDoc(docId: string, userId: string, parentId: string | null) { return this.getService().moveDoc(docId, userId, parentId); },
     async getSystemStats(userId: string) { return this.getService().getSystemStats(userId); },
     async updateProperty(docId: string, key: string, value: any, userId?: string) { return this.getService().updateProperty(docId, key, value, userId); },
     async getArchetypes() { return this.getService().getArchetypes(); },
@@ -41,5 +42,8 @@ export const dataService = {
     async addCollectionRow(docId: string) { return this.getService().addCollectionRow(docId); },
     async pushUpdate(docId: string, update: number[]) { return this.getService().pushUpdate(docId, update); },
     async getUpdates(docId: string) { return this.getService().getUpdates(docId); },
-    async getLatestUpdate(docId: string) { return this.getService().getLatestUpdate(docId); }
+    async getLatestUpdate(docId: string) { return this.getService().getLatestUpdate(docId); },
+    async getHistory(docId: string) { return this.getService().getHistory(docId); },
+    async getStateAt(docId: string, at: string) { return this.getService().getStateAt(docId, at); },
+    async restoreVersion(docId: string, at: string, userId?: string) { return this.getService().restoreVersion(docId, at, userId); }
 };
@@ -54,6 +54,15 @@ export interface CollectionData {
     rows: any[];
 }
 
+export interface DocumentVersion {
+    at: string;          // Restore point: the end of the editing session
+    started_at: string;
+    author_id?: string | null;
+    update_count: number;
+    bytes: number;
+    compacted: boolean;  // The compacted state the history starts from
+}
+
 export interface IDataService {
     // Document Management
     getDoc(docId: string): Promise<DocumentMeta>;
@@ -61,6 +70,8 @@ export interface IDataService {
     getAllDocs(userId: string): Promise<DocumentMeta[]>;
     createDoc(userId: string, title: string, classId?: string, parentId?: string): Promise<DocumentMeta>;
     deleteDoc(docId: string, userId: string): Promise<void>;
+    /** Moves a document under `parentId`, or to the root when null. Rejects cycles and children the parent's archetype does not allow. */
+    moveDoc(docId: string, userId: string, parentId: string | null): Promise<DocumentMeta>;
     
     // System Intelligence
     getSystemStats(userId: string): Promise<SystemStats>;
@@ -79,4 +90,10 @@ export interface IDataService {
     pushUpdate(docId: string, update: number[]): Promise<void>;
     getUpdates(docId: string): Promise<number[][]>;
     getLatestUpdate(docId: string): Promise<number[] | null>;
+
+    // Version History
+    getHistory(docId: string): Promise<DocumentVersion[]>;
+    getStateAt(docId: string, at: string): Promise<number[]>;
+    /** Returns the update to apply locally, or null when the server already relays it to the editor. */
+    restoreVersion(docId: string, at: string, userId?: string): Promise<number[] | null>;
 }
\ No newline at end of file
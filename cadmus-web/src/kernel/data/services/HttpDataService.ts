@@ -1,4 +1,4 @@
-import { IDataService, DocumentMeta, SystemStats, Archetype, CollectionData } from '../IDataService';
+import { IDataService, DocumentMeta, SystemStats, Archetype, CollectionData, DocumentVersion } from '../IDataService';
 import { getAuthHeaders } from '../authHeaders';
 
 // Use environment variable for API base URL, fallback for development/Tauri
@@ -32,7 +32,10 @@ export class HttpDataService implements IDataService {
             headers: getAuthHeaders({ 'Content-Type': 'application/json' }),
             body: JSON.stringify({ user_id: userId, title, class_id: classId, parent_id: parentId })
         });
-        if (!res.ok) throw new Error('Failed to create doc');
+        if (!res.ok) {
+            const body = await res.json().catch(() => null);
+            throw new Error(body?.error || 'Failed to create doc');
+        }
         return res.json();
     }
 
@@ -44,6 +47,19 @@ export class HttpDataService implements IDataService {
         if (!res.ok) throw new Error('Failed to delete doc');
     }
 
+    async moveDoc(docId: string, _userId: string, parentId: string | null): Promise<DocumentMeta> {
+        const res = await fetch(`${this.baseUrl}/${docId}/move`, {
+            method: 'POST',
+            headers: getAuthHeaders({ 'Content-Type': 'application/json' }),
+            body: JSON.stringify({ parent_id: parentId })
+        });
+        if (!res.ok) {
+            const body = await res.json().catch(() => null);
+            throw new Error(body?.error || 'Failed to move doc');
+        }
+        return res.json();
+    }
+
     async getSystemStats(_userId: string): Promise<SystemStats> {
         const headers = getAuthHeaders();
         const res = await fetch(this.statsUrl, { headers });
@@ -143,4 +159,27 @@ export class HttpDataService implements IDataService {
         if (!res.ok) return null;
         return res.json();
     }
+
+    async getHistory(docId: string): Promise<DocumentVersion[]> {
+        const res = await fetch(`${this.baseUrl}/${docId}/history`, { headers: getAuthHeaders() });
+        if (!res.ok) throw new Error('Failed to fetch history');
+        return res.json();
+    }
+
+    async getStateAt(docId: string, at: string): Promise<number[]> {
+        const res = await fetch(`${this.baseUrl}/${docId}/history/state?at=${encodeURIComponent(at)}`, { headers: getAuthHeaders() });
+        if (!res.ok) throw new Error('Failed to fetch version');
+        return res.json();
+    }
+
+    async restoreVersion(docId: string, at: string, _userId?: string): Promise<number[] | null> {
+        const res = await fetch(`${this.baseUrl}/${docId}/restore`, {
+            method: 'POST',
+            headers: getAuthHeaders({ 'Content-Type': 'application/json' }),
+            body: JSON.stringify({ at })
+        });
+        if (!res.ok) throw new Error('Failed to restore version');
+        // The restore reaches the open editor through the collaboration socket.
+        return null;
+    }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 import { invoke } from '@tauri-apps/api/core';
-import { IDataService, DocumentMeta, SystemStats, Archetype, CollectionData } from '../IDataService';
+import { IDataService, DocumentMeta, SystemStats, Archetype, CollectionData, DocumentVersion } from '../IDataService';
 
 export class TauriDataService implements IDataService {
     async getDoc(docId: string): Promise<DocumentMeta> {
@@ -22,6 +22,10 @@ export class TauriDataService implements IDataService {
         return invoke('delete_doc', { docId, userId });
     }
 
+    async moveDoc(docId: string, userId: string, parentId: string | null): Promise<DocumentMeta> {
+        return invoke('move_doc', { docId, userId, parentId });
+    }
+
     async getSystemStats(userId: string): Promise<SystemStats> {
         return invoke('get_system_stats', { userId });
     }
@@ -62,4 +66,16 @@ export class TauriDataService implements IDataService {
     async getLatestUpdate(docId: string): Promise<number[] | null> {
         return invoke('get_latest_update', { docId });
     }
+
+    async getHistory(docId: string): Promise<DocumentVersion[]> {
+        return invoke('get_history', { docId });
+    }
+
+    async getStateAt(docId: string, at: string): Promise<number[]> {
+        return invoke('get_state_at', { docId, at });
+    }
+
+    async restoreVersion(docId: string, at: string, userId?: string): Promise<number[] | null> {
+        return invoke('restore_version', { docId, at, userId });
+    }
 }
@@ -71,15 +71,183 @@ export const getLinks = async (userId: string) => {
     return res.json();
 };
 
-export const createLink = async (fromId: string, toId: string) => {
+export interface DocumentLink {
+    from_id: string;
+    to_id: string;
+    relation: string;
+    metadata: Record<string, unknown>;
+    created_at: string | null;
+}
+
+/** Links two documents under `relation` ("link" when omitted); linking again replaces the metadata. */
+export const createLink = async (userId: string, fromId: string, toId: string, relation?: string, metadata?: Record<string, unknown>) => {
     if (isTauri()) {
-        try { await invoke("create_link", { fromId, toId }); return; }
-        catch (e) { console.error(e); }
+        return await invoke<DocumentLink>("create_link", { userId, fromId, toId, relation, metadata });
+    }
+    const { getAuthHeaders } = await import("./data/authHeaders");
+    const res = await fetch('/api/v1/content/docs/links/create', {
+        method: 'POST',
+        headers: getAuthHeaders({ 'Content-Type': 'application/json' }),
+        body: JSON.stringify({ from_id: fromId, to_id: toId, relation, metadata }),
+    });
+    const body = await res.json().catch(() => ({}));
+    if (!res.ok) throw new Error(body.error || 'Failed to create link');
+    return body as DocumentLink;
+};
+
+/** Removes the link of `relation` between two documents, or all of them when omitted. */
+export const deleteLink = async (userId: string, fromId: string, toId: string, relation?: string) => {
+    if (isTauri()) {
+        await invoke("delete_link", { userId, fromId, toId, relation });
+        return;
+    }
+    const { getAuthHeaders } = await import("./data/authHeaders");
+    const res = await fetch('/api/v1/content/docs/links/delete', {
+        method: 'POST',
+        headers: getAuthHeaders({ 'Content-Type': 'application/json' }),
+        body: JSON.stringify({ from_id: fromId, to_id: toId, relation }),
+    });
+    if (!res.ok) {
+        const body = await res.json().catch(() => ({}));
+        throw new Error(body.error || 'Failed to delete link');
     }
+};
+
+/** Links pointing at a document, oldest first. */
+export const getBacklinks = async (userId: string, docId: string): Promise<DocumentLink[]> => {
+    if (isTauri()) {
+        return await invoke<DocumentLink[]>("get_backlinks", { userId, docId });
+    }
+    const { getAuthHeaders } = await import("./data/authHeaders");
+    const res = await fetch(`/api/v1/content/docs/${docId}/backlinks`, { headers: getAuthHeaders() });
+    if (!res.ok) return [];
+    return res.json();
+};
+
+export interface SyncReport {
+    pulled: number;
+    pushed: number;
+    ignored: number;
+    docs_reconciled: number;
+}
+
+/** Replicates the desktop vault with the server; a no-op in the browser, which is always online. */
+export const syncNow = async (serverUrl: string, token: string): Promise<SyncReport | null> => {
+    if (!isTauri()) return null;
+    return await invoke<SyncReport>("sync_now", { serverUrl, token });
+};
+
+export interface AuditEntry {
+    id: string;
+    user_id: string | null;
+    resource_id: string | null;
+    resource_type: string | null;
+    action: string;
+    details: string;
+    created_at: string;
+    prev_hash: string | null;
+    hash: string | null;
+}
+
+export interface ChainVerification {
+    checked: number;
+    valid: boolean;
+    broken_at: string | null;
+}
+
+/** Latest entries of the desktop vault's audit trail; the server keeps its own in the browser. */
+export const getAuditLog = async (limit?: number): Promise<AuditEntry[]> => {
+    if (!isTauri()) return [];
+    return await invoke<AuditEntry[]>("get_audit_log", { limit });
+};
+
+/** Recomputes the hash chain of the desktop audit trail. */
+export const verifyAuditChain = async (): Promise<ChainVerification | null> => {
+    if (!isTauri()) return null;
+    return await invoke<ChainVerification>("verify_audit_chain");
+};
+
+export interface VaultStatus {
+    encrypted: boolean;
+    unlocked: boolean;
+    /** Why the vault failed to open at startup; vault commands fail with VAULT_UNAVAILABLE meanwhile. */
+    error: string | null;
+}
+
+/** Whether the desktop vault is encrypted, and whether it is open. The browser has no vault. */
+export const getVaultStatus = async (): Promise<VaultStatus | null> => {
+    if (!isTauri()) return null;
+    return await invoke<VaultStatus>("vault_status");
+};
+
+export const unlockVault = async (passphrase: string): Promise<void> => {
+    if (!isTauri()) return;
+    await invoke("unlock_vault", { passphrase });
+};
+
+/** Closes the vault; every vault command fails with VAULT_LOCKED until it is unlocked again. */
+export const lockVault = async (): Promise<void> => {
+    if (!isTauri()) return;
+    await invoke("lock_vault");
+};
+
+export const encryptVault = async (passphrase: string): Promise<void> => {
+    if (!isTauri()) return;
+    await invoke("encrypt_vault", { passphrase });
+};
+
+export const changeVaultPassphrase = async (oldPassphrase: string, newPassphrase: string): Promise<void> => {
+    if (!isTauri()) return;
+    await invoke("change_vault_passphrase", { oldPassphrase, newPassphrase });
+};
+
+export interface ImportReport {
+    documents: number;
+    collection_rows: number;
+    links: number;
+    id_map: Record<string, string>;
+}
+
+/** Writes the desktop workspace to a bundle file and returns how many documents it holds. */
+export const exportWorkspace = async (userId: string, path: string): Promise<number | null> => {
+    if (!isTauri()) return null;
+    return await invoke<number>("export_workspace", { userId, path });
+};
+
+/** Recreates a bundle file in the desktop workspace, under new ids. */
+export const importWorkspace = async (userId: string, path: string): Promise<ImportReport | null> => {
+    if (!isTauri()) return null;
+    return await invoke<ImportReport>("import_workspace", { userId, path });
+};
+
+/** Imports a folder of Markdown notes: folders nest, front matter becomes properties, wikilinks become links. */
+export const importMarkdownVault = async (userId: string, path: string): Promise<ImportReport | null> => {
+    if (!isTauri()) return null;
+    return await invoke<ImportReport>("import_markdown_vault", { userId, path });
+};
+
+/** Writes the desktop workspace as Markdown notes into an empty folder and returns how many documents it holds. */
+export const exportMarkdownVault = async (userId: string, path: string): Promise<number | null> => {
+    if (!isTauri()) return null;
+    return await invoke<number>("export_markdown_vault", { userId, path });
+};
+
+/** Fetches the server workspace as a bundle, ready to be saved as a file. */
+export const downloadWorkspaceBundle = async (): Promise<Blob> => {
     const { getAuthHeaders } = await import("./data/authHeaders");
-    await fetch('/api/v1/content/docs/links/create', {
+    const res = await fetch('/api/v1/workspace/export', { headers: getAuthHeaders() });
+    if (!res.ok) throw new Error(`Workspace export failed: ${res.status}`);
+    return res.blob();
+};
+
+/** Sends a bundle file to the server, which recreates it under new ids. */
+export const uploadWorkspaceBundle = async (bundle: Blob): Promise<ImportReport> => {
+    const { getAuthHeaders } = await import("./data/authHeaders");
+    const res = await fetch('/api/v1/workspace/import', {
         method: 'POST',
         headers: getAuthHeaders({ 'Content-Type': 'application/json' }),
-        body: JSON.stringify({ from_id: fromId, to_id: toId }),
+        body: bundle,
     });
-};
\ No newline at end of file
+    if (!res.ok) throw new Error(`Workspace import failed: ${res.status}`);
+    return res.json();
+};